@@ -0,0 +1,1304 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use peka_core::check::{self, CheckReport, IssueSeverity};
+use peka_core::csv_import::CsvSource;
+use peka_core::export::ExportFormat;
+use peka_core::generator::{self, GeneratorOptions};
+use peka_core::kdbx::KdbxCipher;
+use peka_core::locations::LocationRegistry;
+use peka_core::history::{self, HistoryChangeKind};
+use peka_core::sync::{self, SyncConflictKind, SyncRecordKind, SyncResolution};
+use peka_core::{
+    tags, totp, ContainerFormat, CredentialChanges, NewCredential, PayloadCompression,
+    PayloadLayout, PekaError, StoredCredential, StoredVault, Vault, VaultStatus,
+};
+
+/// Command line access to peka vaults.
+///
+/// The master password is read from the terminal, or from the first line of stdin
+/// when stdin is not a terminal. Further secrets (entry passwords, PINs, export
+/// passwords) are read the same way, one line each.
+#[derive(Parser)]
+#[command(name = "peka-cli", version)]
+struct Cli {
+    /// Vault file path or vault name. Defaults to the only vault in the app data
+    /// directory and the registered vault locations.
+    #[arg(long, short = 'v', global = true, env = "PEKA_VAULT")]
+    vault: Option<String>,
+
+    /// Print machine readable JSON instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Open the vault even if its key derivation takes unusually much memory or time.
+    #[arg(long, global = true)]
+    allow_expensive_kdf: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List credentials without their secrets.
+    List {
+        /// Only credentials in this folder (id or name).
+        #[arg(long)]
+        folder: Option<String>,
+        /// Only credentials with this tag.
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// Show one credential. Secrets are masked unless --reveal is given.
+    Show {
+        /// Credential id, title, or "Folder/Title".
+        entry: String,
+        #[arg(long)]
+        reveal: bool,
+    },
+    /// Print a single field of a credential, e.g. for use in scripts.
+    Get {
+        /// Credential id, title, or "Folder/Title".
+        entry: String,
+        /// title, username, password, url, notes, totp, id, or a custom field name.
+        #[arg(long, short, default_value = "password")]
+        field: String,
+    },
+    /// Add a credential.
+    Add(AddArgs),
+    /// Change fields of a credential.
+    Edit(EditArgs),
+    /// Move a credential to the trash.
+    Rm {
+        /// Credential id, title, or "Folder/Title".
+        entry: String,
+    },
+    /// Generate a random password. Does not need a vault.
+    Generate(GenerateArgs),
+    /// Print the current TOTP code of a credential.
+    Totp {
+        /// Credential id, title, or "Folder/Title".
+        entry: String,
+    },
+    /// Export the vault to another format.
+    Export(ExportArgs),
+    /// Import credentials from a CSV export or a KeePass database.
+    Import(ImportArgs),
+    /// Check the vault file for damage and inconsistencies. Exits with an error when
+    /// the vault has errors.
+    Check {
+        /// Apply the repairs the check offers. A damaged vault file is replaced by its
+        /// newest good backup; the damaged file is kept as a backup.
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Show or change how the vault file is stored.
+    Storage {
+        /// json is readable by every version of peka; binary is smaller and faster.
+        #[arg(long, value_enum)]
+        container: Option<ContainerArg>,
+        /// Compress the vault data before it is encrypted.
+        #[arg(long, value_enum)]
+        compression: Option<CompressionArg>,
+        /// entries encrypts every folder and credential on its own, so saving a large
+        /// vault only encrypts what changed.
+        #[arg(long, value_enum)]
+        layout: Option<LayoutArg>,
+    },
+    /// Sync the vault with its copy in a shared directory, e.g. one kept in sync between
+    /// devices by a file sync service.
+    Sync(SyncArgs),
+    /// List the revisions of the vault kept in its git history, or manage the history.
+    History(HistoryArgs),
+}
+
+#[derive(Args)]
+struct AddArgs {
+    /// Folder id or name.
+    #[arg(long)]
+    folder: String,
+    #[arg(long)]
+    title: String,
+    #[arg(long, default_value = "")]
+    username: String,
+    #[arg(long)]
+    url: Option<String>,
+    #[arg(long)]
+    notes: Option<String>,
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+    #[command(flatten)]
+    secret: SecretArgs,
+}
+
+#[derive(Args)]
+struct EditArgs {
+    /// Credential id, title, or "Folder/Title".
+    entry: String,
+    #[arg(long)]
+    title: Option<String>,
+    #[arg(long)]
+    username: Option<String>,
+    #[arg(long)]
+    url: Option<String>,
+    #[arg(long)]
+    notes: Option<String>,
+    #[command(flatten)]
+    secret: SecretArgs,
+}
+
+/// Secrets are never taken from arguments, where they would end up in shell history
+/// and process listings.
+#[derive(Args)]
+struct SecretArgs {
+    /// Read the entry password like the master password.
+    #[arg(long, conflicts_with = "generate")]
+    ask_password: bool,
+    /// Use a generated password.
+    #[arg(long)]
+    generate: bool,
+    /// Length of the generated password.
+    #[arg(long, default_value_t = generator::DEFAULT_LENGTH, requires = "generate")]
+    length: usize,
+    /// Read a TOTP secret or otpauth:// URI like the master password.
+    #[arg(long)]
+    ask_totp: bool,
+}
+
+#[derive(Args)]
+struct GenerateArgs {
+    #[arg(long, short, default_value_t = generator::DEFAULT_LENGTH)]
+    length: usize,
+    #[arg(long)]
+    no_lowercase: bool,
+    #[arg(long)]
+    no_uppercase: bool,
+    #[arg(long)]
+    no_digits: bool,
+    #[arg(long)]
+    no_symbols: bool,
+    /// Leave out characters that are easy to confuse, like l, 1, O and 0.
+    #[arg(long)]
+    exclude_ambiguous: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportTarget {
+    Json,
+    Csv,
+    Bitwarden,
+    Encrypted,
+    Kdbx,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    #[arg(long, value_enum)]
+    format: ExportTarget,
+    #[arg(long, short)]
+    output: String,
+    /// Only export these folders (ids). Repeatable.
+    #[arg(long = "folder")]
+    folders: Vec<String>,
+    /// Confirm writing unencrypted passwords for json, csv and bitwarden exports.
+    #[arg(long)]
+    yes: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ContainerArg {
+    Json,
+    Binary,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CompressionArg {
+    None,
+    Deflate,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LayoutArg {
+    Single,
+    Entries,
+}
+
+#[derive(Args)]
+struct SyncArgs {
+    /// Link the vault to this directory before syncing.
+    #[arg(long, conflicts_with_all = ["unlink", "status"])]
+    link: Option<String>,
+    /// Stop syncing the vault. Neither the vault nor its shared copy is deleted.
+    #[arg(long, conflicts_with = "status")]
+    unlink: bool,
+    /// Show where the vault syncs to and what changed, without syncing.
+    #[arg(long)]
+    status: bool,
+    /// Resolve a conflict reported by an earlier sync, e.g. "<record id>=local".
+    /// May be repeated.
+    #[arg(long, value_name = "ID=local|remote")]
+    resolve: Vec<String>,
+}
+
+#[derive(Args)]
+#[group(multiple = false)]
+struct HistoryArgs {
+    /// Commit every save of the vault to a git repository next to it. Needs git.
+    #[arg(long)]
+    enable: bool,
+    /// Stop keeping the history and delete the local repository.
+    #[arg(long)]
+    disable: bool,
+    /// Push the history to this git remote from now on, e.g. a bare repository.
+    #[arg(long, value_name = "URL")]
+    remote: Option<String>,
+    /// Forget the remote the history was pushed to.
+    #[arg(long)]
+    remove_remote: bool,
+    /// Push the history to its remote.
+    #[arg(long)]
+    push: bool,
+    /// Show which folders and credentials changed since a revision, or between two
+    /// revisions given as FROM..TO.
+    #[arg(long, value_name = "REVISION")]
+    diff: Option<String>,
+    /// Bring the vault back to a revision. The rollback is saved as a new revision.
+    #[arg(long, value_name = "REVISION")]
+    rollback: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ImportFormat {
+    Csv,
+    Kdbx,
+}
+
+#[derive(Args)]
+struct ImportArgs {
+    file: String,
+    #[arg(long, value_enum)]
+    format: ImportFormat,
+    /// Password manager that wrote the CSV file: bitwarden, 1password, keepassxc,
+    /// lastpass, chrome or generic. Generic expects the columns of peka's own CSV
+    /// export.
+    #[arg(long, default_value = "generic")]
+    source: String,
+    /// Only report what would be imported.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Reads secrets from the terminal without echo, or line by line from piped stdin.
+struct SecretReader {
+    interactive: bool,
+}
+
+impl SecretReader {
+    fn new() -> Self {
+        SecretReader {
+            interactive: io::stdin().is_terminal(),
+        }
+    }
+
+    fn read(&mut self, prompt: &str) -> Result<String, PekaError> {
+        if self.interactive {
+            return rpassword::prompt_password(prompt)
+                .map_err(|e| PekaError::io("Unable to read from the terminal", e));
+        }
+
+        let mut line = String::new();
+        let read = io::stdin()
+            .lock()
+            .read_line(&mut line)
+            .map_err(|e| PekaError::io("Unable to read from stdin", e))?;
+        if read == 0 {
+            return Err(PekaError::InvalidInput(format!(
+                "Expected {} on stdin",
+                prompt.trim_end_matches([':', ' '])
+            )));
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+fn credential(vault: &Vault, (folder, index): (usize, usize)) -> &StoredCredential {
+    &vault.payload().folders[folder].credentials[index]
+}
+
+/// Asks for the PIN of a secure folder. Failed attempts are written to the audit log
+/// like in the app.
+fn unlock(
+    vault: &mut Vault,
+    secrets: &mut SecretReader,
+    folder_index: usize,
+) -> Result<(), PekaError> {
+    let folder = &vault.payload().folders[folder_index];
+    if !folder.secure {
+        return Ok(());
+    }
+
+    let folder_id = folder.id.clone();
+    let pin = secrets.read(&format!("PIN for folder '{}': ", folder.name))?;
+    if vault.verify_folder_pin(&folder_id, &pin)? {
+        return Ok(());
+    }
+
+    vault.save()?;
+    Err(PekaError::Locked("Incorrect PIN".to_string()))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CredentialListing<'a> {
+    id: &'a str,
+    folder_id: &'a str,
+    folder_name: &'a str,
+    title: &'a str,
+    username: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<&'a str>,
+    tags: &'a [String],
+    favorite: bool,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let json = cli.json;
+    match execute(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            if json {
+                eprintln!("{}", json!({ "error": err }));
+            } else {
+                eprintln!("peka-cli: {}", err);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn execute(cli: Cli) -> Result<(), PekaError> {
+    let mut session = Session {
+        vault_arg: cli.vault,
+        allow_expensive_kdf: cli.allow_expensive_kdf,
+        secrets: SecretReader::new(),
+        path: None,
+        master_password: None,
+        vault: None,
+    };
+    let result = run(cli.command, &mut session, cli.json);
+    if let Some(reason) = session.vault.as_ref().and_then(Vault::history_error) {
+        eprintln!(
+            "peka-cli: warning: the change was saved but is missing from the vault history: {}",
+            reason
+        );
+    }
+    result
+}
+
+/// The vault a command works on. Its path is resolved, the master password asked for
+/// and the vault opened only once a command needs them.
+struct Session {
+    vault_arg: Option<String>,
+    allow_expensive_kdf: bool,
+    secrets: SecretReader,
+    path: Option<String>,
+    master_password: Option<String>,
+    vault: Option<Vault>,
+}
+
+impl Session {
+    fn path(&mut self) -> Result<&str, PekaError> {
+        let path = match self.path.take() {
+            Some(path) => path,
+            None => resolve_vault(self.vault_arg.as_deref())?,
+        };
+        Ok(self.path.insert(path))
+    }
+
+    fn master_password(&mut self) -> Result<&str, PekaError> {
+        self.path()?;
+        let master_password = match self.master_password.take() {
+            Some(master_password) => master_password,
+            None => self.secrets.read("Master password: ")?,
+        };
+        Ok(self.master_password.insert(master_password))
+    }
+
+    /// The unlocked vault, with the reader for the PINs and secrets a command asks for.
+    fn vault(&mut self) -> Result<(&mut Vault, &mut SecretReader), PekaError> {
+        let vault = match self.vault.take() {
+            Some(vault) => vault,
+            None => {
+                let master_password = self.master_password()?.to_string();
+                let path = self.path()?.to_string();
+                Vault::open_external(path, &master_password, self.allow_expensive_kdf)?
+            }
+        };
+        Ok((self.vault.insert(vault), &mut self.secrets))
+    }
+}
+
+fn run(command: Command, session: &mut Session, json: bool) -> Result<(), PekaError> {
+    match command {
+        Command::Generate(args) => generate(&args, json),
+        Command::Check { repair } => {
+            let path = session.path()?.to_string();
+            check(&path, session.master_password()?, repair, json)
+        }
+        Command::List { folder, tag } => {
+            let (vault, _) = session.vault()?;
+            list(vault, folder.as_deref(), tag.as_deref(), json)
+        }
+        Command::Show { entry, reveal } => {
+            let (vault, secrets) = session.vault()?;
+            let location = find_entry(vault.payload(), &entry)?;
+            unlock(vault, secrets, location.0)?;
+            show(vault, location, reveal, json)
+        }
+        Command::Get { entry, field } => {
+            let (vault, secrets) = session.vault()?;
+            let location = find_entry(vault.payload(), &entry)?;
+            unlock(vault, secrets, location.0)?;
+            let value = field_value(credential(vault, location), &field)?;
+            print_value(json, json!({ "field": field, "value": value }), &value)
+        }
+        Command::Add(args) => {
+            let (vault, secrets) = session.vault()?;
+            add(vault, secrets, args, json)
+        }
+        Command::Edit(args) => {
+            let (vault, secrets) = session.vault()?;
+            edit(vault, secrets, args, json)
+        }
+        Command::Rm { entry } => {
+            let (vault, secrets) = session.vault()?;
+            let location = find_entry(vault.payload(), &entry)?;
+            unlock(vault, secrets, location.0)?;
+            let folder_id = vault.payload().folders[location.0].id.clone();
+            let credential_id = credential(vault, location).id.clone();
+            vault.delete_credential(&folder_id, &credential_id)?;
+            vault.save()?;
+            print_value(
+                json,
+                json!({ "id": credential_id, "trashed": true }),
+                &format!("Moved {} to the trash", credential_id),
+            )
+        }
+        Command::Totp { entry } => {
+            let (vault, secrets) = session.vault()?;
+            let location = find_entry(vault.payload(), &entry)?;
+            unlock(vault, secrets, location.0)?;
+            let secret = credential(vault, location)
+                .totp
+                .as_deref()
+                .filter(|secret| !secret.trim().is_empty())
+                .ok_or_else(|| PekaError::NotFound("Credential has no TOTP secret".to_string()))?;
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(PekaError::internal)?
+                .as_secs();
+            let code = totp::code_at(secret, now)?;
+            if json {
+                print_json(&code)
+            } else {
+                println!("{}", code.code);
+                Ok(())
+            }
+        }
+        Command::Storage {
+            container,
+            compression,
+            layout,
+        } => storage(session.vault()?.0, container, compression, layout, json),
+        Command::Export(args) => {
+            let (vault, secrets) = session.vault()?;
+            export(vault, secrets, args, json)
+        }
+        Command::Import(args) => {
+            let allow_expensive_kdf = session.allow_expensive_kdf;
+            let (vault, secrets) = session.vault()?;
+            import(vault, secrets, args, allow_expensive_kdf, json)
+        }
+        Command::Sync(args) if args.status => sync_status(session.path()?, json),
+        // Linking and unlinking need the vault unlocked, like syncing itself.
+        Command::Sync(args) if args.unlink => {
+            sync::unlink(session.vault()?.0.path())?;
+            print_value(json, json!({ "linked": false }), "Vault is no longer synced")
+        }
+        Command::Sync(args) => {
+            let (vault, _) = session.vault()?;
+            if let Some(directory) = &args.link {
+                sync::link(vault.path(), Path::new(directory))?;
+            }
+            sync_vault(vault, &args.resolve, json)
+        }
+        Command::History(HistoryArgs {
+            diff: Some(range), ..
+        }) => history_diff(session.vault()?.0, &range, json),
+        Command::History(HistoryArgs {
+            rollback: Some(revision),
+            ..
+        }) => roll_back(session.vault()?.0, &revision, json),
+        // Only listing the revisions works without unlocking the vault.
+        Command::History(args)
+            if args.enable
+                || args.disable
+                || args.remote.is_some()
+                || args.remove_remote
+                || args.push =>
+        {
+            manage_history(session.vault()?.0.path(), &args, json)
+        }
+        Command::History(args) => manage_history(Path::new(session.path()?), &args, json),
+    }
+}
+
+/// Resolves `--vault` to a file: an existing path, or the name of a vault in the app
+/// data directory or a registered location. Without it, the only vault there is used.
+fn resolve_vault(vault: Option<&str>) -> Result<String, PekaError> {
+    if let Some(vault) = vault {
+        if Path::new(vault).is_file() {
+            return Ok(vault.to_string());
+        }
+        return LocationRegistry::load_default()?
+            .list()?
+            .into_iter()
+            .find(|summary| summary.vault_name.eq_ignore_ascii_case(vault.trim()))
+            .map(|summary| summary.path)
+            .ok_or_else(|| PekaError::NotFound(format!("Vault not found: {}", vault)));
+    }
+
+    let mut vaults: Vec<_> = LocationRegistry::load_default()?
+        .list()?
+        .into_iter()
+        .filter(|summary| summary.status == VaultStatus::Ok)
+        .collect();
+    match vaults.len() {
+        0 => Err(PekaError::NotFound(
+            "No vaults found; pass --vault with a vault file".to_string(),
+        )),
+        1 => Ok(vaults.remove(0).path),
+        _ => Err(PekaError::InvalidInput(format!(
+            "Several vaults found; pass --vault with one of: {}",
+            vaults
+                .iter()
+                .map(|summary| summary.vault_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+fn find_folder(payload: &StoredVault, folder: &str) -> Result<usize, PekaError> {
+    payload
+        .folders
+        .iter()
+        .position(|f| f.id == folder)
+        .or_else(|| {
+            payload
+                .folders
+                .iter()
+                .position(|f| f.name.eq_ignore_ascii_case(folder.trim()))
+        })
+        .ok_or_else(|| PekaError::NotFound(format!("Folder not found: {}", folder)))
+}
+
+/// Finds a credential by id, by "Folder/Title", or by title alone. Titles are matched
+/// case-insensitively and have to be unique.
+fn find_entry(payload: &StoredVault, entry: &str) -> Result<(usize, usize), PekaError> {
+    let locations = || {
+        payload
+            .folders
+            .iter()
+            .enumerate()
+            .flat_map(|(folder_index, folder)| {
+                (0..folder.credentials.len()).map(move |index| (folder_index, index))
+            })
+    };
+    let credential = |(folder, index): (usize, usize)| &payload.folders[folder].credentials[index];
+
+    if let Some(location) = locations().find(|location| credential(*location).id == entry) {
+        return Ok(location);
+    }
+
+    let matches_title = |location: (usize, usize), title: &str| {
+        credential(location).title.eq_ignore_ascii_case(title)
+    };
+    let mut matches: Vec<(usize, usize)> = match entry.rsplit_once('/') {
+        Some((folder, title)) => locations()
+            .filter(|location| {
+                payload.folders[location.0]
+                    .name
+                    .eq_ignore_ascii_case(folder)
+                    && matches_title(*location, title)
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    if matches.is_empty() {
+        matches = locations()
+            .filter(|location| matches_title(*location, entry))
+            .collect();
+    }
+
+    match matches.len() {
+        0 => Err(PekaError::NotFound(format!("Credential not found: {}", entry))),
+        1 => Ok(matches[0]),
+        _ => Err(PekaError::InvalidInput(format!(
+            "'{}' matches several credentials; use the id or Folder/Title: {}",
+            entry,
+            matches
+                .iter()
+                .map(|location| format!(
+                    "{}/{} ({})",
+                    payload.folders[location.0].name,
+                    credential(*location).title,
+                    credential(*location).id
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+fn list(
+    vault: &Vault,
+    folder: Option<&str>,
+    tag: Option<&str>,
+    json: bool,
+) -> Result<(), PekaError> {
+    let folder_index = folder
+        .map(|folder| find_folder(vault.payload(), folder))
+        .transpose()?;
+
+    let listings: Vec<CredentialListing> = vault
+        .payload()
+        .folders
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| folder_index.is_none_or(|wanted| wanted == *index))
+        .flat_map(|(_, folder)| {
+            folder
+                .credentials
+                .iter()
+                .filter(|cred| tag.is_none_or(|tag| tags::has_tag(cred, tag)))
+                .map(move |cred| CredentialListing {
+                    id: &cred.id,
+                    folder_id: &folder.id,
+                    folder_name: &folder.name,
+                    title: &cred.title,
+                    username: &cred.username,
+                    url: cred.url.as_deref(),
+                    tags: &cred.tags,
+                    favorite: cred.favorite,
+                })
+        })
+        .collect();
+
+    if json {
+        return print_json(&listings);
+    }
+    for listing in listings {
+        println!(
+            "{}\t{}\t{}\t{}",
+            listing.id, listing.folder_name, listing.title, listing.username
+        );
+    }
+    Ok(())
+}
+
+fn show(
+    vault: &Vault,
+    location: (usize, usize),
+    reveal: bool,
+    json: bool,
+) -> Result<(), PekaError> {
+    let folder = &vault.payload().folders[location.0];
+    let cred = credential(vault, location);
+    let mask = |value: &str, hidden: bool| {
+        if hidden && !reveal {
+            "********".to_string()
+        } else {
+            value.to_string()
+        }
+    };
+
+    if json {
+        return print_json(&json!({
+            "id": cred.id,
+            "folderId": folder.id,
+            "folderName": folder.name,
+            "title": cred.title,
+            "username": cred.username,
+            "password": reveal.then_some(&cred.password),
+            "url": cred.url,
+            "notes": cred.notes,
+            "totp": cred.totp.as_ref().filter(|_| reveal),
+            "hasTotp": cred.totp.is_some(),
+            "customFields": cred
+                .custom_fields
+                .iter()
+                .map(|field| json!({
+                    "name": field.name,
+                    "value": (!field.hidden || reveal).then_some(&field.value),
+                    "hidden": field.hidden,
+                }))
+                .collect::<Vec<_>>(),
+            "tags": cred.tags,
+            "favorite": cred.favorite,
+            "attachments": cred.attachments.iter().map(|a| a.summary()).collect::<Vec<_>>(),
+            "createdAt": cred.created_at,
+            "updatedAt": cred.updated_at,
+        }));
+    }
+
+    println!("Title:    {}", cred.title);
+    println!("Username: {}", cred.username);
+    println!("Password: {}", mask(&cred.password, true));
+    if let Some(url) = &cred.url {
+        println!("URL:      {}", url);
+    }
+    if let Some(totp) = &cred.totp {
+        println!("TOTP:     {}", mask(totp, true));
+    }
+    for field in &cred.custom_fields {
+        println!("{}: {}", field.name, mask(&field.value, field.hidden));
+    }
+    if !cred.tags.is_empty() {
+        println!("Tags:     {}", cred.tags.join(", "));
+    }
+    println!("Folder:   {}", folder.name);
+    println!("Id:       {}", cred.id);
+    println!("Updated:  {}", cred.updated_at);
+    if let Some(notes) = &cred.notes {
+        println!("\n{}", notes);
+    }
+    Ok(())
+}
+
+fn field_value(cred: &StoredCredential, field: &str) -> Result<String, PekaError> {
+    let value = match field.to_ascii_lowercase().as_str() {
+        "id" => Some(cred.id.clone()),
+        "title" => Some(cred.title.clone()),
+        "username" => Some(cred.username.clone()),
+        "password" => Some(cred.password.clone()),
+        "url" => cred.url.clone(),
+        "notes" => cred.notes.clone(),
+        "totp" => cred.totp.clone(),
+        _ => cred
+            .custom_fields
+            .iter()
+            .find(|custom| custom.name.eq_ignore_ascii_case(field))
+            .map(|custom| custom.value.clone()),
+    };
+    value.ok_or_else(|| PekaError::NotFound(format!("Credential has no field '{}'", field)))
+}
+
+fn read_password(
+    secrets: &mut SecretReader,
+    args: &SecretArgs,
+) -> Result<Option<String>, PekaError> {
+    if args.generate {
+        return generator::generate(&GeneratorOptions {
+            length: args.length,
+            ..GeneratorOptions::default()
+        })
+        .map(Some);
+    }
+    if args.ask_password {
+        let password = secrets.read("Entry password: ")?;
+        if password.is_empty() {
+            return Err(PekaError::InvalidInput("Password is required.".to_string()));
+        }
+        return Ok(Some(password));
+    }
+    Ok(None)
+}
+
+fn read_totp(secrets: &mut SecretReader, args: &SecretArgs) -> Result<Option<String>, PekaError> {
+    if !args.ask_totp {
+        return Ok(None);
+    }
+    let secret = secrets.read("TOTP secret: ")?;
+    // Fail early on secrets that could never produce a code.
+    totp::code_at(&secret, 0)?;
+    Ok(Some(secret.trim().to_string()))
+}
+
+fn add(
+    vault: &mut Vault,
+    secrets: &mut SecretReader,
+    args: AddArgs,
+    json: bool,
+) -> Result<(), PekaError> {
+    if args.title.trim().is_empty() {
+        return Err(PekaError::InvalidInput("Title is required.".to_string()));
+    }
+
+    let folder_index = find_folder(vault.payload(), &args.folder)?;
+    unlock(vault, secrets, folder_index)?;
+    let password = read_password(secrets, &args.secret)?
+        .ok_or_else(|| {
+            PekaError::InvalidInput(
+                "Pass --ask-password or --generate to set the password".to_string(),
+            )
+        })?;
+    let totp = read_totp(secrets, &args.secret)?;
+
+    let folder_id = vault.payload().folders[folder_index].id.clone();
+    let credential_id = vault.add_credential(
+        &folder_id,
+        NewCredential {
+            title: args.title,
+            username: args.username,
+            password,
+            url: args.url,
+            notes: args.notes,
+            totp,
+            tags: args.tags,
+        },
+    )?;
+    vault.save()?;
+
+    print_value(
+        json,
+        json!({ "id": credential_id, "folderId": folder_id }),
+        &credential_id,
+    )
+}
+
+fn edit(
+    vault: &mut Vault,
+    secrets: &mut SecretReader,
+    args: EditArgs,
+    json: bool,
+) -> Result<(), PekaError> {
+    let location = find_entry(vault.payload(), &args.entry)?;
+    unlock(vault, secrets, location.0)?;
+    let password = read_password(secrets, &args.secret)?;
+    let totp = read_totp(secrets, &args.secret)?;
+
+    let folder_id = vault.payload().folders[location.0].id.clone();
+    let credential_id = credential(vault, location).id.clone();
+    vault.update_credential(
+        &folder_id,
+        &credential_id,
+        CredentialChanges {
+            title: args.title,
+            username: args.username,
+            password,
+            url: args.url,
+            notes: args.notes,
+            totp,
+        },
+    )?;
+    vault.save()?;
+
+    print_value(json, json!({ "id": credential_id }), &credential_id)
+}
+
+fn storage(
+    vault: &mut Vault,
+    container: Option<ContainerArg>,
+    compression: Option<CompressionArg>,
+    layout: Option<LayoutArg>,
+    json: bool,
+) -> Result<(), PekaError> {
+    let mut format = vault.storage_format();
+    if let Some(container) = container {
+        format.container = match container {
+            ContainerArg::Json => ContainerFormat::Json,
+            ContainerArg::Binary => ContainerFormat::Binary,
+        };
+    }
+    if let Some(compression) = compression {
+        format.compression = match compression {
+            CompressionArg::None => PayloadCompression::None,
+            CompressionArg::Deflate => PayloadCompression::Deflate,
+        };
+    }
+    if let Some(layout) = layout {
+        format.layout = match layout {
+            LayoutArg::Single => PayloadLayout::Single,
+            LayoutArg::Entries => PayloadLayout::Entries,
+        };
+    }
+    vault.set_storage_format(format);
+    vault.save()?;
+
+    if json {
+        return print_json(&format);
+    }
+    let container = match format.container {
+        ContainerFormat::Json => "json",
+        ContainerFormat::Binary => "binary",
+    };
+    let compression = match format.compression {
+        PayloadCompression::None => "uncompressed",
+        PayloadCompression::Deflate => "deflate compressed",
+    };
+    let layout = match format.layout {
+        PayloadLayout::Single => "single payload",
+        PayloadLayout::Entries => "per-entry records",
+    };
+    println!("{}, {}, {}", container, compression, layout);
+    Ok(())
+}
+
+fn sync_status(path: &str, json: bool) -> Result<(), PekaError> {
+    let status = sync::status(Path::new(path))?;
+    if json {
+        return print_json(&status);
+    }
+    let Some(status) = status else {
+        println!("Vault is not synced; link it with --link <directory>");
+        return Ok(());
+    };
+    println!("Syncs with {}", status.shared_path);
+    match &status.last_synced_at {
+        Some(at) => println!("Last synced at {}", at),
+        None => println!("Not synced yet"),
+    }
+    if status.local_changed {
+        println!("Changed here since the last sync");
+    }
+    if status.remote_changed {
+        println!("Changed in the sync directory since the last sync");
+    }
+    Ok(())
+}
+
+fn manage_history(path: &Path, args: &HistoryArgs, json: bool) -> Result<(), PekaError> {
+    if args.enable {
+        history::enable(path)?;
+    } else if args.disable {
+        history::disable(path)?;
+        return print_value(json, json!({ "enabled": false }), "Vault history deleted");
+    } else if let Some(remote) = &args.remote {
+        history::set_remote(path, Some(remote))?;
+    } else if args.remove_remote {
+        history::set_remote(path, None)?;
+    } else if args.push {
+        history::push(path)?;
+        return print_value(json, json!({ "pushed": true }), "Pushed the vault history");
+    }
+
+    let Some(status) = history::status(path)? else {
+        return print_value(
+            json,
+            json!(null),
+            "Vault history is not enabled; enable it with --enable",
+        );
+    };
+    let revisions = history::list(path)?;
+    if json {
+        return print_json(&json!({ "status": status, "revisions": revisions }));
+    }
+    println!("History in {}", status.directory);
+    if let Some(remote) = &status.remote {
+        println!("Pushed to {}", remote);
+    }
+    for revision in revisions {
+        println!(
+            "{}  {}  {}",
+            &revision.revision[..revision.revision.len().min(12)],
+            revision.committed_at,
+            revision.message
+        );
+    }
+    Ok(())
+}
+
+fn history_diff(vault: &Vault, range: &str, json: bool) -> Result<(), PekaError> {
+    let (from, to) = match range.split_once("..") {
+        Some((from, to)) => (from, Some(to)),
+        None => (range, None),
+    };
+    let diff = vault.history_diff(from, to)?;
+    if json {
+        return print_json(&diff);
+    }
+    for change in &diff.changes {
+        let change_kind = match change.change {
+            HistoryChangeKind::Added => "added",
+            HistoryChangeKind::Removed => "removed",
+            HistoryChangeKind::Modified => "changed",
+        };
+        let kind = match change.kind {
+            SyncRecordKind::Folder => "folder",
+            SyncRecordKind::Credential => "credential",
+        };
+        let fields = if change.fields.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", change.fields.join(", "))
+        };
+        println!("{} {} {}{}", change_kind, kind, change.title, fields);
+    }
+    if diff.changes.is_empty() {
+        println!("No changes");
+    }
+    Ok(())
+}
+
+fn roll_back(vault: &mut Vault, revision: &str, json: bool) -> Result<(), PekaError> {
+    let mut result = vault.roll_back(revision)?;
+    vault.save()?;
+    result.vault = None;
+    if json {
+        return print_json(&result);
+    }
+    println!("Rolled back to {}", result.revision);
+    if result.missing_attachments > 0 {
+        println!(
+            "{} attachments of that revision are gone and were left out",
+            result.missing_attachments
+        );
+    }
+    Ok(())
+}
+
+fn sync_vault(vault: &mut Vault, resolve: &[String], json: bool) -> Result<(), PekaError> {
+    let mut resolutions = HashMap::new();
+    for resolution in resolve {
+        let (id, side) = resolution.split_once('=').ok_or_else(|| {
+            PekaError::InvalidInput(format!("Expected ID=local|remote, got '{}'", resolution))
+        })?;
+        let side = match side.trim() {
+            "local" => SyncResolution::Local,
+            "remote" => SyncResolution::Remote,
+            other => {
+                return Err(PekaError::InvalidInput(format!(
+                    "Unknown conflict resolution '{}'",
+                    other
+                )))
+            }
+        };
+        resolutions.insert(id.trim().to_string(), side);
+    }
+
+    let mut report = vault.sync(&resolutions)?;
+    report.vault = None;
+    if json {
+        print_json(&report)?;
+    } else if report.applied {
+        println!(
+            "Pushed {}, pulled {}, merged {}, deleted {}",
+            report.pushed, report.pulled, report.merged, report.deleted
+        );
+    } else {
+        for conflict in &report.conflicts {
+            let kind = match conflict.conflict {
+                SyncConflictKind::BothModified => "changed in both copies",
+                SyncConflictKind::DeletedLocally => "deleted here, changed in the other copy",
+                SyncConflictKind::DeletedRemotely => "changed here, deleted in the other copy",
+            };
+            let fields = if conflict.fields.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", conflict.fields.join(", "))
+            };
+            println!("{} {}: {}{}", conflict.record_id, conflict.title, kind, fields);
+        }
+        println!("Run again with --resolve <id>=local|remote for each conflict");
+    }
+    if report.applied {
+        return Ok(());
+    }
+    Err(PekaError::InvalidInput(format!(
+        "Sync has {} unresolved conflicts",
+        report.conflicts.len()
+    )))
+}
+
+fn generate(args: &GenerateArgs, json: bool) -> Result<(), PekaError> {
+    let password = generator::generate(&GeneratorOptions {
+        length: args.length,
+        lowercase: !args.no_lowercase,
+        uppercase: !args.no_uppercase,
+        digits: !args.no_digits,
+        symbols: !args.no_symbols,
+        exclude_ambiguous: args.exclude_ambiguous,
+    })?;
+    print_value(json, json!({ "password": password }), &password)
+}
+
+fn export(
+    vault: &mut Vault,
+    secrets: &mut SecretReader,
+    args: ExportArgs,
+    json: bool,
+) -> Result<(), PekaError> {
+    let format = match args.format {
+        ExportTarget::Kdbx => {
+            let kdbx_password = secrets.read("KeePass database password: ")?;
+            vault.export_kdbx(Path::new(&args.output), &kdbx_password, KdbxCipher::default())?;
+            vault.save()?;
+            return print_value(
+                json,
+                json!({ "path": args.output }),
+                &format!("Exported to {}", args.output),
+            );
+        }
+        ExportTarget::Json => ExportFormat::Json,
+        ExportTarget::Csv => ExportFormat::Csv,
+        ExportTarget::Bitwarden => ExportFormat::Bitwarden,
+        ExportTarget::Encrypted => ExportFormat::Encrypted,
+    };
+
+    if format.is_plaintext() && !args.yes {
+        return Err(PekaError::PolicyViolation(
+            "This export writes unencrypted passwords; pass --yes to confirm".to_string(),
+        ));
+    }
+    let export_password = match format {
+        ExportFormat::Encrypted => Some(secrets.read("Export password: ")?),
+        _ => None,
+    };
+
+    let result = vault.export(
+        Path::new(&args.output),
+        format,
+        Some(args.folders.as_slice()),
+        export_password.as_deref(),
+        args.yes,
+    )?;
+    vault.save()?;
+    if json {
+        return print_json(&result);
+    }
+    println!(
+        "Exported {} credentials in {} folders to {}",
+        result.credentials, result.folders, result.path
+    );
+    Ok(())
+}
+
+fn import(
+    vault: &mut Vault,
+    secrets: &mut SecretReader,
+    args: ImportArgs,
+    allow_expensive_kdf: bool,
+    json: bool,
+) -> Result<(), PekaError> {
+    let raw = fs::read(&args.file)
+        .map_err(|e| PekaError::io(format!("Unable to read {}", args.file), e))?;
+    let (imported, skipped, folders_created) = match args.format {
+        ImportFormat::Csv => {
+            let source: CsvSource = serde_json::from_value(json!(args.source.to_lowercase()))
+                .map_err(|_| {
+                    PekaError::InvalidInput(format!("Unknown CSV source: {}", args.source))
+                })?;
+            let result = vault.import_csv(&raw, source, None, args.dry_run)?;
+            (
+                result.imported,
+                result.skipped.len(),
+                result.folders_created,
+            )
+        }
+        ImportFormat::Kdbx => {
+            if args.dry_run {
+                return Err(PekaError::InvalidInput(
+                    "--dry-run is only supported for CSV imports".to_string(),
+                ));
+            }
+            let kdbx_password = secrets.read("KeePass database password: ")?;
+            let result = vault.import_kdbx(&raw, &kdbx_password, allow_expensive_kdf)?;
+            (result.imported, result.skipped, result.folders_created)
+        }
+    };
+    vault.save()?;
+
+    if json {
+        return print_json(&json!({
+            "dryRun": args.dry_run,
+            "imported": imported,
+            "skipped": skipped,
+            "foldersCreated": folders_created,
+        }));
+    }
+    println!(
+        "{} {} credentials, skipped {}",
+        if args.dry_run {
+            "Would import"
+        } else {
+            "Imported"
+        },
+        imported,
+        skipped
+    );
+    if !folders_created.is_empty() {
+        println!("New folders: {}", folders_created.join(", "));
+    }
+    Ok(())
+}
+
+fn check(path: &str, master_password: &str, repair: bool, json: bool) -> Result<(), PekaError> {
+    let mut report = check::check_vault(Path::new(path), master_password)?;
+    if repair && !report.repairs.is_empty() {
+        let result = check::repair_vault(Path::new(path), master_password, &report.repairs)?;
+        if !json {
+            if let Some(backup) = &result.restored_from {
+                println!("Restored the vault from {}", backup);
+            }
+            println!("Applied {} repairs", result.applied.len());
+        }
+        report = result.report;
+    }
+
+    if json {
+        print_json(&report)?;
+    } else {
+        print_report(&report);
+    }
+    if report.healthy {
+        return Ok(());
+    }
+    Err(PekaError::Corrupted {
+        message: "Vault has errors".to_string(),
+        details: None,
+    })
+}
+
+fn print_report(report: &CheckReport) {
+    for issue in &report.issues {
+        let severity = match issue.severity {
+            IssueSeverity::Error => "error",
+            IssueSeverity::Warning => "warning",
+        };
+        let repairable = if issue.repair.is_some() {
+            " (repairable)"
+        } else {
+            ""
+        };
+        println!("{}: {}{}", severity, issue.message, repairable);
+    }
+    if report.issues.is_empty() {
+        println!("No problems found");
+    } else if !report.repairs.is_empty() {
+        println!("Run with --repair to apply the repairs");
+    }
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<(), PekaError> {
+    let output = serde_json::to_string_pretty(value).map_err(PekaError::internal)?;
+    println!("{}", output);
+    Ok(())
+}
+
+fn print_value(json: bool, value: serde_json::Value, text: &str) -> Result<(), PekaError> {
+    if json {
+        return print_json(&value);
+    }
+    println!("{}", text);
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::{trash, PekaError, StoredVault};
+
+/// Largest file that can be attached to a credential.
+pub const MAX_ATTACHMENT_SIZE: u64 = 10 * 1024 * 1024;
+/// Most attachments a single credential can hold.
+pub const MAX_ATTACHMENTS_PER_CREDENTIAL: usize = 20;
+
+const AES_GCM_TAG_LENGTH: u64 = 16;
+
+/// Metadata of an encrypted blob stored next to the vault file. The blob key lives
+/// only here, inside the encrypted vault payload, so it is wrapped by the vault key.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredAttachment {
+    pub id: String,
+    pub file_name: String,
+    pub size: u64,
+    key: String,
+    nonce: String,
+    sha256: String,
+    pub created_at: String,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentSummary {
+    pub id: String,
+    pub file_name: String,
+    pub size: u64,
+    pub created_at: String,
+}
+
+impl StoredAttachment {
+    pub fn summary(&self) -> AttachmentSummary {
+        AttachmentSummary {
+            id: self.id.clone(),
+            file_name: self.file_name.clone(),
+            size: self.size,
+            created_at: self.created_at.clone(),
+        }
+    }
+}
+
+/// Directory holding the attachment blobs of the vault at `vault_path`, e.g.
+/// `work.attachments/` next to `work.peka`.
+pub fn attachment_dir(vault_path: &Path) -> PathBuf {
+    let stem = vault_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "vault".to_string());
+    vault_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(format!("{}.attachments", stem))
+}
+
+fn blob_path(vault_path: &Path, attachment_id: &str) -> PathBuf {
+    attachment_dir(vault_path).join(format!("{}.bin", attachment_id))
+}
+
+/// Encrypts `source_file` under a fresh key and writes the blob next to the vault.
+pub fn store(vault_path: &Path, source_file: &Path) -> Result<StoredAttachment, PekaError> {
+    let metadata = fs::metadata(source_file)
+        .map_err(|_| PekaError::NotFound("Attachment file not found".to_string()))?;
+    if !metadata.is_file() {
+        return Err(PekaError::InvalidInput("Attachment must be a file".to_string()));
+    }
+    if metadata.len() > MAX_ATTACHMENT_SIZE {
+        return Err(PekaError::PolicyViolation(format!(
+            "Attachment exceeds the maximum size of {} MB",
+            MAX_ATTACHMENT_SIZE / (1024 * 1024)
+        )));
+    }
+
+    let contents =
+        fs::read(source_file).map_err(|e| PekaError::io("Unable to read attachment file", e))?;
+    let file_name = source_file
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "attachment".to_string());
+
+    let id = Uuid::new_v4().to_string();
+    let mut key = [0u8; 32];
+    let mut nonce_bytes = [0u8; 12];
+    OsRng.fill_bytes(&mut key);
+    OsRng.fill_bytes(&mut nonce_bytes);
+
+    let cipher = Aes256Gcm::new_from_slice(&key).map_err(PekaError::internal)?;
+    // The attachment id is authenticated so blobs cannot be swapped between entries.
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce_bytes),
+            Payload {
+                msg: &contents,
+                aad: id.as_bytes(),
+            },
+        )
+        .map_err(PekaError::internal)?;
+
+    let dir = attachment_dir(vault_path);
+    fs::create_dir_all(&dir)
+        .map_err(|e| PekaError::io("Unable to create attachment directory", e))?;
+    fs::write(blob_path(vault_path, &id), ciphertext)
+        .map_err(|e| PekaError::io("Unable to write attachment", e))?;
+
+    Ok(StoredAttachment {
+        id,
+        file_name,
+        size: contents.len() as u64,
+        key: general_purpose::STANDARD.encode(key),
+        nonce: general_purpose::STANDARD.encode(nonce_bytes),
+        sha256: general_purpose::STANDARD.encode(Sha256::digest(&contents)),
+        created_at: Utc::now().to_rfc3339(),
+    })
+}
+
+/// Decrypts an attachment and verifies it against the recorded size and checksum.
+pub fn read(vault_path: &Path, attachment: &StoredAttachment) -> Result<Vec<u8>, PekaError> {
+    let tampered =
+        || PekaError::Tampered("Attachment is corrupted or has been tampered with".to_string());
+
+    let ciphertext = fs::read(blob_path(vault_path, &attachment.id))
+        .map_err(|_| PekaError::NotFound("Attachment data not found".to_string()))?;
+    if ciphertext.len() as u64 != attachment.size + AES_GCM_TAG_LENGTH {
+        return Err(tampered());
+    }
+
+    let key = general_purpose::STANDARD
+        .decode(&attachment.key)
+        .map_err(|_| tampered())?;
+    let nonce_bytes = general_purpose::STANDARD
+        .decode(&attachment.nonce)
+        .map_err(|_| tampered())?;
+    if nonce_bytes.len() != 12 {
+        return Err(tampered());
+    }
+
+    let cipher = Aes256Gcm::new_from_slice(&key).map_err(|_| tampered())?;
+    let contents = cipher
+        .decrypt(
+            Nonce::from_slice(&nonce_bytes),
+            Payload {
+                msg: &ciphertext,
+                aad: attachment.id.as_bytes(),
+            },
+        )
+        .map_err(|_| tampered())?;
+
+    if general_purpose::STANDARD.encode(Sha256::digest(&contents)) != attachment.sha256 {
+        return Err(tampered());
+    }
+
+    Ok(contents)
+}
+
+/// Whether the blob of an attachment is on disk, without decrypting it.
+pub(crate) fn blob_exists(vault_path: &Path, attachment: &StoredAttachment) -> bool {
+    blob_path(vault_path, &attachment.id).is_file()
+}
+
+/// Removes attachment blobs. Missing blobs are ignored.
+pub fn remove<'a>(vault_path: &Path, attachments: impl IntoIterator<Item = &'a StoredAttachment>) {
+    for attachment in attachments {
+        let _ = fs::remove_file(blob_path(vault_path, &attachment.id));
+    }
+}
+
+/// Removes the blobs of `attachments` that no credential left in `payload`, including
+/// the ones in the trash, refers to.
+/// Merged "keep both" copies share their blobs with the original entry.
+pub fn release<'a>(
+    vault_path: &Path,
+    payload: &StoredVault,
+    attachments: impl IntoIterator<Item = &'a StoredAttachment>,
+) {
+    let still_referenced = |id: &str| {
+        trash::all_credentials(payload)
+            .flat_map(|cred| cred.attachments.iter())
+            .any(|attachment| attachment.id == id)
+    };
+    remove(
+        vault_path,
+        attachments
+            .into_iter()
+            .filter(|attachment| !still_referenced(&attachment.id)),
+    );
+}
+
+/// Copies the blobs of `attachments` from one vault's directory to another's.
+pub fn copy<'a>(
+    source_vault: &Path,
+    destination_vault: &Path,
+    attachments: impl IntoIterator<Item = &'a StoredAttachment>,
+) -> Result<(), PekaError> {
+    let mut attachments = attachments.into_iter().peekable();
+    if attachments.peek().is_none() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(attachment_dir(destination_vault))
+        .map_err(|e| PekaError::io("Unable to create attachment directory", e))?;
+    for attachment in attachments {
+        let source = blob_path(source_vault, &attachment.id);
+        let destination = blob_path(destination_vault, &attachment.id);
+        if source.exists() && !destination.exists() {
+            fs::copy(source, destination)
+                .map_err(|e| PekaError::io("Unable to copy attachment", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies every blob of a vault, for whole-file copies where the payload is not
+/// decrypted.
+pub fn copy_all(source_vault: &Path, destination_vault: &Path) -> Result<(), PekaError> {
+    let source_dir = attachment_dir(source_vault);
+    if !source_dir.is_dir() {
+        return Ok(());
+    }
+
+    let destination_dir = attachment_dir(destination_vault);
+    fs::create_dir_all(&destination_dir)
+        .map_err(|e| PekaError::io("Unable to create attachment directory", e))?;
+    let unreadable = |e| PekaError::io("Unable to read attachment directory", e);
+    for entry in fs::read_dir(source_dir).map_err(unreadable)? {
+        let entry = entry.map_err(unreadable)?;
+        if entry.path().is_file() {
+            fs::copy(entry.path(), destination_dir.join(entry.file_name()))
+                .map_err(|e| PekaError::io("Unable to copy attachment", e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportFormat;
+    use crate::testing::{self, TempDir};
+    use crate::Vault;
+
+    const EXPORT_PASSWORD: &str = "Kx9#mQ2$vL4@-export";
+
+    #[test]
+    fn encrypts_blobs_and_detects_tampering() {
+        let dir = TempDir::new();
+        let vault_path = dir.subdir("vaults").join("work.peka");
+        let files = dir.subdir("files");
+        fs::write(files.join("first.txt"), b"recovery codes: 1").unwrap();
+        fs::write(files.join("second.txt"), b"recovery codes: 2").unwrap();
+        let first = store(&vault_path, &files.join("first.txt")).unwrap();
+        let second = store(&vault_path, &files.join("second.txt")).unwrap();
+
+        let blob = blob_path(&vault_path, &first.id);
+        assert!(!String::from_utf8_lossy(&fs::read(&blob).unwrap()).contains("recovery"));
+        assert_eq!(read(&vault_path, &first).unwrap(), b"recovery codes: 1");
+        assert_eq!(first.file_name, "first.txt");
+
+        let is_tampered = |attachment: &StoredAttachment| {
+            matches!(read(&vault_path, attachment), Err(PekaError::Tampered(_)))
+        };
+        // A blob of the same size moved to another attachment does not decrypt.
+        fs::copy(blob_path(&vault_path, &second.id), &blob).unwrap();
+        assert!(is_tampered(&first));
+
+        let mut flipped = fs::read(blob_path(&vault_path, &second.id)).unwrap();
+        flipped[0] ^= 1;
+        fs::write(blob_path(&vault_path, &second.id), &flipped).unwrap();
+        assert!(is_tampered(&second));
+
+        remove(&vault_path, [&first]);
+        assert!(matches!(read(&vault_path, &first), Err(PekaError::NotFound(_))));
+    }
+
+    #[test]
+    fn limits_attachment_size_and_count() {
+        let dir = TempDir::new();
+        let (mut vault, folder_id, credential_id) =
+            testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let files = dir.subdir("files");
+
+        let large = fs::File::create(files.join("large.bin")).unwrap();
+        large.set_len(MAX_ATTACHMENT_SIZE + 1).unwrap();
+        assert!(matches!(
+            vault.add_attachment(&folder_id, &credential_id, &files.join("large.bin")),
+            Err(PekaError::PolicyViolation(_))
+        ));
+        assert!(matches!(
+            vault.add_attachment(&folder_id, &credential_id, &files),
+            Err(PekaError::InvalidInput(_))
+        ));
+
+        fs::write(files.join("small.txt"), b"small").unwrap();
+        for _ in 0..MAX_ATTACHMENTS_PER_CREDENTIAL {
+            vault.add_attachment(&folder_id, &credential_id, &files.join("small.txt")).unwrap();
+        }
+        assert!(matches!(
+            vault.add_attachment(&folder_id, &credential_id, &files.join("small.txt")),
+            Err(PekaError::PolicyViolation(_))
+        ));
+    }
+
+    #[test]
+    fn keeps_blob_keys_out_of_exports() {
+        let dir = TempDir::new();
+        let (mut vault, folder_id, credential_id) =
+            testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let file = dir.subdir("files").join("recovery.txt");
+        fs::write(&file, b"recovery codes").unwrap();
+        let summary = vault.add_attachment(&folder_id, &credential_id, &file).unwrap();
+        vault.save().unwrap();
+        let key = vault.payload().folders[0].credentials[0].attachments[0].key.clone();
+
+        let exports = dir.subdir("exports");
+        let formats = [
+            (ExportFormat::Json, "work.json"),
+            (ExportFormat::Csv, "work.csv"),
+            (ExportFormat::Bitwarden, "work-bitwarden.json"),
+            (ExportFormat::Encrypted, "work-export.peka"),
+        ];
+        for (format, name) in formats {
+            let destination = exports.join(name);
+            vault.export(&destination, format, None, Some(EXPORT_PASSWORD), true).unwrap();
+            let contents = fs::read(&destination).unwrap();
+            assert!(!String::from_utf8_lossy(&contents).contains(&key), "{}", name);
+        }
+
+        // The recipient of the encrypted export unwraps the blob key with the export
+        // password.
+        let mut exported = Vault::open(exports.join("work-export.peka"), EXPORT_PASSWORD).unwrap();
+        let contents = exported
+            .read_attachment(&folder_id, &credential_id, &summary.id)
+            .unwrap();
+        assert_eq!(contents, b"recovery codes");
+    }
+}
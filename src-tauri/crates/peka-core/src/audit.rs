@@ -0,0 +1,251 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::StoredVault;
+
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Operations recorded in the audit log.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditAction {
+    VaultCreated,
+    VaultImported,
+    VaultExported,
+    VaultMerged,
+    VaultSynced,
+    VaultRolledBack,
+    VaultRepaired,
+    FolderCreated,
+    FolderDeleted,
+    CredentialAdded,
+    CredentialUpdated,
+    CredentialDeleted,
+    CredentialsImported,
+    AttachmentAdded,
+    AttachmentExtracted,
+    AttachmentDeleted,
+    TagAdded,
+    TagRemoved,
+    TagRenamed,
+    FavoriteChanged,
+    TrashRestored,
+    TrashEmptied,
+    TrashPurged,
+    TrashRetentionChanged,
+    StorageFormatChanged,
+    PinVerificationFailed,
+}
+
+/// One record of the log. `hash` covers every other field and the previous entry's
+/// hash, so editing, reordering or dropping entries breaks the chain.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub sequence: u64,
+    pub timestamp: String,
+    pub action: AuditAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_id: Option<String>,
+    pub device: String,
+    pub previous_hash: String,
+    pub hash: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLog {
+    pub entries: Vec<AuditEntry>,
+    /// Whether every entry's hash and link to its predecessor checked out.
+    pub verified: bool,
+    /// Sequence number of the first entry that failed verification.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub broken_at: Option<u64>,
+}
+
+/// Appends an entry for `action` to the vault's log. The log lives in the encrypted
+/// payload, so it is written together with the change it describes.
+pub fn record(payload: &mut StoredVault, action: AuditAction, target_id: Option<&str>) {
+    let (sequence, previous_hash) = match payload.audit_log.last() {
+        Some(last) => (last.sequence + 1, last.hash.clone()),
+        None => (0, GENESIS_HASH.to_string()),
+    };
+
+    let mut entry = AuditEntry {
+        sequence,
+        timestamp: Utc::now().to_rfc3339(),
+        action,
+        target_id: target_id.map(str::to_string),
+        device: device_name(),
+        previous_hash,
+        hash: String::new(),
+    };
+    entry.hash = entry_hash(&entry);
+    payload.audit_log.push(entry);
+}
+
+/// Joins the logs of two copies of a vault: the entries of `local` that `remote` lacks
+/// are appended to `remote`, renumbered and linked into its chain. Both copies end up
+/// with every entry, and the chain still verifies.
+pub(crate) fn join(local: &[AuditEntry], remote: &[AuditEntry]) -> Vec<AuditEntry> {
+    let common = local
+        .iter()
+        .zip(remote)
+        .take_while(|(mine, theirs)| mine.hash == theirs.hash)
+        .count();
+    if common == local.len() {
+        return remote.to_vec();
+    }
+    if common == remote.len() {
+        return local.to_vec();
+    }
+
+    let mut joined = remote.to_vec();
+    for entry in &local[common..] {
+        let (sequence, previous_hash) = match joined.last() {
+            Some(last) => (last.sequence + 1, last.hash.clone()),
+            None => (0, GENESIS_HASH.to_string()),
+        };
+        let mut entry = AuditEntry {
+            sequence,
+            previous_hash,
+            ..entry.clone()
+        };
+        entry.hash = entry_hash(&entry);
+        joined.push(entry);
+    }
+    joined
+}
+
+/// Returns the log and whether its hash chain is intact.
+pub fn read(payload: &StoredVault) -> AuditLog {
+    let mut expected_previous = GENESIS_HASH;
+    let mut broken_at = None;
+    for (index, entry) in payload.audit_log.iter().enumerate() {
+        if entry.sequence != index as u64
+            || entry.previous_hash != expected_previous
+            || entry.hash != entry_hash(entry)
+        {
+            broken_at = Some(entry.sequence);
+            break;
+        }
+        expected_previous = &entry.hash;
+    }
+
+    AuditLog {
+        entries: payload.audit_log.clone(),
+        verified: broken_at.is_none(),
+        broken_at,
+    }
+}
+
+fn entry_hash(entry: &AuditEntry) -> String {
+    let action = serde_json::to_string(&entry.action).unwrap_or_default();
+    let mut hasher = Sha256::new();
+    // Fields are length-prefixed so values cannot bleed into their neighbours.
+    for field in [
+        entry.sequence.to_string().as_str(),
+        entry.timestamp.as_str(),
+        action.as_str(),
+        entry.target_id.as_deref().unwrap_or(""),
+        entry.device.as_str(),
+        entry.previous_hash.as_str(),
+    ] {
+        hasher.update((field.len() as u64).to_be_bytes());
+        hasher.update(field.as_bytes());
+    }
+    hex(&hasher.finalize())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn device_name() -> String {
+    let name = gethostname::gethostname().to_string_lossy().trim().to_string();
+    if name.is_empty() {
+        "unknown".to_string()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir};
+
+    fn sample_payload(dir: &TempDir) -> StoredVault {
+        let (vault, _, _) = testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let payload = vault.payload().clone();
+        assert!(payload.audit_log.len() >= 3);
+        payload
+    }
+
+    #[test]
+    fn verifies_an_intact_chain() {
+        let dir = TempDir::new();
+        let mut payload = sample_payload(&dir);
+        record(&mut payload, AuditAction::VaultExported, Some("export"));
+
+        let log = read(&payload);
+        assert!(log.verified);
+        assert_eq!(log.broken_at, None);
+        let last = log.entries.last().unwrap();
+        assert_eq!(last.action, AuditAction::VaultExported);
+    }
+
+    #[test]
+    fn rejects_tampered_chains() {
+        let dir = TempDir::new();
+        let payload = sample_payload(&dir);
+        let broken_at = |tamper: &dyn Fn(&mut Vec<AuditEntry>)| {
+            let mut tampered = payload.clone();
+            tamper(&mut tampered.audit_log);
+            let log = read(&tampered);
+            assert!(!log.verified);
+            log.broken_at
+        };
+
+        // An edited entry no longer matches its hash.
+        assert_eq!(broken_at(&|log| log[1].device = "another-device".to_string()), Some(1));
+        // Rehashing it breaks the link from the entry after it.
+        assert_eq!(
+            broken_at(&|log| {
+                log[1].action = AuditAction::VaultImported;
+                log[1].hash = entry_hash(&log[1]);
+            }),
+            Some(2)
+        );
+        // Removed and reordered entries leave gaps in the sequence.
+        assert_eq!(broken_at(&|log| drop(log.remove(1))), Some(2));
+        assert_eq!(broken_at(&|log| log.swap(0, 1)), Some(1));
+    }
+
+    #[test]
+    fn joins_diverged_logs_into_one_chain() {
+        let dir = TempDir::new();
+        let mut local = sample_payload(&dir);
+        let mut remote = local.clone();
+        record(&mut local, AuditAction::VaultExported, Some("local"));
+        record(&mut remote, AuditAction::TrashPurged, Some("remote"));
+
+        remote.audit_log = join(&local.audit_log, &remote.audit_log);
+        let log = read(&remote);
+        assert!(log.verified);
+        let targets: Vec<Option<&str>> = log
+            .entries
+            .iter()
+            .rev()
+            .take(2)
+            .map(|entry| entry.target_id.as_deref())
+            .collect();
+        assert_eq!(targets, [Some("local"), Some("remote")]);
+
+        // A copy that is only behind takes the other log as it is.
+        let behind = &log.entries[..log.entries.len() - 2];
+        assert_eq!(join(behind, &log.entries).len(), log.entries.len());
+        assert_eq!(join(&log.entries, behind).len(), log.entries.len());
+    }
+}
@@ -0,0 +1,80 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::PekaError;
+
+/// Previous versions of a vault file that are kept.
+pub const MAX_BACKUPS: usize = 5;
+
+/// A copy of the vault file as it was before one of the last saves. Backups are the
+/// encrypted file unchanged, so they open with the master password of that time.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSummary {
+    pub path: String,
+    pub created_at: String,
+    pub size_bytes: u64,
+}
+
+/// Directory holding the backups of the vault at `vault_path`, e.g. `work.backups/`
+/// next to `work.peka`.
+pub fn backup_dir(vault_path: &Path) -> PathBuf {
+    let stem = vault_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "vault".to_string());
+    vault_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(format!("{}.backups", stem))
+}
+
+/// Copies the vault file at `vault_path` into its backup directory before it is
+/// overwritten, dropping the oldest backups beyond [`MAX_BACKUPS`].
+pub(crate) fn keep(vault_path: &Path) -> Result<(), PekaError> {
+    if !vault_path.is_file() {
+        return Ok(());
+    }
+
+    let dir = backup_dir(vault_path);
+    fs::create_dir_all(&dir)
+        .map_err(|e| PekaError::io("Unable to create the backup directory", e))?;
+    // Names sort by time, which is what the rotation and `list` rely on.
+    let name = format!("{}.peka", Utc::now().format("%Y%m%dT%H%M%S%.6fZ"));
+    fs::copy(vault_path, dir.join(name))
+        .map_err(|e| PekaError::io("Unable to back up the vault file", e))?;
+
+    for stale in list(vault_path).into_iter().skip(MAX_BACKUPS) {
+        let _ = fs::remove_file(stale.path);
+    }
+    Ok(())
+}
+
+/// Backups of the vault at `vault_path`, newest first.
+pub fn list(vault_path: &Path) -> Vec<BackupSummary> {
+    let Ok(entries) = fs::read_dir(backup_dir(vault_path)) else {
+        return Vec::new();
+    };
+
+    let mut backups: Vec<BackupSummary> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("peka"))
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            let created_at = metadata
+                .modified()
+                .map(|modified| DateTime::<Utc>::from(modified).to_rfc3339())
+                .unwrap_or_default();
+            Some(BackupSummary {
+                path: path.to_string_lossy().to_string(),
+                created_at,
+                size_bytes: metadata.len(),
+            })
+        })
+        .collect();
+    backups.sort_by(|a, b| b.path.cmp(&a.path));
+    backups
+}
@@ -0,0 +1,341 @@
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::{PekaError, StoredVault};
+
+const HASH_HEX_LENGTH: usize = 40;
+const PREFIX_LENGTH: usize = 5;
+// Below this many bytes the remaining range is scanned line by line.
+const LINEAR_SCAN_WINDOW: u64 = 4096;
+// Longer than any `HASH:COUNT` line, so one read always covers a line boundary and
+// the full line that follows it.
+const PROBE_LENGTH: u64 = 256;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreachMatch {
+    pub folder_id: String,
+    pub folder_name: String,
+    pub credential_id: String,
+    pub title: String,
+    pub username: String,
+    /// How often the password appears in the corpus.
+    pub occurrences: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreachReport {
+    pub checked: usize,
+    pub breached: usize,
+    pub matches: Vec<BreachMatch>,
+}
+
+/// A breached-password corpus in the Have I Been Pwned format, kept entirely on disk.
+///
+/// Two layouts are supported: a single file of `HASH:COUNT` lines sorted by SHA-1
+/// hash, and a directory of range files named after the first five hex digits of the
+/// hash (`21BD1` or `21BD1.txt`) holding `SUFFIX:COUNT` lines, as written by the
+/// official downloader.
+pub enum BreachCorpus {
+    SortedFile(PathBuf),
+    PrefixDirectory(PathBuf),
+}
+
+impl BreachCorpus {
+    pub fn open(path: &str) -> Result<Self, PekaError> {
+        if path.trim().is_empty() {
+            return Err(PekaError::InvalidInput("Breach corpus path is required".to_string()));
+        }
+
+        let corpus_path = PathBuf::from(path);
+        if corpus_path.is_dir() {
+            Ok(BreachCorpus::PrefixDirectory(corpus_path))
+        } else if corpus_path.is_file() {
+            Ok(BreachCorpus::SortedFile(corpus_path))
+        } else {
+            Err(PekaError::NotFound("Breach corpus not found".to_string()))
+        }
+    }
+
+    /// Returns the number of occurrences of `password` in the corpus, if any.
+    pub fn lookup(&self, password: &str) -> Result<Option<u64>, PekaError> {
+        let hash = sha1_hex(password);
+        match self {
+            BreachCorpus::SortedFile(path) => search_sorted_file(path, &hash),
+            BreachCorpus::PrefixDirectory(dir) => search_prefix_directory(dir, &hash),
+        }
+    }
+}
+
+/// Checks every credential password in `payload` against `corpus`.
+pub fn check_vault(
+    payload: &StoredVault,
+    corpus: &BreachCorpus,
+) -> Result<BreachReport, PekaError> {
+    // Identical passwords only need one lookup.
+    let mut cache: HashMap<&str, Option<u64>> = HashMap::new();
+    let mut checked = 0;
+    let mut matches = Vec::new();
+
+    for folder in &payload.folders {
+        for credential in &folder.credentials {
+            if credential.password.is_empty() {
+                continue;
+            }
+            checked += 1;
+
+            let occurrences = match cache.get(credential.password.as_str()) {
+                Some(result) => *result,
+                None => {
+                    let result = corpus.lookup(&credential.password)?;
+                    cache.insert(credential.password.as_str(), result);
+                    result
+                }
+            };
+
+            if let Some(occurrences) = occurrences {
+                matches.push(BreachMatch {
+                    folder_id: folder.id.clone(),
+                    folder_name: folder.name.clone(),
+                    credential_id: credential.id.clone(),
+                    title: credential.title.clone(),
+                    username: credential.username.clone(),
+                    occurrences,
+                });
+            }
+        }
+    }
+
+    Ok(BreachReport {
+        checked,
+        breached: matches.len(),
+        matches,
+    })
+}
+
+fn sha1_hex(password: &str) -> String {
+    Sha1::digest(password.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect()
+}
+
+/// Splits a `HASH:COUNT` line. Entries without a count are treated as a single hit.
+fn parse_line(line: &[u8]) -> Option<(&[u8], u64)> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let mut parts = line.splitn(2, |byte| *byte == b':');
+    let hash = parts.next()?;
+    let count = parts
+        .next()
+        .and_then(|count| std::str::from_utf8(count).ok())
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(1);
+    Some((hash, count))
+}
+
+fn compare_hash(candidate: &[u8], target: &[u8]) -> std::cmp::Ordering {
+    candidate
+        .iter()
+        .map(u8::to_ascii_uppercase)
+        .cmp(target.iter().copied())
+}
+
+fn unreadable(source: std::io::Error) -> PekaError {
+    PekaError::io("Unable to read breach corpus from disk", source)
+}
+
+fn search_sorted_file(path: &Path, hash: &str) -> Result<Option<u64>, PekaError> {
+    let mut file = File::open(path).map_err(unreadable)?;
+    let file_len = file.metadata().map_err(unreadable)?.len();
+    let target = hash.as_bytes();
+
+    // `low` is always the start of a line; the target line, if present, starts
+    // somewhere in `low..high`.
+    let mut low = 0u64;
+    let mut high = file_len;
+
+    while high - low > LINEAR_SCAN_WINDOW {
+        let mid = low + (high - low) / 2;
+        let probe = read_at(&mut file, mid - 1, PROBE_LENGTH)?;
+        let Some(newline) = probe.iter().position(|byte| *byte == b'\n') else {
+            high = mid;
+            continue;
+        };
+
+        let line_start = mid + newline as u64;
+        if line_start >= high {
+            high = mid;
+            continue;
+        }
+
+        let rest = &probe[newline + 1..];
+        let line_len = rest
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .unwrap_or(rest.len());
+        let (candidate, count) = parse_line(&rest[..line_len])
+            .ok_or_else(|| PekaError::Corrupted {
+                message: "Breach corpus has an unexpected line format".to_string(),
+                details: None,
+            })?;
+
+        match compare_hash(candidate, target) {
+            std::cmp::Ordering::Equal => return Ok(Some(count)),
+            std::cmp::Ordering::Less => low = line_start + line_len as u64 + 1,
+            std::cmp::Ordering::Greater => high = line_start,
+        }
+    }
+
+    let window = read_at(&mut file, low, high - low + PROBE_LENGTH)?;
+    let mut offset = low;
+    for line in window.split(|byte| *byte == b'\n') {
+        if offset >= high {
+            break;
+        }
+        offset += line.len() as u64 + 1;
+
+        let Some((candidate, count)) = parse_line(line) else {
+            continue;
+        };
+        match compare_hash(candidate, target) {
+            std::cmp::Ordering::Equal => return Ok(Some(count)),
+            std::cmp::Ordering::Greater => break,
+            std::cmp::Ordering::Less => {}
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_at(file: &mut File, position: u64, length: u64) -> Result<Vec<u8>, PekaError> {
+    file.seek(SeekFrom::Start(position)).map_err(unreadable)?;
+    let mut buffer = Vec::new();
+    file.take(length)
+        .read_to_end(&mut buffer)
+        .map_err(unreadable)?;
+    Ok(buffer)
+}
+
+fn search_prefix_directory(dir: &Path, hash: &str) -> Result<Option<u64>, PekaError> {
+    let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+    let candidates = [
+        dir.join(prefix),
+        dir.join(format!("{}.txt", prefix)),
+        dir.join(prefix.to_lowercase()),
+        dir.join(format!("{}.txt", prefix.to_lowercase())),
+    ];
+
+    let Some(range_path) = candidates.iter().find(|path| path.is_file()) else {
+        return Ok(None);
+    };
+
+    let contents = fs::read(range_path).map_err(unreadable)?;
+
+    for line in contents.split(|byte| *byte == b'\n') {
+        let Some((candidate, count)) = parse_line(line) else {
+            continue;
+        };
+        // Range files normally hold only the suffix, but full hashes are accepted too.
+        let candidate = if candidate.len() == HASH_HEX_LENGTH {
+            &candidate[PREFIX_LENGTH..]
+        } else {
+            candidate
+        };
+        if compare_hash(candidate, suffix.as_bytes()) == std::cmp::Ordering::Equal {
+            return Ok(Some(count));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir};
+
+    /// `HASH:COUNT` lines for `count` passwords, sorted by hash, with the count of each
+    /// password being its index plus one.
+    fn sorted_lines(count: usize) -> Vec<(String, String)> {
+        let mut lines: Vec<(String, String)> = (0..count)
+            .map(|index| {
+                let hash = sha1_hex(&format!("password-{}", index));
+                let line = format!("{}:{}", hash, index + 1);
+                (hash, line)
+            })
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    fn count_of(line: &str) -> u64 {
+        line.split(':').nth(1).unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn finds_every_line_of_a_sorted_file() {
+        let dir = TempDir::new();
+        // Large enough for several bisection steps before the linear scan.
+        let lines = sorted_lines(2000);
+        for (name, separator) in [("unix.txt", "\n"), ("windows.txt", "\r\n")] {
+            let path = dir.subdir("corpus").join(name);
+            let text: Vec<&str> = lines.iter().map(|(_, line)| line.as_str()).collect();
+            fs::write(&path, text.join(separator)).unwrap();
+
+            for index in [0, 1, 999, 1998, 1999] {
+                let (hash, line) = &lines[index];
+                assert_eq!(
+                    search_sorted_file(&path, hash).unwrap(),
+                    Some(count_of(line)),
+                    "line {} of {}",
+                    index,
+                    name
+                );
+            }
+            assert_eq!(search_sorted_file(&path, &"0".repeat(HASH_HEX_LENGTH)).unwrap(), None);
+            assert_eq!(search_sorted_file(&path, &"F".repeat(HASH_HEX_LENGTH)).unwrap(), None);
+            assert_eq!(search_sorted_file(&path, &sha1_hex("not-in-corpus")).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn reads_lowercase_hashes_and_range_files() {
+        let dir = TempDir::new();
+        let hash = sha1_hex("hunter2");
+        let sorted = dir.subdir("corpus").join("sorted.txt");
+        fs::write(&sorted, format!("{}:17\n", hash.to_lowercase())).unwrap();
+        let corpus = BreachCorpus::open(&sorted.to_string_lossy()).unwrap();
+        assert_eq!(corpus.lookup("hunter2").unwrap(), Some(17));
+
+        let ranges = dir.subdir("ranges");
+        let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+        fs::write(ranges.join(format!("{}.txt", prefix)), format!("{}:3\r\n", suffix)).unwrap();
+        let corpus = BreachCorpus::open(&ranges.to_string_lossy()).unwrap();
+        assert_eq!(corpus.lookup("hunter2").unwrap(), Some(3));
+        assert_eq!(corpus.lookup("hunter3").unwrap(), None);
+
+        assert!(matches!(
+            BreachCorpus::open(&dir.subdir("ranges").join("missing").to_string_lossy()),
+            Err(PekaError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn reports_breached_credentials() {
+        let dir = TempDir::new();
+        let (vault, _, credential_id) =
+            testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let path = dir.subdir("corpus").join("sorted.txt");
+        fs::write(&path, format!("{}:42\n", sha1_hex("first-password"))).unwrap();
+
+        let report = check_vault(vault.payload(), &BreachCorpus::SortedFile(path)).unwrap();
+        assert_eq!((report.checked, report.breached), (1, 1));
+        assert_eq!(report.matches[0].credential_id, credential_id);
+        assert_eq!(report.matches[0].occurrences, 42);
+    }
+}
@@ -0,0 +1,689 @@
+use argon2::password_hash::PasswordHash;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::audit::{self, AuditAction};
+use crate::backup::{self, BackupSummary};
+use crate::container;
+use crate::crypto::{self, VaultFile};
+use crate::lock;
+use crate::trash::{self, TrashedEntry};
+use crate::{attachments, KdfCost, PekaError, StoredCredential, StoredFolder, StoredVault, Vault};
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum IssueSeverity {
+    /// The vault cannot be opened or some of its data cannot be used.
+    Error,
+    /// The vault works, but something is off.
+    Warning,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum IssueKind {
+    InvalidJson,
+    InvalidContainer,
+    UnsupportedVersion,
+    InvalidEncoding,
+    InvalidKdf,
+    DecryptionFailed,
+    InvalidPayload,
+    NameMismatch,
+    DuplicateId,
+    InvalidTimestamp,
+    OrphanedPinHash,
+    MissingPinHash,
+    InvalidPinHash,
+    MissingAttachment,
+    AuditChainBroken,
+}
+
+/// Fixes [`repair_vault`] can apply.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Repair {
+    /// Gives folders, credentials and trash items that share an id a fresh one.
+    RegenerateDuplicateIds,
+    /// Replaces timestamps that are not RFC 3339, preferring a sibling timestamp of
+    /// the same item over the current time.
+    FixTimestamps,
+    /// Drops PIN hashes left on folders that are no longer secure.
+    RemoveOrphanedPinHashes,
+    /// Replaces the vault file with the newest backup that opens with the master
+    /// password and has no errors. The replaced file is kept as a backup.
+    RestoreBackup,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckIssue {
+    pub kind: IssueKind,
+    pub severity: IssueSeverity,
+    pub message: String,
+    /// Id of the folder, credential, trash item or audit entry concerned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repair: Option<Repair>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckReport {
+    pub path: String,
+    /// No errors were found; there may be warnings.
+    pub healthy: bool,
+    /// The payload decrypted with the master password.
+    pub decrypted: bool,
+    pub issues: Vec<CheckIssue>,
+    /// Repairs that address the issues found.
+    pub repairs: Vec<Repair>,
+    pub backups: Vec<BackupSummary>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairResult {
+    /// Repairs that changed something.
+    pub applied: Vec<Repair>,
+    /// Backup the vault was restored from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restored_from: Option<String>,
+    /// The vault checked again after the repairs.
+    pub report: CheckReport,
+}
+
+/// Validates a vault file end to end: header, encodings, key derivation parameters,
+/// decryption, payload schema and the consistency of the payload.
+pub fn check_vault(path: &Path, master_password: &str) -> Result<CheckReport, PekaError> {
+    if !path.is_file() {
+        return Err(PekaError::NotFound("Vault file not found".to_string()));
+    }
+
+    let (payload, mut issues) = inspect(path, path, master_password)?;
+    let unrepairable =
+        |issue: &CheckIssue| issue.severity == IssueSeverity::Error && issue.repair.is_none();
+    if issues.iter().any(unrepairable) && newest_good_backup(path, master_password).is_some() {
+        for issue in issues.iter_mut().filter(|issue| unrepairable(issue)) {
+            if issue.kind == IssueKind::DecryptionFailed {
+                issue.message = "The vault does not decrypt, but a backup does with this \
+                                 master password, so the vault file is damaged"
+                    .to_string();
+            }
+            issue.repair = Some(Repair::RestoreBackup);
+        }
+    }
+
+    Ok(report(path, payload.is_some(), issues))
+}
+
+/// Applies `repairs` and checks the vault again. Restoring a backup happens first, so
+/// the other repairs apply to the restored vault.
+pub fn repair_vault(
+    path: &Path,
+    master_password: &str,
+    repairs: &[Repair],
+) -> Result<RepairResult, PekaError> {
+    if !path.is_file() {
+        return Err(PekaError::NotFound("Vault file not found".to_string()));
+    }
+
+    let mut applied = Vec::new();
+    let mut restored_from = None;
+    if repairs.contains(&Repair::RestoreBackup) {
+        let source = newest_good_backup(path, master_password).ok_or_else(|| {
+            PekaError::NotFound("No backup opens with this master password".to_string())
+        })?;
+        let _lock = lock::acquire(path)?;
+        backup::keep(path)?;
+        fs::copy(&source, path)
+            .map_err(|e| PekaError::io("Unable to restore the vault from a backup", e))?;
+        applied.push(Repair::RestoreBackup);
+        restored_from = Some(source.to_string_lossy().to_string());
+    }
+
+    let payload_repairs: Vec<Repair> = repairs
+        .iter()
+        .copied()
+        .filter(|repair| *repair != Repair::RestoreBackup)
+        .collect();
+    if !payload_repairs.is_empty() || restored_from.is_some() {
+        let mut vault = Vault::open(path, master_password)?;
+        let payload_applied = vault.repair(&payload_repairs);
+        if payload_applied.is_empty() && restored_from.is_some() {
+            // Recorded in the restored vault, whose log does not know about the damage.
+            vault.record(AuditAction::VaultRepaired, None);
+        }
+        applied.extend(payload_applied);
+        vault.save()?;
+    }
+
+    Ok(RepairResult {
+        applied,
+        restored_from,
+        report: check_vault(path, master_password)?,
+    })
+}
+
+/// Applies the payload repairs among `repairs`. Returns the ones that changed
+/// something.
+pub(crate) fn repair_payload(payload: &mut StoredVault, repairs: &[Repair]) -> Vec<Repair> {
+    let mut applied = Vec::new();
+    for repair in repairs {
+        let changed = match repair {
+            Repair::RegenerateDuplicateIds => regenerate_duplicate_ids(payload),
+            Repair::FixTimestamps => fix_timestamps(payload),
+            Repair::RemoveOrphanedPinHashes => remove_orphaned_pin_hashes(payload),
+            Repair::RestoreBackup => false,
+        };
+        if changed && !applied.contains(repair) {
+            applied.push(*repair);
+        }
+    }
+    applied
+}
+
+fn report(path: &Path, decrypted: bool, issues: Vec<CheckIssue>) -> CheckReport {
+    let mut repairs = Vec::new();
+    for repair in issues.iter().filter_map(|issue| issue.repair) {
+        if !repairs.contains(&repair) {
+            repairs.push(repair);
+        }
+    }
+
+    CheckReport {
+        path: path.to_string_lossy().to_string(),
+        healthy: !issues
+            .iter()
+            .any(|issue| issue.severity == IssueSeverity::Error),
+        decrypted,
+        issues,
+        repairs,
+        backups: backup::list(path),
+    }
+}
+
+fn newest_good_backup(vault_path: &Path, master_password: &str) -> Option<PathBuf> {
+    backup::list(vault_path).into_iter().find_map(|summary| {
+        let backup_path = PathBuf::from(summary.path);
+        let (payload, issues) = inspect(&backup_path, vault_path, master_password).ok()?;
+        let good = payload.is_some()
+            && !issues
+                .iter()
+                .any(|issue| issue.severity == IssueSeverity::Error);
+        good.then_some(backup_path)
+    })
+}
+
+/// Checks the file at `path`. Attachment blobs are looked up next to `vault_path`,
+/// which differs from `path` for backups.
+fn inspect(
+    path: &Path,
+    vault_path: &Path,
+    master_password: &str,
+) -> Result<(Option<StoredVault>, Vec<CheckIssue>), PekaError> {
+    let mut issues = Vec::new();
+    let payload = check_file(path, master_password, &mut issues)?;
+    if let Some(payload) = &payload {
+        check_payload(vault_path, payload, &mut issues);
+    }
+    Ok((payload, issues))
+}
+
+fn check_file(
+    path: &Path,
+    master_password: &str,
+    issues: &mut Vec<CheckIssue>,
+) -> Result<Option<StoredVault>, PekaError> {
+    let raw = fs::read(path).map_err(|e| PekaError::io("Unable to read vault file", e))?;
+    let Some(vault_file) = check_header(&raw, issues) else {
+        return Ok(None);
+    };
+
+    let encodings = [
+        crypto::decode_salt(&vault_file).map(|_| ()),
+        crypto::decode_nonce(&vault_file).map(|_| ()),
+        crypto::decode_ciphertext(&vault_file).map(|_| ()),
+    ];
+    let encoding_errors: Vec<PekaError> = encodings.into_iter().filter_map(Result::err).collect();
+    for err in &encoding_errors {
+        let kind = match err {
+            PekaError::UnsafeParameters(_) => IssueKind::InvalidKdf,
+            _ => IssueKind::InvalidEncoding,
+        };
+        issues.push(error(kind, describe(err)));
+    }
+
+    let kdf_errors_before = issues.len();
+    check_kdf(&vault_file, issues);
+    if !encoding_errors.is_empty()
+        || issues[kdf_errors_before..]
+            .iter()
+            .any(|issue| issue.severity == IssueSeverity::Error)
+    {
+        return Ok(None);
+    }
+
+    let decrypted = match crypto::decrypt_bytes(&vault_file, master_password) {
+        Ok(decrypted) => decrypted,
+        Err(PekaError::WrongPassword) => {
+            issues.push(error(
+                IssueKind::DecryptionFailed,
+                "The vault does not decrypt: the master password is wrong or the vault \
+                 file is damaged"
+                    .to_string(),
+            ));
+            return Ok(None);
+        }
+        Err(err) => {
+            issues.push(error(IssueKind::DecryptionFailed, describe(&err)));
+            return Ok(None);
+        }
+    };
+
+    let payload: StoredVault = match serde_json::from_slice(&decrypted) {
+        Ok(payload) => payload,
+        Err(e) => {
+            issues.push(error(
+                IssueKind::InvalidPayload,
+                format!("Vault data does not match the expected structure: {}", e),
+            ));
+            return Ok(None);
+        }
+    };
+
+    if payload.vault_name != vault_file.vault_name {
+        issues.push(warning(
+            IssueKind::NameMismatch,
+            format!(
+                "The header names the vault '{}' but its data '{}'; the next save uses the \
+                 latter",
+                vault_file.vault_name, payload.vault_name
+            ),
+        ));
+    }
+
+    Ok(Some(payload))
+}
+
+fn check_header(raw: &[u8], issues: &mut Vec<CheckIssue>) -> Option<VaultFile> {
+    let vault_file = if container::is_binary(raw) {
+        match container::decode(raw) {
+            Ok(vault_file) => vault_file,
+            Err(err) => {
+                let kind = match err {
+                    PekaError::UnsupportedVersion(_) => IssueKind::UnsupportedVersion,
+                    _ => IssueKind::InvalidContainer,
+                };
+                issues.push(error(kind, describe(&err)));
+                return None;
+            }
+        }
+    } else {
+        let value: serde_json::Value = match serde_json::from_slice(raw) {
+            Ok(value) => value,
+            Err(e) => {
+                issues.push(error(
+                    IssueKind::InvalidJson,
+                    format!("Not valid JSON: {}", e),
+                ));
+                return None;
+            }
+        };
+        if let Some(version) = value.get("version").and_then(serde_json::Value::as_u64) {
+            if let Err(err) = crypto::check_version(version) {
+                issues.push(error(IssueKind::UnsupportedVersion, describe(&err)));
+                return None;
+            }
+        }
+        match serde_json::from_value(value) {
+            Ok(vault_file) => vault_file,
+            Err(e) => {
+                issues.push(error(
+                    IssueKind::InvalidJson,
+                    format!("Vault header is incomplete: {}", e),
+                ));
+                return None;
+            }
+        }
+    };
+
+    if let Err(err) = crypto::check_version(u64::from(vault_file.version)) {
+        issues.push(error(IssueKind::UnsupportedVersion, describe(&err)));
+        return None;
+    }
+    Some(vault_file)
+}
+
+fn check_kdf(vault_file: &VaultFile, issues: &mut Vec<CheckIssue>) {
+    let kdf = &vault_file.kdf;
+    match crypto::kdf_params(kdf).and_then(|_| kdf.cost()) {
+        Ok(KdfCost::Normal) => {}
+        Ok(KdfCost::Expensive) => issues.push(warning(
+            IssueKind::InvalidKdf,
+            "Key derivation takes unusually much memory or time, so the vault is slow to \
+             open"
+                .to_string(),
+        )),
+        Err(err) => issues.push(error(IssueKind::InvalidKdf, describe(&err))),
+    }
+    if let Ok(salt) = crypto::decode_salt(vault_file) {
+        if salt.len() != kdf.salt_length as usize {
+            issues.push(warning(
+                IssueKind::InvalidKdf,
+                format!(
+                    "Salt is {} bytes, but the header says {}",
+                    salt.len(),
+                    kdf.salt_length
+                ),
+            ));
+        }
+    }
+}
+
+fn check_payload(vault_path: &Path, payload: &StoredVault, issues: &mut Vec<CheckIssue>) {
+    let mut folder_ids = HashSet::new();
+    for folder in &payload.folders {
+        if !folder_ids.insert(folder.id.as_str()) {
+            issues.push(duplicate_id("folder", &folder.id));
+        }
+    }
+    let mut credential_ids = HashSet::new();
+    for credential in payload
+        .folders
+        .iter()
+        .flat_map(|folder| &folder.credentials)
+    {
+        if !credential_ids.insert(credential.id.as_str()) {
+            issues.push(duplicate_id("credential", &credential.id));
+        }
+    }
+    let mut trash_ids = HashSet::new();
+    for item in &payload.trash {
+        if !trash_ids.insert(item.id.as_str()) {
+            issues.push(duplicate_id("trash item", &item.id));
+        }
+    }
+
+    for folder in all_folders(payload) {
+        check_timestamp(&folder.created_at, "folder", &folder.id, issues);
+        check_timestamp(&folder.updated_at, "folder", &folder.id, issues);
+        check_pin(
+            folder.secure,
+            folder.pin_hash.as_deref(),
+            &folder.id,
+            issues,
+        );
+    }
+    for credential in trash::all_credentials(payload) {
+        check_timestamp(&credential.created_at, "credential", &credential.id, issues);
+        check_timestamp(&credential.updated_at, "credential", &credential.id, issues);
+        for attachment in &credential.attachments {
+            check_timestamp(&attachment.created_at, "attachment", &attachment.id, issues);
+            if !attachments::blob_exists(vault_path, attachment) {
+                issues.push(CheckIssue {
+                    kind: IssueKind::MissingAttachment,
+                    severity: IssueSeverity::Warning,
+                    message: format!(
+                        "Attachment '{}' of credential '{}' has no data file",
+                        attachment.file_name, credential.title
+                    ),
+                    target_id: Some(attachment.id.clone()),
+                    repair: None,
+                });
+            }
+        }
+    }
+    for item in &payload.trash {
+        check_timestamp(&item.deleted_at, "trash item", &item.id, issues);
+        if let TrashedEntry::Credential {
+            folder_id,
+            folder_secure,
+            folder_pin_hash,
+            ..
+        } = &item.entry
+        {
+            check_pin(
+                *folder_secure,
+                folder_pin_hash.as_deref(),
+                folder_id,
+                issues,
+            );
+        }
+    }
+
+    for entry in &payload.audit_log {
+        if DateTime::parse_from_rfc3339(&entry.timestamp).is_err() {
+            // Audit entries are hashed, so their timestamps are reported but not fixed.
+            issues.push(CheckIssue {
+                kind: IssueKind::InvalidTimestamp,
+                severity: IssueSeverity::Warning,
+                message: format!(
+                    "Audit entry {} has an invalid timestamp '{}'",
+                    entry.sequence, entry.timestamp
+                ),
+                target_id: Some(entry.sequence.to_string()),
+                repair: None,
+            });
+        }
+    }
+    if let Some(broken_at) = audit::read(payload).broken_at {
+        issues.push(CheckIssue {
+            kind: IssueKind::AuditChainBroken,
+            severity: IssueSeverity::Warning,
+            message: format!("The audit log does not verify from entry {} on", broken_at),
+            target_id: Some(broken_at.to_string()),
+            repair: None,
+        });
+    }
+}
+
+fn check_timestamp(value: &str, what: &str, id: &str, issues: &mut Vec<CheckIssue>) {
+    if DateTime::parse_from_rfc3339(value).is_ok() {
+        return;
+    }
+    issues.push(CheckIssue {
+        kind: IssueKind::InvalidTimestamp,
+        severity: IssueSeverity::Warning,
+        message: format!("The {} has an invalid timestamp '{}'", what, value),
+        target_id: Some(id.to_string()),
+        repair: Some(Repair::FixTimestamps),
+    });
+}
+
+fn check_pin(secure: bool, pin_hash: Option<&str>, folder_id: &str, issues: &mut Vec<CheckIssue>) {
+    let (kind, severity, message, repair) = match (secure, pin_hash) {
+        (false, Some(_)) => (
+            IssueKind::OrphanedPinHash,
+            IssueSeverity::Warning,
+            "A folder that is not secure still has a PIN hash",
+            Some(Repair::RemoveOrphanedPinHashes),
+        ),
+        (true, None) => (
+            IssueKind::MissingPinHash,
+            IssueSeverity::Error,
+            "A secure folder has no PIN hash, so it cannot be unlocked",
+            None,
+        ),
+        (true, Some(hash)) if PasswordHash::new(hash).is_err() => (
+            IssueKind::InvalidPinHash,
+            IssueSeverity::Error,
+            "The PIN hash of a secure folder is malformed, so it cannot be unlocked",
+            None,
+        ),
+        _ => return,
+    };
+    issues.push(CheckIssue {
+        kind,
+        severity,
+        message: message.to_string(),
+        target_id: Some(folder_id.to_string()),
+        repair,
+    });
+}
+
+fn duplicate_id(what: &str, id: &str) -> CheckIssue {
+    CheckIssue {
+        kind: IssueKind::DuplicateId,
+        severity: IssueSeverity::Error,
+        message: format!("More than one {} has the id '{}'", what, id),
+        target_id: Some(id.to_string()),
+        repair: Some(Repair::RegenerateDuplicateIds),
+    }
+}
+
+fn error(kind: IssueKind, message: String) -> CheckIssue {
+    CheckIssue {
+        kind,
+        severity: IssueSeverity::Error,
+        message,
+        target_id: None,
+        repair: None,
+    }
+}
+
+fn warning(kind: IssueKind, message: String) -> CheckIssue {
+    CheckIssue {
+        severity: IssueSeverity::Warning,
+        ..error(kind, message)
+    }
+}
+
+fn describe(err: &PekaError) -> String {
+    match err.details() {
+        Some(details) => format!("{}: {}", err, details),
+        None => err.to_string(),
+    }
+}
+
+/// Folders of the vault, including the ones in the trash.
+fn all_folders(payload: &StoredVault) -> impl Iterator<Item = &StoredFolder> {
+    payload
+        .folders
+        .iter()
+        .chain(payload.trash.iter().filter_map(|item| match &item.entry {
+            TrashedEntry::Folder { folder } => Some(folder),
+            TrashedEntry::Credential { .. } => None,
+        }))
+}
+
+fn regenerate_duplicate_ids(payload: &mut StoredVault) -> bool {
+    let mut changed = false;
+    let mut folder_ids = HashSet::new();
+    for folder in &mut payload.folders {
+        changed |= make_unique(&mut folder.id, &mut folder_ids);
+    }
+    let mut credential_ids = HashSet::new();
+    for credential in payload
+        .folders
+        .iter_mut()
+        .flat_map(|folder| folder.credentials.iter_mut())
+    {
+        changed |= make_unique(&mut credential.id, &mut credential_ids);
+    }
+    let mut trash_ids = HashSet::new();
+    for item in &mut payload.trash {
+        changed |= make_unique(&mut item.id, &mut trash_ids);
+    }
+    changed
+}
+
+fn make_unique(id: &mut String, seen: &mut HashSet<String>) -> bool {
+    if seen.insert(id.clone()) {
+        return false;
+    }
+    *id = Uuid::new_v4().to_string();
+    seen.insert(id.clone());
+    true
+}
+
+fn fix_timestamps(payload: &mut StoredVault) -> bool {
+    let now = Utc::now().to_rfc3339();
+    let mut changed = false;
+    for folder in &mut payload.folders {
+        changed |= fix_folder_timestamps(folder, &now);
+    }
+    for item in &mut payload.trash {
+        changed |= fix_timestamp(&mut item.deleted_at, &now);
+        match &mut item.entry {
+            TrashedEntry::Folder { folder } => changed |= fix_folder_timestamps(folder, &now),
+            TrashedEntry::Credential { credential, .. } => {
+                changed |= fix_credential_timestamps(credential, &now)
+            }
+        }
+    }
+    changed
+}
+
+fn fix_folder_timestamps(folder: &mut StoredFolder, now: &str) -> bool {
+    let mut changed = fix_pair(&mut folder.created_at, &mut folder.updated_at, now);
+    for credential in &mut folder.credentials {
+        changed |= fix_credential_timestamps(credential, now);
+    }
+    changed
+}
+
+fn fix_credential_timestamps(credential: &mut StoredCredential, now: &str) -> bool {
+    let mut changed = fix_pair(&mut credential.created_at, &mut credential.updated_at, now);
+    for attachment in &mut credential.attachments {
+        changed |= fix_timestamp(&mut attachment.created_at, &credential.created_at);
+    }
+    changed
+}
+
+/// Fixes a creation and update timestamp, each from the other when that one is valid.
+fn fix_pair(created_at: &mut String, updated_at: &mut String, now: &str) -> bool {
+    let valid = |value: &str| DateTime::parse_from_rfc3339(value).is_ok();
+    match (valid(created_at), valid(updated_at)) {
+        (true, true) => false,
+        (true, false) => {
+            *updated_at = created_at.clone();
+            true
+        }
+        (false, true) => {
+            *created_at = updated_at.clone();
+            true
+        }
+        (false, false) => {
+            *created_at = now.to_string();
+            *updated_at = now.to_string();
+            true
+        }
+    }
+}
+
+fn fix_timestamp(value: &mut String, fallback: &str) -> bool {
+    if DateTime::parse_from_rfc3339(value).is_ok() {
+        return false;
+    }
+    *value = fallback.to_string();
+    true
+}
+
+fn remove_orphaned_pin_hashes(payload: &mut StoredVault) -> bool {
+    let mut changed = false;
+    for folder in &mut payload.folders {
+        if !folder.secure && folder.pin_hash.take().is_some() {
+            changed = true;
+        }
+    }
+    for item in &mut payload.trash {
+        let (secure, pin_hash) = match &mut item.entry {
+            TrashedEntry::Folder { folder } => (folder.secure, &mut folder.pin_hash),
+            TrashedEntry::Credential {
+                folder_secure,
+                folder_pin_hash,
+                ..
+            } => (*folder_secure, folder_pin_hash),
+        };
+        if !secure && pin_hash.take().is_some() {
+            changed = true;
+        }
+    }
+    changed
+}
@@ -0,0 +1,203 @@
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::PekaError;
+
+/// Seconds a copied secret stays on the clipboard when the caller does not say.
+pub const DEFAULT_CLEAR_AFTER_SECONDS: u32 = 30;
+pub const MAX_CLEAR_AFTER_SECONDS: u32 = 600;
+
+/// A clipboard that secrets can be written to. The app implements it on top of the
+/// system clipboard; [`MemoryClipboard`] stands in for it in tests and headless use.
+pub trait ClipboardBackend: Send + 'static {
+    /// Replaces the contents with `text`, marked as sensitive where the platform has
+    /// a way to keep it out of clipboard history and sync.
+    fn set_secret(&mut self, text: &str) -> Result<(), PekaError>;
+    /// Current text contents, `None` when the clipboard is empty or holds no text.
+    fn text(&mut self) -> Result<Option<String>, PekaError>;
+    fn clear(&mut self) -> Result<(), PekaError>;
+}
+
+/// Clipboard kept in memory.
+#[derive(Default)]
+pub struct MemoryClipboard {
+    contents: Option<String>,
+    sensitive: bool,
+}
+
+impl MemoryClipboard {
+    pub fn contents(&self) -> Option<&str> {
+        self.contents.as_deref()
+    }
+
+    /// Whether the current contents were written as a secret.
+    pub fn is_sensitive(&self) -> bool {
+        self.sensitive
+    }
+
+    /// Replaces the contents the way another application copying text would.
+    pub fn set_text(&mut self, text: &str) {
+        self.contents = Some(text.to_string());
+        self.sensitive = false;
+    }
+}
+
+impl ClipboardBackend for MemoryClipboard {
+    fn set_secret(&mut self, text: &str) -> Result<(), PekaError> {
+        self.contents = Some(text.to_string());
+        self.sensitive = true;
+        Ok(())
+    }
+
+    fn text(&mut self) -> Result<Option<String>, PekaError> {
+        Ok(self.contents.clone())
+    }
+
+    fn clear(&mut self) -> Result<(), PekaError> {
+        self.contents = None;
+        self.sensitive = false;
+        Ok(())
+    }
+}
+
+struct ClipboardState<B> {
+    backend: B,
+    copies: u64,
+    /// Digest of the last secret copied; the secret itself is not kept around.
+    last_digest: Option<[u8; 32]>,
+}
+
+/// Writes secrets to a clipboard backend and clears them again after a timeout. A
+/// secret is only cleared while it is still the latest copy and still on the
+/// clipboard, so whatever the user copied since is left alone.
+pub struct SecretClipboard<B> {
+    state: Arc<Mutex<ClipboardState<B>>>,
+}
+
+impl<B> Clone for SecretClipboard<B> {
+    fn clone(&self) -> Self {
+        SecretClipboard {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl<B: ClipboardBackend> SecretClipboard<B> {
+    pub fn new(backend: B) -> Self {
+        SecretClipboard {
+            state: Arc::new(Mutex::new(ClipboardState {
+                backend,
+                copies: 0,
+                last_digest: None,
+            })),
+        }
+    }
+
+    /// Copies `secret` and, with `clear_after`, clears it on a background thread once
+    /// the time is up. Returns the id of this copy for [`Self::clear_if_unchanged`].
+    pub fn copy(&self, secret: &str, clear_after: Option<Duration>) -> Result<u64, PekaError> {
+        let copy_id = {
+            let mut state = self.lock();
+            state.backend.set_secret(secret)?;
+            state.copies += 1;
+            state.last_digest = Some(Sha256::digest(secret.as_bytes()).into());
+            state.copies
+        };
+
+        if let Some(clear_after) = clear_after {
+            let clipboard = self.clone();
+            thread::spawn(move || {
+                thread::sleep(clear_after);
+                // Nobody is left to report a failure to; the value stays until
+                // something else is copied.
+                let _ = clipboard.clear_if_unchanged(copy_id);
+            });
+        }
+
+        Ok(copy_id)
+    }
+
+    /// Clears the clipboard if `copy_id` is the latest copy and the clipboard still
+    /// holds its value. Returns whether it was cleared.
+    pub fn clear_if_unchanged(&self, copy_id: u64) -> Result<bool, PekaError> {
+        let mut state = self.lock();
+        if state.copies != copy_id {
+            return Ok(false);
+        }
+        let Some(expected) = state.last_digest else {
+            return Ok(false);
+        };
+
+        let still_ours = state
+            .backend
+            .text()?
+            .is_some_and(|text| <[u8; 32]>::from(Sha256::digest(text.as_bytes())) == expected);
+        state.last_digest = None;
+        if !still_ours {
+            return Ok(false);
+        }
+
+        state.backend.clear()?;
+        Ok(true)
+    }
+
+    /// Runs `f` on the backend, e.g. to inspect a [`MemoryClipboard`].
+    pub fn with_backend<R>(&self, f: impl FnOnce(&mut B) -> R) -> R {
+        f(&mut self.lock().backend)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ClipboardState<B>> {
+        // The state stays consistent even if a holder panicked mid-way.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(clipboard: &SecretClipboard<MemoryClipboard>) -> Option<String> {
+        clipboard.with_backend(|backend| backend.contents().map(str::to_string))
+    }
+
+    #[test]
+    fn clears_a_secret_still_on_the_clipboard() {
+        let clipboard = SecretClipboard::new(MemoryClipboard::default());
+        let copy_id = clipboard.copy("hunter2", None).unwrap();
+        assert!(clipboard.with_backend(|backend| backend.is_sensitive()));
+
+        assert!(clipboard.clear_if_unchanged(copy_id).unwrap());
+        assert_eq!(contents(&clipboard), None);
+        // Only once.
+        assert!(!clipboard.clear_if_unchanged(copy_id).unwrap());
+    }
+
+    #[test]
+    fn leaves_text_copied_by_another_application() {
+        let clipboard = SecretClipboard::new(MemoryClipboard::default());
+        let copy_id = clipboard.copy("hunter2", None).unwrap();
+        clipboard.with_backend(|backend| backend.set_text("meeting notes"));
+
+        assert!(!clipboard.clear_if_unchanged(copy_id).unwrap());
+        assert_eq!(contents(&clipboard).as_deref(), Some("meeting notes"));
+    }
+
+    #[test]
+    fn a_newer_copy_replaces_the_pending_clear() {
+        let clipboard = SecretClipboard::new(MemoryClipboard::default());
+        let first = clipboard
+            .copy("hunter2", Some(Duration::from_millis(20)))
+            .unwrap();
+        let second = clipboard.copy("correct horse", None).unwrap();
+
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(contents(&clipboard).as_deref(), Some("correct horse"));
+        assert!(!clipboard.clear_if_unchanged(first).unwrap());
+        assert!(clipboard.clear_if_unchanged(second).unwrap());
+        assert_eq!(contents(&clipboard), None);
+    }
+}
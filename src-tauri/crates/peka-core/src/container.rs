@@ -0,0 +1,445 @@
+use base64::{engine::general_purpose, Engine as _};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+use crate::crypto::{KdfParams, VaultFile};
+use crate::records::EncryptedRecord;
+use crate::PekaError;
+
+// The binary container starts like PNG: a non-ASCII byte so it is never taken for
+// text, the name, and line endings that reveal damage from text-mode transfers.
+const MAGIC: [u8; 8] = [0x89, b'P', b'E', b'K', b'A', b'\r', b'\n', 0x1A];
+const CONTAINER_VERSION: u16 = 1;
+
+// Header fields are `tag: u8, length: u32 LE, value`, closed by FIELD_END. The raw
+// ciphertext follows. Unknown tags are skipped so later versions can add fields.
+const FIELD_END: u8 = 0;
+const FIELD_FORMAT_VERSION: u8 = 1;
+const FIELD_VAULT_NAME: u8 = 2;
+const FIELD_KDF_ALGORITHM: u8 = 3;
+const FIELD_KDF_MEMORY_KIB: u8 = 4;
+const FIELD_KDF_TIME_COST: u8 = 5;
+const FIELD_KDF_PARALLELISM: u8 = 6;
+const FIELD_KDF_HASH_LENGTH: u8 = 7;
+const FIELD_SALT: u8 = 8;
+const FIELD_NONCE: u8 = 9;
+const FIELD_COMPRESSION: u8 = 10;
+const FIELD_LAYOUT: u8 = 11;
+/// One per record of a per-entry vault: `id length: u8, id, nonce, ciphertext`.
+const FIELD_RECORD: u8 = 12;
+const NONCE_LENGTH: usize = 12;
+
+/// Largest payload a compressed vault may inflate to.
+const MAX_PAYLOAD_BYTES: u64 = 256 * 1024 * 1024;
+
+/// How a vault file is laid out on disk.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ContainerFormat {
+    /// Pretty-printed JSON with base64 fields, readable by every version of the app.
+    #[default]
+    Json,
+    /// A compact binary header followed by the raw ciphertext.
+    Binary,
+}
+
+/// Compression applied to the payload before it is encrypted.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PayloadCompression {
+    #[default]
+    None,
+    Deflate,
+}
+
+impl PayloadCompression {
+    pub fn is_none(&self) -> bool {
+        *self == PayloadCompression::None
+    }
+}
+
+/// How the decrypted vault data is divided in the file.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PayloadLayout {
+    /// The whole vault is one encrypted payload, encrypted again on every save.
+    #[default]
+    Single,
+    /// Every folder, credential, trash item and page of the audit log is a record
+    /// encrypted on its own, listed in an encrypted index. Saves only encrypt the
+    /// records that changed, so large vaults stay fast to edit.
+    Entries,
+}
+
+impl PayloadLayout {
+    pub fn is_single(&self) -> bool {
+        *self == PayloadLayout::Single
+    }
+}
+
+/// How a vault is written on the next save.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageFormat {
+    pub container: ContainerFormat,
+    pub compression: PayloadCompression,
+    #[serde(default)]
+    pub layout: PayloadLayout,
+}
+
+/// Whether `raw` is a binary container rather than JSON.
+pub(crate) fn is_binary(raw: &[u8]) -> bool {
+    raw.starts_with(&MAGIC)
+}
+
+pub(crate) fn encode(vault_file: &VaultFile) -> Result<Vec<u8>, PekaError> {
+    let decode = |value: &str| {
+        general_purpose::STANDARD
+            .decode(value)
+            .map_err(PekaError::internal)
+    };
+    let kdf = &vault_file.kdf;
+
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&CONTAINER_VERSION.to_le_bytes());
+    write_field(&mut out, FIELD_FORMAT_VERSION, &[vault_file.version]);
+    write_field(&mut out, FIELD_VAULT_NAME, vault_file.vault_name.as_bytes());
+    write_field(&mut out, FIELD_KDF_ALGORITHM, kdf.algorithm.as_bytes());
+    write_field(
+        &mut out,
+        FIELD_KDF_MEMORY_KIB,
+        &kdf.memory_kib.to_le_bytes(),
+    );
+    write_field(&mut out, FIELD_KDF_TIME_COST, &kdf.time_cost.to_le_bytes());
+    write_field(
+        &mut out,
+        FIELD_KDF_PARALLELISM,
+        &kdf.parallelism.to_le_bytes(),
+    );
+    write_field(
+        &mut out,
+        FIELD_KDF_HASH_LENGTH,
+        &kdf.hash_length.to_le_bytes(),
+    );
+    write_field(&mut out, FIELD_SALT, &decode(&vault_file.salt)?);
+    write_field(&mut out, FIELD_NONCE, &decode(&vault_file.nonce)?);
+    let compression = match vault_file.compression {
+        PayloadCompression::None => 0u8,
+        PayloadCompression::Deflate => 1u8,
+    };
+    write_field(&mut out, FIELD_COMPRESSION, &[compression]);
+    if !vault_file.layout.is_single() {
+        write_field(&mut out, FIELD_LAYOUT, &[1]);
+    }
+    for record in &vault_file.records {
+        let mut value = vec![record.id.len() as u8];
+        value.extend_from_slice(record.id.as_bytes());
+        let nonce = decode(&record.nonce)?;
+        if nonce.len() != NONCE_LENGTH || record.id.len() > usize::from(u8::MAX) {
+            return Err(PekaError::internal("Vault record cannot be encoded"));
+        }
+        value.extend_from_slice(&nonce);
+        value.extend_from_slice(&decode(&record.ciphertext)?);
+        write_field(&mut out, FIELD_RECORD, &value);
+    }
+    write_field(&mut out, FIELD_END, &[]);
+    out.extend_from_slice(&decode(&vault_file.ciphertext)?);
+    Ok(out)
+}
+
+/// Reads a binary container into the same [`VaultFile`] a JSON vault file gives.
+pub(crate) fn decode(raw: &[u8]) -> Result<VaultFile, PekaError> {
+    let mut reader = ByteReader {
+        data: raw,
+        position: 0,
+    };
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err(corrupted("Vault file is not a binary vault container"));
+    }
+    let container_version = u16::from_le_bytes(reader.array()?);
+    if container_version > CONTAINER_VERSION {
+        return Err(PekaError::UnsupportedVersion(format!(
+            "Vault container version {} is newer than this app supports ({})",
+            container_version, CONTAINER_VERSION
+        )));
+    }
+
+    let mut version = None;
+    let mut vault_name = None;
+    let mut algorithm = None;
+    let mut memory_kib = None;
+    let mut time_cost = None;
+    let mut parallelism = None;
+    let mut hash_length = None;
+    let mut salt = None;
+    let mut nonce = None;
+    let mut compression = PayloadCompression::None;
+    let mut layout = PayloadLayout::Single;
+    let mut records = Vec::new();
+    loop {
+        let tag = reader.bytes(1)?[0];
+        let length = u32::from_le_bytes(reader.array()?) as usize;
+        let value = reader.bytes(length)?;
+        match tag {
+            FIELD_END => break,
+            FIELD_FORMAT_VERSION => version = Some(read_u8(value)?),
+            FIELD_VAULT_NAME => vault_name = Some(read_string(value)?),
+            FIELD_KDF_ALGORITHM => algorithm = Some(read_string(value)?),
+            FIELD_KDF_MEMORY_KIB => memory_kib = Some(read_u32(value)?),
+            FIELD_KDF_TIME_COST => time_cost = Some(read_u32(value)?),
+            FIELD_KDF_PARALLELISM => parallelism = Some(read_u32(value)?),
+            FIELD_KDF_HASH_LENGTH => hash_length = Some(read_u32(value)?),
+            FIELD_SALT => salt = Some(value),
+            FIELD_NONCE => nonce = Some(value),
+            FIELD_COMPRESSION => {
+                compression = match read_u8(value)? {
+                    0 => PayloadCompression::None,
+                    1 => PayloadCompression::Deflate,
+                    other => {
+                        return Err(PekaError::Unsupported(format!(
+                            "Vault payload compression {} is not supported",
+                            other
+                        )))
+                    }
+                }
+            }
+            FIELD_LAYOUT => {
+                layout = match read_u8(value)? {
+                    0 => PayloadLayout::Single,
+                    1 => PayloadLayout::Entries,
+                    other => {
+                        return Err(PekaError::Unsupported(format!(
+                            "Vault payload layout {} is not supported",
+                            other
+                        )))
+                    }
+                }
+            }
+            FIELD_RECORD => records.push(read_record(value)?),
+            _ => {}
+        }
+    }
+
+    let missing = |name: &str| corrupted(&format!("Vault container is missing the {}", name));
+    let salt = salt.ok_or_else(|| missing("salt"))?;
+    Ok(VaultFile {
+        version: version.ok_or_else(|| missing("format version"))?,
+        vault_name: vault_name.ok_or_else(|| missing("vault name"))?,
+        kdf: KdfParams {
+            algorithm: algorithm.ok_or_else(|| missing("key derivation algorithm"))?,
+            memory_kib: memory_kib.ok_or_else(|| missing("key derivation memory cost"))?,
+            time_cost: time_cost.ok_or_else(|| missing("key derivation time cost"))?,
+            parallelism: parallelism.ok_or_else(|| missing("key derivation parallelism"))?,
+            hash_length: hash_length.ok_or_else(|| missing("key length"))?,
+            salt_length: salt.len() as u32,
+        },
+        compression,
+        layout,
+        container: ContainerFormat::Binary,
+        salt: general_purpose::STANDARD.encode(salt),
+        nonce: general_purpose::STANDARD.encode(nonce.ok_or_else(|| missing("nonce"))?),
+        ciphertext: general_purpose::STANDARD.encode(&raw[reader.position..]),
+        records,
+    })
+}
+
+pub(crate) fn compress(
+    payload: Vec<u8>,
+    compression: PayloadCompression,
+) -> Result<Vec<u8>, PekaError> {
+    match compression {
+        PayloadCompression::None => Ok(payload),
+        PayloadCompression::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&payload).map_err(PekaError::internal)?;
+            encoder.finish().map_err(PekaError::internal)
+        }
+    }
+}
+
+/// Inflates a decrypted payload, refusing anything larger than a vault can be.
+pub(crate) fn decompress(
+    payload: Vec<u8>,
+    compression: PayloadCompression,
+) -> Result<Vec<u8>, PekaError> {
+    match compression {
+        PayloadCompression::None => Ok(payload),
+        PayloadCompression::Deflate => {
+            let mut out = Vec::new();
+            DeflateDecoder::new(payload.as_slice())
+                .take(MAX_PAYLOAD_BYTES + 1)
+                .read_to_end(&mut out)
+                .map_err(|e| PekaError::corrupted("Vault data cannot be decompressed", e))?;
+            if out.len() as u64 > MAX_PAYLOAD_BYTES {
+                return Err(PekaError::UnsafeParameters(
+                    "Vault data decompresses to more than 256 MiB".to_string(),
+                ));
+            }
+            Ok(out)
+        }
+    }
+}
+
+fn write_field(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+fn read_u8(value: &[u8]) -> Result<u8, PekaError> {
+    match value {
+        [byte] => Ok(*byte),
+        _ => Err(corrupted("Vault container header is malformed")),
+    }
+}
+
+fn read_u32(value: &[u8]) -> Result<u32, PekaError> {
+    value
+        .try_into()
+        .map(u32::from_le_bytes)
+        .map_err(|_| corrupted("Vault container header is malformed"))
+}
+
+fn read_record(value: &[u8]) -> Result<EncryptedRecord, PekaError> {
+    let mut reader = ByteReader {
+        data: value,
+        position: 0,
+    };
+    let id_length = usize::from(reader.bytes(1)?[0]);
+    let id = read_string(reader.bytes(id_length)?)?;
+    let nonce = reader.bytes(NONCE_LENGTH)?;
+    Ok(EncryptedRecord {
+        id,
+        nonce: general_purpose::STANDARD.encode(nonce),
+        ciphertext: general_purpose::STANDARD.encode(&value[reader.position..]),
+    })
+}
+
+fn read_string(value: &[u8]) -> Result<String, PekaError> {
+    String::from_utf8(value.to_vec())
+        .map_err(|e| PekaError::corrupted("Vault container header is malformed", e))
+}
+
+fn corrupted(message: &str) -> PekaError {
+    PekaError::Corrupted {
+        message: message.to_string(),
+        details: None,
+    }
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], PekaError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| corrupted("Vault container is truncated"))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], PekaError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base64(bytes: &[u8]) -> String {
+        general_purpose::STANDARD.encode(bytes)
+    }
+
+    fn sample_file() -> VaultFile {
+        VaultFile {
+            version: 3,
+            vault_name: "Wörk".to_string(),
+            kdf: KdfParams {
+                algorithm: "Argon2id".to_string(),
+                memory_kib: 131_072,
+                time_cost: 3,
+                parallelism: 2,
+                hash_length: 32,
+                salt_length: 16,
+            },
+            compression: PayloadCompression::Deflate,
+            layout: PayloadLayout::Entries,
+            container: ContainerFormat::Binary,
+            salt: base64(&[1; 16]),
+            nonce: base64(&[2; NONCE_LENGTH]),
+            ciphertext: base64(b"index ciphertext"),
+            records: vec![EncryptedRecord {
+                id: "record".to_string(),
+                nonce: base64(&[3; NONCE_LENGTH]),
+                ciphertext: base64(b"record ciphertext"),
+            }],
+        }
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let file = sample_file();
+        let raw = encode(&file).unwrap();
+        assert!(is_binary(&raw));
+        assert!(raw.ends_with(b"index ciphertext"));
+
+        let decoded = decode(&raw).unwrap();
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(&file).unwrap()
+        );
+        assert_eq!(decoded.container, ContainerFormat::Binary);
+        assert_eq!(decoded.kdf.salt_length, 16);
+    }
+
+    #[test]
+    fn skips_unknown_fields_and_refuses_newer_containers() {
+        let raw = encode(&sample_file()).unwrap();
+        let header_start = MAGIC.len() + 2;
+
+        let mut extended = raw[..header_start].to_vec();
+        write_field(&mut extended, 200, b"from a later version");
+        extended.extend_from_slice(&raw[header_start..]);
+        assert_eq!(decode(&extended).unwrap().vault_name, "Wörk");
+
+        let mut newer = raw.clone();
+        newer[MAGIC.len()..header_start].copy_from_slice(&2u16.to_le_bytes());
+        assert!(matches!(decode(&newer), Err(PekaError::UnsupportedVersion(_))));
+
+        let mut unknown_layout = raw[..header_start].to_vec();
+        write_field(&mut unknown_layout, FIELD_LAYOUT, &[7]);
+        unknown_layout.extend_from_slice(&raw[header_start..]);
+        assert!(matches!(decode(&unknown_layout), Err(PekaError::Unsupported(_))));
+
+        assert!(matches!(
+            decode(&raw[..header_start + 20]),
+            Err(PekaError::Corrupted { .. })
+        ));
+    }
+
+    #[test]
+    fn compresses_payloads() {
+        let payload = br#"{"folders":[]}"#.repeat(100);
+        let compressed = compress(payload.clone(), PayloadCompression::Deflate).unwrap();
+        assert!(compressed.len() < payload.len() / 10);
+        assert_eq!(decompress(compressed, PayloadCompression::Deflate).unwrap(), payload);
+        assert_eq!(compress(payload.clone(), PayloadCompression::None).unwrap(), payload);
+
+        assert!(matches!(
+            decompress(b"not deflate data".to_vec(), PayloadCompression::Deflate),
+            Err(PekaError::Corrupted { .. })
+        ));
+    }
+}
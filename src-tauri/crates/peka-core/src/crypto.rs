@@ -0,0 +1,544 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose, Engine as _};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::container::{self, ContainerFormat, PayloadCompression, PayloadLayout};
+use crate::records::{self, EncryptedRecord, RecordCache};
+use crate::{PekaError, StoredFolder, StoredVault};
+
+pub(crate) const DEFAULT_MEMORY_KIB: u32 = 131_072;
+pub(crate) const DEFAULT_TIME_COST: u32 = 3;
+pub(crate) const DEFAULT_PARALLELISM: u32 = 2;
+const DEFAULT_HASH_LENGTH: u32 = 32;
+const DEFAULT_SALT_LENGTH: usize = 16;
+/// Newest vault format version. Version 2 added payload compression and version 3 the
+/// per-entry layout; vaults that use neither are still written as version 1 so older
+/// versions of the app keep opening them.
+const CURRENT_VERSION: u8 = 3;
+const COMPRESSED_VERSION: u8 = 2;
+const UNCOMPRESSED_VERSION: u8 = 1;
+
+// Vault files can come from anywhere and their header decides how much memory and time
+// deriving the key takes, so the parameters have to stay within these bounds. 1 GiB is
+// the most a desktop running the app can be expected to spare.
+const MIN_MEMORY_KIB: u32 = 19_456;
+const MAX_MEMORY_KIB: u32 = 1_048_576;
+const MAX_TIME_COST: u32 = 16;
+const MAX_PARALLELISM: u32 = 16;
+const MIN_SALT_LENGTH: usize = 16;
+const MAX_SALT_LENGTH: usize = 64;
+/// Memory, and memory times passes, above which a vault file from elsewhere is only
+/// opened after confirmation: four times what the defaults cost.
+const EXPENSIVE_MEMORY_KIB: u64 = 4 * DEFAULT_MEMORY_KIB as u64;
+const EXPENSIVE_KDF_WORK: u64 = EXPENSIVE_MEMORY_KIB * DEFAULT_TIME_COST as u64;
+/// Argon2 needs at least 8 KiB of memory per lane.
+const ARGON2_MIN_MEMORY_KIB_PER_LANE: u64 = 8;
+
+/// How demanding the key derivation of a vault file is.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum KdfCost {
+    Normal,
+    /// Within the bounds, but unusually slow or memory hungry.
+    Expensive,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KdfParams {
+    pub algorithm: String,
+    pub memory_kib: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+    pub hash_length: u32,
+    pub salt_length: u32,
+}
+
+impl KdfParams {
+    /// Checks the parameters against the bounds every vault file has to stay within.
+    pub fn cost(&self) -> Result<KdfCost, PekaError> {
+        if self.algorithm != "Argon2id" {
+            return Err(PekaError::Unsupported(format!(
+                "Key derivation algorithm '{}' is not supported",
+                self.algorithm
+            )));
+        }
+        if self.hash_length != DEFAULT_HASH_LENGTH {
+            return Err(unsafe_kdf(format!(
+                "the derived key is {} bytes instead of {}",
+                self.hash_length, DEFAULT_HASH_LENGTH
+            )));
+        }
+        if !(MIN_MEMORY_KIB..=MAX_MEMORY_KIB).contains(&self.memory_kib) {
+            return Err(unsafe_kdf(format!(
+                "memory cost of {} KiB is outside {}-{} KiB",
+                self.memory_kib, MIN_MEMORY_KIB, MAX_MEMORY_KIB
+            )));
+        }
+        argon2_cost(
+            u64::from(self.memory_kib),
+            u64::from(self.time_cost),
+            u64::from(self.parallelism),
+        )
+    }
+}
+
+/// Checks Argon2 parameters against the upper bounds and tells how demanding they are.
+/// Shared by vault files and imported KeePass databases, which both come from
+/// anywhere.
+///
+/// The lower bound on memory is left to [`KdfParams::cost`]: it keeps the vaults this
+/// app writes and reopens strong, while a KeePass database is only read once and its
+/// entries are re-encrypted with the vault's own key derivation. Refusing weak settings
+/// there would only lock users out of databases KeePass 2 creates by default, with
+/// 1 MiB of Argon2 memory.
+pub(crate) fn argon2_cost(
+    memory_kib: u64,
+    time_cost: u64,
+    parallelism: u64,
+) -> Result<KdfCost, PekaError> {
+    if !(1..=u64::from(MAX_TIME_COST)).contains(&time_cost) {
+        return Err(unsafe_kdf(format!(
+            "time cost of {} passes is outside 1-{}",
+            time_cost, MAX_TIME_COST
+        )));
+    }
+    if !(1..=u64::from(MAX_PARALLELISM)).contains(&parallelism) {
+        return Err(unsafe_kdf(format!(
+            "parallelism of {} is outside 1-{}",
+            parallelism, MAX_PARALLELISM
+        )));
+    }
+    let min_memory_kib = ARGON2_MIN_MEMORY_KIB_PER_LANE * parallelism;
+    if !(min_memory_kib..=u64::from(MAX_MEMORY_KIB)).contains(&memory_kib) {
+        return Err(unsafe_kdf(format!(
+            "memory cost of {} KiB is outside {}-{} KiB",
+            memory_kib, min_memory_kib, MAX_MEMORY_KIB
+        )));
+    }
+
+    if memory_kib > EXPENSIVE_MEMORY_KIB || memory_kib * time_cost > EXPENSIVE_KDF_WORK {
+        Ok(KdfCost::Expensive)
+    } else {
+        Ok(KdfCost::Normal)
+    }
+}
+
+pub(crate) fn unsafe_kdf(reason: String) -> PekaError {
+    PekaError::UnsafeParameters(format!("Unsafe key derivation parameters: {}", reason))
+}
+
+/// The on-disk `.peka` format: a readable header and the encrypted [`StoredVault`].
+/// Binary containers are read into the same structure.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VaultFile {
+    pub version: u8,
+    pub vault_name: String,
+    pub kdf: KdfParams,
+    #[serde(default, skip_serializing_if = "PayloadCompression::is_none")]
+    pub compression: PayloadCompression,
+    #[serde(default, skip_serializing_if = "PayloadLayout::is_single")]
+    pub layout: PayloadLayout,
+    /// Layout the file was read in or is to be written in; not part of the header.
+    #[serde(skip)]
+    pub container: ContainerFormat,
+    pub salt: String,
+    pub nonce: String,
+    /// Encrypted index in the per-entry layout, the whole payload otherwise.
+    pub ciphertext: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub records: Vec<EncryptedRecord>,
+}
+
+/// Encrypts `payload` into a vault file. With `records`, the per-entry layout is
+/// written, reusing the records and the key derived from the master password that
+/// `records` kept from the last read or write.
+pub(crate) fn encrypt_payload(
+    payload: &StoredVault,
+    master_password: &str,
+    existing_kdf: Option<&KdfParams>,
+    compression: PayloadCompression,
+    records: Option<&mut RecordCache>,
+) -> Result<VaultFile, PekaError> {
+    let (memory_kib, time_cost, parallelism, hash_length, salt_length) =
+        if let Some(kdf) = existing_kdf {
+            (
+                kdf.memory_kib,
+                kdf.time_cost,
+                kdf.parallelism,
+                kdf.hash_length,
+                kdf.salt_length as usize,
+            )
+        } else {
+            (
+                DEFAULT_MEMORY_KIB,
+                DEFAULT_TIME_COST,
+                DEFAULT_PARALLELISM,
+                DEFAULT_HASH_LENGTH,
+                DEFAULT_SALT_LENGTH,
+            )
+        };
+
+    let params = Params::new(
+        memory_kib,
+        time_cost,
+        parallelism,
+        Some(hash_length as usize),
+    )
+    .map_err(|e| PekaError::InvalidInput(format!("Invalid key derivation parameters: {}", e)))?;
+
+    let derived = records.as_deref().and_then(|cache| cache.derived.clone());
+    let (salt, encryption_key) = match derived {
+        Some(derived) => derived,
+        None => {
+            let mut salt = vec![0u8; salt_length];
+            OsRng.fill_bytes(&mut salt);
+
+            let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+            let mut encryption_key = vec![0u8; hash_length as usize];
+            argon2
+                .hash_password_into(master_password.as_bytes(), &salt, &mut encryption_key)
+                .map_err(PekaError::internal)?;
+            (salt, encryption_key)
+        }
+    };
+
+    let (layout, clear_bytes, sealed) = match records {
+        Some(cache) => {
+            let (index, sealed) = records::seal(payload, cache, compression)?;
+            cache.derived = Some((salt.clone(), encryption_key.clone()));
+            (PayloadLayout::Entries, index, sealed)
+        }
+        None => (
+            PayloadLayout::Single,
+            serde_json::to_vec(payload).map_err(PekaError::internal)?,
+            Vec::new(),
+        ),
+    };
+    let clear_bytes = container::compress(clear_bytes, compression)?;
+
+    let mut nonce_bytes = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce_bytes);
+
+    let cipher = Aes256Gcm::new_from_slice(&encryption_key).map_err(PekaError::internal)?;
+    let nonce = Nonce::from_slice(&nonce_bytes);
+    let ciphertext = cipher
+        .encrypt(nonce, clear_bytes.as_ref())
+        .map_err(PekaError::internal)?;
+
+    let version = match (layout, compression) {
+        (PayloadLayout::Entries, _) => CURRENT_VERSION,
+        (PayloadLayout::Single, PayloadCompression::Deflate) => COMPRESSED_VERSION,
+        (PayloadLayout::Single, PayloadCompression::None) => UNCOMPRESSED_VERSION,
+    };
+    Ok(VaultFile {
+        version,
+        vault_name: payload.vault_name.clone(),
+        kdf: KdfParams {
+            algorithm: "Argon2id".to_string(),
+            memory_kib,
+            time_cost,
+            parallelism,
+            hash_length,
+            salt_length: salt_length as u32,
+        },
+        compression,
+        layout,
+        container: ContainerFormat::Json,
+        salt: general_purpose::STANDARD.encode(salt),
+        nonce: general_purpose::STANDARD.encode(nonce_bytes),
+        ciphertext: general_purpose::STANDARD.encode(ciphertext),
+        records: sealed,
+    })
+}
+
+/// Reads the unencrypted header and ciphertext of the vault file at `path`.
+pub(crate) fn read_vault_file(path: &Path) -> Result<VaultFile, PekaError> {
+    let raw = fs::read(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => PekaError::NotFound("Vault file not found".to_string()),
+        _ => PekaError::io("Unable to read vault file from disk", e),
+    })?;
+    parse_vault_file(&raw)
+}
+
+/// Parses a vault file in either container format. Files of a newer format version
+/// are refused before their layout is relied on.
+pub(crate) fn parse_vault_file(raw: &[u8]) -> Result<VaultFile, PekaError> {
+    let vault_file: VaultFile = if container::is_binary(raw) {
+        container::decode(raw)?
+    } else {
+        let value: serde_json::Value = serde_json::from_slice(raw)
+            .map_err(|e| PekaError::corrupted("Vault file is corrupted or invalid", e))?;
+        if let Some(version) = value.get("version").and_then(serde_json::Value::as_u64) {
+            check_version(version)?;
+        }
+        serde_json::from_value(value)
+            .map_err(|e| PekaError::corrupted("Vault file is corrupted or invalid", e))?
+    };
+    check_version(u64::from(vault_file.version))?;
+    Ok(vault_file)
+}
+
+pub(crate) fn check_version(version: u64) -> Result<(), PekaError> {
+    if version > u64::from(CURRENT_VERSION) {
+        return Err(PekaError::UnsupportedVersion(format!(
+            "Vault format version {} is newer than this app supports ({})",
+            version, CURRENT_VERSION
+        )));
+    }
+    Ok(())
+}
+
+/// Contents of the vault file for `vault_file`, in its container format.
+pub(crate) fn serialize_vault_file(vault_file: &VaultFile) -> Result<Vec<u8>, PekaError> {
+    match vault_file.container {
+        ContainerFormat::Json => serde_json::to_vec_pretty(vault_file).map_err(PekaError::internal),
+        ContainerFormat::Binary => container::encode(vault_file),
+    }
+}
+
+/// Checks the parts of the header that can be checked without the password.
+pub(crate) fn check_header(vault_file: &VaultFile) -> Result<(), PekaError> {
+    decode_salt(vault_file)?;
+    decode_nonce(vault_file)?;
+    kdf_params(&vault_file.kdf)?;
+    Ok(())
+}
+
+pub(crate) fn decode_salt(vault_file: &VaultFile) -> Result<Vec<u8>, PekaError> {
+    let salt = general_purpose::STANDARD
+        .decode(&vault_file.salt)
+        .map_err(|e| PekaError::corrupted("Invalid salt encoding", e))?;
+    if !(MIN_SALT_LENGTH..=MAX_SALT_LENGTH).contains(&salt.len()) {
+        return Err(unsafe_kdf(format!(
+            "salt of {} bytes is outside {}-{} bytes",
+            salt.len(),
+            MIN_SALT_LENGTH,
+            MAX_SALT_LENGTH
+        )));
+    }
+    Ok(salt)
+}
+
+pub(crate) fn decode_nonce(vault_file: &VaultFile) -> Result<Vec<u8>, PekaError> {
+    let nonce_bytes = general_purpose::STANDARD
+        .decode(&vault_file.nonce)
+        .map_err(|e| PekaError::corrupted("Invalid nonce encoding", e))?;
+    if nonce_bytes.len() != 12 {
+        return Err(PekaError::Corrupted {
+            message: "Invalid nonce encoding".to_string(),
+            details: Some(format!("expected 12 bytes, found {}", nonce_bytes.len())),
+        });
+    }
+    Ok(nonce_bytes)
+}
+
+pub(crate) fn decode_ciphertext(vault_file: &VaultFile) -> Result<Vec<u8>, PekaError> {
+    general_purpose::STANDARD
+        .decode(&vault_file.ciphertext)
+        .map_err(|e| PekaError::corrupted("Invalid ciphertext encoding", e))
+}
+
+/// Argon2 parameters of `kdf`, which must be within the bounds of [`KdfParams::cost`].
+pub(crate) fn kdf_params(kdf: &KdfParams) -> Result<Params, PekaError> {
+    kdf.cost()?;
+    Params::new(
+        kdf.memory_kib,
+        kdf.time_cost,
+        kdf.parallelism,
+        Some(kdf.hash_length as usize),
+    )
+    .map_err(|e| PekaError::corrupted("Vault key derivation parameters are invalid", e))
+}
+
+pub(crate) fn decrypt_vault(
+    path: &Path,
+    master_password: &str,
+) -> Result<(VaultFile, StoredVault, RecordCache), PekaError> {
+    let vault_file = read_vault_file(path)?;
+    let (decrypted, records) = decrypt_records(&vault_file, master_password)?;
+    let payload: StoredVault = serde_json::from_slice(&decrypted)
+        .map_err(|e| PekaError::corrupted("Vault data is malformed", e))?;
+
+    Ok((vault_file, payload, records))
+}
+
+/// Decrypts and decompresses the payload of `vault_file` without parsing it. The
+/// records of a per-entry vault are joined into the JSON of the whole payload.
+pub(crate) fn decrypt_bytes(
+    vault_file: &VaultFile,
+    master_password: &str,
+) -> Result<Vec<u8>, PekaError> {
+    decrypt_records(vault_file, master_password).map(|(decrypted, _)| decrypted)
+}
+
+fn decrypt_records(
+    vault_file: &VaultFile,
+    master_password: &str,
+) -> Result<(Vec<u8>, RecordCache), PekaError> {
+    let salt = decode_salt(vault_file)?;
+    let nonce_bytes = decode_nonce(vault_file)?;
+    let ciphertext = decode_ciphertext(vault_file)?;
+
+    let params = kdf_params(&vault_file.kdf)?;
+
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    let mut encryption_key = vec![0u8; vault_file.kdf.hash_length as usize];
+    argon2
+        .hash_password_into(master_password.as_bytes(), &salt, &mut encryption_key)
+        .map_err(|e| PekaError::corrupted("Unable to derive encryption key", e))?;
+
+    let cipher = Aes256Gcm::new_from_slice(&encryption_key)
+        .map_err(|e| PekaError::corrupted("Vault key derivation parameters are invalid", e))?;
+    let nonce = Nonce::from_slice(&nonce_bytes);
+    let decrypted = cipher
+        .decrypt(nonce, ciphertext.as_ref())
+        .map_err(|_| PekaError::WrongPassword)?;
+    let decrypted = container::decompress(decrypted, vault_file.compression)?;
+
+    let mut records = RecordCache::default();
+    let decrypted = match vault_file.layout {
+        PayloadLayout::Single => decrypted,
+        PayloadLayout::Entries => records::open(
+            &decrypted,
+            &vault_file.records,
+            vault_file.compression,
+            &mut records,
+        )?,
+    };
+    records.derived = Some((salt, encryption_key));
+    Ok((decrypted, records))
+}
+
+pub(crate) fn hash_pin(pin: &str) -> Result<String, PekaError> {
+    let salt = SaltString::generate(&mut OsRng);
+    let argon = Argon2::default();
+    Ok(argon
+        .hash_password(pin.as_bytes(), &salt)
+        .map_err(PekaError::internal)?
+        .to_string())
+}
+
+pub(crate) fn folder_pin_matches(folder: &StoredFolder, pin: &str) -> Result<bool, PekaError> {
+    if !folder.secure {
+        return Ok(true); // Non-secure folders don't need PIN
+    }
+
+    let pin_hash = folder
+        .pin_hash
+        .as_ref()
+        .ok_or_else(|| PekaError::Corrupted {
+            message: "Folder PIN hash not found".to_string(),
+            details: None,
+        })?;
+
+    let parsed_hash = PasswordHash::new(pin_hash)
+        .map_err(|e| PekaError::corrupted("Folder PIN hash is malformed", e))?;
+    let argon2 = Argon2::default();
+
+    Ok(argon2.verify_password(pin.as_bytes(), &parsed_hash).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir, MASTER_PASSWORD};
+
+    fn kdf(memory_kib: u32, time_cost: u32) -> KdfParams {
+        KdfParams {
+            algorithm: "Argon2id".to_string(),
+            memory_kib,
+            time_cost,
+            parallelism: DEFAULT_PARALLELISM,
+            hash_length: DEFAULT_HASH_LENGTH,
+            salt_length: DEFAULT_SALT_LENGTH as u32,
+        }
+    }
+
+    #[test]
+    fn bounds_vault_key_derivation() {
+        let cost = |memory_kib, time_cost| kdf(memory_kib, time_cost).cost();
+        assert_eq!(cost(DEFAULT_MEMORY_KIB, DEFAULT_TIME_COST).unwrap(), KdfCost::Normal);
+        assert!(matches!(cost(MIN_MEMORY_KIB - 1, 3), Err(PekaError::UnsafeParameters(_))));
+        assert!(matches!(cost(MAX_MEMORY_KIB + 1, 1), Err(PekaError::UnsafeParameters(_))));
+        assert!(matches!(cost(DEFAULT_MEMORY_KIB, 0), Err(PekaError::UnsafeParameters(_))));
+        assert!(matches!(
+            cost(DEFAULT_MEMORY_KIB, MAX_TIME_COST + 1),
+            Err(PekaError::UnsafeParameters(_))
+        ));
+        // Memory alone is enough to ask first.
+        assert_eq!(cost(MAX_MEMORY_KIB, 1).unwrap(), KdfCost::Expensive);
+        assert_eq!(cost(DEFAULT_MEMORY_KIB, MAX_TIME_COST).unwrap(), KdfCost::Expensive);
+
+        let mut scrypt = kdf(DEFAULT_MEMORY_KIB, DEFAULT_TIME_COST);
+        scrypt.algorithm = "scrypt".to_string();
+        assert!(matches!(scrypt.cost(), Err(PekaError::Unsupported(_))));
+    }
+
+    #[test]
+    fn bounds_imported_argon2_parameters() {
+        // KeePass 2 defaults, below the vault minimum but fine to read once.
+        assert_eq!(argon2_cost(1024, 2, 2).unwrap(), KdfCost::Normal);
+        assert!(matches!(argon2_cost(8, 2, 2), Err(PekaError::UnsafeParameters(_))));
+        assert!(matches!(argon2_cost(0, 2, 1), Err(PekaError::UnsafeParameters(_))));
+        assert!(matches!(
+            argon2_cost(u64::from(MAX_MEMORY_KIB) + 1, 1, 1),
+            Err(PekaError::UnsafeParameters(_))
+        ));
+        assert!(matches!(argon2_cost(65_536, 2, 0), Err(PekaError::UnsafeParameters(_))));
+    }
+
+    #[test]
+    fn decrypts_every_format_version() {
+        let dir = TempDir::new();
+        let (vault, _, _) = testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let payload = vault.payload().clone();
+
+        let formats = [
+            (PayloadCompression::None, false, UNCOMPRESSED_VERSION),
+            (PayloadCompression::Deflate, false, COMPRESSED_VERSION),
+            (PayloadCompression::Deflate, true, CURRENT_VERSION),
+        ];
+        for (compression, per_entry, version) in formats {
+            let mut records = RecordCache::default();
+            let mut vault_file = encrypt_payload(
+                &payload,
+                MASTER_PASSWORD,
+                None,
+                compression,
+                per_entry.then_some(&mut records),
+            )
+            .unwrap();
+            assert_eq!(vault_file.version, version);
+
+            for container in [ContainerFormat::Json, ContainerFormat::Binary] {
+                vault_file.container = container;
+                let raw = serialize_vault_file(&vault_file).unwrap();
+                let read = parse_vault_file(&raw).unwrap();
+                assert_eq!(read.layout, vault_file.layout);
+
+                let decrypted: StoredVault =
+                    serde_json::from_slice(&decrypt_bytes(&read, MASTER_PASSWORD).unwrap())
+                        .unwrap();
+                assert_eq!(
+                    serde_json::to_value(&decrypted).unwrap(),
+                    serde_json::to_value(&payload).unwrap(),
+                    "version {} in {:?}",
+                    version,
+                    container
+                );
+            }
+
+            assert!(matches!(
+                decrypt_bytes(&vault_file, "wrong password"),
+                Err(PekaError::WrongPassword)
+            ));
+        }
+    }
+}
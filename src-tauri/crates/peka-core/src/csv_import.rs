@@ -0,0 +1,492 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::sync::RevisionVector;
+use crate::{PekaError, StoredCredential, StoredFolder, StoredVault, VaultContents};
+
+const DEFAULT_IMPORT_FOLDER: &str = "Imported";
+
+/// Password managers whose CSV exports can be imported without a custom mapping.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CsvSource {
+    Bitwarden,
+    #[serde(rename = "1password")]
+    OnePassword,
+    KeePassXc,
+    LastPass,
+    Chrome,
+    Generic,
+}
+
+impl CsvSource {
+    fn label(self) -> &'static str {
+        match self {
+            CsvSource::Bitwarden => "Bitwarden",
+            CsvSource::OnePassword => "1Password",
+            CsvSource::KeePassXc => "KeePassXC",
+            CsvSource::LastPass => "LastPass",
+            CsvSource::Chrome => "Chrome",
+            CsvSource::Generic => "CSV",
+        }
+    }
+
+    fn mapping(self) -> CsvColumnMapping {
+        let column = |name: &str| Some(name.to_string());
+        match self {
+            CsvSource::Bitwarden => CsvColumnMapping {
+                title: column("name"),
+                username: column("login_username"),
+                password: column("login_password"),
+                url: column("login_uri"),
+                notes: column("notes"),
+                totp: column("login_totp"),
+                folder: column("folder"),
+            },
+            CsvSource::OnePassword => CsvColumnMapping {
+                title: column("title"),
+                username: column("username"),
+                password: column("password"),
+                url: column("url"),
+                notes: column("notes"),
+                totp: column("otpauth"),
+                folder: None,
+            },
+            CsvSource::KeePassXc => CsvColumnMapping {
+                title: column("title"),
+                username: column("username"),
+                password: column("password"),
+                url: column("url"),
+                notes: column("notes"),
+                totp: column("totp"),
+                folder: column("group"),
+            },
+            CsvSource::LastPass => CsvColumnMapping {
+                title: column("name"),
+                username: column("username"),
+                password: column("password"),
+                url: column("url"),
+                notes: column("extra"),
+                totp: column("totp"),
+                folder: column("grouping"),
+            },
+            CsvSource::Chrome => CsvColumnMapping {
+                title: column("name"),
+                username: column("username"),
+                password: column("password"),
+                url: column("url"),
+                notes: column("note"),
+                totp: None,
+                folder: None,
+            },
+            // The columns written by `export::to_csv`.
+            CsvSource::Generic => CsvColumnMapping {
+                title: column("title"),
+                username: column("username"),
+                password: column("password"),
+                url: column("url"),
+                notes: column("notes"),
+                totp: column("totp"),
+                folder: column("folder"),
+            },
+        }
+    }
+}
+
+/// Header names of the columns holding each credential field. Replaces the whole
+/// mapping in generic mode and overrides the preset of a known source.
+#[derive(Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CsvColumnMapping {
+    pub title: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub url: Option<String>,
+    pub notes: Option<String>,
+    pub totp: Option<String>,
+    pub folder: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvPreviewEntry {
+    pub line: u64,
+    pub folder: String,
+    pub title: String,
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub has_notes: bool,
+    pub has_totp: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvSkippedRow {
+    pub line: u64,
+    pub reason: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvImportResult {
+    pub dry_run: bool,
+    pub imported: usize,
+    pub folders_created: Vec<String>,
+    pub entries: Vec<CsvPreviewEntry>,
+    pub skipped: Vec<CsvSkippedRow>,
+    /// Updated vault contents; only present when the import was written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vault: Option<VaultContents>,
+}
+
+pub struct CsvEntry {
+    line: u64,
+    folder: String,
+    title: String,
+    username: String,
+    password: String,
+    url: Option<String>,
+    notes: Option<String>,
+    totp: Option<String>,
+}
+
+pub struct ParsedCsv {
+    pub entries: Vec<CsvEntry>,
+    pub skipped: Vec<CsvSkippedRow>,
+}
+
+struct ColumnIndexes {
+    title: Option<usize>,
+    username: Option<usize>,
+    password: usize,
+    url: Option<usize>,
+    notes: Option<usize>,
+    totp: Option<usize>,
+    folder: Option<usize>,
+}
+
+/// Parses a CSV export. `overrides` replaces individual columns of the source preset;
+/// in generic mode it is the whole mapping and must at least name the password column.
+/// Without it, generic mode reads the columns of PEKA's own CSV export.
+pub fn parse_csv(
+    raw: &[u8],
+    source: CsvSource,
+    overrides: Option<CsvColumnMapping>,
+) -> Result<ParsedCsv, PekaError> {
+    let raw = raw.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(raw);
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::Headers)
+        .from_reader(raw);
+
+    let headers: HashMap<String, usize> = reader
+        .headers()
+        .map_err(|e| PekaError::corrupted("CSV file has no header row", e))?
+        .iter()
+        .enumerate()
+        .map(|(index, name)| (name.to_lowercase(), index))
+        .collect();
+
+    let preset = source.mapping();
+    let mapping = match overrides {
+        None => preset,
+        Some(overrides) if source == CsvSource::Generic => overrides,
+        Some(overrides) => CsvColumnMapping {
+            title: overrides.title.or(preset.title),
+            username: overrides.username.or(preset.username),
+            password: overrides.password.or(preset.password),
+            url: overrides.url.or(preset.url),
+            notes: overrides.notes.or(preset.notes),
+            totp: overrides.totp.or(preset.totp),
+            folder: overrides.folder.or(preset.folder),
+        },
+    };
+
+    let find = |name: &str| -> Result<usize, PekaError> {
+        headers
+            .get(&name.trim().to_lowercase())
+            .copied()
+            .ok_or_else(|| {
+                PekaError::InvalidInput(match source {
+                    CsvSource::Generic => format!("Column '{}' not found in CSV header", name),
+                    _ => format!(
+                        "CSV file does not look like a {} export (missing column '{}')",
+                        source.label(),
+                        name
+                    ),
+                })
+            })
+    };
+    let lookup = |column: &Option<String>| -> Result<Option<usize>, PekaError> {
+        column.as_deref().map(find).transpose()
+    };
+
+    let password_column = mapping
+        .password
+        .as_deref()
+        .ok_or_else(|| PekaError::InvalidInput("A password column is required".to_string()))?;
+
+    let columns = ColumnIndexes {
+        title: lookup(&mapping.title)?,
+        username: lookup(&mapping.username)?,
+        password: find(password_column)?,
+        url: lookup(&mapping.url)?,
+        notes: lookup(&mapping.notes)?,
+        totp: lookup(&mapping.totp)?,
+        folder: lookup(&mapping.folder)?,
+    };
+
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+
+    for record in reader.records() {
+        let record = record.map_err(|e| PekaError::corrupted("Invalid CSV data", e))?;
+        let line = record.position().map(|pos| pos.line()).unwrap_or_default();
+        let field = |index: Option<usize>| -> Option<String> {
+            index
+                .and_then(|index| record.get(index))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        // Bitwarden exports secure notes and cards alongside logins.
+        if source == CsvSource::Bitwarden {
+            let entry_type = headers.get("type").and_then(|index| record.get(*index));
+            if entry_type.is_some_and(|kind| !kind.is_empty() && kind != "login") {
+                skipped.push(CsvSkippedRow {
+                    line,
+                    reason: "Not a login entry".to_string(),
+                });
+                continue;
+            }
+        }
+
+        let Some(password) = record.get(columns.password).filter(|p| !p.is_empty()) else {
+            skipped.push(CsvSkippedRow {
+                line,
+                reason: "Password is empty".to_string(),
+            });
+            continue;
+        };
+
+        let username = field(columns.username).unwrap_or_default();
+        let url = field(columns.url);
+        let Some(title) = field(columns.title)
+            .or_else(|| url.clone())
+            .or_else(|| (!username.is_empty()).then(|| username.clone()))
+        else {
+            skipped.push(CsvSkippedRow {
+                line,
+                reason: "Entry has no title, URL or username".to_string(),
+            });
+            continue;
+        };
+
+        entries.push(CsvEntry {
+            line,
+            folder: field(columns.folder)
+                .map(|folder| normalize_folder(source, &folder))
+                .filter(|folder| !folder.is_empty())
+                .unwrap_or_else(|| DEFAULT_IMPORT_FOLDER.to_string()),
+            title,
+            username,
+            password: password.to_string(),
+            url,
+            notes: field(columns.notes),
+            totp: field(columns.totp),
+        });
+    }
+
+    Ok(ParsedCsv { entries, skipped })
+}
+
+/// Adds `entries` to `payload`, reusing folders with the same name and creating the
+/// rest. Returns the names of the created folders.
+pub fn apply_entries(payload: &mut StoredVault, entries: &[CsvEntry]) -> Vec<String> {
+    let now = Utc::now().to_rfc3339();
+    let mut folders_created = Vec::new();
+
+    for entry in entries {
+        let position = match payload
+            .folders
+            .iter()
+            .position(|folder| folder.name == entry.folder)
+        {
+            Some(position) => position,
+            None => {
+                payload.folders.push(StoredFolder {
+                    id: Uuid::new_v4().to_string(),
+                    name: entry.folder.clone(),
+                    secure: false,
+                    pin_hash: None,
+                    credentials: Vec::new(),
+                    revision: RevisionVector::default(),
+                    created_at: now.clone(),
+                    updated_at: now.clone(),
+                });
+                folders_created.push(entry.folder.clone());
+                payload.folders.len() - 1
+            }
+        };
+
+        let folder = &mut payload.folders[position];
+        folder.credentials.push(StoredCredential {
+            id: Uuid::new_v4().to_string(),
+            title: entry.title.clone(),
+            username: entry.username.clone(),
+            password: entry.password.clone(),
+            url: entry.url.clone(),
+            notes: entry.notes.clone(),
+            totp: entry.totp.clone(),
+            custom_fields: Vec::new(),
+            attachments: Vec::new(),
+            tags: Vec::new(),
+            favorite: false,
+            revision: RevisionVector::default(),
+            created_at: now.clone(),
+            updated_at: now.clone(),
+        });
+        folder.updated_at = now.clone();
+    }
+
+    folders_created
+}
+
+pub fn preview_entries(entries: &[CsvEntry]) -> Vec<CsvPreviewEntry> {
+    entries
+        .iter()
+        .map(|entry| CsvPreviewEntry {
+            line: entry.line,
+            folder: entry.folder.clone(),
+            title: entry.title.clone(),
+            username: entry.username.clone(),
+            url: entry.url.clone(),
+            has_notes: entry.notes.is_some(),
+            has_totp: entry.totp.is_some(),
+        })
+        .collect()
+}
+
+fn normalize_folder(source: CsvSource, folder: &str) -> String {
+    match source {
+        // KeePassXC prefixes every group path with the database root group.
+        CsvSource::KeePassXc => folder
+            .strip_prefix("Root/")
+            .or_else(|| folder.strip_prefix("Root"))
+            .unwrap_or(folder)
+            .trim()
+            .to_string(),
+        // LastPass nests groups with backslashes.
+        CsvSource::LastPass => folder.replace('\\', "/"),
+        _ => folder.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export;
+    use crate::testing::{self, TempDir};
+
+    fn overrides(title: &str, username: &str, password: &str) -> CsvColumnMapping {
+        CsvColumnMapping {
+            title: Some(title.to_string()),
+            username: Some(username.to_string()),
+            password: Some(password.to_string()),
+            ..CsvColumnMapping::default()
+        }
+    }
+
+    #[test]
+    fn reads_a_peka_export_without_a_mapping() {
+        let dir = TempDir::new();
+        let (vault, _, _) = testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let csv = export::to_csv(vault.payload()).unwrap();
+
+        let parsed = parse_csv(&csv, CsvSource::Generic, None).unwrap();
+        let [entry] = parsed.entries.as_slice() else {
+            panic!("expected one entry");
+        };
+        assert_eq!(entry.folder, "Email");
+        assert_eq!(entry.title, "Mail");
+        assert_eq!(entry.username, "alice");
+        assert_eq!(entry.password, "first-password");
+    }
+
+    #[test]
+    fn uses_only_the_given_columns_in_generic_mode() {
+        let csv = b"Site,Login,Secret\nmail.example.com,bob,hunter2\n";
+
+        let parsed = parse_csv(
+            csv,
+            CsvSource::Generic,
+            Some(overrides("Site", "Login", "Secret")),
+        )
+        .unwrap();
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(parsed.entries[0].title, "mail.example.com");
+        assert_eq!(parsed.entries[0].folder, DEFAULT_IMPORT_FOLDER);
+
+        let Err(PekaError::InvalidInput(message)) = parse_csv(csv, CsvSource::Generic, None)
+        else {
+            panic!("read a CSV file without its columns");
+        };
+        assert_eq!(message, "Column 'title' not found in CSV header");
+    }
+
+    #[test]
+    fn maps_a_bitwarden_export() {
+        let csv = "\u{feff}folder,favorite,type,name,notes,fields,reprompt,login_uri,\
+                   login_username,login_password,login_totp\n\
+                   Mail,,login,Mail,,,0,https://mail.example.com,alice,secret,\n\
+                   ,,note,Recovery codes,1234,,0,,,,\n\
+                   ,,login,Empty,,,0,,bob,,\n";
+
+        let parsed = parse_csv(csv.as_bytes(), CsvSource::Bitwarden, None).unwrap();
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(parsed.entries[0].folder, "Mail");
+        assert_eq!(parsed.entries[0].url.as_deref(), Some("https://mail.example.com"));
+        let reasons: Vec<&str> = parsed.skipped.iter().map(|row| row.reason.as_str()).collect();
+        assert_eq!(reasons, ["Not a login entry", "Password is empty"]);
+    }
+
+    #[test]
+    fn overrides_single_columns_of_a_preset() {
+        let csv = b"name,url,username,password,comment\nMail,,alice,secret,Work account\n";
+
+        let Err(PekaError::InvalidInput(message)) = parse_csv(csv, CsvSource::Chrome, None) else {
+            panic!("read a Chrome export without a note column");
+        };
+        assert!(message.contains("Chrome export"));
+
+        let mapping = CsvColumnMapping {
+            notes: Some("comment".to_string()),
+            ..CsvColumnMapping::default()
+        };
+        let parsed = parse_csv(csv, CsvSource::Chrome, Some(mapping)).unwrap();
+        assert_eq!(parsed.entries[0].notes.as_deref(), Some("Work account"));
+    }
+
+    #[test]
+    fn previews_an_import_without_changing_the_vault() {
+        let dir = TempDir::new();
+        let (mut vault, _, _) =
+            testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let csv = b"folder,title,username,password,url,notes,totp\n,Bank,alice,secret,,,\n";
+
+        let preview = vault.import_csv(csv, CsvSource::Generic, None, true).unwrap();
+        assert!(preview.dry_run && preview.vault.is_none());
+        assert_eq!(preview.imported, 1);
+        assert_eq!(preview.folders_created, [DEFAULT_IMPORT_FOLDER]);
+        assert_eq!(preview.entries[0].title, "Bank");
+        assert_eq!(vault.payload().folders.len(), 1);
+
+        let result = vault.import_csv(csv, CsvSource::Generic, None, false).unwrap();
+        assert_eq!(result.folders_created, [DEFAULT_IMPORT_FOLDER]);
+        assert_eq!(vault.payload().folders.len(), 2);
+    }
+}
@@ -0,0 +1,137 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+use std::io;
+
+/// Error of every vault operation. The frontend receives it as
+/// `{ code, message, details }`: `code` is stable and meant for branching and
+/// localization, `message` is English text for display and `details` carries the
+/// underlying cause when there is one.
+#[derive(Debug)]
+pub enum PekaError {
+    /// The password does not decrypt the data. AES-GCM cannot tell a wrong key from
+    /// modified ciphertext, so this also covers a damaged vault body.
+    WrongPassword,
+    /// A file or value could not be decoded.
+    Corrupted {
+        message: String,
+        details: Option<String>,
+    },
+    /// Data decoded but failed an integrity check, such as the audit chain or an
+    /// attachment digest.
+    Tampered(String),
+    NotFound(String),
+    AlreadyExists(String),
+    IoError {
+        message: String,
+        source: io::Error,
+    },
+    /// Input was refused by a security rule: password strength, PIN format, size
+    /// limits or an unacknowledged plaintext export.
+    PolicyViolation(String),
+    /// A secure folder was accessed with a wrong PIN, or another process has the vault
+    /// open.
+    Locked(String),
+    /// Input is missing or malformed.
+    InvalidInput(String),
+    /// The file was written in a newer format or container version than this one.
+    UnsupportedVersion(String),
+    /// The data uses a feature this version does not handle.
+    Unsupported(String),
+    /// A file asks for settings outside safe bounds, such as key derivation that
+    /// would exhaust memory or CPU.
+    UnsafeParameters(String),
+    /// The operation is valid but unusually costly and needs the caller's go-ahead.
+    ConfirmationRequired(String),
+    /// A failure that valid input should never cause.
+    Internal(String),
+}
+
+impl PekaError {
+    pub fn corrupted(message: impl Into<String>, cause: impl fmt::Display) -> Self {
+        PekaError::Corrupted {
+            message: message.into(),
+            details: Some(cause.to_string()),
+        }
+    }
+
+    pub fn io(message: impl Into<String>, source: io::Error) -> Self {
+        PekaError::IoError {
+            message: message.into(),
+            source,
+        }
+    }
+
+    pub fn internal(cause: impl fmt::Display) -> Self {
+        PekaError::Internal(cause.to_string())
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            PekaError::WrongPassword => "wrongPassword",
+            PekaError::Corrupted { .. } => "corrupted",
+            PekaError::Tampered(_) => "tampered",
+            PekaError::NotFound(_) => "notFound",
+            PekaError::AlreadyExists(_) => "alreadyExists",
+            PekaError::IoError { .. } => "ioError",
+            PekaError::PolicyViolation(_) => "policyViolation",
+            PekaError::Locked(_) => "locked",
+            PekaError::InvalidInput(_) => "invalidInput",
+            PekaError::UnsupportedVersion(_) => "unsupportedVersion",
+            PekaError::Unsupported(_) => "unsupported",
+            PekaError::UnsafeParameters(_) => "unsafeParameters",
+            PekaError::ConfirmationRequired(_) => "confirmationRequired",
+            PekaError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn details(&self) -> Option<String> {
+        match self {
+            PekaError::Corrupted { details, .. } => details.clone(),
+            PekaError::IoError { source, .. } => Some(source.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PekaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PekaError::WrongPassword => {
+                f.write_str("Failed to decrypt: incorrect password or corrupted data")
+            }
+            PekaError::Corrupted { message, .. } | PekaError::IoError { message, .. } => {
+                f.write_str(message)
+            }
+            PekaError::Tampered(message)
+            | PekaError::NotFound(message)
+            | PekaError::AlreadyExists(message)
+            | PekaError::PolicyViolation(message)
+            | PekaError::Locked(message)
+            | PekaError::InvalidInput(message)
+            | PekaError::UnsupportedVersion(message)
+            | PekaError::Unsupported(message)
+            | PekaError::UnsafeParameters(message)
+            | PekaError::ConfirmationRequired(message)
+            | PekaError::Internal(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for PekaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PekaError::IoError { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for PekaError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("PekaError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("details", &self.details())?;
+        state.end()
+    }
+}
@@ -0,0 +1,359 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{payload_to_public, PekaError, StoredVault, VaultContents};
+
+/// Output formats of [`crate::Vault::export`]. Every format except `Encrypted` writes
+/// secrets in plaintext and has to be acknowledged explicitly.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Json,
+    Csv,
+    Bitwarden,
+    Encrypted,
+}
+
+impl ExportFormat {
+    pub fn is_plaintext(self) -> bool {
+        self != ExportFormat::Encrypted
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportResult {
+    pub path: String,
+    pub folders: usize,
+    pub credentials: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonExport {
+    exported_at: String,
+    #[serde(flatten)]
+    contents: VaultContents,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenExport {
+    encrypted: bool,
+    folders: Vec<BitwardenFolder>,
+    items: Vec<BitwardenItem>,
+}
+
+#[derive(Serialize)]
+struct BitwardenFolder {
+    id: String,
+    name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenItem {
+    id: String,
+    organization_id: Option<String>,
+    folder_id: String,
+    #[serde(rename = "type")]
+    item_type: u8,
+    reprompt: u8,
+    name: String,
+    notes: Option<String>,
+    favorite: bool,
+    fields: Vec<BitwardenField>,
+    login: BitwardenLogin,
+    collection_ids: Option<Vec<String>>,
+    revision_date: String,
+    creation_date: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenField {
+    name: String,
+    value: String,
+    #[serde(rename = "type")]
+    field_type: u8,
+    linked_id: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct BitwardenLogin {
+    uris: Vec<BitwardenUri>,
+    username: String,
+    password: String,
+    totp: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BitwardenUri {
+    #[serde(rename = "match")]
+    match_type: Option<u8>,
+    uri: String,
+}
+
+const BITWARDEN_LOGIN: u8 = 1;
+const BITWARDEN_FIELD_TEXT: u8 = 0;
+const BITWARDEN_FIELD_HIDDEN: u8 = 1;
+
+/// Returns a copy of `payload` limited to `folder_ids`, or the whole vault when no
+/// folders are given.
+pub fn select_folders(
+    payload: &StoredVault,
+    folder_ids: Option<&[String]>,
+) -> Result<StoredVault, PekaError> {
+    let Some(folder_ids) = folder_ids.filter(|ids| !ids.is_empty()) else {
+        return Ok(payload.clone());
+    };
+
+    if let Some(missing) = folder_ids
+        .iter()
+        .find(|id| !payload.folders.iter().any(|folder| &folder.id == *id))
+    {
+        return Err(PekaError::NotFound(format!("Folder not found: {}", missing)));
+    }
+
+    Ok(StoredVault {
+        vault_name: payload.vault_name.clone(),
+        folders: payload
+            .folders
+            .iter()
+            .filter(|folder| folder_ids.contains(&folder.id))
+            .cloned()
+            .collect(),
+        trash: Vec::new(),
+        trash_retention_days: payload.trash_retention_days,
+        audit_log: Vec::new(),
+    })
+}
+
+pub fn to_json(payload: &StoredVault) -> Result<Vec<u8>, PekaError> {
+    let export = JsonExport {
+        exported_at: Utc::now().to_rfc3339(),
+        contents: payload_to_public(payload),
+    };
+    serde_json::to_vec_pretty(&export).map_err(PekaError::internal)
+}
+
+pub fn to_csv(payload: &StoredVault) -> Result<Vec<u8>, PekaError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(["folder", "title", "username", "password", "url", "notes", "totp"])
+        .map_err(PekaError::internal)?;
+
+    for folder in &payload.folders {
+        for cred in &folder.credentials {
+            writer
+                .write_record([
+                    folder.name.as_str(),
+                    cred.title.as_str(),
+                    cred.username.as_str(),
+                    cred.password.as_str(),
+                    cred.url.as_deref().unwrap_or(""),
+                    cred.notes.as_deref().unwrap_or(""),
+                    cred.totp.as_deref().unwrap_or(""),
+                ])
+                .map_err(PekaError::internal)?;
+        }
+    }
+
+    writer.into_inner().map_err(PekaError::internal)
+}
+
+/// Writes the unencrypted JSON format accepted by Bitwarden's importer.
+pub fn to_bitwarden_json(payload: &StoredVault) -> Result<Vec<u8>, PekaError> {
+    let folders = payload
+        .folders
+        .iter()
+        .map(|folder| BitwardenFolder {
+            id: folder.id.clone(),
+            name: folder.name.clone(),
+        })
+        .collect();
+
+    let items = payload
+        .folders
+        .iter()
+        .flat_map(|folder| {
+            folder.credentials.iter().map(move |cred| BitwardenItem {
+                id: cred.id.clone(),
+                organization_id: None,
+                folder_id: folder.id.clone(),
+                item_type: BITWARDEN_LOGIN,
+                // Secure folders are PIN protected here; ask for the master password there.
+                reprompt: folder.secure as u8,
+                name: cred.title.clone(),
+                notes: cred.notes.clone(),
+                favorite: cred.favorite,
+                fields: cred
+                    .custom_fields
+                    .iter()
+                    .map(|field| BitwardenField {
+                        name: field.name.clone(),
+                        value: field.value.clone(),
+                        field_type: if field.hidden {
+                            BITWARDEN_FIELD_HIDDEN
+                        } else {
+                            BITWARDEN_FIELD_TEXT
+                        },
+                        linked_id: None,
+                    })
+                    .collect(),
+                login: BitwardenLogin {
+                    uris: cred
+                        .url
+                        .iter()
+                        .map(|url| BitwardenUri {
+                            match_type: None,
+                            uri: url.clone(),
+                        })
+                        .collect(),
+                    username: cred.username.clone(),
+                    password: cred.password.clone(),
+                    totp: cred.totp.clone(),
+                },
+                collection_ids: None,
+                revision_date: cred.updated_at.clone(),
+                creation_date: cred.created_at.clone(),
+            })
+        })
+        .collect();
+
+    let export = BitwardenExport {
+        encrypted: false,
+        folders,
+        items,
+    };
+    serde_json::to_vec_pretty(&export).map_err(PekaError::internal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir, MASTER_PASSWORD};
+    use crate::CustomField;
+    use serde_json::Value;
+
+    /// A vault with a PIN protected "Email" folder holding one credential, and an
+    /// empty "Bank" folder.
+    fn sample_payload(dir: &TempDir) -> StoredVault {
+        let (vault, _, _) = testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let mut payload = vault.payload().clone();
+        let email = &mut payload.folders[0];
+        email.secure = true;
+        email.pin_hash = Some("pin-hash".to_string());
+        let credential = &mut email.credentials[0];
+        credential.url = Some("https://mail.example.com".to_string());
+        credential.notes = Some("Line one\nline \"two\", with a comma".to_string());
+        credential.custom_fields.push(CustomField {
+            name: "Recovery".to_string(),
+            value: "hidden-code".to_string(),
+            hidden: true,
+        });
+
+        let mut bank = payload.folders[0].clone();
+        bank.id = "bank".to_string();
+        bank.name = "Bank".to_string();
+        bank.secure = false;
+        bank.pin_hash = None;
+        bank.credentials.clear();
+        payload.folders.push(bank);
+        payload
+    }
+
+    #[test]
+    fn selects_folders_without_trash_or_audit_log() {
+        let dir = TempDir::new();
+        let payload = sample_payload(&dir);
+        assert!(!payload.audit_log.is_empty());
+
+        let whole = select_folders(&payload, Some(&[])).unwrap();
+        assert_eq!(whole.folders.len(), 2);
+
+        let selection = select_folders(&payload, Some(&["bank".to_string()])).unwrap();
+        let names: Vec<&str> = selection.folders.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["Bank"]);
+        assert!(selection.trash.is_empty() && selection.audit_log.is_empty());
+
+        assert!(matches!(
+            select_folders(&payload, Some(&["bank".to_string(), "missing".to_string()])),
+            Err(PekaError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn writes_csv_with_quoted_notes() {
+        let dir = TempDir::new();
+        let csv = to_csv(&sample_payload(&dir)).unwrap();
+
+        let mut reader = csv::Reader::from_reader(csv.as_slice());
+        let headers: Vec<String> = reader.headers().unwrap().iter().map(String::from).collect();
+        assert_eq!(headers, ["folder", "title", "username", "password", "url", "notes", "totp"]);
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        let [row] = rows.as_slice() else {
+            panic!("expected one row");
+        };
+        assert_eq!(&row[0], "Email");
+        assert_eq!(&row[3], "first-password");
+        assert_eq!(&row[5], "Line one\nline \"two\", with a comma");
+        assert_eq!(&row[6], "");
+    }
+
+    #[test]
+    fn writes_json_without_pin_hashes() {
+        let dir = TempDir::new();
+        let json: Value = serde_json::from_slice(&to_json(&sample_payload(&dir)).unwrap()).unwrap();
+
+        assert!(json["exportedAt"].is_string());
+        assert_eq!(json["vaultName"], "Work");
+        let credential = &json["folders"][0]["credentials"][0];
+        assert_eq!(credential["password"], "first-password");
+        assert_eq!(credential["customFields"][0]["value"], "hidden-code");
+        assert!(!json.to_string().contains("pin-hash"));
+    }
+
+    #[test]
+    fn writes_bitwarden_items() {
+        let dir = TempDir::new();
+        let payload = sample_payload(&dir);
+        let json: Value = serde_json::from_slice(&to_bitwarden_json(&payload).unwrap()).unwrap();
+
+        assert_eq!(json["encrypted"], false);
+        assert_eq!(json["folders"].as_array().unwrap().len(), 2);
+        let [item] = json["items"].as_array().unwrap().as_slice() else {
+            panic!("expected one item");
+        };
+        assert_eq!(item["folderId"], payload.folders[0].id.as_str());
+        assert_eq!(item["type"], BITWARDEN_LOGIN);
+        assert_eq!(item["reprompt"], 1);
+        assert_eq!(item["login"]["username"], "alice");
+        assert_eq!(item["login"]["password"], "first-password");
+        assert_eq!(item["login"]["uris"][0]["uri"], "https://mail.example.com");
+        assert_eq!(item["fields"][0]["type"], BITWARDEN_FIELD_HIDDEN);
+    }
+
+    #[test]
+    fn refuses_unconfirmed_or_weakly_protected_exports() {
+        let dir = TempDir::new();
+        let (mut vault, _, _) =
+            testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let destination = dir.subdir("exports").join("work.export");
+
+        let attempts = [
+            (ExportFormat::Csv, None, false, "policyViolation"),
+            (ExportFormat::Encrypted, None, true, "invalidInput"),
+            (ExportFormat::Encrypted, Some(MASTER_PASSWORD), true, "policyViolation"),
+            (ExportFormat::Encrypted, Some("Summer2024!"), true, "policyViolation"),
+        ];
+        for (format, export_password, acknowledged, code) in attempts {
+            let Err(error) = vault.export(&destination, format, None, export_password, acknowledged)
+            else {
+                panic!("exported with {:?}", export_password);
+            };
+            assert_eq!(error.code(), code);
+        }
+        assert!(!destination.exists());
+    }
+}
@@ -0,0 +1,82 @@
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::PekaError;
+
+pub const DEFAULT_LENGTH: usize = 20;
+pub const MIN_LENGTH: usize = 8;
+pub const MAX_LENGTH: usize = 256;
+
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!@#$%^&*()-_=+[]{};:,.<>/?~";
+const AMBIGUOUS: &str = "Il1O0o|`'\"";
+
+pub struct GeneratorOptions {
+    pub length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+    pub exclude_ambiguous: bool,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        GeneratorOptions {
+            length: DEFAULT_LENGTH,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+            exclude_ambiguous: false,
+        }
+    }
+}
+
+/// Generates a random password containing at least one character of every enabled
+/// class. Characters are drawn uniformly from the OS random source.
+pub fn generate(options: &GeneratorOptions) -> Result<String, PekaError> {
+    if !(MIN_LENGTH..=MAX_LENGTH).contains(&options.length) {
+        return Err(PekaError::InvalidInput(format!(
+            "Password length must be between {} and {}",
+            MIN_LENGTH, MAX_LENGTH
+        )));
+    }
+
+    let classes: Vec<Vec<char>> = [
+        (options.lowercase, LOWERCASE),
+        (options.uppercase, UPPERCASE),
+        (options.digits, DIGITS),
+        (options.symbols, SYMBOLS),
+    ]
+    .into_iter()
+    .filter(|(enabled, _)| *enabled)
+    .map(|(_, set)| {
+        set.chars()
+            .filter(|c| !options.exclude_ambiguous || !AMBIGUOUS.contains(*c))
+            .collect()
+    })
+    .collect();
+
+    if classes.is_empty() {
+        return Err(PekaError::InvalidInput(
+            "At least one character class must be enabled".to_string(),
+        ));
+    }
+
+    let pool: Vec<char> = classes.iter().flatten().copied().collect();
+    let mut rng = OsRng;
+    let mut password: Vec<char> = classes
+        .iter()
+        .map(|class| class[rng.gen_range(0..class.len())])
+        .collect();
+    while password.len() < options.length {
+        password.push(pool[rng.gen_range(0..pool.len())]);
+    }
+    password.shuffle(&mut rng);
+
+    Ok(password.into_iter().collect())
+}
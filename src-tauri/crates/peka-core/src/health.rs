@@ -0,0 +1,182 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+
+use crate::strength::{self, MIN_MASTER_PASSWORD_SCORE};
+use crate::{PekaError, StoredCredential, StoredVault};
+
+pub const DEFAULT_MAX_PASSWORD_AGE_DAYS: u32 = 365;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Serialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthCounts {
+    pub credentials: usize,
+    pub weak: usize,
+    pub reused: usize,
+    pub old: usize,
+    pub missing_totp: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialHealth {
+    pub credential_id: String,
+    pub title: String,
+    pub username: String,
+    pub score: u8,
+    pub weak: bool,
+    /// Number of other credentials in the vault sharing this password.
+    pub reused_with: usize,
+    pub old: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age_days: Option<i64>,
+    pub missing_totp: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderHealth {
+    pub folder_id: String,
+    pub folder_name: String,
+    pub counts: HealthCounts,
+    /// Only credentials with at least one finding are listed.
+    pub issues: Vec<CredentialHealth>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultHealthReport {
+    pub generated_at: String,
+    pub max_age_days: u32,
+    pub totals: HealthCounts,
+    pub folders: Vec<FolderHealth>,
+}
+
+/// Scans every credential in `payload` for weak, reused and old passwords and
+/// missing TOTP secrets.
+pub fn build_report(
+    payload: &StoredVault,
+    max_age_days: u32,
+) -> Result<VaultHealthReport, PekaError> {
+    let now = Utc::now();
+    let reuse = reuse_counts(payload)?;
+
+    let mut totals = HealthCounts::default();
+    let mut folders = Vec::with_capacity(payload.folders.len());
+
+    for folder in &payload.folders {
+        let mut counts = HealthCounts::default();
+        let mut issues = Vec::new();
+
+        for credential in &folder.credentials {
+            let reused_with = reuse.get(&credential.id).copied().unwrap_or(0);
+            let health = check_credential(credential, reused_with, now, max_age_days);
+
+            counts.credentials += 1;
+            counts.weak += health.weak as usize;
+            counts.reused += (health.reused_with > 0) as usize;
+            counts.old += health.old as usize;
+            counts.missing_totp += health.missing_totp as usize;
+
+            if health.weak || health.reused_with > 0 || health.old || health.missing_totp {
+                issues.push(health);
+            }
+        }
+
+        totals.credentials += counts.credentials;
+        totals.weak += counts.weak;
+        totals.reused += counts.reused;
+        totals.old += counts.old;
+        totals.missing_totp += counts.missing_totp;
+
+        folders.push(FolderHealth {
+            folder_id: folder.id.clone(),
+            folder_name: folder.name.clone(),
+            counts,
+            issues,
+        });
+    }
+
+    Ok(VaultHealthReport {
+        generated_at: now.to_rfc3339(),
+        max_age_days,
+        totals,
+        folders,
+    })
+}
+
+/// Maps every credential id to the number of other credentials sharing its password.
+pub fn reuse_counts(payload: &StoredVault) -> Result<HashMap<String, usize>, PekaError> {
+    // Reuse is detected on keyed hashes so plaintext passwords are never compared
+    // or kept around as map keys. The key only lives for the duration of this scan.
+    let mut hash_key = [0u8; 32];
+    OsRng.fill_bytes(&mut hash_key);
+
+    let mut digests: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut counts: HashMap<Vec<u8>, usize> = HashMap::new();
+    for credential in payload.folders.iter().flat_map(|f| f.credentials.iter()) {
+        if credential.password.is_empty() {
+            continue;
+        }
+        let digest = keyed_hash(&hash_key, &credential.password)?;
+        *counts.entry(digest.clone()).or_insert(0) += 1;
+        digests.insert(credential.id.clone(), digest);
+    }
+
+    Ok(digests
+        .into_iter()
+        .map(|(id, digest)| {
+            let reused_with = counts.get(&digest).map_or(0, |count| count.saturating_sub(1));
+            (id, reused_with)
+        })
+        .collect())
+}
+
+/// Strength score of a credential's password, penalising its own title and username.
+pub fn password_score(credential: &StoredCredential) -> u8 {
+    strength::estimate_strength(
+        &credential.password,
+        &[credential.title.as_str(), credential.username.as_str()],
+    )
+    .score
+}
+
+fn check_credential(
+    credential: &StoredCredential,
+    reused_with: usize,
+    now: DateTime<Utc>,
+    max_age_days: u32,
+) -> CredentialHealth {
+    let score = password_score(credential);
+
+    let age_days = DateTime::parse_from_rfc3339(&credential.updated_at)
+        .ok()
+        .map(|updated| (now - updated.with_timezone(&Utc)).num_days());
+
+    CredentialHealth {
+        credential_id: credential.id.clone(),
+        title: credential.title.clone(),
+        username: credential.username.clone(),
+        score,
+        weak: score < MIN_MASTER_PASSWORD_SCORE,
+        reused_with,
+        old: age_days.is_some_and(|days| days > i64::from(max_age_days)),
+        age_days,
+        missing_totp: credential
+            .totp
+            .as_deref()
+            .is_none_or(|secret| secret.trim().is_empty()),
+    }
+}
+
+fn keyed_hash(key: &[u8], password: &str) -> Result<Vec<u8>, PekaError> {
+    let mut mac = HmacSha256::new_from_slice(key).map_err(PekaError::internal)?;
+    mac.update(password.as_bytes());
+    Ok(mac.finalize().into_bytes().to_vec())
+}
@@ -0,0 +1,594 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::audit::{self, AuditAction};
+use crate::merge;
+use crate::sync::SyncRecordKind;
+use crate::{PekaError, StoredCredential, StoredFolder, StoredVault, VaultContents};
+
+const REMOTE_NAME: &str = "origin";
+/// Kept in the repository while the last save could not be committed.
+const ERROR_FILE_NAME: &str = "peka-last-error";
+
+/// Where a vault's history is pushed to and how many revisions it has.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryStatus {
+    /// The git repository holding the history.
+    pub directory: String,
+    pub remote: Option<String>,
+    pub revisions: usize,
+    /// Why the last save of the vault is missing from the history. The save itself
+    /// succeeded and the next one that commits brings the history up to date.
+    pub last_error: Option<String>,
+}
+
+/// One commit of the vault file. Messages name what changed, such as "credential
+/// updated", never titles or values.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryRevision {
+    pub revision: String,
+    pub committed_at: String,
+    pub author: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum HistoryChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryChange {
+    pub record_id: String,
+    pub kind: SyncRecordKind,
+    pub change: HistoryChangeKind,
+    /// Folder name or credential title, as of the newer revision when it has one.
+    pub title: String,
+    /// Names of the changed fields; the values themselves are not sent.
+    pub fields: Vec<String>,
+}
+
+/// Folders and credentials that differ between two revisions of a vault.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryDiff {
+    pub from: String,
+    /// Unset when compared with the vault as it is now.
+    pub to: Option<String>,
+    pub changes: Vec<HistoryChange>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryRollback {
+    pub revision: String,
+    /// Attachments of the revision whose files are gone and that were left out.
+    pub missing_attachments: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vault: Option<VaultContents>,
+}
+
+/// Git repository holding the history of the vault at `vault_path`, e.g.
+/// `work.history/` next to `work.peka`. It is a bare repository whose work tree is
+/// the vault's directory, so only the vault file itself is ever committed.
+pub fn history_dir(vault_path: &Path) -> PathBuf {
+    let stem = vault_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "vault".to_string());
+    vault_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(format!("{}.history", stem))
+}
+
+/// Whether saves of the vault at `vault_path` are committed to its history.
+pub fn is_enabled(vault_path: &Path) -> bool {
+    history_dir(vault_path).join("HEAD").is_file()
+}
+
+/// Starts keeping the history of the vault at `vault_path` in a git repository,
+/// beginning with the vault file as it is now. Needs `git` on the `PATH`.
+pub fn enable(vault_path: &Path) -> Result<HistoryStatus, PekaError> {
+    if !vault_path.is_file() {
+        return Err(PekaError::NotFound("Vault file not found".to_string()));
+    }
+    if !is_enabled(vault_path) {
+        let dir = history_dir(vault_path);
+        let mut init = Command::new("git");
+        init.args(["init", "--quiet", "--bare"]).arg(&dir);
+        run(init).inspect_err(|_| {
+            let _ = fs::remove_dir_all(&dir);
+        })?;
+        // Commits need an author; the user's own git identity wins when there is one.
+        if git(vault_path, &["config", "user.email"]).is_err() {
+            let email = format!("peka@{}", audit::device_name());
+            git(vault_path, &["config", "user.name", "peka"])?;
+            git(vault_path, &["config", "user.email", &email])?;
+        }
+        commit(vault_path, "vault history enabled")?;
+    }
+    status(vault_path)?.ok_or_else(not_enabled)
+}
+
+/// Stops keeping the history of the vault at `vault_path` and deletes the local
+/// repository. Revisions already pushed stay on the remote.
+pub fn disable(vault_path: &Path) -> Result<(), PekaError> {
+    if !is_enabled(vault_path) {
+        return Err(not_enabled());
+    }
+    fs::remove_dir_all(history_dir(vault_path))
+        .map_err(|e| PekaError::io("Unable to delete the vault history", e))
+}
+
+/// The history of the vault at `vault_path`, or `None` when it is not enabled.
+pub fn status(vault_path: &Path) -> Result<Option<HistoryStatus>, PekaError> {
+    if !is_enabled(vault_path) {
+        return Ok(None);
+    }
+    let revisions = text(git(vault_path, &["rev-list", "--count", "HEAD", "--"])?);
+    Ok(Some(HistoryStatus {
+        directory: history_dir(vault_path).to_string_lossy().to_string(),
+        remote: remote(vault_path),
+        revisions: revisions.parse().unwrap_or_default(),
+        last_error: fs::read_to_string(history_dir(vault_path).join(ERROR_FILE_NAME)).ok(),
+    }))
+}
+
+/// Sets the git remote the history is pushed to, or removes it when `url` is `None`.
+/// Any URL git understands works, including the path of a local bare repository.
+pub fn set_remote(vault_path: &Path, url: Option<&str>) -> Result<HistoryStatus, PekaError> {
+    if !is_enabled(vault_path) {
+        return Err(not_enabled());
+    }
+    let current = remote(vault_path);
+    match url.map(str::trim) {
+        Some(url) if url.is_empty() || url.starts_with('-') => {
+            return Err(PekaError::InvalidInput("Invalid remote URL".to_string()));
+        }
+        Some(url) if current.is_some() => {
+            git(vault_path, &["remote", "set-url", REMOTE_NAME, url])?;
+        }
+        Some(url) => {
+            git(vault_path, &["remote", "add", REMOTE_NAME, url])?;
+        }
+        None if current.is_some() => {
+            git(vault_path, &["remote", "remove", REMOTE_NAME])?;
+        }
+        None => {}
+    }
+    status(vault_path)?.ok_or_else(not_enabled)
+}
+
+/// Pushes the history to its remote. Authentication is left to git, e.g. an SSH
+/// agent or a credential helper; git never prompts.
+pub fn push(vault_path: &Path) -> Result<(), PekaError> {
+    if !is_enabled(vault_path) {
+        return Err(not_enabled());
+    }
+    if remote(vault_path).is_none() {
+        return Err(PekaError::InvalidInput(
+            "Vault history has no remote to push to".to_string(),
+        ));
+    }
+    git(vault_path, &["push", "--quiet", REMOTE_NAME, "HEAD"])?;
+    Ok(())
+}
+
+/// Revisions of the vault at `vault_path`, newest first.
+pub fn list(vault_path: &Path) -> Result<Vec<HistoryRevision>, PekaError> {
+    if !is_enabled(vault_path) {
+        return Err(not_enabled());
+    }
+    let log = text(git(
+        vault_path,
+        &["log", "--format=%H%x1f%cI%x1f%an%x1f%s", "HEAD", "--"],
+    )?);
+    Ok(log
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\u{1f}');
+            Some(HistoryRevision {
+                revision: fields.next()?.to_string(),
+                committed_at: fields.next()?.to_string(),
+                author: fields.next()?.to_string(),
+                message: fields.next()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Commits the vault file at `vault_path` after a save. The file is already on disk,
+/// so a failure does not fail the save: it is returned and kept for
+/// [`HistoryStatus::last_error`] until a later commit succeeds.
+pub(crate) fn commit_save(vault_path: &Path, message: &str) -> Option<String> {
+    let error_path = history_dir(vault_path).join(ERROR_FILE_NAME);
+    match commit(vault_path, message) {
+        Ok(()) => {
+            let _ = fs::remove_file(error_path);
+            None
+        }
+        Err(err) => {
+            let reason = match err.details() {
+                Some(details) => format!("{}: {}", err, details),
+                None => err.to_string(),
+            };
+            let _ = fs::write(error_path, &reason);
+            Some(reason)
+        }
+    }
+}
+
+/// Commits the vault file at `vault_path` if its history is enabled and the file
+/// changed since the last commit.
+pub(crate) fn commit(vault_path: &Path, message: &str) -> Result<(), PekaError> {
+    if !is_enabled(vault_path) {
+        return Ok(());
+    }
+    let name = file_name(vault_path)?;
+    git(vault_path, &["add", "--", &name])?;
+    if git(vault_path, &["diff", "--cached", "--quiet", "--", &name]).is_ok() {
+        return Ok(());
+    }
+    git(
+        vault_path,
+        &[
+            "commit",
+            "--quiet",
+            "--no-verify",
+            "-m",
+            message,
+            "--",
+            &name,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Commit message for a save that recorded `actions`, e.g. "credential updated, tag
+/// added".
+pub(crate) fn message(actions: &[AuditAction]) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    for action in actions {
+        let part = describe(*action);
+        if !parts.contains(&part) {
+            parts.push(part);
+        }
+    }
+    if parts.is_empty() {
+        return "vault updated".to_string();
+    }
+    parts.join(", ")
+}
+
+/// The vault file as committed in `revision`, a full or abbreviated commit id.
+/// Returns the full commit id along with the file.
+pub(crate) fn read(vault_path: &Path, revision: &str) -> Result<(String, Vec<u8>), PekaError> {
+    if !is_enabled(vault_path) {
+        return Err(not_enabled());
+    }
+    let revision = revision.trim();
+    let unknown = || {
+        PekaError::NotFound(format!(
+            "Revision {} not found in the vault history",
+            revision
+        ))
+    };
+    // Only commit ids, so nothing the user types is taken as an option or a ref.
+    if !(4..=40).contains(&revision.len()) || !revision.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(unknown());
+    }
+    let commit = format!("{}^{{commit}}", revision);
+    let full = text(
+        git(vault_path, &["rev-parse", "--verify", "--quiet", &commit]).map_err(|_| unknown())?,
+    );
+    let object = format!("{}:{}", full, file_name(vault_path)?);
+    let contents = git(vault_path, &["show", &object]).map_err(|_| unknown())?;
+    Ok((full, contents))
+}
+
+/// Folders and credentials added, removed or changed from `from` to `to`.
+pub(crate) fn diff(from: &StoredVault, to: &StoredVault) -> Vec<HistoryChange> {
+    let mut changes = Vec::new();
+
+    let old_folders: HashMap<&str, &StoredFolder> = from
+        .folders
+        .iter()
+        .map(|folder| (folder.id.as_str(), folder))
+        .collect();
+    for folder in &to.folders {
+        match old_folders.get(folder.id.as_str()) {
+            None => changes.push(folder_change(folder, HistoryChangeKind::Added, Vec::new())),
+            Some(old) => {
+                let fields = folder_fields(old, folder);
+                if !fields.is_empty() {
+                    changes.push(folder_change(folder, HistoryChangeKind::Modified, fields));
+                }
+            }
+        }
+    }
+    for folder in &from.folders {
+        if !to.folders.iter().any(|other| other.id == folder.id) {
+            changes.push(folder_change(
+                folder,
+                HistoryChangeKind::Removed,
+                Vec::new(),
+            ));
+        }
+    }
+
+    let credentials = |vault: &StoredVault| -> HashMap<String, (String, StoredCredential)> {
+        vault
+            .folders
+            .iter()
+            .flat_map(|folder| {
+                folder
+                    .credentials
+                    .iter()
+                    .map(move |cred| (cred.id.clone(), (folder.id.clone(), cred.clone())))
+            })
+            .collect()
+    };
+    let old_credentials = credentials(from);
+    let new_credentials = credentials(to);
+    for folder in &to.folders {
+        for cred in &folder.credentials {
+            match old_credentials.get(&cred.id) {
+                None => changes.push(credential_change(
+                    cred,
+                    HistoryChangeKind::Added,
+                    Vec::new(),
+                )),
+                Some((old_folder, old)) => {
+                    let mut fields = credential_fields(old, cred);
+                    if *old_folder != folder.id {
+                        fields.push("folder".to_string());
+                    }
+                    if !fields.is_empty() {
+                        changes.push(credential_change(cred, HistoryChangeKind::Modified, fields));
+                    }
+                }
+            }
+        }
+    }
+    for folder in &from.folders {
+        for cred in &folder.credentials {
+            if !new_credentials.contains_key(&cred.id) {
+                changes.push(credential_change(
+                    cred,
+                    HistoryChangeKind::Removed,
+                    Vec::new(),
+                ));
+            }
+        }
+    }
+
+    changes
+}
+
+fn folder_change(
+    folder: &StoredFolder,
+    change: HistoryChangeKind,
+    fields: Vec<String>,
+) -> HistoryChange {
+    HistoryChange {
+        record_id: folder.id.clone(),
+        kind: SyncRecordKind::Folder,
+        change,
+        title: folder.name.clone(),
+        fields,
+    }
+}
+
+fn credential_change(
+    cred: &StoredCredential,
+    change: HistoryChangeKind,
+    fields: Vec<String>,
+) -> HistoryChange {
+    HistoryChange {
+        record_id: cred.id.clone(),
+        kind: SyncRecordKind::Credential,
+        change,
+        title: cred.title.clone(),
+        fields,
+    }
+}
+
+fn folder_fields(old: &StoredFolder, new: &StoredFolder) -> Vec<String> {
+    let mut fields = Vec::new();
+    if old.name != new.name {
+        fields.push("name".to_string());
+    }
+    if old.secure != new.secure {
+        fields.push("secure".to_string());
+    }
+    if old.pin_hash != new.pin_hash {
+        fields.push("pin".to_string());
+    }
+    fields
+}
+
+fn credential_fields(old: &StoredCredential, new: &StoredCredential) -> Vec<String> {
+    let mut fields = merge::differing_fields(old, new);
+    let attachment_ids = |cred: &StoredCredential| {
+        cred.attachments
+            .iter()
+            .map(|attachment| attachment.id.clone())
+            .collect::<Vec<_>>()
+    };
+    if attachment_ids(old) != attachment_ids(new) {
+        fields.push("attachments".to_string());
+    }
+    fields
+}
+
+/// "credentialUpdated" becomes "credential updated".
+fn describe(action: AuditAction) -> String {
+    let name = serde_json::to_value(action)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default();
+    let mut words = String::new();
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            words.push(' ');
+        }
+        words.push(c.to_ascii_lowercase());
+    }
+    words
+}
+
+fn remote(vault_path: &Path) -> Option<String> {
+    git(vault_path, &["remote", "get-url", REMOTE_NAME])
+        .ok()
+        .map(text)
+}
+
+fn file_name(vault_path: &Path) -> Result<String, PekaError> {
+    vault_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| PekaError::InvalidInput("Invalid vault path".to_string()))
+}
+
+fn not_enabled() -> PekaError {
+    PekaError::InvalidInput("Vault history is not enabled".to_string())
+}
+
+/// Runs git on the history of the vault at `vault_path` and returns its output.
+fn git(vault_path: &Path, args: &[&str]) -> Result<Vec<u8>, PekaError> {
+    let mut command = Command::new("git");
+    command
+        .arg("--git-dir")
+        .arg(history_dir(vault_path))
+        .arg("--work-tree")
+        .arg(vault_path.parent().unwrap_or_else(|| Path::new(".")))
+        .args(args);
+    run(command)
+}
+
+fn run(mut command: Command) -> Result<Vec<u8>, PekaError> {
+    let output = command
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => PekaError::Unsupported(
+                "Vault history needs git, which is not installed".to_string(),
+            ),
+            _ => PekaError::io("Unable to run git", e),
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(PekaError::io(
+            "Git failed on the vault history",
+            io::Error::other(stderr),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+fn text(output: Vec<u8>) -> String {
+    String::from_utf8_lossy(&output).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir, MASTER_PASSWORD};
+    use crate::{CredentialChanges, Vault};
+
+    #[test]
+    fn keeps_revisions_to_compare_and_roll_back_to() {
+        let dir = TempDir::new();
+        let (mut vault, folder_id, credential_id) =
+            testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let status = enable(vault.path()).unwrap();
+        assert_eq!(status.revisions, 1);
+
+        let changes = CredentialChanges {
+            password: Some("second-password".to_string()),
+            ..CredentialChanges::default()
+        };
+        vault
+            .update_credential(&folder_id, &credential_id, changes)
+            .unwrap();
+        vault.save().unwrap();
+        assert!(vault.history_error().is_none());
+
+        let revisions = list(vault.path()).unwrap();
+        let messages: Vec<&str> = revisions.iter().map(|rev| rev.message.as_str()).collect();
+        assert_eq!(messages, ["credential updated", "vault history enabled"]);
+        let first = &revisions[1].revision;
+
+        let (full, contents) = read(vault.path(), &first[..8]).unwrap();
+        assert_eq!(&full, first);
+        assert!(!contents.is_empty());
+
+        let diff = vault.history_diff(&first[..8], None).unwrap();
+        assert_eq!(&diff.from, first);
+        assert!(diff.to.is_none());
+        let credential_change = diff
+            .changes
+            .iter()
+            .find(|change| change.record_id == credential_id)
+            .unwrap();
+        assert_eq!(credential_change.change, HistoryChangeKind::Modified);
+        assert!(credential_change.fields.contains(&"password".to_string()));
+        let between = vault
+            .history_diff(first, Some(&revisions[0].revision))
+            .unwrap();
+        assert_eq!(between.changes.len(), diff.changes.len());
+
+        let rollback = vault.roll_back(first).unwrap();
+        assert_eq!(&rollback.revision, first);
+        assert_eq!(rollback.missing_attachments, 0);
+        vault.save().unwrap();
+        assert_eq!(list(vault.path()).unwrap()[0].message, "vault rolled back");
+        let path = vault.path().to_path_buf();
+        assert!(read(&path, "HEAD").is_err());
+        assert!(read(&path, "--all").is_err());
+        drop(vault);
+        let reopened = Vault::open(path, MASTER_PASSWORD).unwrap();
+        let folder = &reopened.payload().folders[0];
+        assert_eq!(folder.credentials[0].password, "first-password");
+    }
+
+    #[test]
+    fn pushes_to_a_remote() {
+        let dir = TempDir::new();
+        let (vault, _, _) = testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        enable(vault.path()).unwrap();
+        assert!(push(vault.path()).is_err());
+
+        let remote_dir = dir.subdir("remote.git");
+        let mut init = Command::new("git");
+        init.args(["init", "--quiet", "--bare"]).arg(&remote_dir);
+        run(init).unwrap();
+        let url = remote_dir.to_string_lossy().to_string();
+        let status = set_remote(vault.path(), Some(&url)).unwrap();
+        assert_eq!(status.remote.as_deref(), Some(url.as_str()));
+        push(vault.path()).unwrap();
+
+        let mut pushed = Command::new("git");
+        pushed
+            .arg("--git-dir")
+            .arg(&remote_dir)
+            .args(["rev-list", "--all"]);
+        let pushed = text(run(pushed).unwrap());
+        assert_eq!(pushed, list(vault.path()).unwrap()[0].revision);
+
+        let status = set_remote(vault.path(), None).unwrap();
+        assert!(status.remote.is_none());
+        assert!(push(vault.path()).is_err());
+    }
+}
@@ -0,0 +1,1049 @@
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes256;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose, Engine as _};
+use cbc::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use chacha20::cipher::StreamCipher;
+use chacha20::ChaCha20;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hmac::{Hmac, Mac};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use uuid::Uuid;
+
+use crate::crypto::{self, DEFAULT_MEMORY_KIB, DEFAULT_PARALLELISM, DEFAULT_TIME_COST};
+use crate::sync::RevisionVector;
+use crate::{
+    tags, CustomField, KdfCost, PekaError, StoredCredential, StoredFolder, StoredVault,
+    VaultContents,
+};
+
+const SIGNATURE_1: u32 = 0x9AA2_D903;
+const SIGNATURE_2: u32 = 0xB54B_FB67;
+const FORMAT_VERSION_4: u32 = 0x0004_0000;
+const MAJOR_VERSION_MASK: u32 = 0xFFFF_0000;
+
+const HEADER_END: u8 = 0;
+const HEADER_CIPHER_ID: u8 = 2;
+const HEADER_COMPRESSION: u8 = 3;
+const HEADER_MASTER_SEED: u8 = 4;
+const HEADER_ENCRYPTION_IV: u8 = 7;
+const HEADER_KDF_PARAMETERS: u8 = 11;
+
+const INNER_HEADER_END: u8 = 0;
+const INNER_RANDOM_STREAM_ID: u8 = 1;
+const INNER_RANDOM_STREAM_KEY: u8 = 2;
+const INNER_STREAM_CHACHA20: u32 = 3;
+
+const CIPHER_AES256: [u8; 16] = hex16("31c1f2e6bf714350be5805216afc5aff");
+const CIPHER_CHACHA20: [u8; 16] = hex16("d6038a2b8b6f4cb5a524339a31dbb59a");
+const KDF_AES: [u8; 16] = hex16("c9d9f39a628a4460bf740d08c18a4fea");
+const KDF_ARGON2D: [u8; 16] = hex16("ef636ddf8c29444b91f7a9a403e30a0c");
+const KDF_ARGON2ID: [u8; 16] = hex16("9e298b1956db4773b23dfc3ec6f0a1e6");
+
+const VARIANT_VERSION: u16 = 0x0100;
+const VARIANT_UINT32: u8 = 0x04;
+const VARIANT_UINT64: u8 = 0x05;
+const VARIANT_BYTES: u8 = 0x42;
+
+// Databases come from anywhere and their header decides how long deriving the key
+// takes. AES-KDF rounds above the maximum are refused, above the expensive mark only
+// decrypted after confirmation; Argon2 shares the bounds of vault files.
+const MAX_AES_KDF_ROUNDS: u64 = 100_000_000;
+const EXPENSIVE_AES_KDF_ROUNDS: u64 = 10_000_000;
+
+const BLOCK_SIZE: usize = 1024 * 1024;
+// Seconds between 0001-01-01 (the KDBX 4 time origin) and the Unix epoch.
+const KDBX_EPOCH_OFFSET: i64 = 62_135_596_800;
+
+type HmacSha256 = Hmac<Sha256>;
+type Aes256CbcEnc = cbc::Encryptor<Aes256>;
+type Aes256CbcDec = cbc::Decryptor<Aes256>;
+
+/// Outer cipher used when writing a KDBX 4 file.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum KdbxCipher {
+    #[default]
+    Aes256,
+    ChaCha20,
+}
+
+/// Groups and entries read from a KDBX file, already mapped onto vault types.
+pub struct KdbxDatabase {
+    pub folders: Vec<StoredFolder>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KdbxImportResult {
+    pub imported: usize,
+    pub skipped: usize,
+    pub folders_created: Vec<String>,
+    pub vault: VaultContents,
+}
+
+const fn hex16(hex: &str) -> [u8; 16] {
+    let bytes = hex.as_bytes();
+    let mut out = [0u8; 16];
+    let mut i = 0;
+    while i < 16 {
+        out[i] = (hex_digit(bytes[i * 2]) << 4) | hex_digit(bytes[i * 2 + 1]);
+        i += 1;
+    }
+    out
+}
+
+const fn hex_digit(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => panic!("invalid hex digit"),
+    }
+}
+
+/// Decrypts and parses a KDBX 4 database protected by `password`. Unless
+/// `allow_expensive_kdf` is set, a database whose key derivation is unusually costly
+/// is refused with [`PekaError::ConfirmationRequired`] before any work is done.
+pub fn read_database(
+    raw: &[u8],
+    password: &str,
+    allow_expensive_kdf: bool,
+) -> Result<KdbxDatabase, PekaError> {
+    let mut cursor = ByteReader::new(raw);
+    if cursor.u32()? != SIGNATURE_1 || cursor.u32()? != SIGNATURE_2 {
+        return Err(PekaError::InvalidInput("File is not a KeePass database".to_string()));
+    }
+    if cursor.u32()? & MAJOR_VERSION_MASK != FORMAT_VERSION_4 {
+        return Err(PekaError::Unsupported("Only KDBX 4 databases are supported".to_string()));
+    }
+
+    let mut fields: HashMap<u8, Vec<u8>> = HashMap::new();
+    loop {
+        let id = cursor.u8()?;
+        let size = cursor.u32()? as usize;
+        let data = cursor.bytes(size)?;
+        if id == HEADER_END {
+            break;
+        }
+        fields.insert(id, data.to_vec());
+    }
+    let header = &raw[..cursor.position];
+
+    let header_hash = cursor.bytes(32)?;
+    if Sha256::digest(header).as_slice() != header_hash {
+        return Err(corrupted("KeePass database header is corrupted"));
+    }
+
+    let field = |id: u8, name: &str| {
+        fields
+            .get(&id)
+            .ok_or_else(|| corrupted(&format!("KeePass database header is missing the {}", name)))
+    };
+    let cipher_id = field(HEADER_CIPHER_ID, "cipher")?;
+    let master_seed = field(HEADER_MASTER_SEED, "master seed")?;
+    let iv = field(HEADER_ENCRYPTION_IV, "encryption IV")?;
+    let kdf = parse_variant_dictionary(field(HEADER_KDF_PARAMETERS, "KDF parameters")?)?;
+    let compressed = match fields.get(&HEADER_COMPRESSION) {
+        Some(flag) => read_u32(flag)? != 0,
+        None => false,
+    };
+
+    if kdf_cost(&kdf)? == KdfCost::Expensive && !allow_expensive_kdf {
+        return Err(PekaError::ConfirmationRequired(
+            "Unlocking this KeePass database takes unusually long because of its key \
+             derivation settings"
+                .to_string(),
+        ));
+    }
+
+    let transformed_key = transform_key(password, &kdf)?;
+    let (encryption_key, hmac_key) = derive_keys(master_seed, &transformed_key);
+
+    let header_hmac = cursor.bytes(32)?;
+    block_mac(&hmac_key, u64::MAX, header)
+        .verify_slice(header_hmac)
+        .map_err(|_| PekaError::WrongPassword)?;
+
+    let mut ciphertext = Vec::new();
+    for index in 0u64.. {
+        let mac = cursor.bytes(32)?;
+        let size = cursor.u32()? as usize;
+        let data = cursor.bytes(size)?;
+
+        let mut block_input = (size as u32).to_le_bytes().to_vec();
+        block_input.extend_from_slice(data);
+        block_mac(&hmac_key, index, &block_input)
+            .verify_slice(mac)
+            .map_err(|_| corrupted("KeePass database content is corrupted"))?;
+
+        if size == 0 {
+            break;
+        }
+        ciphertext.extend_from_slice(data);
+    }
+
+    let decrypted = if cipher_id.as_slice() == CIPHER_AES256 {
+        Aes256CbcDec::new_from_slices(&encryption_key, iv)
+            .map_err(PekaError::internal)?
+            .decrypt_padded_vec_mut::<Pkcs7>(&ciphertext)
+            .map_err(|_| PekaError::WrongPassword)?
+    } else if cipher_id.as_slice() == CIPHER_CHACHA20 {
+        let mut cipher =
+            ChaCha20::new_from_slices(&encryption_key, iv).map_err(PekaError::internal)?;
+        cipher.apply_keystream(&mut ciphertext);
+        ciphertext
+    } else {
+        return Err(PekaError::Unsupported(
+            "KeePass database uses an unsupported cipher".to_string(),
+        ));
+    };
+
+    let plaintext = if compressed {
+        let mut out = Vec::new();
+        GzDecoder::new(decrypted.as_slice())
+            .read_to_end(&mut out)
+            .map_err(|_| corrupted("KeePass database content is corrupted"))?;
+        out
+    } else {
+        decrypted
+    };
+
+    let mut inner = ByteReader::new(&plaintext);
+    let mut stream_id = None;
+    let mut stream_key = None;
+    loop {
+        let id = inner.u8()?;
+        let size = inner.u32()? as usize;
+        let data = inner.bytes(size)?;
+        match id {
+            INNER_HEADER_END => break,
+            INNER_RANDOM_STREAM_ID => stream_id = Some(read_u32(data)?),
+            INNER_RANDOM_STREAM_KEY => stream_key = Some(data.to_vec()),
+            // Binary attachments are not carried over.
+            _ => {}
+        }
+    }
+
+    if stream_id != Some(INNER_STREAM_CHACHA20) {
+        return Err(PekaError::Unsupported(
+            "KeePass database uses an unsupported inner stream cipher".to_string(),
+        ));
+    }
+    let mut stream = inner_stream(
+        stream_key.as_deref().ok_or_else(|| corrupted("KeePass inner stream key is missing"))?,
+    )?;
+
+    let document = parse_xml(&plaintext[inner.position..], &mut stream)?;
+    Ok(KdbxDatabase {
+        folders: map_document(&document)?,
+    })
+}
+
+/// Serializes `payload` into a KDBX 4 database protected by `password`.
+pub fn write_database(
+    payload: &StoredVault,
+    password: &str,
+    cipher: KdbxCipher,
+) -> Result<Vec<u8>, PekaError> {
+    let mut master_seed = [0u8; 32];
+    let mut kdf_salt = [0u8; 32];
+    let mut stream_key = [0u8; 64];
+    OsRng.fill_bytes(&mut master_seed);
+    OsRng.fill_bytes(&mut kdf_salt);
+    OsRng.fill_bytes(&mut stream_key);
+
+    let (cipher_id, iv) = match cipher {
+        KdbxCipher::Aes256 => {
+            let mut iv = vec![0u8; 16];
+            OsRng.fill_bytes(&mut iv);
+            (CIPHER_AES256, iv)
+        }
+        KdbxCipher::ChaCha20 => {
+            let mut iv = vec![0u8; 12];
+            OsRng.fill_bytes(&mut iv);
+            (CIPHER_CHACHA20, iv)
+        }
+    };
+
+    let mut kdf = Vec::new();
+    kdf.extend_from_slice(&VARIANT_VERSION.to_le_bytes());
+    write_variant(&mut kdf, VARIANT_BYTES, "$UUID", &KDF_ARGON2ID);
+    write_variant(&mut kdf, VARIANT_BYTES, "S", &kdf_salt);
+    write_variant(&mut kdf, VARIANT_UINT32, "P", &DEFAULT_PARALLELISM.to_le_bytes());
+    write_variant(
+        &mut kdf,
+        VARIANT_UINT64,
+        "M",
+        &(u64::from(DEFAULT_MEMORY_KIB) * 1024).to_le_bytes(),
+    );
+    write_variant(&mut kdf, VARIANT_UINT64, "I", &u64::from(DEFAULT_TIME_COST).to_le_bytes());
+    write_variant(&mut kdf, VARIANT_UINT32, "V", &0x13u32.to_le_bytes());
+    kdf.push(0);
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&SIGNATURE_1.to_le_bytes());
+    header.extend_from_slice(&SIGNATURE_2.to_le_bytes());
+    header.extend_from_slice(&FORMAT_VERSION_4.to_le_bytes());
+    write_field(&mut header, HEADER_CIPHER_ID, &cipher_id);
+    write_field(&mut header, HEADER_COMPRESSION, &1u32.to_le_bytes());
+    write_field(&mut header, HEADER_MASTER_SEED, &master_seed);
+    write_field(&mut header, HEADER_ENCRYPTION_IV, &iv);
+    write_field(&mut header, HEADER_KDF_PARAMETERS, &kdf);
+    write_field(&mut header, HEADER_END, b"\r\n\r\n");
+
+    let transformed_key = transform_key(password, &parse_variant_dictionary(&kdf)?)?;
+    let (encryption_key, hmac_key) = derive_keys(&master_seed, &transformed_key);
+
+    let mut plaintext = Vec::new();
+    write_field(&mut plaintext, INNER_RANDOM_STREAM_ID, &INNER_STREAM_CHACHA20.to_le_bytes());
+    write_field(&mut plaintext, INNER_RANDOM_STREAM_KEY, &stream_key);
+    write_field(&mut plaintext, INNER_HEADER_END, &[]);
+    let mut stream = inner_stream(&stream_key)?;
+    plaintext.extend_from_slice(build_xml(payload, &mut stream).as_bytes());
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&plaintext).map_err(PekaError::internal)?;
+    let compressed = encoder.finish().map_err(PekaError::internal)?;
+
+    let ciphertext = match cipher {
+        KdbxCipher::Aes256 => Aes256CbcEnc::new_from_slices(&encryption_key, &iv)
+            .map_err(PekaError::internal)?
+            .encrypt_padded_vec_mut::<Pkcs7>(&compressed),
+        KdbxCipher::ChaCha20 => {
+            let mut data = compressed;
+            ChaCha20::new_from_slices(&encryption_key, &iv)
+                .map_err(PekaError::internal)?
+                .apply_keystream(&mut data);
+            data
+        }
+    };
+
+    let mut out = header.clone();
+    out.extend_from_slice(&Sha256::digest(&header));
+    out.extend_from_slice(&block_mac(&hmac_key, u64::MAX, &header).finalize().into_bytes());
+
+    let blocks = ciphertext.chunks(BLOCK_SIZE).chain(std::iter::once(&[][..]));
+    for (index, block) in blocks.enumerate() {
+        let size = (block.len() as u32).to_le_bytes();
+        let mut block_input = size.to_vec();
+        block_input.extend_from_slice(block);
+        let mac = block_mac(&hmac_key, index as u64, &block_input).finalize();
+        out.extend_from_slice(&mac.into_bytes());
+        out.extend_from_slice(&size);
+        out.extend_from_slice(block);
+    }
+
+    Ok(out)
+}
+
+/// Checks the key derivation parameters of a database against the bounds before any
+/// key is derived. Argon2 memory below the vault minimum is accepted, see
+/// [`crypto::argon2_cost`].
+fn kdf_cost(kdf: &HashMap<String, Vec<u8>>) -> Result<KdfCost, PekaError> {
+    let param = |name: &str| {
+        kdf.get(name)
+            .ok_or_else(|| corrupted(&format!("KeePass KDF parameter '{}' is missing", name)))
+    };
+
+    let kdf_id = param("$UUID")?.as_slice();
+    if kdf_id == KDF_ARGON2D || kdf_id == KDF_ARGON2ID {
+        crypto::argon2_cost(
+            read_u64(param("M")?)? / 1024,
+            read_u64(param("I")?)?,
+            u64::from(read_u32(param("P")?)?),
+        )
+    } else if kdf_id == KDF_AES {
+        let rounds = read_u64(param("R")?)?;
+        if rounds > MAX_AES_KDF_ROUNDS {
+            return Err(crypto::unsafe_kdf(format!(
+                "{} AES-KDF rounds are above {}",
+                rounds, MAX_AES_KDF_ROUNDS
+            )));
+        }
+        if rounds > EXPENSIVE_AES_KDF_ROUNDS {
+            Ok(KdfCost::Expensive)
+        } else {
+            Ok(KdfCost::Normal)
+        }
+    } else {
+        Err(PekaError::Unsupported(
+            "KeePass database uses an unsupported key derivation function".to_string(),
+        ))
+    }
+}
+
+fn transform_key(password: &str, kdf: &HashMap<String, Vec<u8>>) -> Result<Vec<u8>, PekaError> {
+    kdf_cost(kdf)?;
+    let composite = Sha256::digest(Sha256::digest(password.as_bytes()));
+    let param = |name: &str| {
+        kdf.get(name)
+            .ok_or_else(|| corrupted(&format!("KeePass KDF parameter '{}' is missing", name)))
+    };
+
+    let kdf_id = param("$UUID")?.as_slice();
+    if kdf_id == KDF_ARGON2D || kdf_id == KDF_ARGON2ID {
+        let algorithm = if kdf_id == KDF_ARGON2D {
+            Algorithm::Argon2d
+        } else {
+            Algorithm::Argon2id
+        };
+        let memory_kib = u32::try_from(read_u64(param("M")?)? / 1024)
+            .map_err(|_| {
+                PekaError::Unsupported("KeePass KDF memory parameter is too large".to_string())
+            })?;
+        let iterations = u32::try_from(read_u64(param("I")?)?)
+            .map_err(|_| {
+                PekaError::Unsupported("KeePass KDF iteration parameter is too large".to_string())
+            })?;
+        let parallelism = read_u32(param("P")?)?;
+        let version = match kdf.get("V").map(|v| read_u32(v)).transpose()? {
+            Some(0x10) => Version::V0x10,
+            _ => Version::V0x13,
+        };
+
+        let params = Params::new(memory_kib, iterations, parallelism, Some(32))
+            .map_err(|e| PekaError::corrupted("KeePass KDF parameters are invalid", e))?;
+        let mut key = vec![0u8; 32];
+        Argon2::new(algorithm, version, params)
+            .hash_password_into(&composite, param("S")?, &mut key)
+            .map_err(|_| PekaError::Internal("Unable to derive encryption key".to_string()))?;
+        Ok(key)
+    } else if kdf_id == KDF_AES {
+        let rounds = read_u64(param("R")?)?;
+        let cipher = Aes256::new_from_slice(param("S")?)
+            .map_err(|e| PekaError::corrupted("KeePass KDF parameters are invalid", e))?;
+        let mut blocks = [
+            aes::Block::clone_from_slice(&composite[..16]),
+            aes::Block::clone_from_slice(&composite[16..]),
+        ];
+        for _ in 0..rounds {
+            cipher.encrypt_blocks(&mut blocks);
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(blocks[0]);
+        hasher.update(blocks[1]);
+        Ok(hasher.finalize().to_vec())
+    } else {
+        Err(PekaError::Unsupported(
+            "KeePass database uses an unsupported key derivation function".to_string(),
+        ))
+    }
+}
+
+fn derive_keys(master_seed: &[u8], transformed_key: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut encryption = Sha256::new();
+    encryption.update(master_seed);
+    encryption.update(transformed_key);
+
+    let mut hmac = Sha512::new();
+    hmac.update(master_seed);
+    hmac.update(transformed_key);
+    hmac.update([1u8]);
+
+    (encryption.finalize().to_vec(), hmac.finalize().to_vec())
+}
+
+fn block_mac(hmac_key: &[u8], index: u64, data: &[u8]) -> HmacSha256 {
+    let mut block_key = Sha512::new();
+    block_key.update(index.to_le_bytes());
+    block_key.update(hmac_key);
+
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(&block_key.finalize())
+        .expect("HMAC accepts keys of any length");
+    // The header MAC covers only the header; block MACs are prefixed with the index.
+    if index != u64::MAX {
+        mac.update(&index.to_le_bytes());
+    }
+    mac.update(data);
+    mac
+}
+
+fn inner_stream(key: &[u8]) -> Result<ChaCha20, PekaError> {
+    let hash = Sha512::digest(key);
+    ChaCha20::new_from_slices(&hash[..32], &hash[32..44]).map_err(PekaError::internal)
+}
+
+fn parse_variant_dictionary(data: &[u8]) -> Result<HashMap<String, Vec<u8>>, PekaError> {
+    let mut cursor = ByteReader::new(data);
+    if cursor.u16()? >> 8 != VARIANT_VERSION >> 8 {
+        return Err(PekaError::Unsupported(
+            "KeePass KDF parameters use an unsupported version".to_string(),
+        ));
+    }
+
+    let mut items = HashMap::new();
+    loop {
+        let kind = cursor.u8()?;
+        if kind == 0 {
+            break;
+        }
+        let key_len = cursor.u32()? as usize;
+        let key = String::from_utf8_lossy(cursor.bytes(key_len)?).to_string();
+        let value_len = cursor.u32()? as usize;
+        items.insert(key, cursor.bytes(value_len)?.to_vec());
+    }
+    Ok(items)
+}
+
+fn write_variant(out: &mut Vec<u8>, kind: u8, key: &str, value: &[u8]) {
+    out.push(kind);
+    out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    out.extend_from_slice(key.as_bytes());
+    out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+fn write_field(out: &mut Vec<u8>, id: u8, data: &[u8]) {
+    out.push(id);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+fn read_u32(data: &[u8]) -> Result<u32, PekaError> {
+    data.try_into()
+        .map(u32::from_le_bytes)
+        .map_err(|_| corrupted("KeePass database header is malformed"))
+}
+
+fn read_u64(data: &[u8]) -> Result<u64, PekaError> {
+    data.try_into()
+        .map(u64::from_le_bytes)
+        .map_err(|_| corrupted("KeePass database header is malformed"))
+}
+
+fn corrupted(message: &str) -> PekaError {
+    PekaError::Corrupted {
+        message: message.to_string(),
+        details: None,
+    }
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, position: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PekaError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| corrupted("KeePass database is truncated"))?;
+        let slice = &self.data[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, PekaError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, PekaError> {
+        Ok(u16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn u32(&mut self) -> Result<u32, PekaError> {
+        read_u32(self.bytes(4)?)
+    }
+}
+
+struct XmlNode {
+    name: String,
+    protected: bool,
+    text: String,
+    children: Vec<XmlNode>,
+}
+
+impl XmlNode {
+    fn new(name: String, protected: bool) -> Self {
+        XmlNode {
+            name,
+            protected,
+            text: String::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn child(&self, name: &str) -> Option<&XmlNode> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlNode> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn child_text(&self, name: &str) -> &str {
+        self.child(name).map(|child| child.text.as_str()).unwrap_or("")
+    }
+}
+
+fn start_node(element: &BytesStart) -> Result<XmlNode, PekaError> {
+    let name = String::from_utf8_lossy(element.name().as_ref()).to_string();
+    let protected = element
+        .attributes()
+        .filter_map(Result::ok)
+        .any(|attr| attr.key.as_ref() == b"Protected" && attr.value.as_ref() == b"True");
+    Ok(XmlNode::new(name, protected))
+}
+
+/// Builds a tree of the inner XML document. Protected values are XORed with the inner
+/// stream in document order, so they are unmasked as soon as their element closes.
+fn parse_xml(xml: &[u8], stream: &mut ChaCha20) -> Result<XmlNode, PekaError> {
+    const MALFORMED: &str = "KeePass database XML is malformed";
+    let malformed = |e: quick_xml::Error| PekaError::corrupted(MALFORMED, e);
+    let mut reader = Reader::from_reader(xml);
+    let mut buffer = Vec::new();
+    let mut stack = vec![XmlNode::new(String::new(), false)];
+
+    loop {
+        match reader.read_event_into(&mut buffer).map_err(malformed)? {
+            Event::Start(element) => stack.push(start_node(&element)?),
+            Event::Empty(element) => {
+                let node = start_node(&element)?;
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(node);
+                }
+            }
+            Event::Text(text) => {
+                if let Some(node) = stack.last_mut() {
+                    node.text.push_str(&text.unescape().map_err(malformed)?);
+                }
+            }
+            Event::CData(data) => {
+                if let Some(node) = stack.last_mut() {
+                    node.text.push_str(&String::from_utf8_lossy(&data));
+                }
+            }
+            Event::End(_) => {
+                let mut node = stack.pop().ok_or_else(|| corrupted(MALFORMED))?;
+                if node.protected {
+                    let mut value = general_purpose::STANDARD
+                        .decode(node.text.trim())
+                        .map_err(|e| {
+                            PekaError::corrupted("KeePass protected value is malformed", e)
+                        })?;
+                    stream.apply_keystream(&mut value);
+                    node.text = String::from_utf8_lossy(&value).to_string();
+                }
+                stack.last_mut().ok_or_else(|| corrupted(MALFORMED))?.children.push(node);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buffer.clear();
+    }
+
+    stack.pop().ok_or_else(|| corrupted(MALFORMED))
+}
+
+fn map_document(document: &XmlNode) -> Result<Vec<StoredFolder>, PekaError> {
+    let file = document
+        .child("KeePassFile")
+        .ok_or_else(|| corrupted("KeePass database XML is malformed"))?;
+    let recycle_bin = file
+        .child("Meta")
+        .map(|meta| meta.child_text("RecycleBinUUID").trim().to_string())
+        .unwrap_or_default();
+    let root_group = file
+        .child("Root")
+        .and_then(|root| root.child("Group"))
+        .ok_or_else(|| corrupted("KeePass database has no root group"))?;
+
+    let mut folders = Vec::new();
+    collect_group(root_group, None, &recycle_bin, &mut folders);
+    Ok(folders)
+}
+
+/// Flattens the group tree into folders named by their path below the root group.
+fn collect_group(
+    group: &XmlNode,
+    parent_path: Option<&str>,
+    recycle_bin: &str,
+    folders: &mut Vec<StoredFolder>,
+) {
+    let uuid = group.child_text("UUID").trim();
+    if !recycle_bin.is_empty() && uuid == recycle_bin {
+        return;
+    }
+
+    let name = group.child_text("Name").trim();
+    let path = match parent_path {
+        None | Some("") => name.to_string(),
+        Some(parent) => format!("{}/{}", parent, name),
+    };
+
+    let credentials: Vec<StoredCredential> = group.children_named("Entry").map(map_entry).collect();
+    let (created_at, updated_at) = read_times(group);
+
+    // Entries stored directly in the root group still need a folder to live in.
+    if parent_path.is_some() || !credentials.is_empty() {
+        folders.push(StoredFolder {
+            id: decode_uuid(uuid),
+            name: if path.is_empty() { "KeePass".to_string() } else { path.clone() },
+            secure: false,
+            pin_hash: None,
+            credentials,
+            revision: RevisionVector::default(),
+            created_at,
+            updated_at,
+        });
+    }
+
+    let child_path = if parent_path.is_some() { path.as_str() } else { "" };
+    for child in group.children_named("Group") {
+        collect_group(child, Some(child_path), recycle_bin, folders);
+    }
+}
+
+fn map_entry(entry: &XmlNode) -> StoredCredential {
+    let mut fields: Vec<(String, String, bool)> = entry
+        .children_named("String")
+        .map(|string| {
+            let value = string.child("Value");
+            (
+                string.child_text("Key").to_string(),
+                value.map(|v| v.text.clone()).unwrap_or_default(),
+                value.is_some_and(|v| v.protected),
+            )
+        })
+        .collect();
+
+    let mut take = |key: &str| -> Option<String> {
+        let position = fields.iter().position(|(name, _, _)| name == key)?;
+        let (_, value, _) = fields.remove(position);
+        (!value.is_empty()).then_some(value)
+    };
+
+    let title = take("Title");
+    let username = take("UserName").unwrap_or_default();
+    let password = take("Password").unwrap_or_default();
+    let url = take("URL");
+    let notes = take("Notes");
+    let totp = take("otp").or_else(|| take("TimeOtp-Secret-Base32"));
+    let (created_at, updated_at) = read_times(entry);
+
+    StoredCredential {
+        id: decode_uuid(entry.child_text("UUID").trim()),
+        title: title
+            .or_else(|| url.clone())
+            .unwrap_or_else(|| username.clone()),
+        username,
+        password,
+        url,
+        notes,
+        totp,
+        custom_fields: fields
+            .into_iter()
+            .map(|(name, value, hidden)| CustomField {
+                name,
+                value,
+                hidden,
+            })
+            .collect(),
+        attachments: Vec::new(),
+        tags: entry
+            .child_text("Tags")
+            .split([';', ','])
+            .filter_map(|tag| tags::normalize(tag).ok())
+            .fold(Vec::new(), |mut unique, tag| {
+                if !unique.iter().any(|existing| tags::same_tag(existing, &tag)) {
+                    unique.push(tag);
+                }
+                unique
+            }),
+        favorite: false,
+        revision: RevisionVector::default(),
+        created_at,
+        updated_at,
+    }
+}
+
+fn read_times(node: &XmlNode) -> (String, String) {
+    let times = node.child("Times");
+    let read = |name: &str| {
+        times
+            .and_then(|times| parse_time(times.child_text(name).trim()))
+            .unwrap_or_else(|| Utc::now().to_rfc3339())
+    };
+    (read("CreationTime"), read("LastModificationTime"))
+}
+
+fn parse_time(text: &str) -> Option<String> {
+    if let Ok(bytes) = general_purpose::STANDARD.decode(text) {
+        let seconds = i64::from_le_bytes(bytes.try_into().ok()?);
+        return DateTime::from_timestamp(seconds - KDBX_EPOCH_OFFSET, 0)
+            .map(|time| time.to_rfc3339());
+    }
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|time| time.with_timezone(&Utc).to_rfc3339())
+}
+
+fn format_time(rfc3339: &str) -> String {
+    let seconds = DateTime::parse_from_rfc3339(rfc3339)
+        .map(|time| time.timestamp())
+        .unwrap_or_else(|_| Utc::now().timestamp());
+    general_purpose::STANDARD.encode((seconds + KDBX_EPOCH_OFFSET).to_le_bytes())
+}
+
+fn decode_uuid(text: &str) -> String {
+    general_purpose::STANDARD
+        .decode(text)
+        .ok()
+        .and_then(|bytes| Uuid::from_slice(&bytes).ok())
+        .unwrap_or_else(Uuid::new_v4)
+        .to_string()
+}
+
+fn encode_uuid(id: &str) -> String {
+    let uuid = Uuid::parse_str(id).unwrap_or_else(|_| Uuid::new_v4());
+    general_purpose::STANDARD.encode(uuid.as_bytes())
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn push_times(xml: &mut String, created_at: &str, updated_at: &str) {
+    let created = format_time(created_at);
+    let updated = format_time(updated_at);
+    xml.push_str("<Times>");
+    xml.push_str(&format!("<CreationTime>{}</CreationTime>", created));
+    xml.push_str(&format!("<LastModificationTime>{}</LastModificationTime>", updated));
+    xml.push_str(&format!("<LastAccessTime>{}</LastAccessTime>", updated));
+    xml.push_str(&format!("<ExpiryTime>{}</ExpiryTime>", updated));
+    xml.push_str("<Expires>False</Expires><UsageCount>0</UsageCount>");
+    xml.push_str(&format!("<LocationChanged>{}</LocationChanged>", updated));
+    xml.push_str("</Times>");
+}
+
+fn push_string(xml: &mut String, stream: &mut ChaCha20, key: &str, value: &str, protect: bool) {
+    xml.push_str(&format!("<String><Key>{}</Key>", escape_xml(key)));
+    if protect {
+        let mut masked = value.as_bytes().to_vec();
+        stream.apply_keystream(&mut masked);
+        xml.push_str(&format!(
+            "<Value Protected=\"True\">{}</Value>",
+            general_purpose::STANDARD.encode(masked)
+        ));
+    } else {
+        xml.push_str(&format!("<Value>{}</Value>", escape_xml(value)));
+    }
+    xml.push_str("</String>");
+}
+
+fn build_xml(payload: &StoredVault, stream: &mut ChaCha20) -> String {
+    let now = Utc::now().to_rfc3339();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>");
+    xml.push_str("<KeePassFile><Meta><Generator>peka</Generator>");
+    xml.push_str(&format!(
+        "<DatabaseName>{}</DatabaseName>",
+        escape_xml(&payload.vault_name)
+    ));
+    xml.push_str("<RecycleBinEnabled>False</RecycleBinEnabled></Meta><Root><Group>");
+    xml.push_str(&format!("<UUID>{}</UUID>", encode_uuid("")));
+    xml.push_str(&format!("<Name>{}</Name>", escape_xml(&payload.vault_name)));
+    push_times(&mut xml, &now, &now);
+    xml.push_str("<IsExpanded>True</IsExpanded>");
+
+    for folder in &payload.folders {
+        xml.push_str("<Group>");
+        xml.push_str(&format!("<UUID>{}</UUID>", encode_uuid(&folder.id)));
+        xml.push_str(&format!("<Name>{}</Name>", escape_xml(&folder.name)));
+        push_times(&mut xml, &folder.created_at, &folder.updated_at);
+
+        for credential in &folder.credentials {
+            xml.push_str("<Entry>");
+            xml.push_str(&format!("<UUID>{}</UUID>", encode_uuid(&credential.id)));
+            if !credential.tags.is_empty() {
+                xml.push_str(&format!(
+                    "<Tags>{}</Tags>",
+                    escape_xml(&credential.tags.join(";"))
+                ));
+            }
+            push_times(&mut xml, &credential.created_at, &credential.updated_at);
+            push_string(&mut xml, stream, "Title", &credential.title, false);
+            push_string(&mut xml, stream, "UserName", &credential.username, false);
+            push_string(&mut xml, stream, "Password", &credential.password, true);
+            push_string(
+                &mut xml,
+                stream,
+                "URL",
+                credential.url.as_deref().unwrap_or(""),
+                false,
+            );
+            push_string(
+                &mut xml,
+                stream,
+                "Notes",
+                credential.notes.as_deref().unwrap_or(""),
+                false,
+            );
+            if let Some(totp) = &credential.totp {
+                push_string(&mut xml, stream, "otp", totp, true);
+            }
+            for field in &credential.custom_fields {
+                push_string(&mut xml, stream, &field.name, &field.value, field.hidden);
+            }
+            xml.push_str("</Entry>");
+        }
+
+        xml.push_str("</Group>");
+    }
+
+    xml.push_str("</Group></Root></KeePassFile>");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir};
+
+    const KDBX_PASSWORD: &str = "keepass <&> password";
+
+    fn sample_payload(dir: &TempDir) -> StoredVault {
+        let (vault, _, _) = testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let mut payload = vault.payload().clone();
+        let folder = &mut payload.folders[0];
+        let mut second = folder.credentials[0].clone();
+        second.id = Uuid::new_v4().to_string();
+        second.title = "Bank <online> & \"mobile\"".to_string();
+        second.password = "second-password".to_string();
+        second.url = Some("https://bank.example.com/?a=1&b=2".to_string());
+        second.notes = Some("PIN is in the safe".to_string());
+        second.totp = Some("otpauth://totp/Bank?secret=JBSWY3DPEHPK3PXP".to_string());
+        second.tags = vec!["finance".to_string(), "family".to_string()];
+        second.custom_fields = vec![
+            CustomField {
+                name: "Account".to_string(),
+                value: "12345".to_string(),
+                hidden: false,
+            },
+            CustomField {
+                name: "Recovery".to_string(),
+                value: "hidden-code".to_string(),
+                hidden: true,
+            },
+        ];
+        folder.credentials.push(second);
+        payload
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let dir = TempDir::new();
+        let payload = sample_payload(&dir);
+
+        for cipher in [KdbxCipher::Aes256, KdbxCipher::ChaCha20] {
+            let raw = write_database(&payload, KDBX_PASSWORD, cipher).unwrap();
+            let database = read_database(&raw, KDBX_PASSWORD, false).unwrap();
+
+            let [folder] = database.folders.as_slice() else {
+                panic!("expected one folder");
+            };
+            let original = &payload.folders[0];
+            assert_eq!(folder.id, original.id);
+            assert_eq!(folder.name, original.name);
+            assert_eq!(folder.credentials.len(), original.credentials.len());
+            for (read, written) in folder.credentials.iter().zip(&original.credentials) {
+                assert_eq!(read.id, written.id);
+                assert_eq!(read.title, written.title);
+                assert_eq!(read.username, written.username);
+                assert_eq!(read.password, written.password);
+                assert_eq!(read.url, written.url);
+                assert_eq!(read.notes, written.notes);
+                assert_eq!(read.totp, written.totp);
+                assert_eq!(read.tags, written.tags);
+                assert_eq!(
+                    serde_json::to_value(&read.custom_fields).unwrap(),
+                    serde_json::to_value(&written.custom_fields).unwrap()
+                );
+                // KeePass keeps whole seconds.
+                assert_eq!(
+                    DateTime::parse_from_rfc3339(&read.created_at).unwrap().timestamp(),
+                    DateTime::parse_from_rfc3339(&written.created_at).unwrap().timestamp()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_a_wrong_password_and_tampered_content() {
+        let dir = TempDir::new();
+        let raw = write_database(&sample_payload(&dir), KDBX_PASSWORD, KdbxCipher::Aes256)
+            .unwrap();
+
+        assert!(matches!(
+            read_database(&raw, "wrong password", false),
+            Err(PekaError::WrongPassword)
+        ));
+
+        let mut tampered = raw.clone();
+        let last = tampered.len() - 40;
+        tampered[last] ^= 1;
+        assert!(matches!(
+            read_database(&tampered, KDBX_PASSWORD, false),
+            Err(PekaError::Corrupted { .. })
+        ));
+
+        assert!(matches!(
+            read_database(b"not a keepass database", KDBX_PASSWORD, false),
+            Err(PekaError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn bounds_the_key_derivation_of_a_database() {
+        let aes_kdf = |rounds: u64| {
+            HashMap::from([
+                ("$UUID".to_string(), KDF_AES.to_vec()),
+                ("R".to_string(), rounds.to_le_bytes().to_vec()),
+            ])
+        };
+        assert_eq!(kdf_cost(&aes_kdf(60_000)).unwrap(), KdfCost::Normal);
+        assert_eq!(kdf_cost(&aes_kdf(20_000_000)).unwrap(), KdfCost::Expensive);
+        assert!(matches!(
+            kdf_cost(&aes_kdf(200_000_000)),
+            Err(PekaError::UnsafeParameters(_))
+        ));
+
+        // KeePass 2 creates databases with 1 MiB of Argon2 memory.
+        let keepass_default = HashMap::from([
+            ("$UUID".to_string(), KDF_ARGON2D.to_vec()),
+            ("M".to_string(), (1024u64 * 1024).to_le_bytes().to_vec()),
+            ("I".to_string(), 2u64.to_le_bytes().to_vec()),
+            ("P".to_string(), 2u32.to_le_bytes().to_vec()),
+        ]);
+        assert_eq!(kdf_cost(&keepass_default).unwrap(), KdfCost::Normal);
+
+        let unknown = HashMap::from([("$UUID".to_string(), vec![0u8; 16])]);
+        assert!(matches!(kdf_cost(&unknown), Err(PekaError::Unsupported(_))));
+    }
+}
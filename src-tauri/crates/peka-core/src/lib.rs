@@ -0,0 +1,48 @@
+//! The vault format and operations behind peka, without any GUI.
+//!
+//! [`VaultStore`] manages a directory of vault files, [`locations::LocationRegistry`]
+//! the directories and files registered besides it, and [`Vault`] is one unlocked
+//! vault. Changes are made in memory and written with [`Vault::save`].
+
+pub mod attachments;
+pub mod audit;
+pub mod backup;
+pub mod breach;
+pub mod check;
+pub mod clipboard;
+mod container;
+mod crypto;
+pub mod csv_import;
+mod error;
+pub mod export;
+pub mod generator;
+pub mod health;
+pub mod history;
+pub mod kdbx;
+pub mod locations;
+pub mod lock;
+pub mod merge;
+mod model;
+mod records;
+pub mod search;
+mod store;
+pub mod strength;
+pub mod sync;
+pub mod tags;
+#[cfg(test)]
+mod testing;
+pub mod totp;
+pub mod trash;
+mod vault;
+
+pub use container::{ContainerFormat, PayloadCompression, PayloadLayout, StorageFormat};
+pub use crypto::{KdfCost, KdfParams, VaultFile};
+pub use error::PekaError;
+pub(crate) use model::payload_to_public;
+pub use model::{
+    CustomField, StoredCredential, StoredFolder, StoredVault, VaultContents, VaultCredential,
+    VaultFolderPublic, VaultStatus, VaultSummary,
+};
+pub use records::EncryptedRecord;
+pub use store::{read_summary, VaultStore};
+pub use vault::{CredentialChanges, NewCredential, Vault};
@@ -0,0 +1,381 @@
+use chrono::Utc;
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::store::{self, VaultStore};
+use crate::{PekaError, Vault, VaultStatus, VaultSummary};
+
+const CONFIG_FILE_NAME: &str = "locations.json";
+/// Recently opened vault files that are remembered.
+pub const MAX_RECENT_VAULTS: usize = 10;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum LocationKind {
+    /// A directory of vault files, such as a shared drive or a git checkout. Vaults
+    /// can be created in it and every `.peka` file in it is listed.
+    Directory,
+    /// A single vault file that is opened where it is instead of being imported.
+    File,
+}
+
+/// A place outside the app data directory where vaults are kept.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultLocation {
+    pub id: String,
+    pub kind: LocationKind,
+    /// Canonical path of the directory or vault file.
+    pub path: String,
+    pub label: String,
+    pub added_at: String,
+}
+
+/// A vault file outside every location that was opened in place.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentVault {
+    /// Canonical path of the vault file.
+    pub path: String,
+    pub vault_name: String,
+    pub opened_at: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct LocationsFile {
+    #[serde(default)]
+    locations: Vec<VaultLocation>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    recent: Vec<RecentVault>,
+}
+
+/// The vault locations known to the app: the default [`VaultStore`] plus the
+/// directories and files the user registered, and the vault files recently opened
+/// from elsewhere. They are kept in a JSON file in the app config directory; changes
+/// are made in memory and written with [`LocationRegistry::save`].
+pub struct LocationRegistry {
+    config_path: PathBuf,
+    default_store: VaultStore,
+    locations: Vec<VaultLocation>,
+    recent: Vec<RecentVault>,
+}
+
+impl LocationRegistry {
+    /// Loads the registry kept in `config_path`. A missing file is an empty registry.
+    pub fn load(
+        config_path: impl Into<PathBuf>,
+        default_store: VaultStore,
+    ) -> Result<Self, PekaError> {
+        let config_path = config_path.into();
+        let file = match fs::read_to_string(&config_path) {
+            Ok(raw) => serde_json::from_str::<LocationsFile>(&raw)
+                .map_err(|e| PekaError::corrupted("Vault location registry is invalid", e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => LocationsFile::default(),
+            Err(e) => {
+                return Err(PekaError::io(
+                    "Unable to read the vault location registry",
+                    e,
+                ))
+            }
+        };
+
+        Ok(LocationRegistry {
+            config_path,
+            default_store,
+            locations: file.locations,
+            recent: file.recent,
+        })
+    }
+
+    /// The registry of the app, next to [`VaultStore::default_location`].
+    pub fn load_default() -> Result<Self, PekaError> {
+        LocationRegistry::load(default_config_path()?, VaultStore::default_location()?)
+    }
+
+    pub fn save(&self) -> Result<(), PekaError> {
+        if let Some(parent) = self.config_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| PekaError::io("Unable to create the config directory", e))?;
+        }
+
+        let file = LocationsFile {
+            locations: self.locations.clone(),
+            recent: self.recent.clone(),
+        };
+        let json = serde_json::to_string_pretty(&file).map_err(PekaError::internal)?;
+        fs::write(&self.config_path, json)
+            .map_err(|e| PekaError::io("Unable to write the vault location registry", e))
+    }
+
+    pub fn default_store(&self) -> &VaultStore {
+        &self.default_store
+    }
+
+    pub fn locations(&self) -> &[VaultLocation] {
+        &self.locations
+    }
+
+    /// Recently opened vault files, most recent first.
+    pub fn recent(&self) -> &[RecentVault] {
+        &self.recent
+    }
+
+    /// Remembers a vault file that was opened in place. Vaults of a location are
+    /// listed anyway and not remembered; returns whether the vault was.
+    pub fn record_opened(&mut self, path: &Path, vault_name: &str) -> bool {
+        let Ok(canonical) = path.canonicalize() else {
+            return false;
+        };
+        if self.in_location(&canonical) {
+            return false;
+        }
+
+        let canonical_string = canonical.to_string_lossy().to_string();
+        self.recent.retain(|recent| recent.path != canonical_string);
+        self.recent.insert(
+            0,
+            RecentVault {
+                path: canonical_string,
+                vault_name: vault_name.to_string(),
+                opened_at: Utc::now().to_rfc3339(),
+            },
+        );
+        self.recent.truncate(MAX_RECENT_VAULTS);
+        true
+    }
+
+    /// Drops a vault file from the recently opened ones. Returns whether it was there.
+    pub fn forget_recent(&mut self, path: &Path) -> bool {
+        let path_string = path
+            .canonicalize()
+            .unwrap_or_else(|_| path.to_path_buf())
+            .to_string_lossy()
+            .to_string();
+        let before = self.recent.len();
+        self.recent.retain(|recent| recent.path != path_string);
+        self.recent.len() != before
+    }
+
+    /// Registers a directory or vault file. The path must exist; a vault file must have
+    /// a readable header. Without `label` the directory or file name is used.
+    pub fn add(
+        &mut self,
+        path: &Path,
+        kind: LocationKind,
+        label: Option<&str>,
+    ) -> Result<VaultLocation, PekaError> {
+        if !path.exists() {
+            return Err(PekaError::NotFound(format!(
+                "Location not found: {}",
+                path.display()
+            )));
+        }
+
+        let canonical = match kind {
+            LocationKind::Directory => {
+                let canonical = path
+                    .canonicalize()
+                    .map_err(|e| PekaError::io("Unable to resolve location path", e))?;
+                if !canonical.is_dir() {
+                    return Err(PekaError::InvalidInput(
+                        "Location is not a directory".to_string(),
+                    ));
+                }
+                if self.default_store.contains(&canonical) {
+                    return Err(PekaError::InvalidInput(
+                        "Vaults in the app data directory are listed already".to_string(),
+                    ));
+                }
+                canonical
+            }
+            LocationKind::File => {
+                let canonical =
+                    store::canonical_vault_path(path, "Unable to resolve location path")?;
+                let summary = store::read_summary(&canonical);
+                if summary.status != VaultStatus::Ok {
+                    return Err(PekaError::Corrupted {
+                        message: "Vault file cannot be registered".to_string(),
+                        details: summary.status_reason,
+                    });
+                }
+                canonical
+            }
+        };
+
+        let canonical_string = canonical.to_string_lossy().to_string();
+        if self
+            .locations
+            .iter()
+            .any(|location| location.kind == kind && location.path == canonical_string)
+        {
+            return Err(PekaError::AlreadyExists(format!(
+                "Location is registered already: {}",
+                canonical_string
+            )));
+        }
+
+        let label = label
+            .map(str::trim)
+            .filter(|label| !label.is_empty())
+            .map(str::to_string)
+            .or_else(|| {
+                let name = match kind {
+                    LocationKind::Directory => canonical.file_name(),
+                    LocationKind::File => canonical.file_stem(),
+                };
+                name.map(|name| name.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| canonical_string.clone());
+
+        let location = VaultLocation {
+            id: Uuid::new_v4().to_string(),
+            kind,
+            path: canonical_string,
+            label,
+            added_at: Utc::now().to_rfc3339(),
+        };
+        self.locations.push(location.clone());
+        Ok(location)
+    }
+
+    /// Unregisters a location. Nothing on disk is touched.
+    pub fn remove(&mut self, location_id: &str) -> Result<VaultLocation, PekaError> {
+        let index = self
+            .locations
+            .iter()
+            .position(|location| location.id == location_id)
+            .ok_or_else(|| PekaError::NotFound("Vault location not found".to_string()))?;
+        Ok(self.locations.remove(index))
+    }
+
+    /// The store vaults are created in: the default store, or a registered directory.
+    pub fn store(&self, location_id: Option<&str>) -> Result<VaultStore, PekaError> {
+        let Some(location_id) = location_id else {
+            return Ok(self.default_store.clone());
+        };
+
+        match self
+            .locations
+            .iter()
+            .find(|location| location.id == location_id)
+        {
+            Some(location) if location.kind == LocationKind::Directory => {
+                Ok(VaultStore::new(&location.path))
+            }
+            Some(_) => Err(PekaError::InvalidInput(
+                "Vaults can only be created in a directory location".to_string(),
+            )),
+            None => Err(PekaError::NotFound("Vault location not found".to_string())),
+        }
+    }
+
+    /// Vaults of all locations. Locations that are unavailable, e.g. an unmounted
+    /// drive, are skipped, and a vault reachable through several locations is listed
+    /// once.
+    pub fn list(&self) -> Result<Vec<VaultSummary>, PekaError> {
+        let mut summaries = self.default_store.list()?;
+        let mut seen: HashSet<PathBuf> = summaries
+            .iter()
+            .filter_map(|summary| Path::new(&summary.path).canonicalize().ok())
+            .collect();
+
+        for location in &self.locations {
+            let found = match location.kind {
+                LocationKind::Directory => match VaultStore::new(&location.path).list() {
+                    Ok(found) => found,
+                    Err(_) => continue,
+                },
+                LocationKind::File if Path::new(&location.path).is_file() => {
+                    vec![store::read_summary(Path::new(&location.path))]
+                }
+                LocationKind::File => continue,
+            };
+
+            for mut summary in found {
+                let Ok(canonical) = Path::new(&summary.path).canonicalize() else {
+                    continue;
+                };
+                if seen.insert(canonical) {
+                    summary.location_id = Some(location.id.clone());
+                    summaries.push(summary);
+                }
+            }
+        }
+
+        Ok(summaries)
+    }
+
+    /// Deletes a vault of any of the locations or a recently opened one, and its
+    /// attachments. A vault registered as a file location is unregistered as well.
+    pub fn delete(&mut self, path: &Path) -> Result<(), PekaError> {
+        if !path.exists() {
+            return Err(PekaError::NotFound("Vault file not found".to_string()));
+        }
+
+        let canonical_target = self.resolve(path, "Unable to resolve vault path")?;
+        store::delete_files(&canonical_target)?;
+        let canonical_string = canonical_target.to_string_lossy().to_string();
+        self.locations.retain(|location| {
+            location.kind != LocationKind::File || location.path != canonical_string
+        });
+        self.recent.retain(|recent| recent.path != canonical_string);
+        Ok(())
+    }
+
+    /// Copies a vault of any of the locations or a recently opened one, and its
+    /// attachments, to `destination`, see [`Vault::copy_file_to`].
+    pub fn copy_out(
+        &self,
+        source: &Path,
+        master_password: &str,
+        destination: &Path,
+    ) -> Result<(), PekaError> {
+        if !source.exists() {
+            return Err(PekaError::NotFound("Vault file not found".to_string()));
+        }
+
+        let canonical_source = self.resolve(source, "Unable to resolve source path")?;
+        Vault::open(canonical_source, master_password)?.copy_file_to(destination)
+    }
+
+    /// Canonical path of `path`, which must be a `.peka` file in the default store, in
+    /// a registered directory, a registered file itself or a recently opened file.
+    pub fn resolve(&self, path: &Path, unresolved: &str) -> Result<PathBuf, PekaError> {
+        let canonical = store::canonical_vault_path(path, unresolved)?;
+        let recent = self
+            .recent
+            .iter()
+            .any(|recent| canonical == Path::new(&recent.path));
+        if !recent && !self.in_location(&canonical) {
+            return Err(PekaError::InvalidInput("Vault path is invalid".to_string()));
+        }
+
+        Ok(canonical)
+    }
+
+    /// Whether the canonical path `canonical` is in the default store, in a registered
+    /// directory or a registered file itself.
+    fn in_location(&self, canonical: &Path) -> bool {
+        self.default_store.contains(canonical)
+            || self.locations.iter().any(|location| match location.kind {
+                LocationKind::Directory => canonical.starts_with(&location.path),
+                LocationKind::File => canonical == Path::new(&location.path),
+            })
+    }
+}
+
+/// File the registry is kept in: the per-user config directory of the app, or
+/// `./locations.json` when the platform has none.
+pub fn default_config_path() -> Result<PathBuf, PekaError> {
+    if let Some(proj_dirs) = ProjectDirs::from("com", "nalsan", "peka") {
+        return Ok(proj_dirs.config_dir().join(CONFIG_FILE_NAME));
+    }
+    let mut path = std::env::current_dir()
+        .map_err(|e| PekaError::io("Unable to resolve the current directory", e))?;
+    path.push(CONFIG_FILE_NAME);
+    Ok(path)
+}
@@ -0,0 +1,144 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::audit;
+use crate::PekaError;
+
+/// How long opening a vault waits for another process to close it.
+const WAIT_FOR_LOCK: Duration = Duration::from_secs(5);
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The process that has a vault open.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LockOwner {
+    pub device: String,
+    pub process_id: u32,
+    pub acquired_at: String,
+}
+
+/// Lock file of the vault at `vault_path`, e.g. `work.lock` next to `work.peka`. A
+/// process holds an OS lock on it from opening the vault until closing it, so the app,
+/// the CLI or another device on a shared drive cannot save over each other's changes.
+/// The OS releases the lock when the process ends, even if it crashes.
+pub fn lock_path(vault_path: &Path) -> PathBuf {
+    let stem = vault_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "vault".to_string());
+    vault_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(format!("{}.lock", stem))
+}
+
+/// Which other process has the vault at `vault_path` open, or `None` when no other
+/// process does.
+pub fn owner(vault_path: &Path) -> Option<LockOwner> {
+    let file = File::open(lock_path(vault_path)).ok()?;
+    match file.try_lock_shared() {
+        Err(TryLockError::WouldBlock) => {}
+        _ => return None,
+    }
+    let owner = read_owner(&file);
+    (owner.process_id != std::process::id()).then_some(owner)
+}
+
+/// Held while a vault is open; the OS lock goes with the file when dropped.
+pub(crate) struct VaultLock {
+    file: File,
+}
+
+impl Drop for VaultLock {
+    fn drop(&mut self) {
+        // The file stays so a process waiting on it keeps locking the same file.
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// Locks the vault at `vault_path` for this process, waiting a few seconds for
+/// another process to close it.
+pub(crate) fn acquire(vault_path: &Path) -> Result<VaultLock, PekaError> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(lock_path(vault_path))
+        .map_err(|e| PekaError::io("Unable to lock the vault", e))?;
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) if started.elapsed() < WAIT_FOR_LOCK => {
+                thread::sleep(RETRY_INTERVAL)
+            }
+            Err(TryLockError::WouldBlock) => {
+                let owner = read_owner(&file);
+                return Err(PekaError::Locked(format!(
+                    "The vault is open in process {} on {}",
+                    owner.process_id, owner.device
+                )));
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(PekaError::io("Unable to lock the vault", e));
+            }
+        }
+    }
+
+    let owner = LockOwner {
+        device: audit::device_name(),
+        process_id: std::process::id(),
+        acquired_at: Utc::now().to_rfc3339(),
+    };
+    let contents = serde_json::to_vec(&owner).map_err(PekaError::internal)?;
+    file.set_len(0)
+        .and_then(|_| file.write_all(&contents))
+        .map_err(|e| PekaError::io("Unable to lock the vault", e))?;
+    Ok(VaultLock { file })
+}
+
+/// Removes the lock file of a deleted vault.
+pub(crate) fn remove(vault_path: &Path) {
+    let _ = fs::remove_file(lock_path(vault_path));
+}
+
+fn read_owner(file: &File) -> LockOwner {
+    // Platforms with mandatory locks refuse the read, and the owner may not have
+    // written its details yet.
+    serde_json::from_reader(file).unwrap_or_else(|_| LockOwner {
+        device: "an unknown device".to_string(),
+        process_id: 0,
+        acquired_at: String::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir, MASTER_PASSWORD};
+    use crate::Vault;
+
+    #[test]
+    fn keeps_an_open_vault_from_being_opened_again() {
+        let dir = TempDir::new();
+        let (vault, _, _) = testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let path = vault.path().to_path_buf();
+        // Only other processes count as owners.
+        assert!(owner(&path).is_none());
+
+        let Err(PekaError::Locked(message)) = Vault::open(&path, MASTER_PASSWORD) else {
+            panic!("opened a vault that is open already");
+        };
+        assert!(message.contains(&std::process::id().to_string()));
+
+        drop(vault);
+        Vault::open(&path, MASTER_PASSWORD).unwrap();
+        assert!(lock_path(&path).is_file());
+    }
+}
@@ -0,0 +1,340 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::{tags, StoredCredential, StoredFolder, StoredVault, VaultContents};
+
+/// How credentials present in both vaults with different contents are resolved.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MergeStrategy {
+    /// Keep whichever side has the newer `updated_at`.
+    Newest,
+    /// Keep the target entry and add the source entry next to it.
+    KeepBoth,
+    /// Report conflicts and only apply once every one has a resolution.
+    Interactive,
+}
+
+/// Which version of a conflicting credential to keep.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictResolution {
+    Target,
+    Source,
+    Both,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeConflict {
+    pub source_credential_id: String,
+    pub target_credential_id: String,
+    pub target_folder_id: String,
+    pub title: String,
+    pub username: String,
+    /// Names of the fields whose values differ; the values themselves are not sent.
+    pub differing_fields: Vec<String>,
+    pub source_updated_at: String,
+    pub target_updated_at: String,
+    pub source_is_newer: bool,
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeResult {
+    pub applied: bool,
+    pub folders_added: usize,
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub kept_both: usize,
+    pub conflicts: Vec<MergeConflict>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vault: Option<VaultContents>,
+}
+
+/// Merges `source` into `target`. Folders are matched by id, then by name; credentials
+/// by id, then by title and username. `target` is left untouched when the merge is not
+/// applied, i.e. when interactive conflicts are still unresolved.
+pub fn merge_vaults(
+    target: &mut StoredVault,
+    source: &StoredVault,
+    strategy: MergeStrategy,
+    resolutions: &HashMap<String, ConflictResolution>,
+) -> MergeResult {
+    let mut merged = target.clone();
+    let mut result = MergeResult::default();
+    let now = Utc::now().to_rfc3339();
+
+    for source_folder in &source.folders {
+        let folder_index = match merged
+            .folders
+            .iter()
+            .position(|f| f.id == source_folder.id)
+            .or_else(|| merged.folders.iter().position(|f| f.name == source_folder.name))
+        {
+            Some(index) => index,
+            None => {
+                let id_taken = merged.folders.iter().any(|f| f.id == source_folder.id);
+                merged.folders.push(StoredFolder {
+                    id: if id_taken {
+                        Uuid::new_v4().to_string()
+                    } else {
+                        source_folder.id.clone()
+                    },
+                    credentials: Vec::new(),
+                    ..source_folder.clone()
+                });
+                result.folders_added += 1;
+                merged.folders.len() - 1
+            }
+        };
+
+        for source_cred in &source_folder.credentials {
+            let Some((target_folder, target_index)) = find_match(&merged, source_cred) else {
+                push_credential(&mut merged, folder_index, source_cred.clone(), &now);
+                result.added += 1;
+                continue;
+            };
+
+            let target_cred = &merged.folders[target_folder].credentials[target_index];
+            let differing_fields = differing_fields(target_cred, source_cred);
+            if differing_fields.is_empty() {
+                result.unchanged += 1;
+                continue;
+            }
+
+            let source_is_newer = is_newer(&source_cred.updated_at, &target_cred.updated_at);
+            let resolution = match strategy {
+                MergeStrategy::Newest if source_is_newer => ConflictResolution::Source,
+                MergeStrategy::Newest => ConflictResolution::Target,
+                MergeStrategy::KeepBoth => ConflictResolution::Both,
+                MergeStrategy::Interactive => match resolutions.get(&source_cred.id) {
+                    Some(resolution) => *resolution,
+                    None => {
+                        result.conflicts.push(MergeConflict {
+                            source_credential_id: source_cred.id.clone(),
+                            target_credential_id: target_cred.id.clone(),
+                            target_folder_id: merged.folders[target_folder].id.clone(),
+                            title: target_cred.title.clone(),
+                            username: target_cred.username.clone(),
+                            differing_fields,
+                            source_updated_at: source_cred.updated_at.clone(),
+                            target_updated_at: target_cred.updated_at.clone(),
+                            source_is_newer,
+                        });
+                        continue;
+                    }
+                },
+            };
+
+            match resolution {
+                ConflictResolution::Target => result.unchanged += 1,
+                ConflictResolution::Source => {
+                    let folder = &mut merged.folders[target_folder];
+                    let target_cred = &mut folder.credentials[target_index];
+                    *target_cred = StoredCredential {
+                        id: target_cred.id.clone(),
+                        created_at: target_cred.created_at.clone(),
+                        ..source_cred.clone()
+                    };
+                    folder.updated_at = now.clone();
+                    result.updated += 1;
+                }
+                ConflictResolution::Both => {
+                    let copy = StoredCredential {
+                        id: Uuid::new_v4().to_string(),
+                        ..source_cred.clone()
+                    };
+                    push_credential(&mut merged, folder_index, copy, &now);
+                    result.kept_both += 1;
+                }
+            }
+        }
+    }
+
+    if result.conflicts.is_empty() {
+        *target = merged;
+        result.applied = true;
+    }
+
+    result
+}
+
+fn find_match(vault: &StoredVault, credential: &StoredCredential) -> Option<(usize, usize)> {
+    let locate = |matches: &dyn Fn(&StoredCredential) -> bool| {
+        vault.folders.iter().enumerate().find_map(|(folder_index, folder)| {
+            folder
+                .credentials
+                .iter()
+                .position(matches)
+                .map(|cred_index| (folder_index, cred_index))
+        })
+    };
+
+    let title = credential.title.trim().to_lowercase();
+    let username = credential.username.trim().to_lowercase();
+    locate(&|c| c.id == credential.id).or_else(|| {
+        locate(&|c| {
+            c.title.trim().to_lowercase() == title && c.username.trim().to_lowercase() == username
+        })
+    })
+}
+
+fn push_credential(vault: &mut StoredVault, folder_index: usize, cred: StoredCredential, now: &str) {
+    let folder = &mut vault.folders[folder_index];
+    folder.credentials.push(cred);
+    folder.updated_at = now.to_string();
+}
+
+pub(crate) fn differing_fields(target: &StoredCredential, source: &StoredCredential) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut compare = |name: &str, same: bool| {
+        if !same {
+            fields.push(name.to_string());
+        }
+    };
+
+    compare("title", target.title == source.title);
+    compare("username", target.username == source.username);
+    compare("password", target.password == source.password);
+    compare("url", target.url == source.url);
+    compare("notes", target.notes == source.notes);
+    compare("totp", target.totp == source.totp);
+    compare(
+        "customFields",
+        target.custom_fields.len() == source.custom_fields.len()
+            && target
+                .custom_fields
+                .iter()
+                .zip(&source.custom_fields)
+                .all(|(a, b)| a.name == b.name && a.value == b.value && a.hidden == b.hidden),
+    );
+    compare(
+        "tags",
+        target.tags.len() == source.tags.len()
+            && target
+                .tags
+                .iter()
+                .all(|tag| source.tags.iter().any(|other| tags::same_tag(tag, other))),
+    );
+    compare("favorite", target.favorite == source.favorite);
+    fields
+}
+
+/// Unparsable timestamps lose against valid ones.
+pub(crate) fn is_newer(candidate: &str, current: &str) -> bool {
+    let parse = |value: &str| DateTime::parse_from_rfc3339(value).ok();
+    match (parse(candidate), parse(current)) {
+        (Some(candidate), Some(current)) => candidate > current,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir};
+
+    const EARLIER: &str = "2026-01-01T00:00:00+00:00";
+    const LATER: &str = "2026-02-01T00:00:00+00:00";
+
+    /// A target vault and a copy of it whose credential has a changed password,
+    /// updated at `source_updated_at`.
+    fn diverged(dir: &TempDir, source_updated_at: &str) -> (StoredVault, StoredVault) {
+        let (vault, _, _) = testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let mut target = vault.payload().clone();
+        target.folders[0].credentials[0].updated_at = EARLIER.to_string();
+        let mut source = target.clone();
+        let credential = &mut source.folders[0].credentials[0];
+        credential.password = "second-password".to_string();
+        credential.updated_at = source_updated_at.to_string();
+        (target, source)
+    }
+
+    fn password(vault: &StoredVault) -> &str {
+        &vault.folders[0].credentials[0].password
+    }
+
+    #[test]
+    fn keeps_the_newer_side() {
+        let dir = TempDir::new();
+        let (mut target, source) = diverged(&dir, LATER);
+        let created_at = target.folders[0].credentials[0].created_at.clone();
+
+        let result = merge_vaults(&mut target, &source, MergeStrategy::Newest, &HashMap::new());
+        assert!(result.applied);
+        assert_eq!((result.updated, result.unchanged), (1, 0));
+        assert_eq!(password(&target), "second-password");
+        assert_eq!(target.folders[0].credentials[0].created_at, created_at);
+
+        let (mut target, mut source) = diverged(&dir, LATER);
+        source.folders[0].credentials[0].updated_at = "not a date".to_string();
+        let result = merge_vaults(&mut target, &source, MergeStrategy::Newest, &HashMap::new());
+        assert_eq!((result.updated, result.unchanged), (0, 1));
+        assert_eq!(password(&target), "first-password");
+    }
+
+    #[test]
+    fn matches_credentials_by_title_and_username() {
+        let dir = TempDir::new();
+        let (mut target, mut source) = diverged(&dir, LATER);
+        let credential = &mut source.folders[0].credentials[0];
+        credential.id = Uuid::new_v4().to_string();
+        credential.title = " MAIL ".to_string();
+        credential.password = "first-password".to_string();
+        let mut bank = source.folders[0].clone();
+        bank.id = Uuid::new_v4().to_string();
+        bank.name = "Bank".to_string();
+        bank.credentials[0].id = Uuid::new_v4().to_string();
+        bank.credentials[0].title = "Bank".to_string();
+        source.folders.push(bank);
+
+        let result = merge_vaults(&mut target, &source, MergeStrategy::Newest, &HashMap::new());
+        assert_eq!(result.folders_added, 1);
+        assert_eq!(result.added, 1);
+        // Only the title's case and spacing differ.
+        assert_eq!(result.updated, 1);
+        assert_eq!(target.folders[0].credentials.len(), 1);
+        assert_eq!(target.folders[1].credentials[0].title, "Bank");
+    }
+
+    #[test]
+    fn keeps_both_versions() {
+        let dir = TempDir::new();
+        let (mut target, source) = diverged(&dir, EARLIER);
+
+        let result = merge_vaults(&mut target, &source, MergeStrategy::KeepBoth, &HashMap::new());
+        assert_eq!(result.kept_both, 1);
+        let credentials = &target.folders[0].credentials;
+        assert_eq!(credentials.len(), 2);
+        assert_ne!(credentials[0].id, credentials[1].id);
+        assert_eq!(credentials[1].password, "second-password");
+    }
+
+    #[test]
+    fn applies_interactive_merges_once_every_conflict_is_resolved() {
+        let dir = TempDir::new();
+        let (mut target, source) = diverged(&dir, LATER);
+        let source_id = source.folders[0].credentials[0].id.clone();
+
+        let result =
+            merge_vaults(&mut target, &source, MergeStrategy::Interactive, &HashMap::new());
+        assert!(!result.applied);
+        let [conflict] = result.conflicts.as_slice() else {
+            panic!("expected one conflict");
+        };
+        assert_eq!(conflict.source_credential_id, source_id);
+        assert_eq!(conflict.differing_fields, ["password"]);
+        assert!(conflict.source_is_newer);
+        assert_eq!(password(&target), "first-password");
+
+        let resolutions = HashMap::from([(source_id, ConflictResolution::Source)]);
+        let result = merge_vaults(&mut target, &source, MergeStrategy::Interactive, &resolutions);
+        assert!(result.applied && result.conflicts.is_empty());
+        assert_eq!(password(&target), "second-password");
+    }
+}
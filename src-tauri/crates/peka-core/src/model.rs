@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+
+use crate::attachments::{AttachmentSummary, StoredAttachment};
+use crate::audit::AuditEntry;
+use crate::container::StorageFormat;
+use crate::crypto::KdfParams;
+use crate::lock::LockOwner;
+use crate::sync::RevisionVector;
+use crate::trash::TrashedItem;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomField {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredCredential {
+    pub id: String,
+    pub title: String,
+    pub username: String,
+    pub password: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_fields: Vec<CustomField>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<StoredAttachment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub favorite: bool,
+    /// Edits per synced copy of the vault, see [`crate::sync`].
+    #[serde(default, skip_serializing_if = "RevisionVector::is_empty")]
+    pub revision: RevisionVector,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredFolder {
+    pub id: String,
+    pub name: String,
+    pub secure: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin_hash: Option<String>,
+    pub credentials: Vec<StoredCredential>,
+    #[serde(default, skip_serializing_if = "RevisionVector::is_empty")]
+    pub revision: RevisionVector,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// The decrypted contents of a vault file.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredVault {
+    pub vault_name: String,
+    pub folders: Vec<StoredFolder>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trash: Vec<TrashedItem>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trash_retention_days: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub audit_log: Vec<AuditEntry>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum VaultStatus {
+    /// The header is readable; the password decides the rest.
+    Ok,
+    /// The file could not be read from disk.
+    Unreadable,
+    /// The file is not a valid vault file.
+    Corrupted,
+    /// The file was written by a newer version of the app.
+    UnsupportedVersion,
+    /// The file uses a feature this version does not handle, such as an unknown key
+    /// derivation algorithm or payload compression.
+    UnsupportedFeature,
+    /// The header asks for key derivation outside safe bounds.
+    UnsafeParameters,
+}
+
+/// What can be told about a vault file without the master password.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultSummary {
+    pub path: String,
+    /// Name from the header, or the file name when the header is unreadable.
+    pub vault_name: String,
+    /// Registered location the vault was found through; unset for the app data
+    /// directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location_id: Option<String>,
+    pub status: VaultStatus,
+    /// Why the status is not `ok`.
+    pub status_reason: Option<String>,
+    pub size_bytes: u64,
+    pub modified_at: Option<String>,
+    pub format_version: Option<u8>,
+    pub kdf: Option<KdfParams>,
+    pub storage: Option<StorageFormat>,
+    /// The file's permissions forbid writing it, so changes to the vault could not be
+    /// saved. Independent of `locked_by`.
+    pub read_only: bool,
+    /// Another process that has the vault open, if any, e.g. the CLI while the app
+    /// lists vaults.
+    pub locked_by: Option<LockOwner>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultCredential {
+    pub id: String,
+    pub title: String,
+    pub username: String,
+    pub password: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub totp: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub custom_fields: Vec<CustomField>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<AttachmentSummary>,
+    pub tags: Vec<String>,
+    pub favorite: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultFolderPublic {
+    pub id: String,
+    pub name: String,
+    pub secure: bool,
+    pub credentials: Vec<VaultCredential>,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+}
+
+/// What the app shows of an open vault: folders and credentials without PIN hashes,
+/// trash or audit log.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultContents {
+    pub vault_name: String,
+    pub folders: Vec<VaultFolderPublic>,
+}
+
+pub(crate) fn payload_to_public(payload: &StoredVault) -> VaultContents {
+    let folders = payload
+        .folders
+        .iter()
+        .map(|folder| VaultFolderPublic {
+            id: folder.id.clone(),
+            name: folder.name.clone(),
+            secure: folder.secure,
+            credentials: folder
+                .credentials
+                .iter()
+                .map(|cred| VaultCredential {
+                    id: cred.id.clone(),
+                    title: cred.title.clone(),
+                    username: cred.username.clone(),
+                    password: cred.password.clone(),
+                    url: cred.url.clone(),
+                    notes: cred.notes.clone(),
+                    totp: cred.totp.clone(),
+                    custom_fields: cred.custom_fields.clone(),
+                    attachments: cred
+                        .attachments
+                        .iter()
+                        .map(StoredAttachment::summary)
+                        .collect(),
+                    tags: cred.tags.clone(),
+                    favorite: cred.favorite,
+                    created_at: cred.created_at.clone(),
+                    updated_at: cred.updated_at.clone(),
+                })
+                .collect(),
+            created_at: folder.created_at.clone(),
+            updated_at: folder.updated_at.clone(),
+        })
+        .collect();
+
+    VaultContents {
+        vault_name: payload.vault_name.clone(),
+        folders,
+    }
+}
@@ -0,0 +1,378 @@
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::container::{self, PayloadCompression};
+use crate::{PekaError, StoredVault};
+
+/// Audit entries per record. Entries are only ever appended, so every page but the
+/// last stays unchanged between saves.
+const AUDIT_PAGE_SIZE: usize = 256;
+
+/// One separately encrypted part of a vault in the per-entry layout. The record id is
+/// authenticated, so records cannot be swapped for one another.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptedRecord {
+    pub id: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// The payload of a per-entry vault file, encrypted with the master password. It holds
+/// the key of the records and the order they form the vault in.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecordIndex {
+    key: String,
+    vault_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trash_retention_days: Option<u32>,
+    folders: Vec<IndexedFolder>,
+    #[serde(default)]
+    trash: Vec<String>,
+    #[serde(default)]
+    audit_log: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexedFolder {
+    record: String,
+    credentials: Vec<String>,
+}
+
+/// Records of the last read or write of a vault, by the digest of their contents, so
+/// that the next save reuses the ciphertext of every record that did not change.
+#[derive(Default)]
+pub(crate) struct RecordCache {
+    key: Option<Vec<u8>>,
+    records: HashMap<[u8; 32], EncryptedRecord>,
+    /// Salt and key derived from the master password when the vault was opened. Saves
+    /// of a per-entry vault only encrypt the index again, with a fresh nonce, instead
+    /// of deriving a new key.
+    pub(crate) derived: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+/// Splits `payload` into records and returns the index to encrypt with the master
+/// password together with the records. Only records whose contents changed since the
+/// last read or write are encrypted.
+pub(crate) fn seal(
+    payload: &StoredVault,
+    cache: &mut RecordCache,
+    compression: PayloadCompression,
+) -> Result<(Vec<u8>, Vec<EncryptedRecord>), PekaError> {
+    let key = cache
+        .key
+        .get_or_insert_with(|| {
+            let mut key = vec![0u8; 32];
+            OsRng.fill_bytes(&mut key);
+            key
+        })
+        .clone();
+    let mut sealer = Sealer {
+        cipher: Aes256Gcm::new_from_slice(&key).map_err(PekaError::internal)?,
+        compression,
+        previous: std::mem::take(&mut cache.records),
+        current: HashMap::new(),
+        records: Vec::new(),
+    };
+
+    let mut folders = Vec::with_capacity(payload.folders.len());
+    for folder in &payload.folders {
+        let mut value = serde_json::to_value(folder).map_err(PekaError::internal)?;
+        if let Value::Object(fields) = &mut value {
+            fields.remove("credentials");
+        }
+        let record = sealer.seal(&value)?;
+        let credentials = folder
+            .credentials
+            .iter()
+            .map(|cred| sealer.seal(cred))
+            .collect::<Result<_, _>>()?;
+        folders.push(IndexedFolder {
+            record,
+            credentials,
+        });
+    }
+    let trash = payload
+        .trash
+        .iter()
+        .map(|item| sealer.seal(item))
+        .collect::<Result<_, _>>()?;
+    let audit_log = payload
+        .audit_log
+        .chunks(AUDIT_PAGE_SIZE)
+        .map(|page| sealer.seal(&page))
+        .collect::<Result<_, _>>()?;
+
+    let index = RecordIndex {
+        key: general_purpose::STANDARD.encode(&key),
+        vault_name: payload.vault_name.clone(),
+        trash_retention_days: payload.trash_retention_days,
+        folders,
+        trash,
+        audit_log,
+    };
+    cache.records = sealer.current;
+    Ok((
+        serde_json::to_vec(&index).map_err(PekaError::internal)?,
+        sealer.records,
+    ))
+}
+
+/// Decrypts the records listed in `index` and joins them into the JSON of the whole
+/// payload, as a single-payload vault holds it. Records no longer listed are ignored.
+pub(crate) fn open(
+    index: &[u8],
+    records: &[EncryptedRecord],
+    compression: PayloadCompression,
+    cache: &mut RecordCache,
+) -> Result<Vec<u8>, PekaError> {
+    let index: RecordIndex = serde_json::from_slice(index)
+        .map_err(|e| PekaError::corrupted("Vault index is malformed", e))?;
+    let key = general_purpose::STANDARD
+        .decode(&index.key)
+        .ok()
+        .filter(|key| key.len() == 32)
+        .ok_or_else(|| corrupted("Vault index holds an invalid record key".to_string()))?;
+    let cipher = Aes256Gcm::new_from_slice(&key).map_err(PekaError::internal)?;
+    let by_id: HashMap<&str, &EncryptedRecord> = records
+        .iter()
+        .map(|record| (record.id.as_str(), record))
+        .collect();
+
+    let mut read = |id: &str| -> Result<Value, PekaError> {
+        let record = by_id
+            .get(id)
+            .ok_or_else(|| corrupted(format!("Vault record {} is missing", id)))?;
+        let (digest, value) = open_record(&cipher, record, compression)?;
+        cache.records.insert(digest, (*record).clone());
+        Ok(value)
+    };
+
+    let mut folders = Vec::with_capacity(index.folders.len());
+    for folder in &index.folders {
+        let mut value = read(&folder.record)?;
+        let credentials = folder
+            .credentials
+            .iter()
+            .map(|id| read(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        let Value::Object(fields) = &mut value else {
+            return Err(corrupted(format!(
+                "Vault record {} is not a folder",
+                folder.record
+            )));
+        };
+        fields.insert("credentials".to_string(), Value::Array(credentials));
+        folders.push(value);
+    }
+    let trash = index
+        .trash
+        .iter()
+        .map(|id| read(id))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut audit_log = Vec::new();
+    for id in &index.audit_log {
+        match read(id)? {
+            Value::Array(entries) => audit_log.extend(entries),
+            _ => {
+                return Err(corrupted(format!(
+                    "Vault record {} is not a page of the audit log",
+                    id
+                )))
+            }
+        }
+    }
+
+    let mut payload = Map::new();
+    payload.insert("vaultName".to_string(), Value::from(index.vault_name));
+    payload.insert("folders".to_string(), Value::Array(folders));
+    payload.insert("trash".to_string(), Value::Array(trash));
+    if let Some(days) = index.trash_retention_days {
+        payload.insert("trashRetentionDays".to_string(), Value::from(days));
+    }
+    payload.insert("auditLog".to_string(), Value::Array(audit_log));
+    cache.key = Some(key);
+    serde_json::to_vec(&Value::Object(payload)).map_err(PekaError::internal)
+}
+
+struct Sealer {
+    cipher: Aes256Gcm,
+    compression: PayloadCompression,
+    previous: HashMap<[u8; 32], EncryptedRecord>,
+    current: HashMap<[u8; 32], EncryptedRecord>,
+    records: Vec<EncryptedRecord>,
+}
+
+impl Sealer {
+    /// Adds the record for `value` and returns its id. A record of the last read or
+    /// write with the same contents is reused, once, as it is.
+    fn seal(&mut self, value: &impl Serialize) -> Result<String, PekaError> {
+        let plaintext = serde_json::to_vec(value).map_err(PekaError::internal)?;
+        let digest = digest(&plaintext, self.compression);
+        let record = match self.previous.remove(&digest) {
+            Some(record) => record,
+            None => {
+                let id = Uuid::new_v4().to_string();
+                let mut nonce_bytes = [0u8; 12];
+                OsRng.fill_bytes(&mut nonce_bytes);
+                let ciphertext = self
+                    .cipher
+                    .encrypt(
+                        Nonce::from_slice(&nonce_bytes),
+                        Payload {
+                            msg: &container::compress(plaintext, self.compression)?,
+                            aad: id.as_bytes(),
+                        },
+                    )
+                    .map_err(PekaError::internal)?;
+                EncryptedRecord {
+                    id,
+                    nonce: general_purpose::STANDARD.encode(nonce_bytes),
+                    ciphertext: general_purpose::STANDARD.encode(ciphertext),
+                }
+            }
+        };
+
+        let id = record.id.clone();
+        self.current.insert(digest, record.clone());
+        self.records.push(record);
+        Ok(id)
+    }
+}
+
+fn open_record(
+    cipher: &Aes256Gcm,
+    record: &EncryptedRecord,
+    compression: PayloadCompression,
+) -> Result<([u8; 32], Value), PekaError> {
+    let damaged = || {
+        PekaError::Tampered(format!(
+            "Vault record {} is damaged or has been tampered with",
+            record.id
+        ))
+    };
+    let nonce_bytes = general_purpose::STANDARD
+        .decode(&record.nonce)
+        .ok()
+        .filter(|nonce| nonce.len() == 12)
+        .ok_or_else(damaged)?;
+    let ciphertext = general_purpose::STANDARD
+        .decode(&record.ciphertext)
+        .map_err(|_| damaged())?;
+    let decrypted = cipher
+        .decrypt(
+            Nonce::from_slice(&nonce_bytes),
+            Payload {
+                msg: &ciphertext,
+                aad: record.id.as_bytes(),
+            },
+        )
+        .map_err(|_| damaged())?;
+    let plaintext = container::decompress(decrypted, compression)?;
+    let value = serde_json::from_slice(&plaintext)
+        .map_err(|e| PekaError::corrupted("Vault record is malformed", e))?;
+    Ok((digest(&plaintext, compression), value))
+}
+
+/// Identifies the contents of a record. The compression is included because a record
+/// has to be encrypted again when it changes.
+fn digest(plaintext: &[u8], compression: PayloadCompression) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([u8::from(!compression.is_none())]);
+    hasher.update(plaintext);
+    hasher.finalize().into()
+}
+
+fn corrupted(message: String) -> PekaError {
+    PekaError::Corrupted {
+        message,
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir};
+
+    fn sample_payload(dir: &TempDir) -> StoredVault {
+        let (vault, _, _) = testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let mut payload = vault.payload().clone();
+        let mut second = payload.folders[0].credentials[0].clone();
+        second.id = Uuid::new_v4().to_string();
+        second.title = "Bank".to_string();
+        payload.folders[0].credentials.push(second);
+        payload
+    }
+
+    #[test]
+    fn joins_records_back_into_the_payload() {
+        let dir = TempDir::new();
+        let payload = sample_payload(&dir);
+
+        for compression in [PayloadCompression::None, PayloadCompression::Deflate] {
+            let (index, records) =
+                seal(&payload, &mut RecordCache::default(), compression).unwrap();
+            // One folder, its two credentials and one page of the audit log.
+            assert_eq!(records.len(), 4);
+
+            let joined = open(&index, &records, compression, &mut RecordCache::default()).unwrap();
+            let joined: StoredVault = serde_json::from_slice(&joined).unwrap();
+            assert_eq!(
+                serde_json::to_value(&joined).unwrap(),
+                serde_json::to_value(&payload).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn encrypts_only_changed_records_again() {
+        let dir = TempDir::new();
+        let mut payload = sample_payload(&dir);
+        let mut cache = RecordCache::default();
+        let (_, before) = seal(&payload, &mut cache, PayloadCompression::None).unwrap();
+
+        payload.folders[0].credentials[1].password = "changed-password".to_string();
+        let (_, after) = seal(&payload, &mut cache, PayloadCompression::None).unwrap();
+
+        let changed: Vec<usize> = (0..before.len())
+            .filter(|&index| {
+                before[index].id != after[index].id
+                    || before[index].ciphertext != after[index].ciphertext
+            })
+            .collect();
+        // Records are sealed as folder, credentials, then audit pages.
+        assert_eq!(changed, [2]);
+    }
+
+    #[test]
+    fn rejects_swapped_and_missing_records() {
+        let dir = TempDir::new();
+        let payload = sample_payload(&dir);
+        let (index, records) =
+            seal(&payload, &mut RecordCache::default(), PayloadCompression::None).unwrap();
+        let open_records = |records: &[EncryptedRecord]| {
+            open(&index, records, PayloadCompression::None, &mut RecordCache::default())
+        };
+
+        let mut swapped = records.clone();
+        let first = swapped[1].ciphertext.clone();
+        swapped[1].ciphertext = swapped[2].ciphertext.clone();
+        swapped[2].ciphertext = first;
+        swapped[1].nonce = records[2].nonce.clone();
+        swapped[2].nonce = records[1].nonce.clone();
+        assert!(matches!(open_records(&swapped), Err(PekaError::Tampered(_))));
+
+        assert!(matches!(open_records(&records[1..]), Err(PekaError::Corrupted { .. })));
+    }
+}
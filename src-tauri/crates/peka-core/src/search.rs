@@ -0,0 +1,579 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::strength::MIN_MASTER_PASSWORD_SCORE;
+use crate::{health, tags};
+use crate::{PekaError, StoredCredential, StoredFolder, StoredVault};
+
+/// Characters of context kept on each side of a match in a notes snippet.
+const SNIPPET_CONTEXT: usize = 24;
+
+// Field weights, so a title hit outranks the same hit in the notes.
+const TITLE_WEIGHT: u32 = 4;
+const USERNAME_WEIGHT: u32 = 3;
+const URL_WEIGHT: u32 = 2;
+const NOTES_WEIGHT: u32 = 1;
+const TAG_WEIGHT: u32 = 3;
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchFilters {
+    /// Restrict results to these folders. Empty means every folder.
+    pub folder_ids: Vec<String>,
+    /// Only credentials whose password scores below the strength threshold.
+    pub weak: bool,
+    /// Only credentials sharing their password with another credential.
+    pub reused: bool,
+    /// Only credentials with (`true`) or without (`false`) a TOTP secret.
+    pub has_totp: Option<bool>,
+    /// Only credentials carrying every one of these tags.
+    pub tags: Vec<String>,
+    /// Only credentials marked as favorite.
+    pub favorites: bool,
+    /// Only credentials of this kind, judged by the fields they fill in.
+    pub kind: Option<CredentialKind>,
+}
+
+/// What a credential is used for, judged by the fields it fills in.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CredentialKind {
+    /// Has a username or password.
+    Login,
+    /// Only holds notes: no username, password or TOTP secret.
+    Note,
+    /// Only holds a TOTP secret: no username or password.
+    Totp,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SearchSort {
+    #[default]
+    Relevance,
+    TitleAsc,
+    TitleDesc,
+    UpdatedNewest,
+    UpdatedOldest,
+    CreatedNewest,
+    CreatedOldest,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SearchField {
+    Title,
+    Username,
+    Url,
+    Notes,
+    Tags,
+}
+
+/// Matched ranges within one field. Ranges are `[start, end)` offsets in UTF-16 code
+/// units, so the frontend can slice its strings with them directly.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHighlight {
+    pub field: SearchField,
+    /// Excerpt of the notes around the first match; ranges are relative to it. Not set
+    /// for the other fields, whose values are part of the hit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    /// Index into the hit's `tags` for tag matches.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<usize>,
+    pub ranges: Vec<[usize; 2]>,
+}
+
+/// Credential metadata returned by a search. Passwords, TOTP secrets and custom
+/// fields never leave the backend through this path.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub credential_id: String,
+    pub folder_id: String,
+    pub folder_name: String,
+    pub secure: bool,
+    pub title: String,
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub has_totp: bool,
+    pub tags: Vec<String>,
+    pub favorite: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    pub score: u32,
+    pub highlights: Vec<SearchHighlight>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResults {
+    /// Number of matches before `limit` was applied.
+    pub total: usize,
+    pub hits: Vec<SearchHit>,
+}
+
+/// Searches title, username, URL, notes and tags. Every whitespace separated term of `query`
+/// has to match at least one field, either as a substring or as a fuzzy subsequence.
+/// An empty query matches every credential that passes `filters`.
+pub fn search(
+    payload: &StoredVault,
+    query: &str,
+    filters: &SearchFilters,
+    sort: SearchSort,
+    limit: Option<usize>,
+) -> Result<SearchResults, PekaError> {
+    let terms: Vec<Vec<char>> = query.split_whitespace().map(lowercase_chars).collect();
+
+    let reuse = if filters.reused {
+        Some(health::reuse_counts(payload)?)
+    } else {
+        None
+    };
+
+    let mut hits = Vec::new();
+    for folder in &payload.folders {
+        if !filters.folder_ids.is_empty() && !filters.folder_ids.contains(&folder.id) {
+            continue;
+        }
+        for credential in &folder.credentials {
+            if !passes_filters(credential, filters, reuse.as_ref()) {
+                continue;
+            }
+            if let Some((score, highlights)) = match_credential(credential, &terms) {
+                hits.push(to_hit(folder, credential, score, highlights));
+            }
+        }
+    }
+
+    sort_hits(&mut hits, sort);
+    let total = hits.len();
+    if let Some(limit) = limit {
+        hits.truncate(limit);
+    }
+
+    Ok(SearchResults { total, hits })
+}
+
+fn passes_filters(
+    credential: &StoredCredential,
+    filters: &SearchFilters,
+    reuse: Option<&HashMap<String, usize>>,
+) -> bool {
+    if filters.favorites && !credential.favorite {
+        return false;
+    }
+    if !filters
+        .tags
+        .iter()
+        .all(|tag| tags::has_tag(credential, tag))
+    {
+        return false;
+    }
+    let has_totp = has_totp(credential);
+    if filters.has_totp.is_some_and(|wanted| wanted != has_totp) {
+        return false;
+    }
+    if filters
+        .kind
+        .is_some_and(|wanted| kind(credential, has_totp) != Some(wanted))
+    {
+        return false;
+    }
+    if let Some(reuse) = reuse {
+        if reuse.get(&credential.id).copied().unwrap_or(0) == 0 {
+            return false;
+        }
+    }
+    // Strength estimation is the expensive check, so it runs last.
+    if filters.weak && health::password_score(credential) >= MIN_MASTER_PASSWORD_SCORE {
+        return false;
+    }
+    true
+}
+
+fn kind(credential: &StoredCredential, has_totp: bool) -> Option<CredentialKind> {
+    let has_notes = credential
+        .notes
+        .as_deref()
+        .is_some_and(|notes| !notes.trim().is_empty());
+    if !credential.username.trim().is_empty() || !credential.password.is_empty() {
+        Some(CredentialKind::Login)
+    } else if has_totp {
+        Some(CredentialKind::Totp)
+    } else if has_notes {
+        Some(CredentialKind::Note)
+    } else {
+        None
+    }
+}
+
+fn has_totp(credential: &StoredCredential) -> bool {
+    credential
+        .totp
+        .as_deref()
+        .is_some_and(|secret| !secret.trim().is_empty())
+}
+
+fn match_credential(
+    credential: &StoredCredential,
+    terms: &[Vec<char>],
+) -> Option<(u32, Vec<SearchHighlight>)> {
+    // (field, weight, tag index, text)
+    let mut fields = vec![
+        (
+            SearchField::Title,
+            TITLE_WEIGHT,
+            None,
+            Some(credential.title.as_str()),
+        ),
+        (
+            SearchField::Username,
+            USERNAME_WEIGHT,
+            None,
+            Some(credential.username.as_str()),
+        ),
+        (
+            SearchField::Url,
+            URL_WEIGHT,
+            None,
+            credential.url.as_deref(),
+        ),
+        (
+            SearchField::Notes,
+            NOTES_WEIGHT,
+            None,
+            credential.notes.as_deref(),
+        ),
+    ];
+    fields.extend(credential.tags.iter().enumerate().map(|(index, tag)| {
+        (
+            SearchField::Tags,
+            TAG_WEIGHT,
+            Some(index),
+            Some(tag.as_str()),
+        )
+    }));
+    let lowered: Vec<Option<Vec<char>>> = fields
+        .iter()
+        .map(|(_, _, _, text)| text.map(lowercase_chars))
+        .collect();
+
+    let mut total = 0;
+    let mut matched: Vec<Vec<(usize, usize)>> = vec![Vec::new(); fields.len()];
+    for term in terms {
+        let mut best = 0;
+        for (index, (_, weight, _, _)) in fields.iter().enumerate() {
+            let Some(haystack) = &lowered[index] else {
+                continue;
+            };
+            if let Some((score, ranges)) = match_term(haystack, term) {
+                best = best.max(score * weight);
+                matched[index].extend(ranges);
+            }
+        }
+        if best == 0 {
+            return None;
+        }
+        total += best;
+    }
+
+    let highlights = fields
+        .iter()
+        .zip(matched)
+        .filter(|(_, ranges)| !ranges.is_empty())
+        .map(|((field, _, tag, text), ranges)| {
+            let mut highlight = highlight(*field, text.unwrap_or_default(), merge_ranges(ranges));
+            highlight.tag = *tag;
+            highlight
+        })
+        .collect();
+
+    Some((total, highlights))
+}
+
+/// Scores `term` against `haystack`, returning the matched char ranges. Substring
+/// matches always outscore fuzzy ones; matches at the start of the field or of a word
+/// score higher.
+fn match_term(haystack: &[char], term: &[char]) -> Option<(u32, Vec<(usize, usize)>)> {
+    if term.is_empty() || term.len() > haystack.len() {
+        return None;
+    }
+
+    if let Some(start) = haystack
+        .windows(term.len())
+        .position(|window| window == term)
+    {
+        let score = if start == 0 {
+            100
+        } else if !haystack[start - 1].is_alphanumeric() {
+            80
+        } else {
+            60
+        };
+        return Some((score, vec![(start, start + term.len())]));
+    }
+
+    // Fuzzy: every char of the term appears in order. Single characters only match
+    // as substrings, otherwise they would match almost everything.
+    if term.len() < 2 {
+        return None;
+    }
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut position = 0;
+    for ch in term {
+        let offset = haystack[position..].iter().position(|c| c == ch)?;
+        let index = position + offset;
+        match ranges.last_mut() {
+            Some(last) if last.1 == index => last.1 += 1,
+            _ => ranges.push((index, index + 1)),
+        }
+        position = index + 1;
+    }
+
+    // Scattered matches are barely related to the term; drop them.
+    let span = ranges.last().map_or(0, |r| r.1) - ranges.first().map_or(0, |r| r.0);
+    if span > term.len() * 3 {
+        return None;
+    }
+    let gaps = (ranges.len() - 1) as u32;
+    Some((40u32.saturating_sub(gaps * 5).max(10), ranges))
+}
+
+fn merge_ranges(mut ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn highlight(field: SearchField, text: &str, ranges: Vec<(usize, usize)>) -> SearchHighlight {
+    let chars: Vec<char> = text.chars().collect();
+    if field != SearchField::Notes {
+        return SearchHighlight {
+            field,
+            snippet: None,
+            tag: None,
+            ranges: to_utf16_ranges(&chars, 0, &ranges),
+        };
+    }
+
+    let (first_start, first_end) = ranges.first().copied().unwrap_or_default();
+    let start = first_start.saturating_sub(SNIPPET_CONTEXT);
+    let end = (first_end + SNIPPET_CONTEXT).min(chars.len());
+    let visible: Vec<(usize, usize)> = ranges.into_iter().filter(|r| r.1 <= end).collect();
+
+    SearchHighlight {
+        field,
+        snippet: Some(chars[start..end].iter().collect()),
+        tag: None,
+        ranges: to_utf16_ranges(&chars, start, &visible),
+    }
+}
+
+/// Converts char ranges into UTF-16 offsets relative to the char at `origin`.
+fn to_utf16_ranges(chars: &[char], origin: usize, ranges: &[(usize, usize)]) -> Vec<[usize; 2]> {
+    let offset =
+        |index: usize| -> usize { chars[origin..index].iter().map(|c| c.len_utf16()).sum() };
+    ranges
+        .iter()
+        .map(|(start, end)| [offset(*start), offset(*end)])
+        .collect()
+}
+
+/// Lowercases char by char so indices line up with the original text.
+fn lowercase_chars(text: &str) -> Vec<char> {
+    text.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
+
+fn to_hit(
+    folder: &StoredFolder,
+    credential: &StoredCredential,
+    score: u32,
+    highlights: Vec<SearchHighlight>,
+) -> SearchHit {
+    SearchHit {
+        credential_id: credential.id.clone(),
+        folder_id: folder.id.clone(),
+        folder_name: folder.name.clone(),
+        secure: folder.secure,
+        title: credential.title.clone(),
+        username: credential.username.clone(),
+        url: credential.url.clone(),
+        has_totp: has_totp(credential),
+        tags: credential.tags.clone(),
+        favorite: credential.favorite,
+        created_at: credential.created_at.clone(),
+        updated_at: credential.updated_at.clone(),
+        score,
+        highlights,
+    }
+}
+
+fn sort_hits(hits: &mut [SearchHit], sort: SearchSort) {
+    let by_title = |a: &SearchHit, b: &SearchHit| {
+        a.title
+            .to_lowercase()
+            .cmp(&b.title.to_lowercase())
+            .then_with(|| a.username.to_lowercase().cmp(&b.username.to_lowercase()))
+    };
+    hits.sort_by(|a, b| match sort {
+        SearchSort::Relevance => b.score.cmp(&a.score).then_with(|| by_title(a, b)),
+        SearchSort::TitleAsc => by_title(a, b),
+        SearchSort::TitleDesc => by_title(b, a),
+        SearchSort::UpdatedNewest => timestamp(&b.updated_at).cmp(&timestamp(&a.updated_at)),
+        SearchSort::UpdatedOldest => timestamp(&a.updated_at).cmp(&timestamp(&b.updated_at)),
+        SearchSort::CreatedNewest => timestamp(&b.created_at).cmp(&timestamp(&a.created_at)),
+        SearchSort::CreatedOldest => timestamp(&a.created_at).cmp(&timestamp(&b.created_at)),
+    });
+}
+
+/// Unparsable timestamps sort before every valid one.
+fn timestamp(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir};
+
+    /// "Email" holds a login, a reused and tagged favorite login with a TOTP secret, a
+    /// note and a TOTP-only entry.
+    fn sample_payload(dir: &TempDir) -> StoredVault {
+        let (vault, _, _) = testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let mut payload = vault.payload().clone();
+        let mail = payload.folders[0].credentials[0].clone();
+        let entry = |title: &str, username: &str, password: &str| StoredCredential {
+            id: format!("{}-id", title.to_lowercase()),
+            title: title.to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+            ..mail.clone()
+        };
+
+        let mut bank = entry("Bank", "bob", "first-password");
+        bank.tags = vec!["finance".to_string()];
+        bank.favorite = true;
+        bank.totp = Some("JBSWY3DPEHPK3PXP".to_string());
+        let mut note = entry("Recovery", "", "");
+        note.notes = Some("🔑 codes for the café account".to_string());
+        let mut authenticator = entry("Authenticator", "", "");
+        authenticator.totp = Some("JBSWY3DPEHPK3PXP".to_string());
+
+        payload.folders[0].credentials.extend([bank, note, authenticator]);
+        payload
+    }
+
+    fn titles(payload: &StoredVault, query: &str, filters: &SearchFilters) -> Vec<String> {
+        search(payload, query, filters, SearchSort::TitleAsc, None)
+            .unwrap()
+            .hits
+            .into_iter()
+            .map(|hit| hit.title)
+            .collect()
+    }
+
+    #[test]
+    fn ranks_and_highlights_matches() {
+        let dir = TempDir::new();
+        let payload = sample_payload(&dir);
+
+        let results =
+            search(&payload, "mail", &SearchFilters::default(), SearchSort::Relevance, None)
+                .unwrap();
+        assert_eq!(results.hits[0].title, "Mail");
+        let highlight = &results.hits[0].highlights[0];
+        assert!(highlight.field == SearchField::Title);
+        assert_eq!(highlight.ranges, [[0, 4]]);
+
+        // Every term has to match, fuzzy matches included.
+        assert_eq!(titles(&payload, "bnk bob", &SearchFilters::default()), ["Bank"]);
+        assert!(titles(&payload, "mail bob", &SearchFilters::default()).is_empty());
+        assert_eq!(titles(&payload, "finance", &SearchFilters::default()), ["Bank"]);
+    }
+
+    #[test]
+    fn highlights_notes_in_utf16_offsets() {
+        let dir = TempDir::new();
+        let payload = sample_payload(&dir);
+
+        let results =
+            search(&payload, "café", &SearchFilters::default(), SearchSort::Relevance, None)
+                .unwrap();
+        let [hit] = results.hits.as_slice() else {
+            panic!("expected one hit");
+        };
+        let highlight = &hit.highlights[0];
+        assert!(highlight.field == SearchField::Notes);
+        assert_eq!(highlight.snippet.as_deref(), Some("🔑 codes for the café account"));
+        // The key emoji takes two UTF-16 code units.
+        assert_eq!(highlight.ranges, [[17, 21]]);
+    }
+
+    #[test]
+    fn filters_credentials() {
+        let dir = TempDir::new();
+        let payload = sample_payload(&dir);
+        let filtered = |filters: SearchFilters| titles(&payload, "", &filters);
+
+        assert_eq!(filtered(SearchFilters::default()).len(), 4);
+        let kind = |kind| SearchFilters {
+            kind: Some(kind),
+            ..SearchFilters::default()
+        };
+        assert_eq!(filtered(kind(CredentialKind::Login)), ["Bank", "Mail"]);
+        assert_eq!(filtered(kind(CredentialKind::Note)), ["Recovery"]);
+        assert_eq!(filtered(kind(CredentialKind::Totp)), ["Authenticator"]);
+        assert_eq!(
+            filtered(SearchFilters {
+                has_totp: Some(true),
+                ..SearchFilters::default()
+            }),
+            ["Authenticator", "Bank"]
+        );
+        assert_eq!(
+            filtered(SearchFilters {
+                reused: true,
+                ..SearchFilters::default()
+            }),
+            ["Bank", "Mail"]
+        );
+        assert_eq!(
+            filtered(SearchFilters {
+                tags: vec!["Finance".to_string()],
+                favorites: true,
+                ..SearchFilters::default()
+            }),
+            ["Bank"]
+        );
+        assert!(filtered(SearchFilters {
+            folder_ids: vec!["another-folder".to_string()],
+            ..SearchFilters::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn sorts_and_limits_results() {
+        let dir = TempDir::new();
+        let payload = sample_payload(&dir);
+
+        let results =
+            search(&payload, "", &SearchFilters::default(), SearchSort::TitleDesc, Some(2))
+                .unwrap();
+        assert_eq!(results.total, 4);
+        let titles: Vec<&str> = results.hits.iter().map(|hit| hit.title.as_str()).collect();
+        assert_eq!(titles, ["Recovery", "Mail"]);
+    }
+}
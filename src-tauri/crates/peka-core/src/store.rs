@@ -0,0 +1,363 @@
+use chrono::{DateTime, Utc};
+use directories::ProjectDirs;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::attachments;
+use crate::audit::AuditAction;
+use crate::backup;
+use crate::crypto;
+use crate::history;
+use crate::lock;
+use crate::sync;
+use crate::{PekaError, StorageFormat, Vault, VaultStatus, VaultSummary};
+
+/// A directory holding `.peka` vault files. The app keeps its vaults in
+/// [`VaultStore::default_location`]; tools and tests can point a store anywhere.
+#[derive(Clone)]
+pub struct VaultStore {
+    dir: PathBuf,
+}
+
+impl VaultStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        VaultStore { dir: dir.into() }
+    }
+
+    /// The per-user data directory of the app, or `./vaults` when the platform has
+    /// none.
+    pub fn default_location() -> Result<Self, PekaError> {
+        if let Some(proj_dirs) = ProjectDirs::from("com", "nalsan", "peka") {
+            return Ok(VaultStore::new(proj_dirs.data_dir().join("vaults")));
+        }
+        let mut dir = std::env::current_dir()
+            .map_err(|e| PekaError::io("Unable to resolve the current directory", e))?;
+        dir.push("vaults");
+        Ok(VaultStore::new(dir))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// File a vault with this name is stored at.
+    pub fn path_for(&self, vault_name: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.peka", sanitize_file_name(vault_name)))
+    }
+
+    /// Vaults in the directory, including files that are not valid vaults.
+    pub fn list(&self) -> Result<Vec<VaultSummary>, PekaError> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut summaries = Vec::new();
+        let unreadable = |e| PekaError::io("Unable to read the vault directory", e);
+        for entry in fs::read_dir(&self.dir).map_err(unreadable)? {
+            let entry = entry.map_err(unreadable)?;
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            if path.extension().and_then(|ext| ext.to_str()) != Some("peka") {
+                continue;
+            }
+
+            summaries.push(read_summary(&path));
+        }
+
+        Ok(summaries)
+    }
+
+    pub fn create(&self, vault_name: &str, master_password: &str) -> Result<Vault, PekaError> {
+        let trimmed_vault_name = vault_name.trim();
+        if trimmed_vault_name.is_empty() {
+            return Err(PekaError::InvalidInput("Vault name cannot be empty".to_string()));
+        }
+
+        self.create_dir()?;
+        Vault::create(
+            self.path_for(trimmed_vault_name),
+            trimmed_vault_name,
+            master_password,
+        )
+    }
+
+    /// Copies a vault file from elsewhere into the store under a new name. The source
+    /// is decrypted first, so only vaults the caller can open are taken in; see
+    /// [`Vault::open_external`] for `allow_expensive_kdf`.
+    pub fn import(
+        &self,
+        source_path: &Path,
+        vault_name: &str,
+        master_password: &str,
+        allow_expensive_kdf: bool,
+    ) -> Result<Vault, PekaError> {
+        let trimmed_vault_name = vault_name.trim();
+        if trimmed_vault_name.is_empty() {
+            return Err(PekaError::InvalidInput("Vault name cannot be empty".to_string()));
+        }
+
+        if master_password.trim().is_empty() {
+            return Err(PekaError::InvalidInput(
+                "Master password cannot be empty".to_string(),
+            ));
+        }
+
+        if !source_path.exists() {
+            return Err(PekaError::NotFound("Source vault file not found".to_string()));
+        }
+
+        if source_path.extension().and_then(|ext| ext.to_str()) != Some("peka") {
+            return Err(PekaError::InvalidInput(
+                "Source file must be a .peka vault file".to_string(),
+            ));
+        }
+
+        // Validate the master password by trying to decrypt the vault
+        let mut vault = Vault::open_external(source_path, master_password, allow_expensive_kdf)?;
+
+        self.create_dir()?;
+        let destination_path = self.path_for(trimmed_vault_name);
+
+        // Check if a vault with this name already exists
+        if destination_path.exists() {
+            return Err(PekaError::AlreadyExists(format!(
+                "A vault with the name '{}' already exists",
+                trimmed_vault_name
+            )));
+        }
+
+        // Re-encrypted rather than copied so the import lands in the vault's audit log.
+        vault.record(AuditAction::VaultImported, None);
+        vault.save_as(&destination_path)?;
+        Ok(vault)
+    }
+
+    /// Deletes a vault file of this store and its attachments.
+    pub fn delete(&self, path: &Path) -> Result<(), PekaError> {
+        if !path.exists() {
+            return Err(PekaError::NotFound("Vault file not found".to_string()));
+        }
+
+        let canonical_target = self.resolve(path, "Unable to resolve vault path")?;
+        delete_files(&canonical_target)
+    }
+
+    /// Copies a vault file of this store and its attachments to `destination`, see
+    /// [`Vault::copy_file_to`].
+    pub fn copy_out(
+        &self,
+        source: &Path,
+        master_password: &str,
+        destination: &Path,
+    ) -> Result<(), PekaError> {
+        if !source.exists() {
+            return Err(PekaError::NotFound("Vault file not found".to_string()));
+        }
+
+        let canonical_source = self.resolve(source, "Unable to resolve source path")?;
+        Vault::open(canonical_source, master_password)?.copy_file_to(destination)
+    }
+
+    /// Whether the canonical path `canonical` lies inside the store.
+    pub(crate) fn contains(&self, canonical: &Path) -> bool {
+        let canonical_dir = self.dir.canonicalize().unwrap_or(self.dir.clone());
+        canonical.starts_with(&canonical_dir)
+    }
+
+    /// Canonical path of `path`, which must be a `.peka` file inside the store.
+    fn resolve(&self, path: &Path, unresolved: &str) -> Result<PathBuf, PekaError> {
+        let canonical = canonical_vault_path(path, unresolved)?;
+        if !self.contains(&canonical) {
+            return Err(PekaError::InvalidInput("Vault path is invalid".to_string()));
+        }
+        Ok(canonical)
+    }
+
+    fn create_dir(&self) -> Result<(), PekaError> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| PekaError::io("Unable to create the vault directory", e))
+    }
+}
+
+/// Describes the vault file at `path` from its metadata and unencrypted header,
+/// without the master password. Problems with the file are reported in the status.
+pub fn read_summary(path: &Path) -> VaultSummary {
+    let mut summary = VaultSummary {
+        path: path.to_string_lossy().to_string(),
+        vault_name: path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        location_id: None,
+        status: VaultStatus::Ok,
+        status_reason: None,
+        size_bytes: 0,
+        modified_at: None,
+        format_version: None,
+        kdf: None,
+        storage: None,
+        read_only: false,
+        locked_by: lock::owner(path),
+    };
+
+    match fs::metadata(path) {
+        Ok(metadata) => {
+            summary.size_bytes = metadata.len();
+            summary.modified_at = metadata
+                .modified()
+                .ok()
+                .map(|modified| DateTime::<Utc>::from(modified).to_rfc3339());
+            summary.read_only = metadata.permissions().readonly();
+        }
+        Err(e) => {
+            summary.status = VaultStatus::Unreadable;
+            summary.status_reason = Some(e.to_string());
+            return summary;
+        }
+    }
+
+    let header = crypto::read_vault_file(path).and_then(|vault_file| {
+        crypto::check_header(&vault_file)?;
+        Ok(vault_file)
+    });
+    match header {
+        Ok(vault_file) => {
+            summary.vault_name = vault_file.vault_name;
+            summary.format_version = Some(vault_file.version);
+            summary.kdf = Some(vault_file.kdf);
+            summary.storage = Some(StorageFormat {
+                container: vault_file.container,
+                compression: vault_file.compression,
+                layout: vault_file.layout,
+            });
+        }
+        Err(err) => {
+            summary.status = match err {
+                PekaError::UnsupportedVersion(_) => VaultStatus::UnsupportedVersion,
+                PekaError::Unsupported(_) => VaultStatus::UnsupportedFeature,
+                PekaError::UnsafeParameters(_) => VaultStatus::UnsafeParameters,
+                PekaError::Corrupted { .. } => VaultStatus::Corrupted,
+                _ => VaultStatus::Unreadable,
+            };
+            summary.status_reason = Some(match err.details() {
+                Some(details) => format!("{}: {}", err, details),
+                None => err.to_string(),
+            });
+        }
+    }
+
+    summary
+}
+
+/// Canonical form of `path`, which must name a `.peka` file.
+pub(crate) fn canonical_vault_path(path: &Path, unresolved: &str) -> Result<PathBuf, PekaError> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| PekaError::io(unresolved, e))?;
+
+    if canonical.extension().and_then(|ext| ext.to_str()) != Some("peka") {
+        return Err(PekaError::InvalidInput("Invalid vault file".to_string()));
+    }
+
+    Ok(canonical)
+}
+
+/// Deletes the vault file at the canonical path `vault_path`, its attachments, its
+/// backups, its sync state and its history. A shared copy in the sync directory and
+/// history pushed to a remote are left alone.
+pub(crate) fn delete_files(vault_path: &Path) -> Result<(), PekaError> {
+    let attachment_dir = attachments::attachment_dir(vault_path);
+    let backup_dir = backup::backup_dir(vault_path);
+    let sync_dir = sync::sync_dir(vault_path);
+    let history_dir = history::history_dir(vault_path);
+    let vault_lock = lock::acquire(vault_path)?;
+    fs::remove_file(vault_path).map_err(|e| PekaError::io("Unable to delete vault file", e))?;
+    if attachment_dir.is_dir() {
+        fs::remove_dir_all(attachment_dir)
+            .map_err(|e| PekaError::io("Unable to delete vault attachments", e))?;
+    }
+    if backup_dir.is_dir() {
+        fs::remove_dir_all(backup_dir)
+            .map_err(|e| PekaError::io("Unable to delete vault backups", e))?;
+    }
+    if sync_dir.is_dir() {
+        fs::remove_dir_all(sync_dir)
+            .map_err(|e| PekaError::io("Unable to delete the vault sync state", e))?;
+    }
+    if history_dir.is_dir() {
+        fs::remove_dir_all(history_dir)
+            .map_err(|e| PekaError::io("Unable to delete the vault history", e))?;
+    }
+    drop(vault_lock);
+    lock::remove(vault_path);
+    Ok(())
+}
+
+/// Copies the vault file at the canonical path `vault_path` and its attachments to
+/// `destination` unchanged. The caller has the vault open, so no save gets in the way.
+pub(crate) fn copy_files(vault_path: &Path, destination: &Path) -> Result<(), PekaError> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| PekaError::io("Unable to create destination directory", e))?;
+    }
+
+    fs::copy(vault_path, destination).map_err(|e| PekaError::io("Unable to copy vault file", e))?;
+    attachments::copy_all(vault_path, destination)?;
+    Ok(())
+}
+
+fn sanitize_file_name(input: &str) -> String {
+    let sanitized: String = input
+        .trim()
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let trimmed = sanitized.trim_matches('_');
+
+    if trimmed.is_empty() {
+        "vault".to_string()
+    } else {
+        trimmed.replace("__", "_")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir};
+
+    /// The vault file at `path` with its JSON header changed by `edit`.
+    fn edit_header(path: &Path, edit: impl FnOnce(&mut serde_json::Value)) {
+        let mut header: serde_json::Value =
+            serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+        edit(&mut header);
+        fs::write(path, serde_json::to_vec(&header).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn tells_a_newer_version_from_an_unsupported_feature() {
+        let dir = TempDir::new();
+        let (vault, _, _) = testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let path = vault.path().to_path_buf();
+        drop(vault);
+        assert_eq!(read_summary(&path).status, VaultStatus::Ok);
+
+        edit_header(&path, |header| header["kdf"]["algorithm"] = "scrypt".into());
+        let summary = read_summary(&path);
+        assert_eq!(summary.status, VaultStatus::UnsupportedFeature);
+        assert!(summary.status_reason.unwrap().contains("scrypt"));
+
+        edit_header(&path, |header| header["version"] = 99.into());
+        assert_eq!(read_summary(&path).status, VaultStatus::UnsupportedVersion);
+    }
+}
@@ -0,0 +1,288 @@
+use serde::Serialize;
+
+use crate::PekaError;
+
+/// Minimum score a master password has to reach. The frontend checks the score of
+/// [`estimate_strength`] against the same bound while the password is typed.
+pub const MIN_MASTER_PASSWORD_SCORE: u8 = 3;
+
+const LOWERCASE_POOL: f64 = 26.0;
+const UPPERCASE_POOL: f64 = 26.0;
+const DIGIT_POOL: f64 = 10.0;
+const SYMBOL_POOL: f64 = 33.0;
+const OTHER_POOL: f64 = 100.0;
+
+// Bits credited to a character that only continues a repeat, sequence or keyboard run.
+const RUN_CHAR_BITS: f64 = 1.0;
+// Bits credited to a whole dictionary hit, roughly log2 of a large wordlist.
+const DICTIONARY_WORD_BITS: f64 = 11.0;
+
+const KEYBOARD_ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+const COMMON_WORDS: &[&str] = &[
+    "password", "passw0rd", "qwerty", "letmein", "welcome", "admin", "login", "iloveyou", "monkey",
+    "dragon", "master", "secret", "sunshine", "princess", "football", "baseball", "shadow",
+    "superman", "batman", "trustno1", "hello", "freedom", "whatever", "starwars", "computer",
+    "michael", "jennifer", "charlie", "summer", "winter", "spring", "autumn", "peka", "vault",
+    "changeme", "default", "abc123", "pokemon", "hunter", "killer", "soccer", "hockey", "ranger",
+    "buster", "ginger", "cookie", "cheese", "orange", "banana", "flower", "purple", "pepper",
+    "matrix", "access", "mustang", "internet",
+];
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordStrength {
+    /// Score from 0 (trivially guessable) to 4 (very strong).
+    pub score: u8,
+    pub entropy_bits: f64,
+    pub warnings: Vec<String>,
+    pub suggestions: Vec<String>,
+}
+
+/// Estimates the strength of `password`. `user_inputs` holds context such as the vault
+/// name or username, which an attacker would try first and therefore earns no credit.
+pub fn estimate_strength(password: &str, user_inputs: &[&str]) -> PasswordStrength {
+    let mut warnings = Vec::new();
+    let mut suggestions = Vec::new();
+
+    if password.is_empty() {
+        return PasswordStrength {
+            score: 0,
+            entropy_bits: 0.0,
+            warnings: vec!["Password is empty".to_string()],
+            suggestions: vec!["Use a long passphrase or a generated password".to_string()],
+        };
+    }
+
+    let chars: Vec<char> = password.chars().collect();
+    let lowered: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let bits_per_char = character_pool(&chars).log2();
+
+    // Mark characters covered by dictionary words and user inputs; each hit is
+    // credited once as a whole instead of per character.
+    let mut covered = vec![false; chars.len()];
+    let mut dictionary_bits = 0.0;
+    let mut found_common = false;
+    let mut found_user_input = false;
+
+    let user_words: Vec<String> = user_inputs
+        .iter()
+        .map(|input| input.trim().to_lowercase())
+        .filter(|input| input.chars().count() >= 3)
+        .collect();
+
+    for word in user_words.iter().map(String::as_str) {
+        if mark_occurrences(&lowered, word, &mut covered) {
+            dictionary_bits += 1.0;
+            found_user_input = true;
+        }
+    }
+
+    for word in COMMON_WORDS {
+        if mark_occurrences(&lowered, word, &mut covered) {
+            dictionary_bits += DICTIONARY_WORD_BITS;
+            found_common = true;
+        }
+    }
+
+    let mut entropy_bits = dictionary_bits;
+    let mut run_length = 0usize;
+    let mut found_run = false;
+
+    for (index, ch) in lowered.iter().enumerate() {
+        if covered[index] {
+            run_length = 0;
+            continue;
+        }
+
+        let continues_run =
+            index > 0 && !covered[index - 1] && is_run_step(lowered[index - 1], *ch);
+        if continues_run {
+            run_length += 1;
+        } else {
+            run_length = 0;
+        }
+
+        // The second character of a run may still be a coincidence; from the third on
+        // the pattern is obvious to any cracking tool.
+        if run_length >= 2 {
+            entropy_bits += RUN_CHAR_BITS;
+            found_run = true;
+        } else {
+            entropy_bits += bits_per_char;
+        }
+    }
+
+    if found_common {
+        warnings.push("Contains a commonly used password or word".to_string());
+        suggestions.push("Avoid common words and well-known passwords".to_string());
+    }
+    if found_user_input {
+        warnings.push("Contains the vault name or username".to_string());
+        suggestions.push("Do not reuse names that are stored next to the password".to_string());
+    }
+    if found_run {
+        warnings.push("Contains repeated characters, sequences or keyboard patterns".to_string());
+        suggestions.push("Avoid patterns like 'aaa', 'abc', '123' or 'qwerty'".to_string());
+    }
+    if chars.len() < 12 {
+        suggestions.push("Use at least 12 characters".to_string());
+    }
+
+    let score = score_from_bits(entropy_bits);
+    if score < MIN_MASTER_PASSWORD_SCORE && suggestions.is_empty() {
+        suggestions.push("Add more words or characters to make it longer".to_string());
+    }
+
+    PasswordStrength {
+        score,
+        entropy_bits: (entropy_bits * 10.0).round() / 10.0,
+        warnings,
+        suggestions,
+    }
+}
+
+/// Rejects master passwords below [`MIN_MASTER_PASSWORD_SCORE`].
+pub fn validate_master_password(
+    master_password: &str,
+    user_inputs: &[&str],
+) -> Result<(), PekaError> {
+    if master_password.trim().is_empty() {
+        return Err(PekaError::InvalidInput("Master password cannot be empty".to_string()));
+    }
+
+    let strength = estimate_strength(master_password, user_inputs);
+    if strength.score < MIN_MASTER_PASSWORD_SCORE {
+        return Err(PekaError::PolicyViolation(
+            "Password is too weak. Please use a stronger password".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn character_pool(chars: &[char]) -> f64 {
+    let mut pool = 0.0;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        pool += LOWERCASE_POOL;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        pool += UPPERCASE_POOL;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        pool += DIGIT_POOL;
+    }
+    if chars
+        .iter()
+        .any(|c| c.is_ascii() && !c.is_ascii_alphanumeric())
+    {
+        pool += SYMBOL_POOL;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        pool += OTHER_POOL;
+    }
+    pool
+}
+
+fn score_from_bits(bits: f64) -> u8 {
+    if bits < 28.0 {
+        0
+    } else if bits < 36.0 {
+        1
+    } else if bits < 50.0 {
+        2
+    } else if bits < 64.0 {
+        3
+    } else {
+        4
+    }
+}
+
+fn is_run_step(previous: char, current: char) -> bool {
+    if previous == current {
+        return true;
+    }
+
+    let delta = current as i64 - previous as i64;
+    if (delta == 1 || delta == -1)
+        && previous.is_ascii_alphanumeric()
+        && current.is_ascii_alphanumeric()
+    {
+        return true;
+    }
+
+    KEYBOARD_ROWS.iter().any(|row| {
+        let row: Vec<char> = row.chars().collect();
+        row.windows(2).any(|pair| {
+            (pair[0] == previous && pair[1] == current)
+                || (pair[1] == previous && pair[0] == current)
+        })
+    })
+}
+
+fn mark_occurrences(haystack: &[char], word: &str, covered: &mut [bool]) -> bool {
+    let needle: Vec<char> = word.chars().collect();
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return false;
+    }
+
+    let mut found = false;
+    for start in 0..=haystack.len() - needle.len() {
+        let end = start + needle.len();
+        if haystack[start..end] == needle[..] && !covered[start..end].iter().any(|c| *c) {
+            covered[start..end].iter_mut().for_each(|c| *c = true);
+            found = true;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_passwords_around_the_master_password_bound() {
+        let scores = [
+            ("Password1!", 0),
+            ("password123!", 1),
+            ("Summer2024!", 2),
+            ("Kx9#mQ2$", 3),
+            ("Kx9#mQ2$vL", 4),
+        ];
+        for (password, score) in scores {
+            assert_eq!(estimate_strength(password, &[]).score, score, "{}", password);
+        }
+
+        assert!(matches!(
+            validate_master_password("Summer2024!", &[]),
+            Err(PekaError::PolicyViolation(_))
+        ));
+        assert!(validate_master_password("Kx9#mQ2$", &[]).is_ok());
+        assert!(matches!(
+            validate_master_password("  ", &[]),
+            Err(PekaError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn gives_no_credit_for_user_inputs() {
+        let without = estimate_strength("Work-Kx9#mQ2$", &[]);
+        let with = estimate_strength("Work-Kx9#mQ2$", &["Work"]);
+        assert!(with.entropy_bits < without.entropy_bits);
+        assert!(with.warnings.iter().any(|warning| warning.contains("vault name")));
+    }
+
+    #[test]
+    fn gives_little_credit_for_patterns() {
+        for password in ["abcdefghijkl", "aaaaaaaaaaaaaaaa", "qwertyuiop12"] {
+            let strength = estimate_strength(password, &[]);
+            assert!(strength.score < MIN_MASTER_PASSWORD_SCORE, "{}", password);
+            assert!(strength.warnings.iter().any(|warning| warning.contains("patterns")));
+        }
+        assert_eq!(estimate_strength("", &[]).score, 0);
+    }
+}
@@ -0,0 +1,1013 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::audit;
+use crate::merge;
+use crate::{PekaError, StoredCredential, StoredFolder, StoredVault, VaultContents};
+
+const STATE_FILE_NAME: &str = "state.json";
+const BASE_FILE_NAME: &str = "base.peka";
+
+/// Edits of a folder or credential counted per copy of the vault. A version whose
+/// counts are all at least those of another has seen every edit of the other.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+#[serde(transparent)]
+pub struct RevisionVector(BTreeMap<String, u64>);
+
+impl RevisionVector {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Counts one more edit by the copy `replica_id`.
+    pub fn bump(&mut self, replica_id: &str) {
+        *self.0.entry(replica_id.to_string()).or_insert(0) += 1;
+    }
+
+    /// `Less` when `other` has seen every edit of this version, `Greater` the other
+    /// way round, `None` when both have edits the other has not seen.
+    pub fn compare(&self, other: &RevisionVector) -> Option<Ordering> {
+        let mut ordering = Ordering::Equal;
+        for replica_id in self.0.keys().chain(other.0.keys()) {
+            let mine = self.0.get(replica_id).copied().unwrap_or(0);
+            let theirs = other.0.get(replica_id).copied().unwrap_or(0);
+            match mine.cmp(&theirs) {
+                Ordering::Equal => {}
+                step if ordering == Ordering::Equal => ordering = step,
+                step if step != ordering => return None,
+                _ => {}
+            }
+        }
+        Some(ordering)
+    }
+
+    /// The version that has seen the edits of both.
+    fn joined(&self, other: &RevisionVector) -> RevisionVector {
+        let mut joined = self.clone();
+        for (replica_id, count) in &other.0 {
+            let entry = joined.0.entry(replica_id.clone()).or_insert(0);
+            *entry = (*entry).max(*count);
+        }
+        joined
+    }
+}
+
+/// Where a vault syncs to and what the last sync left behind. Kept in `state.json` of
+/// the vault's sync directory, e.g. `work.sync/` next to `work.peka`, together with
+/// the copy of the vault the last sync agreed on, which is the base of three-way
+/// merges.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SyncState {
+    /// Identifies this copy of the vault in revision vectors.
+    pub(crate) replica_id: String,
+    directory: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_synced_at: Option<String>,
+    /// SHA-256 of the vault file and of the shared copy as the last sync left them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    local_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shared_hash: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    pub directory: String,
+    /// The copy of the vault in the sync directory.
+    pub shared_path: String,
+    pub shared_exists: bool,
+    pub last_synced_at: Option<String>,
+    /// The vault changed here since the last sync.
+    pub local_changed: bool,
+    /// The copy in the sync directory changed since the last sync, e.g. because
+    /// another device synced.
+    pub remote_changed: bool,
+}
+
+/// Which side of a conflict to keep.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncResolution {
+    Local,
+    Remote,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncRecordKind {
+    Folder,
+    Credential,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncConflictKind {
+    /// Both copies changed the same fields differently.
+    BothModified,
+    /// Deleted here, changed in the other copy.
+    DeletedLocally,
+    /// Changed here, deleted in the other copy.
+    DeletedRemotely,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncConflict {
+    pub record_id: String,
+    pub kind: SyncRecordKind,
+    pub conflict: SyncConflictKind,
+    /// Folder name or credential title.
+    pub title: String,
+    /// Names of the fields both copies changed; the values themselves are not sent.
+    pub fields: Vec<String>,
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    /// Nothing is applied while conflicts are unresolved.
+    pub applied: bool,
+    /// Folders and credentials changed here since the last sync.
+    pub pushed: usize,
+    /// Folders and credentials added or changed in the other copy and taken from it.
+    pub pulled: usize,
+    /// Folders and credentials both copies changed, merged field by field.
+    pub merged: usize,
+    /// Folders and credentials deleted in one copy and removed from the other.
+    pub deleted: usize,
+    pub conflicts: Vec<SyncConflict>,
+    /// The copy in the sync directory was written.
+    pub published: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vault: Option<VaultContents>,
+}
+
+/// Directory holding the sync state of the vault at `vault_path`, e.g. `work.sync/`
+/// next to `work.peka`.
+pub fn sync_dir(vault_path: &Path) -> PathBuf {
+    let stem = vault_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "vault".to_string());
+    vault_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(format!("{}.sync", stem))
+}
+
+/// Links the vault at `vault_path` to `directory`, where its shared copy is kept
+/// under the same file name. Relinking to another directory starts over with a
+/// first sync.
+pub fn link(vault_path: &Path, directory: &Path) -> Result<SyncStatus, PekaError> {
+    let directory = directory
+        .canonicalize()
+        .map_err(|e| PekaError::io("Unable to resolve the sync directory", e))?;
+    if !directory.is_dir() {
+        return Err(PekaError::InvalidInput(
+            "Sync directory is not a directory".to_string(),
+        ));
+    }
+    let vault_dir = vault_path
+        .canonicalize()
+        .map_err(|e| PekaError::io("Unable to resolve vault path", e))?
+        .parent()
+        .map(Path::to_path_buf);
+    if vault_dir.as_deref() == Some(directory.as_path()) {
+        return Err(PekaError::InvalidInput(
+            "The sync directory must not be the directory of the vault".to_string(),
+        ));
+    }
+
+    let directory = directory.to_string_lossy().to_string();
+    let state = match load_state(vault_path)? {
+        Some(state) if state.directory == directory => state,
+        previous => {
+            let _ = fs::remove_file(sync_dir(vault_path).join(BASE_FILE_NAME));
+            SyncState {
+                replica_id: previous
+                    .map(|state| state.replica_id)
+                    .unwrap_or_else(|| Uuid::new_v4().to_string()),
+                directory,
+                last_synced_at: None,
+                local_hash: None,
+                shared_hash: None,
+            }
+        }
+    };
+    save_state(vault_path, &state)?;
+    Ok(state.status(vault_path))
+}
+
+/// Stops syncing the vault at `vault_path`. Neither the vault nor its shared copy is
+/// touched.
+pub fn unlink(vault_path: &Path) -> Result<(), PekaError> {
+    let dir = sync_dir(vault_path);
+    if !dir.join(STATE_FILE_NAME).is_file() {
+        return Err(not_linked());
+    }
+    fs::remove_dir_all(dir).map_err(|e| PekaError::io("Unable to remove the sync state", e))
+}
+
+/// Where the vault at `vault_path` syncs to and whether either side changed since the
+/// last sync, without the master password. `None` when it is not linked.
+pub fn status(vault_path: &Path) -> Result<Option<SyncStatus>, PekaError> {
+    Ok(load_state(vault_path)?.map(|state| state.status(vault_path)))
+}
+
+pub(crate) fn load_state(vault_path: &Path) -> Result<Option<SyncState>, PekaError> {
+    match fs::read_to_string(sync_dir(vault_path).join(STATE_FILE_NAME)) {
+        Ok(raw) => serde_json::from_str(&raw)
+            .map(Some)
+            .map_err(|e| PekaError::corrupted("Vault sync state is invalid", e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(PekaError::io("Unable to read the vault sync state", e)),
+    }
+}
+
+pub(crate) fn linked_state(vault_path: &Path) -> Result<SyncState, PekaError> {
+    load_state(vault_path)?.ok_or_else(not_linked)
+}
+
+fn save_state(vault_path: &Path, state: &SyncState) -> Result<(), PekaError> {
+    let dir = sync_dir(vault_path);
+    fs::create_dir_all(&dir)
+        .map_err(|e| PekaError::io("Unable to create the sync state directory", e))?;
+    let json = serde_json::to_string_pretty(state).map_err(PekaError::internal)?;
+    fs::write(dir.join(STATE_FILE_NAME), json)
+        .map_err(|e| PekaError::io("Unable to write the vault sync state", e))
+}
+
+fn not_linked() -> PekaError {
+    PekaError::InvalidInput("Vault is not linked to a sync directory".to_string())
+}
+
+impl SyncState {
+    pub(crate) fn shared_path(&self, vault_path: &Path) -> PathBuf {
+        let file_name = vault_path
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_else(|| "vault.peka".into());
+        Path::new(&self.directory).join(file_name)
+    }
+
+    pub(crate) fn base_path(&self, vault_path: &Path) -> PathBuf {
+        sync_dir(vault_path).join(BASE_FILE_NAME)
+    }
+
+    /// Whether the vault file and the shared copy are as the last sync left them.
+    pub(crate) fn in_sync(&self, vault_path: &Path) -> bool {
+        let shared_hash = file_hash(&self.shared_path(vault_path));
+        shared_hash.is_some()
+            && shared_hash == self.shared_hash
+            && file_hash(vault_path) == self.local_hash
+            && self.base_path(vault_path).is_file()
+    }
+
+    /// Whether the shared copy is still the one the last sync saw.
+    pub(crate) fn shared_unchanged(&self, vault_path: &Path) -> bool {
+        let shared_hash = file_hash(&self.shared_path(vault_path));
+        shared_hash.is_some() && shared_hash == self.shared_hash
+    }
+
+    /// Records that the sync left the vault file and the shared copy as they are now,
+    /// and keeps `base` as the base of the next merge.
+    pub(crate) fn finish(&mut self, vault_path: &Path, base: &[u8]) -> Result<(), PekaError> {
+        fs::create_dir_all(sync_dir(vault_path))
+            .map_err(|e| PekaError::io("Unable to create the sync state directory", e))?;
+        fs::write(self.base_path(vault_path), base)
+            .map_err(|e| PekaError::io("Unable to write the sync base", e))?;
+        self.local_hash = file_hash(vault_path);
+        self.shared_hash = file_hash(&self.shared_path(vault_path));
+        self.last_synced_at = Some(Utc::now().to_rfc3339());
+        save_state(vault_path, self)
+    }
+
+    fn status(&self, vault_path: &Path) -> SyncStatus {
+        let shared_path = self.shared_path(vault_path);
+        let shared_hash = file_hash(&shared_path);
+        SyncStatus {
+            directory: self.directory.clone(),
+            shared_path: shared_path.to_string_lossy().to_string(),
+            shared_exists: shared_hash.is_some(),
+            last_synced_at: self.last_synced_at.clone(),
+            local_changed: file_hash(vault_path) != self.local_hash,
+            remote_changed: shared_hash.is_some() && shared_hash != self.shared_hash,
+        }
+    }
+}
+
+/// Writes `contents` as the shared copy, through a temporary file so other devices
+/// never see half of it.
+pub(crate) fn publish(shared_path: &Path, contents: &[u8]) -> Result<(), PekaError> {
+    let file_name = shared_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temporary = shared_path.with_file_name(format!(".{}.tmp", file_name));
+    fs::write(&temporary, contents)
+        .map_err(|e| PekaError::io("Unable to write to the sync directory", e))?;
+    fs::rename(&temporary, shared_path)
+        .map_err(|e| PekaError::io("Unable to write to the sync directory", e))
+}
+
+fn file_hash(path: &Path) -> Option<String> {
+    let contents = fs::read(path).ok()?;
+    Some(
+        Sha256::digest(contents)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect(),
+    )
+}
+
+/// Counts an edit by `replica_id` on every folder and credential of `payload` that
+/// differs from `base`, the version the copies last agreed on. Returns how many there
+/// were.
+pub(crate) fn stamp(
+    payload: &mut StoredVault,
+    base: Option<&StoredVault>,
+    replica_id: &str,
+) -> usize {
+    let base_folders: HashMap<_, _> = base
+        .map(folder_records)
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let base_credentials: HashMap<_, _> = base
+        .map(credential_records)
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let changed = |records: &HashMap<String, Record>, id: &str, record: Record| {
+        records
+            .get(id)
+            .is_none_or(|base| base.fields != record.fields)
+    };
+
+    let mut stamped = 0;
+    for folder in &mut payload.folders {
+        for cred in &mut folder.credentials {
+            if changed(
+                &base_credentials,
+                &cred.id,
+                credential_record(&folder.id, cred),
+            ) {
+                cred.revision.bump(replica_id);
+                stamped += 1;
+            }
+        }
+        if changed(&base_folders, &folder.id, folder_record(folder)) {
+            folder.revision.bump(replica_id);
+            stamped += 1;
+        }
+    }
+    stamped
+}
+
+/// The outcome of merging two copies of a vault.
+pub(crate) struct SyncMerge {
+    pub(crate) payload: StoredVault,
+    pub(crate) pulled: usize,
+    pub(crate) merged: usize,
+    pub(crate) deleted: usize,
+    pub(crate) conflicts: Vec<SyncConflict>,
+}
+
+/// Three-way merges the folders and credentials of `local` and `remote`, the two
+/// copies of a vault, against `base`, the version both started from. A record changed
+/// on one side only is taken from that side; one changed on both is merged field by
+/// field, unless its revision vectors show that one side has seen the other's edits.
+/// Fields changed differently on both sides, and records deleted on one side but
+/// changed on the other, are conflicts unless `resolutions` settles them. Without a
+/// base, as on a first sync, the copies are joined and nothing is deleted.
+///
+/// The trash is merged as a set, and the audit log entries made here are appended to
+/// the other copy's log.
+pub(crate) fn merge(
+    local: &StoredVault,
+    remote: &StoredVault,
+    base: Option<&StoredVault>,
+    replica_id: &str,
+    resolutions: &HashMap<String, SyncResolution>,
+) -> Result<SyncMerge, PekaError> {
+    let mut outcome = SyncMerge {
+        payload: local.clone(),
+        pulled: 0,
+        merged: 0,
+        deleted: 0,
+        conflicts: Vec::new(),
+    };
+    let mut merger = Merger {
+        replica_id,
+        resolutions,
+        outcome: &mut outcome,
+    };
+
+    let folders = merger.merge_records(
+        SyncRecordKind::Folder,
+        folder_records(local),
+        folder_records(remote),
+        base.map(folder_records).unwrap_or_default(),
+    );
+    let credentials = merger.merge_records(
+        SyncRecordKind::Credential,
+        credential_records(local),
+        credential_records(remote),
+        base.map(credential_records).unwrap_or_default(),
+    );
+    if !outcome.conflicts.is_empty() {
+        return Ok(outcome);
+    }
+
+    let mut folders = folders
+        .into_iter()
+        .map(|record| record.into_folder())
+        .collect::<Result<Vec<_>, _>>()?;
+    for record in credentials {
+        let folder_id = record.folder_id();
+        let position = match folders.iter().position(|folder| folder.id == folder_id) {
+            Some(position) => position,
+            // The folder was deleted on one side while the other added or changed
+            // credentials in it, so it comes back with just those.
+            None => {
+                let Some(folder) = [local, remote]
+                    .iter()
+                    .flat_map(|payload| &payload.folders)
+                    .find(|folder| folder.id == folder_id)
+                else {
+                    continue;
+                };
+                folders.push(StoredFolder {
+                    credentials: Vec::new(),
+                    ..folder.clone()
+                });
+                folders.len() - 1
+            }
+        };
+        folders[position]
+            .credentials
+            .push(record.into_credential()?);
+    }
+
+    let base_trash: Option<HashSet<&str>> =
+        base.map(|base| base.trash.iter().map(|item| item.id.as_str()).collect());
+    let local_trash: HashSet<&str> = local.trash.iter().map(|item| item.id.as_str()).collect();
+    let remote_trash: HashSet<&str> = remote.trash.iter().map(|item| item.id.as_str()).collect();
+    let kept_in = |others: &HashSet<&str>, id: &str| {
+        others.contains(id) || !base_trash.as_ref().is_some_and(|base| base.contains(id))
+    };
+    let mut trash: Vec<_> = local
+        .trash
+        .iter()
+        .filter(|item| kept_in(&remote_trash, &item.id))
+        .cloned()
+        .collect();
+    trash.extend(
+        remote
+            .trash
+            .iter()
+            .filter(|item| !local_trash.contains(item.id.as_str()))
+            .filter(|item| kept_in(&local_trash, &item.id))
+            .cloned(),
+    );
+
+    outcome.payload = StoredVault {
+        vault_name: pick(
+            &local.vault_name,
+            &remote.vault_name,
+            base.map(|base| &base.vault_name),
+        ),
+        folders,
+        trash,
+        trash_retention_days: pick(
+            &local.trash_retention_days,
+            &remote.trash_retention_days,
+            base.map(|base| &base.trash_retention_days),
+        ),
+        audit_log: audit::join(&local.audit_log, &remote.audit_log),
+    };
+    Ok(outcome)
+}
+
+/// A folder without its credentials, or a credential with the id of its folder, as
+/// JSON fields without the revision vector.
+struct Record {
+    fields: Map<String, Value>,
+    revision: RevisionVector,
+}
+
+impl Record {
+    fn title(&self) -> String {
+        ["name", "title"]
+            .iter()
+            .find_map(|key| self.fields.get(*key).and_then(Value::as_str))
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn folder_id(&self) -> String {
+        self.fields
+            .get("folderId")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn into_folder(self) -> Result<StoredFolder, PekaError> {
+        let mut fields = self.fields;
+        fields.insert("credentials".to_string(), Value::Array(Vec::new()));
+        let mut folder: StoredFolder =
+            serde_json::from_value(Value::Object(fields)).map_err(PekaError::internal)?;
+        folder.revision = self.revision;
+        Ok(folder)
+    }
+
+    fn into_credential(self) -> Result<StoredCredential, PekaError> {
+        let mut fields = self.fields;
+        fields.remove("folderId");
+        let mut cred: StoredCredential =
+            serde_json::from_value(Value::Object(fields)).map_err(PekaError::internal)?;
+        cred.revision = self.revision;
+        Ok(cred)
+    }
+}
+
+fn record_fields(value: &impl Serialize) -> Map<String, Value> {
+    match serde_json::to_value(value) {
+        Ok(Value::Object(mut fields)) => {
+            fields.remove("revision");
+            fields
+        }
+        _ => Map::new(),
+    }
+}
+
+fn folder_record(folder: &StoredFolder) -> Record {
+    let mut fields = record_fields(folder);
+    fields.remove("credentials");
+    Record {
+        fields,
+        revision: folder.revision.clone(),
+    }
+}
+
+fn credential_record(folder_id: &str, cred: &StoredCredential) -> Record {
+    let mut fields = record_fields(cred);
+    fields.insert("folderId".to_string(), Value::from(folder_id));
+    Record {
+        fields,
+        revision: cred.revision.clone(),
+    }
+}
+
+/// Folder records of `payload` by id, in vault order.
+fn folder_records(payload: &StoredVault) -> Vec<(String, Record)> {
+    payload
+        .folders
+        .iter()
+        .map(|folder| (folder.id.clone(), folder_record(folder)))
+        .collect()
+}
+
+/// Credential records of `payload` by id, in vault order.
+fn credential_records(payload: &StoredVault) -> Vec<(String, Record)> {
+    payload
+        .folders
+        .iter()
+        .flat_map(|folder| {
+            folder
+                .credentials
+                .iter()
+                .map(|cred| (cred.id.clone(), credential_record(&folder.id, cred)))
+        })
+        .collect()
+}
+
+struct Merger<'a> {
+    replica_id: &'a str,
+    resolutions: &'a HashMap<String, SyncResolution>,
+    outcome: &'a mut SyncMerge,
+}
+
+impl Merger<'_> {
+    /// Merged records in local order, followed by the ones only the remote copy has.
+    fn merge_records(
+        &mut self,
+        kind: SyncRecordKind,
+        local: Vec<(String, Record)>,
+        remote: Vec<(String, Record)>,
+        base: Vec<(String, Record)>,
+    ) -> Vec<Record> {
+        let local_ids: Vec<String> = local.iter().map(|(id, _)| id.clone()).collect();
+        let remote_ids: Vec<String> = remote.iter().map(|(id, _)| id.clone()).collect();
+        let mut local: HashMap<String, Record> = local.into_iter().collect();
+        let mut remote: HashMap<String, Record> = remote.into_iter().collect();
+        let base: HashMap<String, Record> = base.into_iter().collect();
+
+        let mut merged = Vec::new();
+        let mut seen = HashSet::new();
+        for id in local_ids.into_iter().chain(remote_ids) {
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            let record = self.merge_record(
+                kind,
+                &id,
+                local.remove(&id),
+                remote.remove(&id),
+                base.get(&id),
+            );
+            merged.extend(record);
+        }
+        merged
+    }
+
+    fn merge_record(
+        &mut self,
+        kind: SyncRecordKind,
+        id: &str,
+        local: Option<Record>,
+        remote: Option<Record>,
+        base: Option<&Record>,
+    ) -> Option<Record> {
+        let unchanged = |record: &Record| base.is_some_and(|base| base.fields == record.fields);
+        match (local, remote) {
+            (Some(local), Some(remote)) => {
+                if local.fields == remote.fields {
+                    return Some(Record {
+                        revision: local.revision.joined(&remote.revision),
+                        ..local
+                    });
+                }
+                if unchanged(&remote) {
+                    return Some(joined(local, &remote.revision));
+                }
+                if unchanged(&local) {
+                    self.outcome.pulled += 1;
+                    return Some(joined(remote, &local.revision));
+                }
+                match local.revision.compare(&remote.revision) {
+                    Some(Ordering::Greater) => return Some(joined(local, &remote.revision)),
+                    Some(Ordering::Less) => {
+                        self.outcome.pulled += 1;
+                        return Some(joined(remote, &local.revision));
+                    }
+                    _ => {}
+                }
+
+                let mut revision = local.revision.joined(&remote.revision);
+                revision.bump(self.replica_id);
+                match three_way(base.map(|base| &base.fields), &local.fields, &remote.fields) {
+                    Ok(fields) => {
+                        self.outcome.merged += 1;
+                        Some(Record { fields, revision })
+                    }
+                    Err(fields) => {
+                        let resolved = match self.resolutions.get(id) {
+                            Some(SyncResolution::Local) => local,
+                            Some(SyncResolution::Remote) => {
+                                self.outcome.pulled += 1;
+                                remote
+                            }
+                            None => {
+                                self.conflict(
+                                    kind,
+                                    id,
+                                    SyncConflictKind::BothModified,
+                                    &local,
+                                    fields,
+                                );
+                                return None;
+                            }
+                        };
+                        Some(Record {
+                            fields: resolved.fields,
+                            revision,
+                        })
+                    }
+                }
+            }
+            (Some(local), None) => {
+                if base.is_none() {
+                    return Some(local);
+                }
+                if unchanged(&local) {
+                    self.outcome.deleted += 1;
+                    return None;
+                }
+                match self.resolutions.get(id) {
+                    Some(SyncResolution::Local) => Some(bumped(local, self.replica_id)),
+                    Some(SyncResolution::Remote) => {
+                        self.outcome.deleted += 1;
+                        None
+                    }
+                    None => {
+                        self.conflict(
+                            kind,
+                            id,
+                            SyncConflictKind::DeletedRemotely,
+                            &local,
+                            Vec::new(),
+                        );
+                        None
+                    }
+                }
+            }
+            (None, Some(remote)) => {
+                if base.is_none() {
+                    self.outcome.pulled += 1;
+                    return Some(remote);
+                }
+                if unchanged(&remote) {
+                    return None;
+                }
+                match self.resolutions.get(id) {
+                    Some(SyncResolution::Local) => None,
+                    Some(SyncResolution::Remote) => {
+                        self.outcome.pulled += 1;
+                        Some(bumped(remote, self.replica_id))
+                    }
+                    None => {
+                        self.conflict(
+                            kind,
+                            id,
+                            SyncConflictKind::DeletedLocally,
+                            &remote,
+                            Vec::new(),
+                        );
+                        None
+                    }
+                }
+            }
+            (None, None) => None,
+        }
+    }
+
+    fn conflict(
+        &mut self,
+        kind: SyncRecordKind,
+        id: &str,
+        conflict: SyncConflictKind,
+        record: &Record,
+        fields: Vec<String>,
+    ) {
+        self.outcome.conflicts.push(SyncConflict {
+            record_id: id.to_string(),
+            kind,
+            conflict,
+            title: record.title(),
+            fields,
+        });
+    }
+}
+
+/// `record` with a revision that has also seen `other`.
+fn joined(record: Record, other: &RevisionVector) -> Record {
+    Record {
+        revision: record.revision.joined(other),
+        ..record
+    }
+}
+
+/// `record` with one more edit by this copy, so that it wins over the versions it
+/// was resolved against.
+fn bumped(mut record: Record, replica_id: &str) -> Record {
+    record.revision.bump(replica_id);
+    record
+}
+
+/// Merges the fields of a record changed on both sides. Returns the names of the
+/// fields both changed differently when there are any. `updatedAt` takes the newer
+/// value rather than conflicting.
+fn three_way(
+    base: Option<&Map<String, Value>>,
+    local: &Map<String, Value>,
+    remote: &Map<String, Value>,
+) -> Result<Map<String, Value>, Vec<String>> {
+    let keys: BTreeSet<&String> = local.keys().chain(remote.keys()).collect();
+    let mut merged = Map::new();
+    let mut conflicts = Vec::new();
+    for key in keys {
+        let (mine, theirs) = (local.get(key), remote.get(key));
+        let original = base.and_then(|base| base.get(key));
+        let value = if mine == theirs || theirs == original {
+            mine
+        } else if mine == original {
+            theirs
+        } else if key == "updatedAt" {
+            let newer = |candidate: Option<&Value>, current: Option<&Value>| {
+                merge::is_newer(
+                    candidate.and_then(Value::as_str).unwrap_or_default(),
+                    current.and_then(Value::as_str).unwrap_or_default(),
+                )
+            };
+            if newer(theirs, mine) {
+                theirs
+            } else {
+                mine
+            }
+        } else {
+            conflicts.push(key.clone());
+            continue;
+        };
+        if let Some(value) = value {
+            merged.insert(key.clone(), value.clone());
+        }
+    }
+
+    if conflicts.is_empty() {
+        Ok(merged)
+    } else {
+        Err(conflicts)
+    }
+}
+
+/// `local` unless only `remote` changed it since `base`.
+fn pick<T: PartialEq + Clone>(local: &T, remote: &T, base: Option<&T>) -> T {
+    if base == Some(local) {
+        remote.clone()
+    } else {
+        local.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir, MASTER_PASSWORD};
+    use crate::{CredentialChanges, NewCredential, Vault};
+
+    /// Two copies of a vault with one credential, both synced through the same
+    /// directory, and the ids of its folder and credential.
+    fn two_copies(dir: &TempDir) -> (Vault, Vault, String, String) {
+        let shared = dir.subdir("shared");
+        let (mut first, folder_id, credential_id) =
+            testing::vault_with_credential(dir.subdir("first").join("work.peka"));
+        link(first.path(), &shared).unwrap();
+        first.sync(&HashMap::new()).unwrap();
+
+        let second_path = dir.subdir("second").join("work.peka");
+        fs::copy(first.path(), &second_path).unwrap();
+        let mut second = Vault::open(second_path, MASTER_PASSWORD).unwrap();
+        link(second.path(), &shared).unwrap();
+        let report = second.sync(&HashMap::new()).unwrap();
+        assert!(report.applied);
+        assert_eq!(report.pushed + report.pulled, 0);
+        (first, second, folder_id, credential_id)
+    }
+
+    fn credential<'a>(vault: &'a Vault, credential_id: &str) -> Option<&'a StoredCredential> {
+        vault
+            .payload()
+            .folders
+            .iter()
+            .flat_map(|folder| &folder.credentials)
+            .find(|cred| cred.id == credential_id)
+    }
+
+    #[test]
+    fn takes_an_edit_made_in_one_copy() {
+        let dir = TempDir::new();
+        let (mut first, mut second, folder_id, credential_id) = two_copies(&dir);
+
+        let changes = CredentialChanges {
+            password: Some("second-password".to_string()),
+            ..CredentialChanges::default()
+        };
+        first
+            .update_credential(&folder_id, &credential_id, changes)
+            .unwrap();
+        // The edit touches the credential and the update time of its folder.
+        let report = first.sync(&HashMap::new()).unwrap();
+        assert_eq!(report.pushed, 2);
+        assert!(report.published);
+
+        let report = second.sync(&HashMap::new()).unwrap();
+        assert!(report.applied);
+        assert_eq!(report.pulled, 2);
+        assert!(!report.published);
+        let cred = credential(&second, &credential_id).unwrap();
+        assert_eq!(cred.password, "second-password");
+
+        let path = second.path().to_path_buf();
+        drop(second);
+        let reopened = Vault::open(path, MASTER_PASSWORD).unwrap();
+        let cred = credential(&reopened, &credential_id).unwrap();
+        assert_eq!(cred.password, "second-password");
+    }
+
+    #[test]
+    fn merges_fields_edited_in_both_copies() {
+        let dir = TempDir::new();
+        let (mut first, mut second, folder_id, credential_id) = two_copies(&dir);
+
+        let username = CredentialChanges {
+            username: Some("alice@example.com".to_string()),
+            ..CredentialChanges::default()
+        };
+        first
+            .update_credential(&folder_id, &credential_id, username)
+            .unwrap();
+        first.sync(&HashMap::new()).unwrap();
+        let notes = CredentialChanges {
+            notes: Some("Recovery codes are in the drawer".to_string()),
+            ..CredentialChanges::default()
+        };
+        second
+            .update_credential(&folder_id, &credential_id, notes)
+            .unwrap();
+
+        let report = second.sync(&HashMap::new()).unwrap();
+        assert!(report.applied);
+        assert_eq!(report.merged, 2);
+        assert!(report.published);
+        first.sync(&HashMap::new()).unwrap();
+        for vault in [&first, &second] {
+            let cred = credential(vault, &credential_id).unwrap();
+            assert_eq!(cred.username, "alice@example.com");
+            assert_eq!(
+                cred.notes.as_deref(),
+                Some("Recovery codes are in the drawer")
+            );
+        }
+    }
+
+    #[test]
+    fn reports_an_edit_of_a_credential_deleted_in_the_other_copy() {
+        let dir = TempDir::new();
+        let (mut first, mut second, folder_id, credential_id) = two_copies(&dir);
+
+        first.delete_credential(&folder_id, &credential_id).unwrap();
+        first.sync(&HashMap::new()).unwrap();
+        let changes = CredentialChanges {
+            password: Some("second-password".to_string()),
+            ..CredentialChanges::default()
+        };
+        second
+            .update_credential(&folder_id, &credential_id, changes)
+            .unwrap();
+
+        let report = second.sync(&HashMap::new()).unwrap();
+        assert!(!report.applied);
+        assert_eq!(report.conflicts.len(), 1);
+        let conflict = &report.conflicts[0];
+        assert_eq!(conflict.record_id, credential_id);
+        assert_eq!(conflict.kind, SyncRecordKind::Credential);
+        assert_eq!(conflict.conflict, SyncConflictKind::DeletedRemotely);
+        assert!(credential(&second, &credential_id).is_some());
+
+        let resolutions = HashMap::from([(credential_id.clone(), SyncResolution::Local)]);
+        let report = second.sync(&resolutions).unwrap();
+        assert!(report.applied);
+        first.sync(&HashMap::new()).unwrap();
+        for vault in [&first, &second] {
+            let cred = credential(vault, &credential_id).unwrap();
+            assert_eq!(cred.password, "second-password");
+        }
+    }
+
+    #[test]
+    fn joins_both_copies_on_a_first_sync() {
+        let dir = TempDir::new();
+        let shared = dir.subdir("shared");
+        let (mut first, _, first_credential) =
+            testing::vault_with_credential(dir.subdir("first").join("work.peka"));
+        link(first.path(), &shared).unwrap();
+        first.sync(&HashMap::new()).unwrap();
+
+        let (mut second, folder_id, _) =
+            testing::vault_with_credential(dir.subdir("second").join("work.peka"));
+        let second_credential = second
+            .add_credential(
+                &folder_id,
+                NewCredential {
+                    title: "Bank".to_string(),
+                    username: "alice".to_string(),
+                    password: "bank-password".to_string(),
+                    ..NewCredential::default()
+                },
+            )
+            .unwrap();
+        link(second.path(), &shared).unwrap();
+        let report = second.sync(&HashMap::new()).unwrap();
+        assert!(report.applied);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.deleted, 0);
+        assert!(report.published);
+
+        first.sync(&HashMap::new()).unwrap();
+        for vault in [&first, &second] {
+            assert!(credential(vault, &first_credential).is_some());
+            assert!(credential(vault, &second_credential).is_some());
+        }
+    }
+}
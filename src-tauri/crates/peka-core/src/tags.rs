@@ -0,0 +1,125 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::{PekaError, StoredCredential, StoredVault};
+
+pub const MAX_TAG_LENGTH: usize = 64;
+pub const MAX_TAGS_PER_CREDENTIAL: usize = 32;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagSummary {
+    pub name: String,
+    pub credentials: usize,
+}
+
+/// Trims a tag and collapses inner whitespace. Tags are compared case-insensitively
+/// but keep the spelling they were first added with.
+pub fn normalize(tag: &str) -> Result<String, PekaError> {
+    let tag = tag.split_whitespace().collect::<Vec<_>>().join(" ");
+    if tag.is_empty() {
+        return Err(PekaError::InvalidInput("Tag cannot be empty".to_string()));
+    }
+    if tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(PekaError::InvalidInput(format!(
+            "Tag cannot be longer than {} characters",
+            MAX_TAG_LENGTH
+        )));
+    }
+    Ok(tag)
+}
+
+pub fn same_tag(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+pub fn has_tag(credential: &StoredCredential, tag: &str) -> bool {
+    credential
+        .tags
+        .iter()
+        .any(|existing| same_tag(existing, tag))
+}
+
+/// Adds `tag` to the credential. Returns `false` when it was already present.
+pub fn add(credential: &mut StoredCredential, tag: &str) -> Result<bool, PekaError> {
+    let tag = normalize(tag)?;
+    if has_tag(credential, &tag) {
+        return Ok(false);
+    }
+    if credential.tags.len() >= MAX_TAGS_PER_CREDENTIAL {
+        return Err(PekaError::PolicyViolation(format!(
+            "A credential can have at most {} tags",
+            MAX_TAGS_PER_CREDENTIAL
+        )));
+    }
+    credential.tags.push(tag);
+    Ok(true)
+}
+
+/// Removes `tag` from the credential. Returns `false` when it was not present.
+pub fn remove(credential: &mut StoredCredential, tag: &str) -> bool {
+    let before = credential.tags.len();
+    credential.tags.retain(|existing| !same_tag(existing, tag));
+    credential.tags.len() != before
+}
+
+/// Renames `from` to `to` on every credential of the vault, merging it into `to` where
+/// a credential already has both. Returns the ids of the credentials that changed.
+pub fn rename(
+    payload: &mut StoredVault,
+    from: &str,
+    to: &str,
+) -> Result<Vec<String>, PekaError> {
+    let to = normalize(to)?;
+    let mut changed = Vec::new();
+
+    for credential in payload
+        .folders
+        .iter_mut()
+        .flat_map(|folder| folder.credentials.iter_mut())
+    {
+        let Some(position) = credential
+            .tags
+            .iter()
+            .position(|existing| same_tag(existing, from))
+        else {
+            continue;
+        };
+
+        if credential.tags[position] == to {
+            continue;
+        }
+        let already_tagged = credential
+            .tags
+            .iter()
+            .enumerate()
+            .any(|(index, existing)| index != position && same_tag(existing, &to));
+        if already_tagged {
+            credential.tags.remove(position);
+        } else {
+            credential.tags[position] = to.clone();
+        }
+        changed.push(credential.id.clone());
+    }
+
+    Ok(changed)
+}
+
+/// Every tag in the vault with the number of credentials carrying it, sorted by name.
+pub fn list(payload: &StoredVault) -> Vec<TagSummary> {
+    let mut tags: BTreeMap<String, TagSummary> = BTreeMap::new();
+    for tag in payload
+        .folders
+        .iter()
+        .flat_map(|folder| folder.credentials.iter())
+        .flat_map(|credential| credential.tags.iter())
+    {
+        tags.entry(tag.to_lowercase())
+            .or_insert_with(|| TagSummary {
+                name: tag.clone(),
+                credentials: 0,
+            })
+            .credentials += 1;
+    }
+    tags.into_values().collect()
+}
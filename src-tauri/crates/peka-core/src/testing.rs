@@ -0,0 +1,53 @@
+//! Helpers shared by the tests of several modules.
+
+use std::fs;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::{NewCredential, Vault};
+
+pub(crate) const MASTER_PASSWORD: &str = "Tr0ub4dor&3-horse-Battery!";
+
+/// A fresh directory under the system temp directory, removed again on drop.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> TempDir {
+        let path = std::env::temp_dir().join(format!("peka-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    /// Creates the directory `name` inside this one.
+    pub(crate) fn subdir(&self, name: &str) -> PathBuf {
+        let path = self.0.join(name);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Creates a saved vault at `path` with one folder holding one credential, and returns
+/// it with the ids of both.
+pub(crate) fn vault_with_credential(path: PathBuf) -> (Vault, String, String) {
+    let mut vault = Vault::create(path, "Work", MASTER_PASSWORD).unwrap();
+    let folder_id = vault.create_folder("Email", false, None).unwrap();
+    let credential_id = vault
+        .add_credential(
+            &folder_id,
+            NewCredential {
+                title: "Mail".to_string(),
+                username: "alice".to_string(),
+                password: "first-password".to_string(),
+                ..NewCredential::default()
+            },
+        )
+        .unwrap();
+    vault.save().unwrap();
+    (vault, folder_id, credential_id)
+}
@@ -0,0 +1,199 @@
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+use crate::PekaError;
+
+const DEFAULT_DIGITS: u32 = 6;
+const DEFAULT_PERIOD: u64 = 30;
+
+#[derive(Clone, Copy)]
+enum TotpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+/// TOTP parameters from a bare base32 secret or an `otpauth://totp/` URI.
+struct TotpConfig {
+    secret: Vec<u8>,
+    algorithm: TotpAlgorithm,
+    digits: u32,
+    period: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpCode {
+    pub code: String,
+    pub period: u64,
+    /// Seconds until the code changes.
+    pub remaining: u64,
+}
+
+/// Computes the RFC 6238 code for `unix_time`.
+pub fn code_at(secret: &str, unix_time: u64) -> Result<TotpCode, PekaError> {
+    let config = parse(secret)?;
+    let counter = unix_time / config.period;
+
+    let digest = match config.algorithm {
+        TotpAlgorithm::Sha1 => hmac_digest::<Hmac<Sha1>>(&config.secret, counter)?,
+        TotpAlgorithm::Sha256 => hmac_digest::<Hmac<Sha256>>(&config.secret, counter)?,
+        TotpAlgorithm::Sha512 => hmac_digest::<Hmac<Sha512>>(&config.secret, counter)?,
+    };
+
+    // Dynamic truncation, RFC 4226 section 5.3.
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    let code = binary % 10u32.pow(config.digits);
+
+    Ok(TotpCode {
+        code: format!("{:0width$}", code, width = config.digits as usize),
+        period: config.period,
+        remaining: config.period - unix_time % config.period,
+    })
+}
+
+fn hmac_digest<M: Mac + hmac::digest::KeyInit>(
+    key: &[u8],
+    counter: u64,
+) -> Result<Vec<u8>, PekaError> {
+    let mut mac = <M as Mac>::new_from_slice(key).map_err(PekaError::internal)?;
+    mac.update(&counter.to_be_bytes());
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+fn parse(secret: &str) -> Result<TotpConfig, PekaError> {
+    let secret = secret.trim();
+    let Some(rest) = secret.strip_prefix("otpauth://") else {
+        return Ok(TotpConfig {
+            secret: decode_base32(secret)?,
+            algorithm: TotpAlgorithm::Sha1,
+            digits: DEFAULT_DIGITS,
+            period: DEFAULT_PERIOD,
+        });
+    };
+
+    if !rest.to_ascii_lowercase().starts_with("totp/") {
+        return Err(PekaError::Unsupported(
+            "Only time-based (totp) otpauth URIs are supported".to_string(),
+        ));
+    }
+
+    let query = rest.split_once('?').map(|(_, query)| query).unwrap_or("");
+    let mut config = TotpConfig {
+        secret: Vec::new(),
+        algorithm: TotpAlgorithm::Sha1,
+        digits: DEFAULT_DIGITS,
+        period: DEFAULT_PERIOD,
+    };
+    for pair in query.split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value);
+        match key.to_ascii_lowercase().as_str() {
+            "secret" => config.secret = decode_base32(&value)?,
+            "algorithm" => {
+                config.algorithm = match value.to_ascii_uppercase().as_str() {
+                    "SHA1" => TotpAlgorithm::Sha1,
+                    "SHA256" => TotpAlgorithm::Sha256,
+                    "SHA512" => TotpAlgorithm::Sha512,
+                    _ => {
+                        return Err(PekaError::Unsupported(format!(
+                            "Unsupported TOTP algorithm: {}",
+                            value
+                        )))
+                    }
+                }
+            }
+            "digits" => {
+                config.digits = value
+                    .parse()
+                    .ok()
+                    .filter(|digits| (6..=8).contains(digits))
+                    .ok_or_else(|| {
+                        PekaError::InvalidInput("TOTP digits must be between 6 and 8".to_string())
+                    })?
+            }
+            "period" => {
+                config.period = value
+                    .parse()
+                    .ok()
+                    .filter(|period| *period > 0)
+                    .ok_or_else(|| {
+                        PekaError::InvalidInput("TOTP period must be a positive number".to_string())
+                    })?
+            }
+            _ => {}
+        }
+    }
+
+    if config.secret.is_empty() {
+        return Err(PekaError::InvalidInput("TOTP URI has no secret".to_string()));
+    }
+    Ok(config)
+}
+
+/// RFC 4648 base32, ignoring case, spaces, dashes and padding.
+fn decode_base32(input: &str) -> Result<Vec<u8>, PekaError> {
+    let mut bits: u64 = 0;
+    let mut bit_count = 0;
+    let mut output = Vec::new();
+
+    for ch in input.chars() {
+        let value = match ch.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u64 - 'A' as u64,
+            c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+            ' ' | '-' | '=' => continue,
+            _ => {
+                return Err(PekaError::InvalidInput(
+                    "TOTP secret is not valid base32".to_string(),
+                ))
+            }
+        };
+        bits = (bits << 5) | value;
+        bit_count += 5;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            output.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+
+    if output.is_empty() {
+        return Err(PekaError::InvalidInput("TOTP secret is empty".to_string()));
+    }
+    Ok(output)
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
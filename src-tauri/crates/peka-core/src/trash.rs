@@ -0,0 +1,396 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::audit::{self, AuditAction};
+use crate::sync::RevisionVector;
+use crate::{PekaError, StoredCredential, StoredFolder, StoredVault};
+
+/// Days a deleted item stays in the trash when the vault does not override it.
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+pub const MAX_RETENTION_DAYS: u32 = 3650;
+
+/// A folder or credential removed from the vault, kept until it is restored, the
+/// trash is emptied or the retention period runs out.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashedItem {
+    pub id: String,
+    pub deleted_at: String,
+    #[serde(flatten)]
+    pub entry: TrashedEntry,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TrashedEntry {
+    Folder {
+        folder: StoredFolder,
+    },
+    /// Keeps enough of the parent folder to recreate it if it is gone by the time the
+    /// credential is restored, including its PIN protection.
+    #[serde(rename_all = "camelCase")]
+    Credential {
+        folder_id: String,
+        folder_name: String,
+        folder_secure: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        folder_pin_hash: Option<String>,
+        credential: Box<StoredCredential>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashItemSummary {
+    pub id: String,
+    pub kind: &'static str,
+    /// Folder name for folders, credential title for credentials.
+    pub name: String,
+    pub folder_id: String,
+    pub folder_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    pub credentials: usize,
+    pub deleted_at: String,
+    /// When the item is purged automatically; unset when retention is disabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purge_at: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashContents {
+    pub retention_days: u32,
+    pub items: Vec<TrashItemSummary>,
+}
+
+impl TrashedItem {
+    fn credentials(&self) -> Box<dyn Iterator<Item = &StoredCredential> + '_> {
+        match &self.entry {
+            TrashedEntry::Folder { folder } => Box::new(folder.credentials.iter()),
+            TrashedEntry::Credential { credential, .. } => {
+                Box::new(std::iter::once(credential.as_ref()))
+            }
+        }
+    }
+
+    fn credentials_mut(&mut self) -> Box<dyn Iterator<Item = &mut StoredCredential> + '_> {
+        match &mut self.entry {
+            TrashedEntry::Folder { folder } => Box::new(folder.credentials.iter_mut()),
+            TrashedEntry::Credential { credential, .. } => {
+                Box::new(std::iter::once(credential.as_mut()))
+            }
+        }
+    }
+}
+
+/// Retention period of the vault in days. `0` disables automatic purging.
+pub fn retention_days(payload: &StoredVault) -> u32 {
+    payload
+        .trash_retention_days
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+/// Every credential of the vault, including the ones in the trash.
+pub fn all_credentials(payload: &StoredVault) -> impl Iterator<Item = &StoredCredential> {
+    payload
+        .folders
+        .iter()
+        .flat_map(|folder| folder.credentials.iter())
+        .chain(payload.trash.iter().flat_map(TrashedItem::credentials))
+}
+
+pub(crate) fn all_credentials_mut(
+    payload: &mut StoredVault,
+) -> impl Iterator<Item = &mut StoredCredential> {
+    payload
+        .folders
+        .iter_mut()
+        .flat_map(|folder| folder.credentials.iter_mut())
+        .chain(payload.trash.iter_mut().flat_map(TrashedItem::credentials_mut))
+}
+
+pub fn trash_folder(payload: &mut StoredVault, position: usize) {
+    let folder = payload.folders.remove(position);
+    push(payload, TrashedEntry::Folder { folder });
+}
+
+pub fn trash_credential(payload: &mut StoredVault, folder_index: usize, position: usize) {
+    let folder = &mut payload.folders[folder_index];
+    let credential = folder.credentials.remove(position);
+    folder.updated_at = Utc::now().to_rfc3339();
+    let entry = TrashedEntry::Credential {
+        folder_id: folder.id.clone(),
+        folder_name: folder.name.clone(),
+        folder_secure: folder.secure,
+        folder_pin_hash: folder.pin_hash.clone(),
+        credential: Box::new(credential),
+    };
+    push(payload, entry);
+}
+
+fn push(payload: &mut StoredVault, entry: TrashedEntry) {
+    payload.trash.push(TrashedItem {
+        id: Uuid::new_v4().to_string(),
+        deleted_at: Utc::now().to_rfc3339(),
+        entry,
+    });
+}
+
+/// Moves a trashed item back into the vault. A credential goes back to its folder,
+/// which is recreated if it no longer exists; a folder whose id is taken again has its
+/// credentials merged into the existing one.
+pub fn restore(payload: &mut StoredVault, trash_id: &str) -> Result<(), PekaError> {
+    let position = payload
+        .trash
+        .iter()
+        .position(|item| item.id == trash_id)
+        .ok_or_else(|| PekaError::NotFound("Trash item not found".to_string()))?;
+    let item = payload.trash.remove(position);
+    let now = Utc::now().to_rfc3339();
+
+    match item.entry {
+        TrashedEntry::Folder { folder } => {
+            match payload.folders.iter_mut().find(|f| f.id == folder.id) {
+                Some(existing) => {
+                    existing.credentials.extend(folder.credentials);
+                    existing.updated_at = now;
+                }
+                None => payload.folders.push(folder),
+            }
+        }
+        TrashedEntry::Credential {
+            folder_id,
+            folder_name,
+            folder_secure,
+            folder_pin_hash,
+            credential,
+        } => match payload.folders.iter_mut().find(|f| f.id == folder_id) {
+            Some(folder) => {
+                folder.credentials.push(*credential);
+                folder.updated_at = now;
+            }
+            None => payload.folders.push(StoredFolder {
+                id: folder_id,
+                name: folder_name,
+                secure: folder_secure,
+                pin_hash: folder_pin_hash,
+                credentials: vec![*credential],
+                revision: RevisionVector::default(),
+                created_at: now.clone(),
+                updated_at: now,
+            }),
+        },
+    }
+
+    Ok(())
+}
+
+/// Permanently removes the given trash items, or the whole trash when `trash_ids` is
+/// `None`. Returns the removed items so their attachments can be released.
+pub fn empty(
+    payload: &mut StoredVault,
+    trash_ids: Option<&[String]>,
+) -> Result<Vec<TrashedItem>, PekaError> {
+    let Some(trash_ids) = trash_ids else {
+        return Ok(std::mem::take(&mut payload.trash));
+    };
+
+    if let Some(missing) = trash_ids
+        .iter()
+        .find(|id| !payload.trash.iter().any(|item| &item.id == *id))
+    {
+        return Err(PekaError::NotFound(format!(
+            "Trash item not found: {}",
+            missing
+        )));
+    }
+
+    let (removed, kept) = std::mem::take(&mut payload.trash)
+        .into_iter()
+        .partition(|item| trash_ids.contains(&item.id));
+    payload.trash = kept;
+    Ok(removed)
+}
+
+/// Drops items that have been in the trash longer than the retention period. Items
+/// with an unreadable `deleted_at` are kept.
+pub fn purge_expired(payload: &mut StoredVault) -> Vec<TrashedItem> {
+    let days = retention_days(payload);
+    if days == 0 || payload.trash.is_empty() {
+        return Vec::new();
+    }
+
+    let cutoff = Utc::now() - Duration::days(i64::from(days));
+    let (expired, kept) = std::mem::take(&mut payload.trash)
+        .into_iter()
+        .partition(|item| deleted_at(item).is_some_and(|deleted| deleted < cutoff));
+    payload.trash = kept;
+    if !expired.is_empty() {
+        audit::record(payload, AuditAction::TrashPurged, None);
+    }
+    expired
+}
+
+pub fn contents(payload: &StoredVault) -> TrashContents {
+    let days = retention_days(payload);
+    let items = payload
+        .trash
+        .iter()
+        .map(|item| {
+            let purge_at = (days > 0)
+                .then(|| deleted_at(item))
+                .flatten()
+                .map(|deleted| (deleted + Duration::days(i64::from(days))).to_rfc3339());
+            let (kind, name, folder_id, folder_name, username, credentials) = match &item.entry {
+                TrashedEntry::Folder { folder } => (
+                    "folder",
+                    folder.name.clone(),
+                    folder.id.clone(),
+                    folder.name.clone(),
+                    None,
+                    folder.credentials.len(),
+                ),
+                TrashedEntry::Credential {
+                    folder_id,
+                    folder_name,
+                    credential,
+                    ..
+                } => (
+                    "credential",
+                    credential.title.clone(),
+                    folder_id.clone(),
+                    folder_name.clone(),
+                    Some(credential.username.clone()),
+                    1,
+                ),
+            };
+            TrashItemSummary {
+                id: item.id.clone(),
+                kind,
+                name,
+                folder_id,
+                folder_name,
+                username,
+                credentials,
+                deleted_at: item.deleted_at.clone(),
+                purge_at,
+            }
+        })
+        .collect();
+
+    TrashContents {
+        retention_days: days,
+        items,
+    }
+}
+
+/// Credentials of removed trash items, for releasing their attachment blobs.
+pub fn removed_credentials(items: &[TrashedItem]) -> impl Iterator<Item = &StoredCredential> {
+    items.iter().flat_map(TrashedItem::credentials)
+}
+
+fn deleted_at(item: &TrashedItem) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(&item.deleted_at)
+        .ok()
+        .map(|deleted| deleted.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir};
+
+    fn sample_payload(dir: &TempDir) -> StoredVault {
+        let (vault, _, _) = testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        vault.payload().clone()
+    }
+
+    fn days_ago(days: i64) -> String {
+        (Utc::now() - Duration::days(days)).to_rfc3339()
+    }
+
+    #[test]
+    fn purges_items_past_the_retention_period() {
+        let dir = TempDir::new();
+        let mut payload = sample_payload(&dir);
+        let mut bank = payload.folders[0].clone();
+        bank.id = "bank".to_string();
+        payload.folders.push(bank);
+        trash_folder(&mut payload, 1);
+        trash_credential(&mut payload, 0, 0);
+        payload.trash[0].deleted_at = days_ago(i64::from(DEFAULT_RETENTION_DAYS) + 1);
+        payload.trash[1].deleted_at = days_ago(1);
+
+        payload.trash_retention_days = Some(0);
+        assert!(purge_expired(&mut payload).is_empty());
+
+        payload.trash_retention_days = None;
+        let expired = purge_expired(&mut payload);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(removed_credentials(&expired).count(), 1);
+        assert_eq!(payload.trash.len(), 1);
+        assert_eq!(
+            payload.audit_log.last().map(|entry| entry.action),
+            Some(AuditAction::TrashPurged)
+        );
+
+        // Unreadable deletion times never expire.
+        payload.trash[0].deleted_at = "yesterday".to_string();
+        assert!(purge_expired(&mut payload).is_empty());
+        assert!(contents(&payload).items[0].purge_at.is_none());
+    }
+
+    #[test]
+    fn restores_a_credential_into_a_recreated_folder() {
+        let dir = TempDir::new();
+        let mut payload = sample_payload(&dir);
+        payload.folders[0].secure = true;
+        payload.folders[0].pin_hash = Some("pin-hash".to_string());
+        let folder_id = payload.folders[0].id.clone();
+
+        trash_credential(&mut payload, 0, 0);
+        let credential_trash_id = payload.trash[0].id.clone();
+        trash_folder(&mut payload, 0);
+        let folder_trash_id = payload.trash[1].id.clone();
+        empty(&mut payload, Some(&[folder_trash_id])).unwrap();
+        assert!(payload.folders.is_empty());
+
+        restore(&mut payload, &credential_trash_id).unwrap();
+        let [folder] = payload.folders.as_slice() else {
+            panic!("expected the folder to be recreated");
+        };
+        assert_eq!(folder.id, folder_id);
+        assert!(folder.secure);
+        assert_eq!(folder.pin_hash.as_deref(), Some("pin-hash"));
+        assert_eq!(folder.credentials[0].title, "Mail");
+        assert!(payload.trash.is_empty());
+
+        assert!(matches!(
+            restore(&mut payload, &credential_trash_id),
+            Err(PekaError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn empties_only_existing_items() {
+        let dir = TempDir::new();
+        let mut payload = sample_payload(&dir);
+        trash_credential(&mut payload, 0, 0);
+        let trash_id = payload.trash[0].id.clone();
+
+        assert!(matches!(
+            empty(&mut payload, Some(&[trash_id.clone(), "missing".to_string()])),
+            Err(PekaError::NotFound(_))
+        ));
+        assert_eq!(payload.trash.len(), 1);
+
+        let summary = &contents(&payload).items[0];
+        assert_eq!((summary.kind, summary.name.as_str()), ("credential", "Mail"));
+        assert!(summary.purge_at.is_some());
+
+        assert_eq!(empty(&mut payload, None).unwrap().len(), 1);
+        assert!(payload.trash.is_empty());
+    }
+}
@@ -0,0 +1,1265 @@
+use chrono::Utc;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use crate::attachments::{self, AttachmentSummary, StoredAttachment};
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::backup;
+use crate::breach::{self, BreachCorpus, BreachReport};
+use crate::check::{self, Repair};
+use crate::container::{PayloadCompression, PayloadLayout, StorageFormat};
+use crate::crypto::{self, VaultFile};
+use crate::csv_import::{self, CsvColumnMapping, CsvImportResult, CsvSource};
+use crate::export::{self, ExportFormat, ExportResult};
+use crate::health::{self, VaultHealthReport};
+use crate::history::{self, HistoryDiff, HistoryRollback};
+use crate::kdbx::{self, KdbxCipher, KdbxImportResult};
+use crate::lock::{self, VaultLock};
+use crate::merge::{self, ConflictResolution, MergeResult, MergeStrategy};
+use crate::records::RecordCache;
+use crate::search::{self, SearchFilters, SearchResults, SearchSort};
+use crate::sync::{self, RevisionVector, SyncReport, SyncResolution};
+use crate::tags::{self, TagSummary};
+use crate::trash::{self, TrashContents, TrashedItem};
+use crate::{payload_to_public, store, strength, totp};
+use crate::{KdfCost, PekaError, StoredCredential, StoredFolder, StoredVault, VaultContents};
+
+/// An unlocked vault. Operations change the decrypted copy in memory and record
+/// themselves in the audit log; nothing reaches the disk until [`Vault::save`].
+pub struct Vault {
+    path: PathBuf,
+    master_password: String,
+    file: VaultFile,
+    payload: StoredVault,
+    /// Records of a per-entry vault as last read or written.
+    records: RecordCache,
+    modified: bool,
+    /// Attachment blobs written since the last save, removed again if it fails.
+    stored_blobs: Vec<StoredAttachment>,
+    /// Attachments dropped since the last save, whose blobs are released after it.
+    dropped_blobs: Vec<StoredAttachment>,
+    /// What was recorded since the last save; describes the save in the history.
+    unsaved_actions: Vec<AuditAction>,
+    /// Why the last save could not be committed to the vault history.
+    history_error: Option<String>,
+    /// Keeps other processes from opening the vault while this one has it open.
+    _lock: VaultLock,
+}
+
+/// Fields of a credential to add. Empty `url`, `notes` and `totp` are left unset.
+#[derive(Default)]
+pub struct NewCredential {
+    pub title: String,
+    pub username: String,
+    pub password: String,
+    pub url: Option<String>,
+    pub notes: Option<String>,
+    pub totp: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// Fields of a credential to change; `None` keeps the current value. An empty `url`,
+/// `notes` or `totp` clears it.
+#[derive(Default)]
+pub struct CredentialChanges {
+    pub title: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub url: Option<String>,
+    pub notes: Option<String>,
+    pub totp: Option<String>,
+}
+
+impl Vault {
+    /// Creates an empty vault at `path`, replacing any file that is there.
+    pub fn create(
+        path: impl Into<PathBuf>,
+        vault_name: &str,
+        master_password: &str,
+    ) -> Result<Vault, PekaError> {
+        let vault_name = vault_name.trim();
+        if vault_name.is_empty() {
+            return Err(PekaError::InvalidInput("Vault name cannot be empty".to_string()));
+        }
+
+        strength::validate_master_password(master_password, &[vault_name])?;
+
+        let mut payload = StoredVault {
+            vault_name: vault_name.to_string(),
+            folders: Vec::new(),
+            trash: Vec::new(),
+            trash_retention_days: None,
+            audit_log: Vec::new(),
+        };
+        audit::record(&mut payload, AuditAction::VaultCreated, None);
+
+        let path = path.into();
+        let lock = lock::acquire(&path)?;
+        let file = crypto::encrypt_payload(
+            &payload,
+            master_password,
+            None,
+            PayloadCompression::None,
+            None,
+        )?;
+        let mut vault = Vault {
+            path,
+            master_password: master_password.to_string(),
+            file,
+            payload,
+            records: RecordCache::default(),
+            modified: true,
+            stored_blobs: Vec::new(),
+            dropped_blobs: Vec::new(),
+            unsaved_actions: vec![AuditAction::VaultCreated],
+            history_error: None,
+            _lock: lock,
+        };
+        vault.save()?;
+        Ok(vault)
+    }
+
+    /// Opens and decrypts the vault at `path`. The vault stays locked against other
+    /// processes until the returned value is dropped; if another process has it open,
+    /// this waits a few seconds and then fails with [`PekaError::Locked`].
+    pub fn open(path: impl Into<PathBuf>, master_password: &str) -> Result<Vault, PekaError> {
+        let path = path.into();
+        if !path.is_file() {
+            return Err(PekaError::NotFound("Vault file not found".to_string()));
+        }
+        // Locked first, so a save of another process cannot be read half way.
+        let lock = lock::acquire(&path)?;
+        let (file, payload, records) = crypto::decrypt_vault(&path, master_password)?;
+        Ok(Vault {
+            path,
+            master_password: master_password.to_string(),
+            file,
+            payload,
+            records,
+            modified: false,
+            stored_blobs: Vec::new(),
+            dropped_blobs: Vec::new(),
+            unsaved_actions: Vec::new(),
+            history_error: None,
+            _lock: lock,
+        })
+    }
+
+    /// Opens a vault file that may come from elsewhere, such as an import or merge
+    /// source. Unless `allow_expensive_kdf` is set, a file whose key derivation is
+    /// unusually costly is refused with [`PekaError::ConfirmationRequired`] before any
+    /// work is done, so the user can be asked first.
+    pub fn open_external(
+        path: impl Into<PathBuf>,
+        master_password: &str,
+        allow_expensive_kdf: bool,
+    ) -> Result<Vault, PekaError> {
+        let path = path.into();
+        let kdf = crypto::read_vault_file(&path)?.kdf;
+        if kdf.cost()? == KdfCost::Expensive && !allow_expensive_kdf {
+            return Err(PekaError::ConfirmationRequired(format!(
+                "Unlocking this vault takes unusually long: its key derivation uses {} MiB \
+                 of memory and {} passes",
+                kdf.memory_kib / 1024,
+                kdf.time_cost
+            )));
+        }
+        Vault::open(path, master_password)
+    }
+
+    /// Writes pending changes, re-encrypting with the vault's KDF parameters and a
+    /// fresh salt and nonce. A per-entry vault keeps its salt and only encrypts the
+    /// records that changed. When the vault keeps a history, the written file is
+    /// committed to it; a failed commit does not fail the save, see
+    /// [`Vault::history_error`]. Does nothing when nothing changed.
+    pub fn save(&mut self) -> Result<(), PekaError> {
+        if !self.modified {
+            return Ok(());
+        }
+
+        let path = self.path.clone();
+        if let Err(err) = self.write(&path) {
+            attachments::remove(&self.path, &self.stored_blobs);
+            self.stored_blobs.clear();
+            return Err(err);
+        }
+
+        attachments::release(&self.path, &self.payload, &self.dropped_blobs);
+        self.stored_blobs.clear();
+        self.dropped_blobs.clear();
+        self.modified = false;
+        let actions = std::mem::take(&mut self.unsaved_actions);
+        self.history_error = history::commit_save(&self.path, &history::message(&actions));
+        Ok(())
+    }
+
+    /// Writes the vault and its attachments to `path`, which becomes its location.
+    pub fn save_as(&mut self, path: impl Into<PathBuf>) -> Result<(), PekaError> {
+        let path = path.into();
+        let lock = lock::acquire(&path)?;
+        self.write(&path)?;
+        attachments::copy(
+            &self.path,
+            &path,
+            trash::all_credentials(&self.payload).flat_map(|cred| cred.attachments.iter()),
+        )?;
+
+        // Blobs of this session belong to the new location now; the old vault file
+        // never referenced them.
+        attachments::remove(&self.path, &self.stored_blobs);
+        self.path = path;
+        self._lock = lock;
+        self.stored_blobs.clear();
+        self.dropped_blobs.clear();
+        self.unsaved_actions.clear();
+        self.modified = false;
+        Ok(())
+    }
+
+    fn write(&mut self, path: &Path) -> Result<(), PekaError> {
+        let mut updated_file = crypto::encrypt_payload(
+            &self.payload,
+            &self.master_password,
+            Some(&self.file.kdf),
+            self.file.compression,
+            match self.file.layout {
+                PayloadLayout::Single => None,
+                PayloadLayout::Entries => Some(&mut self.records),
+            },
+        )?;
+        updated_file.container = self.file.container;
+        let contents = crypto::serialize_vault_file(&updated_file)?;
+        backup::keep(path)?;
+        fs::write(path, contents).map_err(|e| PekaError::io("Unable to write vault file", e))?;
+        self.file = updated_file;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn name(&self) -> &str {
+        &self.payload.vault_name
+    }
+
+    /// The unencrypted header of the vault file.
+    pub fn file(&self) -> &VaultFile {
+        &self.file
+    }
+
+    pub fn payload(&self) -> &StoredVault {
+        &self.payload
+    }
+
+    pub fn contents(&self) -> VaultContents {
+        payload_to_public(&self.payload)
+    }
+
+    pub fn storage_format(&self) -> StorageFormat {
+        StorageFormat {
+            container: self.file.container,
+            compression: self.file.compression,
+            layout: self.file.layout,
+        }
+    }
+
+    /// Changes how the vault file is written, taking effect on the next save.
+    pub fn set_storage_format(&mut self, format: StorageFormat) {
+        if format == self.storage_format() {
+            return;
+        }
+        self.file.container = format.container;
+        self.file.compression = format.compression;
+        self.file.layout = format.layout;
+        self.record(AuditAction::StorageFormatChanged, None);
+    }
+
+    /// Why the last save could not be committed to the vault history, if it keeps one.
+    pub fn history_error(&self) -> Option<&str> {
+        self.history_error.as_deref()
+    }
+
+    /// Whether there are changes that [`Vault::save`] has not written yet.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub(crate) fn record(&mut self, action: AuditAction, target_id: Option<&str>) {
+        audit::record(&mut self.payload, action, target_id);
+        self.unsaved_actions.push(action);
+        self.modified = true;
+    }
+
+    pub fn folder(&self, folder_id: &str) -> Result<&StoredFolder, PekaError> {
+        self.payload
+            .folders
+            .iter()
+            .find(|folder| folder.id == folder_id)
+            .ok_or_else(|| PekaError::NotFound("Folder not found".to_string()))
+    }
+
+    pub fn credential(
+        &self,
+        folder_id: &str,
+        credential_id: &str,
+    ) -> Result<&StoredCredential, PekaError> {
+        self.folder(folder_id)?
+            .credentials
+            .iter()
+            .find(|cred| cred.id == credential_id)
+            .ok_or_else(|| PekaError::NotFound("Credential not found".to_string()))
+    }
+
+    /// The value to copy for `field` of a credential: `password`, `username`, `url`,
+    /// `notes`, `totp` for the current one-time code, or the name of a custom field.
+    pub fn secret_value(
+        &self,
+        folder_id: &str,
+        credential_id: &str,
+        field: &str,
+    ) -> Result<String, PekaError> {
+        let cred = self.credential(folder_id, credential_id)?;
+        let value = match field.to_ascii_lowercase().as_str() {
+            "password" => Some(cred.password.clone()),
+            "username" => Some(cred.username.clone()),
+            "url" => cred.url.clone(),
+            "notes" => cred.notes.clone(),
+            "totp" => match cred.totp.as_deref() {
+                Some(secret) => {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_err(PekaError::internal)?
+                        .as_secs();
+                    Some(totp::code_at(secret, now)?.code)
+                }
+                None => None,
+            },
+            _ => cred
+                .custom_fields
+                .iter()
+                .find(|custom| custom.name.eq_ignore_ascii_case(field))
+                .map(|custom| custom.value.clone()),
+        };
+
+        value
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| PekaError::NotFound(format!("Credential has no field '{}'", field)))
+    }
+
+    fn folder_mut(&mut self, folder_id: &str) -> Result<&mut StoredFolder, PekaError> {
+        self.payload
+            .folders
+            .iter_mut()
+            .find(|folder| folder.id == folder_id)
+            .ok_or_else(|| PekaError::NotFound("Folder not found".to_string()))
+    }
+
+    fn credential_mut(
+        &mut self,
+        folder_id: &str,
+        credential_id: &str,
+    ) -> Result<&mut StoredCredential, PekaError> {
+        self.folder_mut(folder_id)?
+            .credentials
+            .iter_mut()
+            .find(|cred| cred.id == credential_id)
+            .ok_or_else(|| PekaError::NotFound("Credential not found".to_string()))
+    }
+
+    /// Adds a folder and returns its id. Secure folders need a 4 digit PIN.
+    pub fn create_folder(
+        &mut self,
+        name: &str,
+        secure: bool,
+        pin: Option<&str>,
+    ) -> Result<String, PekaError> {
+        let folder_name = name.trim();
+        if folder_name.is_empty() {
+            return Err(PekaError::InvalidInput("Folder name is required.".to_string()));
+        }
+
+        let pin_hash = if secure {
+            let pin = pin.ok_or_else(|| {
+                PekaError::InvalidInput("PIN is required for secure folders.".to_string())
+            })?;
+            if !pin.chars().all(|c| c.is_ascii_digit()) || pin.len() != 4 {
+                return Err(PekaError::PolicyViolation(
+                    "PIN must be exactly 4 digits.".to_string(),
+                ));
+            }
+            Some(crypto::hash_pin(pin)?)
+        } else {
+            None
+        };
+
+        let now = Utc::now().to_rfc3339();
+        let folder_id = Uuid::new_v4().to_string();
+        self.payload.folders.push(StoredFolder {
+            id: folder_id.clone(),
+            name: folder_name.to_string(),
+            secure,
+            pin_hash,
+            credentials: Vec::new(),
+            revision: RevisionVector::default(),
+            created_at: now.clone(),
+            updated_at: now,
+        });
+        self.record(AuditAction::FolderCreated, Some(&folder_id));
+        Ok(folder_id)
+    }
+
+    /// Moves a folder and its credentials to the trash.
+    pub fn delete_folder(&mut self, folder_id: &str) -> Result<(), PekaError> {
+        let position = self
+            .payload
+            .folders
+            .iter()
+            .position(|folder| folder.id == folder_id)
+            .ok_or_else(|| PekaError::NotFound("Folder not found".to_string()))?;
+
+        trash::trash_folder(&mut self.payload, position);
+        self.purge_expired_trash();
+        self.record(AuditAction::FolderDeleted, Some(folder_id));
+        Ok(())
+    }
+
+    /// Checks the PIN of a secure folder; non-secure folders accept any PIN. A wrong
+    /// PIN is recorded in the audit log.
+    pub fn verify_folder_pin(&mut self, folder_id: &str, pin: &str) -> Result<bool, PekaError> {
+        if crypto::folder_pin_matches(self.folder(folder_id)?, pin)? {
+            return Ok(true);
+        }
+
+        self.record(AuditAction::PinVerificationFailed, Some(folder_id));
+        Ok(false)
+    }
+
+    /// Adds a credential to a folder and returns its id.
+    pub fn add_credential(
+        &mut self,
+        folder_id: &str,
+        credential: NewCredential,
+    ) -> Result<String, PekaError> {
+        if credential.title.trim().is_empty() {
+            return Err(PekaError::InvalidInput("Title is required.".to_string()));
+        }
+
+        if credential.password.is_empty() {
+            return Err(PekaError::InvalidInput("Password is required.".to_string()));
+        }
+
+        let now = Utc::now().to_rfc3339();
+        let optional = |value: Option<String>| value.filter(|value| !value.trim().is_empty());
+        let mut stored = StoredCredential {
+            id: Uuid::new_v4().to_string(),
+            title: credential.title.trim().to_string(),
+            username: credential.username,
+            password: credential.password,
+            url: optional(credential.url),
+            notes: optional(credential.notes),
+            totp: optional(credential.totp).map(|totp| totp.trim().to_string()),
+            custom_fields: Vec::new(),
+            attachments: Vec::new(),
+            tags: Vec::new(),
+            favorite: false,
+            revision: RevisionVector::default(),
+            created_at: now.clone(),
+            updated_at: now.clone(),
+        };
+        for tag in &credential.tags {
+            tags::add(&mut stored, tag)?;
+        }
+
+        let credential_id = stored.id.clone();
+        let folder = self.folder_mut(folder_id)?;
+        folder.credentials.push(stored);
+        folder.updated_at = now;
+        self.record(AuditAction::CredentialAdded, Some(&credential_id));
+        Ok(credential_id)
+    }
+
+    pub fn update_credential(
+        &mut self,
+        folder_id: &str,
+        credential_id: &str,
+        changes: CredentialChanges,
+    ) -> Result<(), PekaError> {
+        if changes
+            .title
+            .as_deref()
+            .is_some_and(|title| title.trim().is_empty())
+        {
+            return Err(PekaError::InvalidInput("Title is required.".to_string()));
+        }
+
+        if changes.password.as_deref() == Some("") {
+            return Err(PekaError::InvalidInput("Password is required.".to_string()));
+        }
+
+        let optional = |value: String| (!value.trim().is_empty()).then_some(value);
+        let now = Utc::now().to_rfc3339();
+        let credential = self.credential_mut(folder_id, credential_id)?;
+        if let Some(title) = changes.title {
+            credential.title = title.trim().to_string();
+        }
+        if let Some(username) = changes.username {
+            credential.username = username;
+        }
+        if let Some(password) = changes.password {
+            credential.password = password;
+        }
+        if let Some(url) = changes.url {
+            credential.url = optional(url);
+        }
+        if let Some(notes) = changes.notes {
+            credential.notes = optional(notes);
+        }
+        if let Some(totp) = changes.totp {
+            credential.totp = optional(totp.trim().to_string());
+        }
+        credential.updated_at = now.clone();
+
+        self.folder_mut(folder_id)?.updated_at = now;
+        self.record(AuditAction::CredentialUpdated, Some(credential_id));
+        Ok(())
+    }
+
+    /// Moves a credential to the trash.
+    pub fn delete_credential(
+        &mut self,
+        folder_id: &str,
+        credential_id: &str,
+    ) -> Result<(), PekaError> {
+        let folder_index = self
+            .payload
+            .folders
+            .iter()
+            .position(|folder| folder.id == folder_id)
+            .ok_or_else(|| PekaError::NotFound("Folder not found".to_string()))?;
+
+        let position = self.payload.folders[folder_index]
+            .credentials
+            .iter()
+            .position(|cred| cred.id == credential_id)
+            .ok_or_else(|| PekaError::NotFound("Credential not found".to_string()))?;
+
+        trash::trash_credential(&mut self.payload, folder_index, position);
+        self.purge_expired_trash();
+        self.record(AuditAction::CredentialDeleted, Some(credential_id));
+        Ok(())
+    }
+
+    /// Encrypts `file_path` next to the vault and attaches it to a credential.
+    pub fn add_attachment(
+        &mut self,
+        folder_id: &str,
+        credential_id: &str,
+        file_path: &Path,
+    ) -> Result<AttachmentSummary, PekaError> {
+        if self.credential(folder_id, credential_id)?.attachments.len()
+            >= attachments::MAX_ATTACHMENTS_PER_CREDENTIAL
+        {
+            return Err(PekaError::PolicyViolation(format!(
+                "A credential can hold at most {} attachments",
+                attachments::MAX_ATTACHMENTS_PER_CREDENTIAL
+            )));
+        }
+
+        let attachment = attachments::store(&self.path, file_path)?;
+        let now = Utc::now().to_rfc3339();
+        let folder = self.folder_mut(folder_id)?;
+        folder.updated_at = now.clone();
+        let credential = folder
+            .credentials
+            .iter_mut()
+            .find(|cred| cred.id == credential_id)
+            .ok_or_else(|| PekaError::NotFound("Credential not found".to_string()))?;
+        credential.attachments.push(attachment.clone());
+        credential.updated_at = now;
+
+        self.record(AuditAction::AttachmentAdded, Some(&attachment.id));
+        let summary = attachment.summary();
+        self.stored_blobs.push(attachment);
+        Ok(summary)
+    }
+
+    /// Decrypts an attachment. The access is recorded in the audit log.
+    pub fn read_attachment(
+        &mut self,
+        folder_id: &str,
+        credential_id: &str,
+        attachment_id: &str,
+    ) -> Result<Vec<u8>, PekaError> {
+        let attachment = self
+            .credential(folder_id, credential_id)?
+            .attachments
+            .iter()
+            .find(|attachment| attachment.id == attachment_id)
+            .ok_or_else(|| PekaError::NotFound("Attachment not found".to_string()))?;
+
+        let contents = attachments::read(&self.path, attachment)?;
+        self.record(AuditAction::AttachmentExtracted, Some(attachment_id));
+        Ok(contents)
+    }
+
+    pub fn delete_attachment(
+        &mut self,
+        folder_id: &str,
+        credential_id: &str,
+        attachment_id: &str,
+    ) -> Result<(), PekaError> {
+        let now = Utc::now().to_rfc3339();
+        let folder = self.folder_mut(folder_id)?;
+        folder.updated_at = now.clone();
+        let credential = folder
+            .credentials
+            .iter_mut()
+            .find(|cred| cred.id == credential_id)
+            .ok_or_else(|| PekaError::NotFound("Credential not found".to_string()))?;
+
+        let position = credential
+            .attachments
+            .iter()
+            .position(|attachment| attachment.id == attachment_id)
+            .ok_or_else(|| PekaError::NotFound("Attachment not found".to_string()))?;
+
+        let removed = credential.attachments.remove(position);
+        credential.updated_at = now;
+
+        self.record(AuditAction::AttachmentDeleted, Some(attachment_id));
+        self.dropped_blobs.push(removed);
+        Ok(())
+    }
+
+    pub fn tags(&self) -> Vec<TagSummary> {
+        tags::list(&self.payload)
+    }
+
+    pub fn add_tag(
+        &mut self,
+        folder_id: &str,
+        credential_id: &str,
+        tag: &str,
+    ) -> Result<(), PekaError> {
+        // Tags and favorites are organisational only and leave `updated_at` alone, so they
+        // do not reset password age in the health report.
+        if tags::add(self.credential_mut(folder_id, credential_id)?, tag)? {
+            self.record(AuditAction::TagAdded, Some(credential_id));
+        }
+        Ok(())
+    }
+
+    /// Removes `tag` from one credential, given as `(folder_id, credential_id)`, or
+    /// from every credential in the vault.
+    pub fn remove_tag(
+        &mut self,
+        tag: &str,
+        credential: Option<(&str, &str)>,
+    ) -> Result<(), PekaError> {
+        let removed = match credential {
+            Some((folder_id, credential_id)) => {
+                tags::remove(self.credential_mut(folder_id, credential_id)?, tag)
+            }
+            None => {
+                let mut removed = false;
+                for cred in self
+                    .payload
+                    .folders
+                    .iter_mut()
+                    .flat_map(|folder| folder.credentials.iter_mut())
+                {
+                    removed |= tags::remove(cred, tag);
+                }
+                removed
+            }
+        };
+
+        if removed {
+            self.record(
+                AuditAction::TagRemoved,
+                credential.map(|(_, credential_id)| credential_id),
+            );
+        }
+        Ok(())
+    }
+
+    pub fn rename_tag(&mut self, from: &str, to: &str) -> Result<(), PekaError> {
+        if !tags::rename(&mut self.payload, from, to)?.is_empty() {
+            self.record(AuditAction::TagRenamed, None);
+        }
+        Ok(())
+    }
+
+    pub fn set_favorite(
+        &mut self,
+        folder_id: &str,
+        credential_id: &str,
+        favorite: bool,
+    ) -> Result<(), PekaError> {
+        let credential = self.credential_mut(folder_id, credential_id)?;
+        if credential.favorite == favorite {
+            return Ok(());
+        }
+        credential.favorite = favorite;
+
+        self.record(AuditAction::FavoriteChanged, Some(credential_id));
+        Ok(())
+    }
+
+    /// Trash contents, leaving out items past the retention period.
+    pub fn trash(&self) -> TrashContents {
+        let mut payload = self.payload.clone();
+        trash::purge_expired(&mut payload);
+        trash::contents(&payload)
+    }
+
+    /// Drops trash items past the retention period and returns how many there were.
+    pub fn purge_expired_trash(&mut self) -> usize {
+        let expired = trash::purge_expired(&mut self.payload);
+        if !expired.is_empty() {
+            self.modified = true;
+            self.drop_trashed(&expired);
+        }
+        expired.len()
+    }
+
+    fn drop_trashed(&mut self, items: &[TrashedItem]) {
+        self.dropped_blobs.extend(
+            trash::removed_credentials(items).flat_map(|cred| cred.attachments.iter().cloned()),
+        );
+    }
+
+    pub fn restore_from_trash(&mut self, trash_id: &str) -> Result<(), PekaError> {
+        self.purge_expired_trash();
+        trash::restore(&mut self.payload, trash_id)?;
+        self.record(AuditAction::TrashRestored, Some(trash_id));
+        Ok(())
+    }
+
+    /// Permanently deletes the given trash items, or everything in the trash when no
+    /// ids are given.
+    pub fn empty_trash(&mut self, trash_ids: Option<&[String]>) -> Result<(), PekaError> {
+        let removed = trash::empty(&mut self.payload, trash_ids)?;
+        self.drop_trashed(&removed);
+        self.purge_expired_trash();
+        self.record(AuditAction::TrashEmptied, None);
+        Ok(())
+    }
+
+    /// Sets how many days deleted items are kept. `0` keeps them until the trash is
+    /// emptied by hand.
+    pub fn set_trash_retention(&mut self, retention_days: u32) -> Result<(), PekaError> {
+        if retention_days > trash::MAX_RETENTION_DAYS {
+            return Err(PekaError::InvalidInput(format!(
+                "Retention cannot be longer than {} days",
+                trash::MAX_RETENTION_DAYS
+            )));
+        }
+
+        self.payload.trash_retention_days = Some(retention_days);
+        self.purge_expired_trash();
+        self.record(AuditAction::TrashRetentionChanged, None);
+        Ok(())
+    }
+
+    /// Applies payload repairs found by [`check::check_vault`]. Returns the ones that
+    /// changed something.
+    pub fn repair(&mut self, repairs: &[Repair]) -> Vec<Repair> {
+        let applied = check::repair_payload(&mut self.payload, repairs);
+        if !applied.is_empty() {
+            self.record(AuditAction::VaultRepaired, None);
+        }
+        applied
+    }
+
+    pub fn audit_log(&self) -> AuditLog {
+        audit::read(&self.payload)
+    }
+
+    pub fn health_report(&self, max_age_days: u32) -> Result<VaultHealthReport, PekaError> {
+        health::build_report(&self.payload, max_age_days)
+    }
+
+    pub fn check_breaches(&self, corpus: &BreachCorpus) -> Result<BreachReport, PekaError> {
+        breach::check_vault(&self.payload, corpus)
+    }
+
+    pub fn search(
+        &self,
+        query: &str,
+        filters: &SearchFilters,
+        sort: SearchSort,
+        limit: Option<usize>,
+    ) -> Result<SearchResults, PekaError> {
+        search::search(&self.payload, query, filters, sort, limit)
+    }
+
+    /// Imports the rows of a CSV export. A dry run reports what would be imported
+    /// without changing the vault.
+    pub fn import_csv(
+        &mut self,
+        raw: &[u8],
+        source: CsvSource,
+        mapping: Option<CsvColumnMapping>,
+        dry_run: bool,
+    ) -> Result<CsvImportResult, PekaError> {
+        let parsed = csv_import::parse_csv(raw, source, mapping)?;
+
+        let (folders_created, vault) = if dry_run {
+            let mut preview = self.payload.clone();
+            (
+                csv_import::apply_entries(&mut preview, &parsed.entries),
+                None,
+            )
+        } else {
+            let folders_created = csv_import::apply_entries(&mut self.payload, &parsed.entries);
+            self.record(AuditAction::CredentialsImported, None);
+            (folders_created, Some(self.contents()))
+        };
+
+        Ok(CsvImportResult {
+            dry_run,
+            imported: parsed.entries.len(),
+            folders_created,
+            entries: csv_import::preview_entries(&parsed.entries),
+            skipped: parsed.skipped,
+            vault,
+        })
+    }
+
+    /// Imports a KeePass KDBX 4 database. Entries imported before, recognised by their
+    /// KeePass UUID, are skipped. A database with unusually costly key derivation is
+    /// only decrypted with `allow_expensive_kdf`, see [`kdbx::read_database`].
+    pub fn import_kdbx(
+        &mut self,
+        raw: &[u8],
+        kdbx_password: &str,
+        allow_expensive_kdf: bool,
+    ) -> Result<KdbxImportResult, PekaError> {
+        let database = kdbx::read_database(raw, kdbx_password, allow_expensive_kdf)?;
+
+        let payload = &mut self.payload;
+        let now = Utc::now().to_rfc3339();
+        let mut imported = 0;
+        let mut skipped = 0;
+        let mut folders_created = Vec::new();
+
+        for folder in database.folders {
+            // Entries that were already imported keep their KeePass UUID as id.
+            let credentials: Vec<StoredCredential> = folder
+                .credentials
+                .into_iter()
+                .filter(|cred| {
+                    let exists = payload
+                        .folders
+                        .iter()
+                        .any(|f| f.credentials.iter().any(|c| c.id == cred.id));
+                    skipped += exists as usize;
+                    !exists
+                })
+                .collect();
+            imported += credentials.len();
+
+            match payload.folders.iter_mut().find(|f| f.name == folder.name) {
+                Some(existing) => {
+                    if !credentials.is_empty() {
+                        existing.credentials.extend(credentials);
+                        existing.updated_at = now.clone();
+                    }
+                }
+                None => {
+                    let id_taken = payload.folders.iter().any(|f| f.id == folder.id);
+                    folders_created.push(folder.name.clone());
+                    payload.folders.push(StoredFolder {
+                        id: if id_taken {
+                            Uuid::new_v4().to_string()
+                        } else {
+                            folder.id
+                        },
+                        credentials,
+                        ..folder
+                    });
+                }
+            }
+        }
+
+        self.record(AuditAction::CredentialsImported, None);
+
+        Ok(KdbxImportResult {
+            imported,
+            skipped,
+            folders_created,
+            vault: self.contents(),
+        })
+    }
+
+    /// Copies the vault file and its attachments to `destination` as they are, after
+    /// recording the export in the audit log and saving; the copy opens with the
+    /// master password and carries the entry itself.
+    pub fn copy_file_to(&mut self, destination: &Path) -> Result<(), PekaError> {
+        self.record(AuditAction::VaultExported, None);
+        self.save()?;
+        store::copy_files(&self.path, destination)
+    }
+
+    /// Writes the vault, or the folders in `folder_ids`, to `destination`. Plaintext
+    /// formats have to be acknowledged; the encrypted format needs an export password
+    /// other than the master password. Only the encrypted format carries attachments,
+    /// with their blob keys wrapped by the export password; the plaintext formats leave
+    /// out the blob keys.
+    pub fn export(
+        &mut self,
+        destination: &Path,
+        format: ExportFormat,
+        folder_ids: Option<&[String]>,
+        export_password: Option<&str>,
+        acknowledge_plaintext: bool,
+    ) -> Result<ExportResult, PekaError> {
+        if format.is_plaintext() && !acknowledge_plaintext {
+            return Err(PekaError::PolicyViolation(
+                "Plaintext export must be confirmed: the file will contain unencrypted passwords"
+                    .to_string(),
+            ));
+        }
+
+        let selection = export::select_folders(&self.payload, folder_ids)?;
+
+        let contents = match format {
+            ExportFormat::Json => export::to_json(&selection)?,
+            ExportFormat::Csv => export::to_csv(&selection)?,
+            ExportFormat::Bitwarden => export::to_bitwarden_json(&selection)?,
+            ExportFormat::Encrypted => {
+                // The archive is a regular vault file, so the recipient can import it with
+                // the export password and never learns the master password.
+                let export_password = export_password.ok_or_else(|| {
+                    PekaError::InvalidInput("Export password is required".to_string())
+                })?;
+                if export_password == self.master_password {
+                    return Err(PekaError::PolicyViolation(
+                        "Export password must differ from the master password".to_string(),
+                    ));
+                }
+                strength::validate_master_password(export_password, &[&selection.vault_name])?;
+
+                // Written as plain JSON so any version of the app can import it.
+                let export_file = crypto::encrypt_payload(
+                    &selection,
+                    export_password,
+                    None,
+                    PayloadCompression::None,
+                    None,
+                )?;
+                crypto::serialize_vault_file(&export_file)?
+            }
+        };
+
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| PekaError::io("Unable to create destination directory", e))?;
+        }
+
+        fs::write(destination, contents)
+            .map_err(|e| PekaError::io("Unable to write export file", e))?;
+
+        if format == ExportFormat::Encrypted {
+            attachments::copy(
+                &self.path,
+                destination,
+                selection
+                    .folders
+                    .iter()
+                    .flat_map(|folder| folder.credentials.iter())
+                    .flat_map(|cred| cred.attachments.iter()),
+            )?;
+        }
+
+        self.record(AuditAction::VaultExported, None);
+
+        Ok(ExportResult {
+            path: destination.to_string_lossy().to_string(),
+            folders: selection.folders.len(),
+            credentials: selection
+                .folders
+                .iter()
+                .map(|folder| folder.credentials.len())
+                .sum(),
+        })
+    }
+
+    /// Writes the vault as a KeePass KDBX 4 database.
+    pub fn export_kdbx(
+        &mut self,
+        destination: &Path,
+        kdbx_password: &str,
+        cipher: KdbxCipher,
+    ) -> Result<(), PekaError> {
+        if kdbx_password.is_empty() {
+            return Err(PekaError::InvalidInput(
+                "KeePass database password is required".to_string(),
+            ));
+        }
+
+        let database = kdbx::write_database(&self.payload, kdbx_password, cipher)?;
+
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| PekaError::io("Unable to create destination directory", e))?;
+        }
+
+        fs::write(destination, database)
+            .map_err(|e| PekaError::io("Unable to write KeePass database", e))?;
+
+        self.record(AuditAction::VaultExported, None);
+        Ok(())
+    }
+
+    /// Merges another vault into this one. With [`MergeStrategy::Interactive`] and unresolved
+    /// conflicts nothing is applied and the conflicts are returned.
+    pub fn merge_from(
+        &mut self,
+        source: &Vault,
+        strategy: MergeStrategy,
+        resolutions: &HashMap<String, ConflictResolution>,
+    ) -> Result<MergeResult, PekaError> {
+        let canonical_source = source
+            .path
+            .canonicalize()
+            .map_err(|e| PekaError::io("Unable to resolve source path", e))?;
+        let canonical_target = self
+            .path
+            .canonicalize()
+            .map_err(|e| PekaError::io("Unable to resolve vault path", e))?;
+        if canonical_source == canonical_target {
+            return Err(PekaError::InvalidInput(
+                "A vault cannot be merged into itself".to_string(),
+            ));
+        }
+
+        let mut result =
+            merge::merge_vaults(&mut self.payload, &source.payload, strategy, resolutions);
+        if result.applied {
+            // Only the blobs of credentials the merge took in. They belong to this save,
+            // so a failed one removes them again.
+            let taken: Vec<StoredAttachment> = trash::all_credentials(&self.payload)
+                .flat_map(|cred| cred.attachments.iter())
+                .filter(|attachment| !attachments::blob_exists(&canonical_target, attachment))
+                .cloned()
+                .collect();
+            attachments::copy(&canonical_source, &canonical_target, &taken)?;
+            self.stored_blobs.extend(
+                taken
+                    .into_iter()
+                    .filter(|attachment| attachments::blob_exists(&canonical_target, attachment)),
+            );
+
+            self.record(AuditAction::VaultMerged, None);
+            result.vault = Some(self.contents());
+        }
+
+        Ok(result)
+    }
+
+    /// Syncs with the copy of the vault in the directory it was linked to with
+    /// [`sync::link`]. Folders and credentials changed here or in the other copy since
+    /// the last sync are merged, see [`sync`]; with unresolved conflicts nothing is
+    /// applied and the conflicts are returned. Pending changes are saved first.
+    pub fn sync(
+        &mut self,
+        resolutions: &HashMap<String, SyncResolution>,
+    ) -> Result<SyncReport, PekaError> {
+        let mut state = sync::linked_state(&self.path)?;
+        self.save()?;
+        let mut report = SyncReport {
+            applied: true,
+            ..SyncReport::default()
+        };
+        if state.in_sync(&self.path) {
+            return Ok(report);
+        }
+
+        let shared_path = state.shared_path(&self.path);
+        let open = |path: &Path| {
+            crypto::decrypt_vault(path, &self.master_password).map(|(_, payload, _)| payload)
+        };
+        let base = open(&state.base_path(&self.path)).ok();
+        // The shared copy is only decrypted when another device changed it; otherwise
+        // it is still the base.
+        let remote = if !shared_path.is_file() {
+            None
+        } else if state.shared_unchanged(&self.path) && base.is_some() {
+            base.clone()
+        } else {
+            Some(open(&shared_path).map_err(|err| match err {
+                PekaError::WrongPassword => PekaError::InvalidInput(
+                    "The copy in the sync directory does not open with this master password"
+                        .to_string(),
+                ),
+                err => err,
+            })?)
+        };
+
+        let mut local = self.payload.clone();
+        // Without a base, as on a first sync, what both copies agree on is unchanged.
+        report.pushed =
+            sync::stamp(&mut local, base.as_ref().or(remote.as_ref()), &state.replica_id);
+        let merged = match &remote {
+            Some(remote) => {
+                let outcome =
+                    sync::merge(&local, remote, base.as_ref(), &state.replica_id, resolutions)?;
+                report.pulled = outcome.pulled;
+                report.merged = outcome.merged;
+                report.deleted = outcome.deleted;
+                if !outcome.conflicts.is_empty() {
+                    report.applied = false;
+                    report.conflicts = outcome.conflicts;
+                    return Ok(report);
+                }
+                outcome.payload
+            }
+            None => local,
+        };
+
+        // Only a result the shared copy lacks is published; a sync that just takes the
+        // other copy's changes leaves it alone, so devices do not keep rewriting it.
+        let same = |a: &StoredVault, b: &StoredVault| {
+            serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+        };
+        let publish = remote.as_ref().is_none_or(|remote| !same(&merged, remote));
+        if publish || !same(&merged, &self.payload) {
+            if remote.is_some() {
+                attachments::copy(
+                    &shared_path,
+                    &self.path,
+                    trash::all_credentials(&merged).flat_map(|cred| cred.attachments.iter()),
+                )?;
+            }
+            self.dropped_blobs.extend(
+                trash::all_credentials(&self.payload)
+                    .flat_map(|cred| cred.attachments.iter())
+                    .cloned(),
+            );
+            self.payload = merged;
+            if publish {
+                self.record(AuditAction::VaultSynced, None);
+            } else {
+                // Only the log of the shared copy knows about the changes pulled.
+                self.unsaved_actions.push(AuditAction::VaultSynced);
+                self.modified = true;
+            }
+            self.save()?;
+        }
+
+        let base_contents = if publish {
+            let contents = fs::read(&self.path)
+                .map_err(|e| PekaError::io("Unable to read vault file from disk", e))?;
+            attachments::copy(
+                &self.path,
+                &shared_path,
+                trash::all_credentials(&self.payload).flat_map(|cred| cred.attachments.iter()),
+            )?;
+            sync::publish(&shared_path, &contents)?;
+            report.published = true;
+            contents
+        } else {
+            fs::read(&shared_path)
+                .map_err(|e| PekaError::io("Unable to read from the sync directory", e))?
+        };
+        state.finish(&self.path, &base_contents)?;
+        report.vault = Some(self.contents());
+        Ok(report)
+    }
+
+    /// Compares revision `from` of the vault history with revision `to`, or with the
+    /// vault as it is now. Both are decrypted with the current master password.
+    pub fn history_diff(&self, from: &str, to: Option<&str>) -> Result<HistoryDiff, PekaError> {
+        let (from, old) = self.open_revision(from)?;
+        let (to, new) = match to {
+            Some(to) => {
+                let (to, new) = self.open_revision(to)?;
+                (Some(to), new)
+            }
+            None => (None, self.payload.clone()),
+        };
+        Ok(HistoryDiff {
+            changes: history::diff(&old, &new),
+            from,
+            to,
+        })
+    }
+
+    /// Brings folders, credentials, trash and settings back to how they were in
+    /// `revision` of the vault history. The audit log is kept and records the rollback,
+    /// which is saved as a new revision; nothing is lost from the history.
+    pub fn roll_back(&mut self, revision: &str) -> Result<HistoryRollback, PekaError> {
+        let (revision, mut restored) = self.open_revision(revision)?;
+
+        // Blobs of attachments deleted since then may already be gone.
+        let mut missing_attachments = 0;
+        for cred in trash::all_credentials_mut(&mut restored) {
+            let before = cred.attachments.len();
+            cred.attachments
+                .retain(|attachment| attachments::blob_exists(&self.path, attachment));
+            missing_attachments += before - cred.attachments.len();
+        }
+
+        self.dropped_blobs.extend(
+            trash::all_credentials(&self.payload)
+                .flat_map(|cred| cred.attachments.iter())
+                .cloned(),
+        );
+        self.payload.vault_name = restored.vault_name;
+        self.payload.folders = restored.folders;
+        self.payload.trash = restored.trash;
+        self.payload.trash_retention_days = restored.trash_retention_days;
+        self.record(AuditAction::VaultRolledBack, Some(&revision));
+
+        Ok(HistoryRollback {
+            revision,
+            missing_attachments,
+            vault: Some(self.contents()),
+        })
+    }
+
+    fn open_revision(&self, revision: &str) -> Result<(String, StoredVault), PekaError> {
+        let (revision, contents) = history::read(&self.path, revision)?;
+        let file = crypto::parse_vault_file(&contents)?;
+        let decrypted = crypto::decrypt_bytes(&file, &self.master_password).map_err(|err| {
+            match err {
+                PekaError::WrongPassword => PekaError::InvalidInput(format!(
+                    "Revision {} does not open with the current master password",
+                    &revision[..revision.len().min(8)]
+                )),
+                err => err,
+            }
+        })?;
+        let payload = serde_json::from_slice(&decrypted)
+            .map_err(|e| PekaError::corrupted("Vault data is malformed", e))?;
+        Ok((revision, payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir, MASTER_PASSWORD};
+
+    #[test]
+    fn records_copying_the_vault_file_in_the_copy() {
+        let dir = TempDir::new();
+        let (mut vault, _, _) =
+            testing::vault_with_credential(dir.subdir("vaults").join("work.peka"));
+        let destination = dir.subdir("copies").join("work.peka");
+        vault.copy_file_to(&destination).unwrap();
+
+        let copy = Vault::open(&destination, MASTER_PASSWORD).unwrap();
+        let log = copy.audit_log();
+        assert!(log.verified);
+        let last = log.entries.last().unwrap();
+        assert!(matches!(last.action, AuditAction::VaultExported));
+        assert_eq!(log.entries.len(), vault.audit_log().entries.len());
+    }
+}
@@ -0,0 +1,63 @@
+#[cfg(target_os = "macos")]
+use arboard::SetExtApple;
+#[cfg(target_os = "linux")]
+use arboard::SetExtLinux;
+#[cfg(windows)]
+use arboard::SetExtWindows;
+use peka_core::clipboard::ClipboardBackend;
+use peka_core::PekaError;
+
+/// The system clipboard. On Linux it works on X11 and, through the data-control
+/// protocol, on Wayland.
+///
+/// The connection is opened on first use, so the app still starts without a
+/// display. It then stays open for the lifetime of the app, because on X11 and
+/// Wayland the copied value is served by this process and would go away with it.
+#[derive(Default)]
+pub struct SystemClipboard {
+    clipboard: Option<arboard::Clipboard>,
+}
+
+impl SystemClipboard {
+    fn handle(&mut self) -> Result<&mut arboard::Clipboard, PekaError> {
+        if self.clipboard.is_none() {
+            let clipboard = arboard::Clipboard::new().map_err(|e| {
+                PekaError::Unsupported(format!("Clipboard is not available: {}", e))
+            })?;
+            self.clipboard = Some(clipboard);
+        }
+        Ok(self.clipboard.as_mut().expect("clipboard was just opened"))
+    }
+}
+
+impl ClipboardBackend for SystemClipboard {
+    fn set_secret(&mut self, text: &str) -> Result<(), PekaError> {
+        let set = self.handle()?.set();
+        // Keeps the value out of clipboard managers (KDE's password manager hint on
+        // Linux, the concealed type of nspasteboard.org on macOS) and, on Windows, out
+        // of clipboard history and cloud sync.
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        let set = set.exclude_from_history();
+        #[cfg(windows)]
+        let set = set.exclude_from_history().exclude_from_cloud();
+        set.text(text)
+            .map_err(|e| PekaError::Internal(format!("Unable to write to the clipboard: {}", e)))
+    }
+
+    fn text(&mut self) -> Result<Option<String>, PekaError> {
+        match self.handle()?.get_text() {
+            Ok(text) => Ok(Some(text)),
+            Err(arboard::Error::ContentNotAvailable) => Ok(None),
+            Err(e) => Err(PekaError::Internal(format!(
+                "Unable to read the clipboard: {}",
+                e
+            ))),
+        }
+    }
+
+    fn clear(&mut self) -> Result<(), PekaError> {
+        self.handle()?
+            .clear()
+            .map_err(|e| PekaError::Internal(format!("Unable to clear the clipboard: {}", e)))
+    }
+}
@@ -0,0 +1,39 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A vault file the app was asked to open, waiting for the frontend to pick it up.
+pub struct LaunchVault(Mutex<Option<String>>);
+
+impl LaunchVault {
+    /// Takes the `.peka` file among the command-line arguments, which is how file
+    /// associations hand over the file on Windows and Linux.
+    pub fn from_args(args: impl IntoIterator<Item = OsString>) -> Self {
+        let path = args
+            .into_iter()
+            .skip(1)
+            .map(PathBuf::from)
+            .find(|arg| is_vault_file(arg))
+            .map(|path| path.to_string_lossy().to_string());
+        LaunchVault(Mutex::new(path))
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub fn set(&self, path: String) {
+        *self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(path);
+    }
+
+    pub fn take(&self) -> Option<String> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take()
+    }
+}
+
+pub fn is_vault_file(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()) == Some("peka") && path.is_file()
+}
@@ -1,23 +1,35 @@
-use aes_gcm::aead::{Aead, KeyInit};
-use aes_gcm::{Aes256Gcm, Nonce};
-use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
-use argon2::{Algorithm, Argon2, Params, Version};
-use base64::{engine::general_purpose, Engine as _};
-use chrono::Utc;
-use directories::ProjectDirs;
-use rand::rngs::OsRng;
-use rand::RngCore;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
-use uuid::Uuid;
-
-const DEFAULT_MEMORY_KIB: u32 = 131_072;
-const DEFAULT_TIME_COST: u32 = 3;
-const DEFAULT_PARALLELISM: u32 = 2;
-const DEFAULT_HASH_LENGTH: u32 = 32;
-const DEFAULT_SALT_LENGTH: usize = 16;
-const CURRENT_VERSION: u8 = 1;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use peka_core::attachments::{AttachmentSummary, StoredAttachment};
+use peka_core::audit::AuditLog;
+use peka_core::breach::{BreachCorpus, BreachReport};
+use peka_core::check::{self, CheckReport, Repair, RepairResult};
+use peka_core::clipboard::{SecretClipboard, DEFAULT_CLEAR_AFTER_SECONDS, MAX_CLEAR_AFTER_SECONDS};
+use peka_core::csv_import::{CsvColumnMapping, CsvImportResult, CsvSource};
+use peka_core::export::{ExportFormat, ExportResult};
+use peka_core::health::{self, VaultHealthReport};
+use peka_core::history::{self, HistoryDiff, HistoryRevision, HistoryRollback, HistoryStatus};
+use peka_core::kdbx::{KdbxCipher, KdbxImportResult};
+use peka_core::locations::{LocationKind, LocationRegistry, RecentVault, VaultLocation};
+use peka_core::merge::{ConflictResolution, MergeResult, MergeStrategy};
+use peka_core::search::{SearchFilters, SearchResults, SearchSort};
+use peka_core::strength::{self, PasswordStrength};
+use peka_core::sync::{self, SyncReport, SyncResolution, SyncStatus};
+use peka_core::tags::TagSummary;
+use peka_core::trash::TrashContents;
+use peka_core::{
+    read_summary, NewCredential, PekaError, StorageFormat, Vault, VaultContents, VaultSummary,
+};
+
+mod clipboard;
+mod launch;
+
+use clipboard::SystemClipboard;
+use launch::LaunchVault;
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
@@ -25,26 +37,6 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct KdfParams {
-    algorithm: String,
-    memory_kib: u32,
-    time_cost: u32,
-    parallelism: u32,
-    hash_length: u32,
-    salt_length: u32,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-struct VaultFile {
-    version: u8,
-    vault_name: String,
-    kdf: KdfParams,
-    salt: String,
-    nonce: String,
-    ciphertext: String,
-}
-
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 struct CreateVaultResult {
@@ -57,131 +49,136 @@ struct ImportVaultResult {
     path: String,
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct VaultSummary {
-    path: String,
-    vault_name: String,
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn create_vault(
+    vaultName: String,
+    masterPassword: String,
+    locationId: Option<String>,
+) -> Result<CreateVaultResult, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        create_vault_inner(vaultName, masterPassword, locationId.as_deref())
+    })
+    .await
+    .map_err(PekaError::internal)?
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-#[serde(rename_all = "camelCase")]
-struct StoredCredential {
-    id: String,
-    title: String,
-    username: String,
-    password: String,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    notes: Option<String>,
-    created_at: String,
-    updated_at: String,
-}
+fn create_vault_inner(
+    vault_name: String,
+    master_password: String,
+    location_id: Option<&str>,
+) -> Result<CreateVaultResult, PekaError> {
+    let vault = LocationRegistry::load_default()?
+        .store(location_id)?
+        .create(&vault_name, &master_password)?;
 
-#[derive(Serialize, Deserialize, Clone)]
-#[serde(rename_all = "camelCase")]
-struct StoredFolder {
-    id: String,
-    name: String,
-    secure: bool,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pin_hash: Option<String>,
-    credentials: Vec<StoredCredential>,
-    created_at: String,
-    updated_at: String,
+    Ok(CreateVaultResult {
+        path: vault.path().to_string_lossy().to_string(),
+    })
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-#[serde(rename_all = "camelCase")]
-struct StoredVault {
-    vault_name: String,
-    folders: Vec<StoredFolder>,
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn open_vault(
+    path: String,
+    masterPassword: String,
+    allowExpensiveKdf: Option<bool>,
+) -> Result<VaultContents, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        open_vault_inner(&path, &masterPassword, allowExpensiveKdf.unwrap_or(false))
+    })
+    .await
+    .map_err(PekaError::internal)?
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct VaultCredential {
-    id: String,
-    title: String,
-    username: String,
-    password: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    notes: Option<String>,
-    #[serde(rename = "createdAt")]
-    created_at: String,
-    #[serde(rename = "updatedAt")]
-    updated_at: String,
-}
+fn open_vault_inner(
+    path: &str,
+    master_password: &str,
+    allow_expensive_kdf: bool,
+) -> Result<VaultContents, PekaError> {
+    // Unlocking is where a vault file first gets decrypted, so costly key derivation
+    // is confirmed here; later commands on the unlocked vault do not ask again.
+    let mut vault = Vault::open_external(path, master_password, allow_expensive_kdf)?;
+    vault.purge_expired_trash();
+    vault.save()?;
+
+    // Vault files opened from outside every location are remembered for the next
+    // start. Failing to remember one must not keep the vault from opening.
+    if let Ok(mut registry) = LocationRegistry::load_default() {
+        if registry.record_opened(vault.path(), vault.name()) {
+            let _ = registry.save();
+        }
+    }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct VaultFolderPublic {
-    id: String,
-    name: String,
-    secure: bool,
-    credentials: Vec<VaultCredential>,
-    #[serde(rename = "createdAt")]
-    created_at: String,
-    #[serde(rename = "updatedAt")]
-    updated_at: String,
+    Ok(vault.contents())
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct VaultContents {
-    vault_name: String,
-    folders: Vec<VaultFolderPublic>,
+#[tauri::command]
+async fn describe_vault_file(path: String) -> Result<VaultSummary, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || Ok(read_summary(Path::new(&path))))
+        .await
+        .map_err(PekaError::internal)?
 }
 
 #[tauri::command]
 #[allow(non_snake_case)]
-async fn create_vault(vaultName: String, masterPassword: String) -> Result<CreateVaultResult, String> {
-    tauri::async_runtime::spawn_blocking(move || create_vault_inner(vaultName, masterPassword))
-        .await
-        .map_err(|e| e.to_string())?
+async fn check_vault(path: String, masterPassword: String) -> Result<CheckReport, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        check::check_vault(Path::new(&path), &masterPassword)
+    })
+    .await
+    .map_err(PekaError::internal)?
 }
 
-fn create_vault_inner(vault_name: String, master_password: String) -> Result<CreateVaultResult, String> {
-    let trimmed_vault_name = vault_name.trim();
-    if trimmed_vault_name.is_empty() {
-        return Err("Vault name cannot be empty".to_string());
-    }
-
-    if master_password.trim().is_empty() {
-        return Err("Master password cannot be empty".to_string());
-    }
-
-    let payload = StoredVault {
-        vault_name: trimmed_vault_name.to_string(),
-        folders: Vec::new(),
-    };
-
-    let vault_file = encrypt_payload(&payload, &master_password, None)?;
-    let vault_json = serde_json::to_string_pretty(&vault_file).map_err(|e| e.to_string())?;
-
-    let base_dir = resolve_vault_directory()?;
-    fs::create_dir_all(&base_dir).map_err(|e| e.to_string())?;
-
-    let file_name = format!("{}.peka", sanitize_file_name(trimmed_vault_name));
-    let file_path = base_dir.join(file_name);
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn repair_vault(
+    path: String,
+    masterPassword: String,
+    repairs: Vec<Repair>,
+) -> Result<RepairResult, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        check::repair_vault(Path::new(&path), &masterPassword, &repairs)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
 
-    fs::write(&file_path, vault_json).map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn list_recent_vaults() -> Result<Vec<RecentVault>, PekaError> {
+    tauri::async_runtime::spawn_blocking(list_recent_vaults_inner)
+        .await
+        .map_err(PekaError::internal)?
+}
 
-    Ok(CreateVaultResult {
-        path: file_path.to_string_lossy().to_string(),
-    })
+fn list_recent_vaults_inner() -> Result<Vec<RecentVault>, PekaError> {
+    // Files that are gone for now, e.g. on an unmounted drive, stay remembered.
+    Ok(LocationRegistry::load_default()?
+        .recent()
+        .iter()
+        .filter(|recent| Path::new(&recent.path).is_file())
+        .cloned()
+        .collect())
 }
 
 #[tauri::command]
-#[allow(non_snake_case)]
-async fn open_vault(path: String, masterPassword: String) -> Result<VaultContents, String> {
-    tauri::async_runtime::spawn_blocking(move || open_vault_inner(&path, &masterPassword))
+async fn forget_recent_vault(path: String) -> Result<(), PekaError> {
+    tauri::async_runtime::spawn_blocking(move || forget_recent_vault_inner(&path))
         .await
-        .map_err(|e| e.to_string())?
+        .map_err(PekaError::internal)?
+}
+
+fn forget_recent_vault_inner(path: &str) -> Result<(), PekaError> {
+    let mut registry = LocationRegistry::load_default()?;
+    if registry.forget_recent(Path::new(path)) {
+        registry.save()?;
+    }
+    Ok(())
 }
 
-fn open_vault_inner(path: &str, master_password: &str) -> Result<VaultContents, String> {
-    let (_vault_file, payload) = decrypt_vault(path, master_password)?;
-    Ok(payload_to_public(&payload))
+#[tauri::command]
+fn take_launch_vault(launch: tauri::State<'_, LaunchVault>) -> Option<String> {
+    launch.take()
 }
 
 #[tauri::command]
@@ -192,335 +189,198 @@ async fn create_folder(
     name: String,
     secure: bool,
     pin: Option<String>,
-) -> Result<VaultContents, String> {
+) -> Result<VaultContents, PekaError> {
     tauri::async_runtime::spawn_blocking(move || {
-        create_folder_inner(&path, &masterPassword, name, secure, pin)
+        create_folder_inner(&path, &masterPassword, &name, secure, pin.as_deref())
     })
     .await
-    .map_err(|e| e.to_string())?
+    .map_err(PekaError::internal)?
 }
 
 fn create_folder_inner(
     path: &str,
     master_password: &str,
-    name: String,
+    name: &str,
     secure: bool,
-    pin: Option<String>,
-) -> Result<VaultContents, String> {
-    let folder_name = name.trim();
-    if folder_name.is_empty() {
-        return Err("Folder name is required.".to_string());
-    }
-
-    if secure {
-        let pin_ref = pin.as_deref().ok_or_else(|| "PIN is required for secure folders.".to_string())?;
-        if !pin_ref.chars().all(|c| c.is_ascii_digit()) || pin_ref.len() != 4 {
-            return Err("PIN must be exactly 4 digits.".to_string());
-        }
-    }
-
-    let (vault_file, mut payload) = decrypt_vault(path, master_password)?;
-
-    let now = Utc::now().to_rfc3339();
-    let stored_folder = StoredFolder {
-        id: Uuid::new_v4().to_string(),
-        name: folder_name.to_string(),
-        secure,
-        pin_hash: if secure {
-            let pin_value = pin.unwrap();
-            let salt = SaltString::generate(&mut OsRng);
-            let argon = Argon2::default();
-            Some(
-                argon
-                    .hash_password(pin_value.as_bytes(), &salt)
-                    .map_err(|e| e.to_string())?
-                    .to_string(),
-            )
-        } else {
-            None
-        },
-        credentials: Vec::new(),
-        created_at: now.clone(),
-        updated_at: now.clone(),
-    };
-
-    payload.folders.push(stored_folder);
+    pin: Option<&str>,
+) -> Result<VaultContents, PekaError> {
+    let mut vault = Vault::open(path, master_password)?;
+    vault.create_folder(name, secure, pin)?;
+    vault.save()?;
 
-    let updated_file = encrypt_payload(&payload, master_password, Some(&vault_file.kdf))?;
-    let vault_json = serde_json::to_string_pretty(&updated_file).map_err(|e| e.to_string())?;
-    fs::write(path, vault_json).map_err(|e| e.to_string())?;
-
-    Ok(payload_to_public(&payload))
-}
-
-fn encrypt_payload(
-    payload: &StoredVault,
-    master_password: &str,
-    existing_kdf: Option<&KdfParams>,
-) -> Result<VaultFile, String> {
-    let (memory_kib, time_cost, parallelism, hash_length, salt_length) = if let Some(kdf) = existing_kdf {
-        (
-            kdf.memory_kib,
-            kdf.time_cost,
-            kdf.parallelism,
-            kdf.hash_length,
-            kdf.salt_length as usize,
-        )
-    } else {
-        (
-            DEFAULT_MEMORY_KIB,
-            DEFAULT_TIME_COST,
-            DEFAULT_PARALLELISM,
-            DEFAULT_HASH_LENGTH,
-            DEFAULT_SALT_LENGTH,
-        )
-    };
-
-    let params = Params::new(
-        memory_kib,
-        time_cost,
-        parallelism,
-        Some(hash_length as usize),
-    )
-    .map_err(|e| e.to_string())?;
-
-    let mut salt = vec![0u8; salt_length];
-    OsRng.fill_bytes(&mut salt);
-
-    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
-    let mut encryption_key = vec![0u8; hash_length as usize];
-    argon2
-        .hash_password_into(master_password.as_bytes(), &salt, &mut encryption_key)
-        .map_err(|e| e.to_string())?;
-
-    let clear_bytes = serde_json::to_vec(payload).map_err(|e| e.to_string())?;
-
-    let mut nonce_bytes = [0u8; 12];
-    OsRng.fill_bytes(&mut nonce_bytes);
-
-    let cipher = Aes256Gcm::new_from_slice(&encryption_key).map_err(|e| e.to_string())?;
-    let nonce = Nonce::from_slice(&nonce_bytes);
-    let ciphertext = cipher
-        .encrypt(nonce, clear_bytes.as_ref())
-        .map_err(|e| e.to_string())?;
-
-    Ok(VaultFile {
-        version: CURRENT_VERSION,
-        vault_name: payload.vault_name.clone(),
-        kdf: KdfParams {
-            algorithm: "Argon2id".to_string(),
-            memory_kib,
-            time_cost,
-            parallelism,
-            hash_length,
-            salt_length: salt_length as u32,
-        },
-        salt: general_purpose::STANDARD.encode(salt),
-        nonce: general_purpose::STANDARD.encode(nonce_bytes),
-        ciphertext: general_purpose::STANDARD.encode(ciphertext),
-    })
-}
-
-fn decrypt_vault(path: &str, master_password: &str) -> Result<(VaultFile, StoredVault), String> {
-    let raw =
-        fs::read_to_string(path).map_err(|_| "Unable to read vault file from disk".to_string())?;
-    let vault_file: VaultFile =
-        serde_json::from_str(&raw).map_err(|_| "Vault file is corrupted or invalid".to_string())?;
-
-    let salt = general_purpose::STANDARD
-        .decode(vault_file.salt.clone())
-        .map_err(|_| "Invalid salt encoding".to_string())?;
-    let nonce_bytes = general_purpose::STANDARD
-        .decode(vault_file.nonce.clone())
-        .map_err(|_| "Invalid nonce encoding".to_string())?;
-    let ciphertext = general_purpose::STANDARD
-        .decode(vault_file.ciphertext.clone())
-        .map_err(|_| "Invalid ciphertext encoding".to_string())?;
-
-    let params = Params::new(
-        vault_file.kdf.memory_kib,
-        vault_file.kdf.time_cost,
-        vault_file.kdf.parallelism,
-        Some(vault_file.kdf.hash_length as usize),
-    )
-    .map_err(|e| e.to_string())?;
-
-    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
-    let mut encryption_key = vec![0u8; vault_file.kdf.hash_length as usize];
-    argon2
-        .hash_password_into(master_password.as_bytes(), &salt, &mut encryption_key)
-        .map_err(|_| "Unable to derive encryption key".to_string())?;
-
-    let cipher = Aes256Gcm::new_from_slice(&encryption_key).map_err(|e| e.to_string())?;
-    let nonce = Nonce::from_slice(&nonce_bytes);
-    let decrypted = cipher
-        .decrypt(nonce, ciphertext.as_ref())
-        .map_err(|_| "Failed to decrypt vault: incorrect password or corrupted data".to_string())?;
-
-    let payload: StoredVault =
-        serde_json::from_slice(&decrypted).map_err(|_| "Vault data is malformed".to_string())?;
-
-    Ok((vault_file, payload))
-}
-
-fn payload_to_public(payload: &StoredVault) -> VaultContents {
-    let folders = payload
-        .folders
-        .iter()
-        .map(|folder| VaultFolderPublic {
-            id: folder.id.clone(),
-            name: folder.name.clone(),
-            secure: folder.secure,
-            credentials: folder
-                .credentials
-                .iter()
-                .map(|cred| VaultCredential {
-                    id: cred.id.clone(),
-                    title: cred.title.clone(),
-                    username: cred.username.clone(),
-                    password: cred.password.clone(),
-                    notes: cred.notes.clone(),
-                    created_at: cred.created_at.clone(),
-                    updated_at: cred.updated_at.clone(),
-                })
-                .collect(),
-            created_at: folder.created_at.clone(),
-            updated_at: folder.updated_at.clone(),
-        })
-        .collect();
-
-    VaultContents {
-        vault_name: payload.vault_name.clone(),
-        folders,
-    }
+    Ok(vault.contents())
 }
 
-fn resolve_vault_directory() -> Result<PathBuf, String> {
-    if let Some(proj_dirs) = ProjectDirs::from("com", "nalsan", "peka") {
-        return Ok(proj_dirs.data_dir().join("vaults"));
-    }
-    let mut dir = std::env::current_dir().map_err(|e| e.to_string())?;
-    dir.push("vaults");
-    Ok(dir)
-}
-
-fn sanitize_file_name(input: &str) -> String {
-    let sanitized: String = input
-        .trim()
-        .chars()
-        .map(|ch| {
-            if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
-                ch
-            } else if ch.is_whitespace() {
-                '_'
-            } else {
-                '_'
-            }
-        })
-        .collect();
-
-    let trimmed = sanitized.trim_matches('_');
-
-    if trimmed.is_empty() {
-        "vault".to_string()
-    } else {
-        trimmed.replace("__", "_")
-    }
+#[tauri::command]
+#[allow(non_snake_case)]
+fn estimate_password_strength(password: String, userInputs: Option<Vec<String>>) -> PasswordStrength {
+    let inputs = userInputs.unwrap_or_default();
+    let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+    strength::estimate_strength(&password, &inputs)
 }
 
 #[tauri::command]
-async fn list_vaults() -> Result<Vec<VaultSummary>, String> {
+async fn list_vaults() -> Result<Vec<VaultSummary>, PekaError> {
     tauri::async_runtime::spawn_blocking(list_vaults_inner)
         .await
-        .map_err(|e| e.to_string())?
+        .map_err(PekaError::internal)?
+}
+
+fn list_vaults_inner() -> Result<Vec<VaultSummary>, PekaError> {
+    LocationRegistry::load_default()?.list()
 }
 
 #[tauri::command]
-async fn delete_vault(path: String) -> Result<(), String> {
+async fn delete_vault(path: String) -> Result<(), PekaError> {
     tauri::async_runtime::spawn_blocking(move || delete_vault_inner(path))
         .await
-        .map_err(|e| e.to_string())?
+        .map_err(PekaError::internal)?
 }
 
-fn delete_vault_inner(path: String) -> Result<(), String> {
+fn delete_vault_inner(path: String) -> Result<(), PekaError> {
     if path.trim().is_empty() {
-        return Err("Vault path is required".to_string());
-    }
-
-    let target_path = PathBuf::from(&path);
-    if !target_path.exists() {
-        return Err("Vault file not found".to_string());
-    }
-
-    let canonical_target =
-        target_path.canonicalize().map_err(|_| "Unable to resolve vault path".to_string())?;
-    let vault_dir = resolve_vault_directory()?;
-    let canonical_vault_dir =
-        vault_dir.canonicalize().unwrap_or(vault_dir.clone());
-
-    if !canonical_target.starts_with(&canonical_vault_dir) {
-        return Err("Vault path is invalid".to_string());
-    }
-
-    if canonical_target
-        .extension()
-        .and_then(|ext| ext.to_str())
-        != Some("peka")
-    {
-        return Err("Invalid vault file".to_string());
+        return Err(PekaError::InvalidInput("Vault path is required".to_string()));
     }
 
-    fs::remove_file(canonical_target).map_err(|e| e.to_string())
+    let mut registry = LocationRegistry::load_default()?;
+    registry.delete(Path::new(&path))?;
+    registry.save()
 }
 
 #[tauri::command]
 #[allow(non_snake_case)]
-async fn export_vault_file(sourcePath: String, destinationPath: String) -> Result<(), String> {
+async fn export_vault_file(
+    sourcePath: String,
+    masterPassword: String,
+    destinationPath: String,
+) -> Result<(), PekaError> {
     tauri::async_runtime::spawn_blocking(move || {
-        export_vault_file_inner(sourcePath, destinationPath)
+        export_vault_file_inner(sourcePath, masterPassword, destinationPath)
     })
     .await
-    .map_err(|e| e.to_string())?
+    .map_err(PekaError::internal)?
 }
 
-fn export_vault_file_inner(source_path: String, destination_path: String) -> Result<(), String> {
+fn export_vault_file_inner(
+    source_path: String,
+    master_password: String,
+    destination_path: String,
+) -> Result<(), PekaError> {
     if source_path.trim().is_empty() {
-        return Err("Source path is required".to_string());
+        return Err(PekaError::InvalidInput("Source path is required".to_string()));
     }
 
     if destination_path.trim().is_empty() {
-        return Err("Destination path is required".to_string());
+        return Err(PekaError::InvalidInput("Destination path is required".to_string()));
     }
 
-    let source = PathBuf::from(&source_path);
-    if !source.exists() {
-        return Err("Vault file not found".to_string());
-    }
+    LocationRegistry::load_default()?
+        .copy_out(Path::new(&source_path), &master_password, Path::new(&destination_path))
+}
 
-    let canonical_source =
-        source.canonicalize().map_err(|_| "Unable to resolve source path".to_string())?;
-    let vault_dir = resolve_vault_directory()?;
-    let canonical_vault_dir = vault_dir.canonicalize().unwrap_or(vault_dir.clone());
+#[tauri::command]
+async fn list_vault_locations() -> Result<Vec<VaultLocation>, PekaError> {
+    tauri::async_runtime::spawn_blocking(list_vault_locations_inner)
+        .await
+        .map_err(PekaError::internal)?
+}
 
-    if !canonical_source.starts_with(&canonical_vault_dir) {
-        return Err("Vault path is invalid".to_string());
-    }
+fn list_vault_locations_inner() -> Result<Vec<VaultLocation>, PekaError> {
+    Ok(LocationRegistry::load_default()?.locations().to_vec())
+}
 
-    if canonical_source
-        .extension()
-        .and_then(|ext| ext.to_str())
-        != Some("peka")
-    {
-        return Err("Invalid vault file".to_string());
+#[tauri::command]
+async fn add_vault_location(
+    path: String,
+    kind: LocationKind,
+    label: Option<String>,
+) -> Result<VaultLocation, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        add_vault_location_inner(&path, kind, label.as_deref())
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn add_vault_location_inner(
+    path: &str,
+    kind: LocationKind,
+    label: Option<&str>,
+) -> Result<VaultLocation, PekaError> {
+    if path.trim().is_empty() {
+        return Err(PekaError::InvalidInput("Location path is required".to_string()));
     }
 
-    let destination = PathBuf::from(destination_path);
-    if let Some(parent) = destination.parent() {
-        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    let mut registry = LocationRegistry::load_default()?;
+    let location = registry.add(Path::new(path), kind, label)?;
+    registry.save()?;
+    Ok(location)
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn remove_vault_location(locationId: String) -> Result<(), PekaError> {
+    tauri::async_runtime::spawn_blocking(move || remove_vault_location_inner(&locationId))
+        .await
+        .map_err(PekaError::internal)?
+}
+
+fn remove_vault_location_inner(location_id: &str) -> Result<(), PekaError> {
+    let mut registry = LocationRegistry::load_default()?;
+    registry.remove(location_id)?;
+    registry.save()
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn export_vault(
+    path: String,
+    masterPassword: String,
+    destinationPath: String,
+    format: ExportFormat,
+    folderIds: Option<Vec<String>>,
+    exportPassword: Option<String>,
+    acknowledgePlaintext: bool,
+) -> Result<ExportResult, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        export_vault_inner(
+            &path,
+            &masterPassword,
+            &destinationPath,
+            format,
+            folderIds.as_deref(),
+            exportPassword.as_deref(),
+            acknowledgePlaintext,
+        )
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn export_vault_inner(
+    path: &str,
+    master_password: &str,
+    destination_path: &str,
+    format: ExportFormat,
+    folder_ids: Option<&[String]>,
+    export_password: Option<&str>,
+    acknowledge_plaintext: bool,
+) -> Result<ExportResult, PekaError> {
+    if destination_path.trim().is_empty() {
+        return Err(PekaError::InvalidInput("Destination path is required".to_string()));
     }
 
-    fs::copy(canonical_source, destination).map_err(|e| e.to_string())?;
-    Ok(())
+    let mut vault = Vault::open(path, master_password)?;
+    let result = vault.export(
+        Path::new(destination_path),
+        format,
+        folder_ids,
+        export_password,
+        acknowledge_plaintext,
+    )?;
+    vault.save()?;
+
+    Ok(result)
 }
 
 #[tauri::command]
@@ -529,34 +389,24 @@ async fn delete_folder(
     path: String,
     masterPassword: String,
     folderId: String,
-) -> Result<VaultContents, String> {
+) -> Result<VaultContents, PekaError> {
     tauri::async_runtime::spawn_blocking(move || {
         delete_folder_inner(&path, &masterPassword, &folderId)
     })
     .await
-    .map_err(|e| e.to_string())?
+    .map_err(PekaError::internal)?
 }
 
 fn delete_folder_inner(
     path: &str,
     master_password: &str,
     folder_id: &str,
-) -> Result<VaultContents, String> {
-    let (vault_file, mut payload) = decrypt_vault(path, master_password)?;
-
-    let position = payload
-        .folders
-        .iter()
-        .position(|folder| folder.id == folder_id)
-        .ok_or_else(|| "Folder not found".to_string())?;
-
-    payload.folders.remove(position);
+) -> Result<VaultContents, PekaError> {
+    let mut vault = Vault::open(path, master_password)?;
+    vault.delete_folder(folder_id)?;
+    vault.save()?;
 
-    let updated_file = encrypt_payload(&payload, master_password, Some(&vault_file.kdf))?;
-    let vault_json = serde_json::to_string_pretty(&updated_file).map_err(|e| e.to_string())?;
-    fs::write(path, vault_json).map_err(|e| e.to_string())?;
-
-    Ok(payload_to_public(&payload))
+    Ok(vault.contents())
 }
 
 #[tauri::command]
@@ -566,12 +416,12 @@ async fn delete_credential(
     masterPassword: String,
     folderId: String,
     credentialId: String,
-) -> Result<VaultContents, String> {
+) -> Result<VaultContents, PekaError> {
     tauri::async_runtime::spawn_blocking(move || {
         delete_credential_inner(&path, &masterPassword, &folderId, &credentialId)
     })
     .await
-    .map_err(|e| e.to_string())?
+    .map_err(PekaError::internal)?
 }
 
 fn delete_credential_inner(
@@ -579,28 +429,12 @@ fn delete_credential_inner(
     master_password: &str,
     folder_id: &str,
     credential_id: &str,
-) -> Result<VaultContents, String> {
-    let (vault_file, mut payload) = decrypt_vault(path, master_password)?;
-    let folder = payload
-        .folders
-        .iter_mut()
-        .find(|folder| folder.id == folder_id)
-        .ok_or_else(|| "Folder not found".to_string())?;
-
-    let position = folder
-        .credentials
-        .iter()
-        .position(|cred| cred.id == credential_id)
-        .ok_or_else(|| "Credential not found".to_string())?;
-
-    folder.credentials.remove(position);
-    folder.updated_at = Utc::now().to_rfc3339();
-
-    let updated_file = encrypt_payload(&payload, master_password, Some(&vault_file.kdf))?;
-    let vault_json = serde_json::to_string_pretty(&updated_file).map_err(|e| e.to_string())?;
-    fs::write(path, vault_json).map_err(|e| e.to_string())?;
+) -> Result<VaultContents, PekaError> {
+    let mut vault = Vault::open(path, master_password)?;
+    vault.delete_credential(folder_id, credential_id)?;
+    vault.save()?;
 
-    Ok(payload_to_public(&payload))
+    Ok(vault.contents())
 }
 
 #[tauri::command]
@@ -612,7 +446,7 @@ async fn add_credential(
     identifier: String,
     username: String,
     password: String,
-) -> Result<VaultContents, String> {
+) -> Result<VaultContents, PekaError> {
     tauri::async_runtime::spawn_blocking(move || {
         add_credential_inner(
             &path,
@@ -624,7 +458,7 @@ async fn add_credential(
         )
     })
     .await
-    .map_err(|e| e.to_string())?
+    .map_err(PekaError::internal)?
 }
 
 fn add_credential_inner(
@@ -634,76 +468,24 @@ fn add_credential_inner(
     identifier: String,
     username: String,
     password: String,
-) -> Result<VaultContents, String> {
+) -> Result<VaultContents, PekaError> {
     if identifier.trim().is_empty() {
-        return Err("Username or email is required.".to_string());
-    }
-
-    if password.is_empty() {
-        return Err("Password is required.".to_string());
-    }
-
-    let (vault_file, mut payload) = decrypt_vault(path, master_password)?;
-    let folder = payload
-        .folders
-        .iter_mut()
-        .find(|folder| folder.id == folder_id)
-        .ok_or_else(|| "Folder not found".to_string())?;
-
-    let now = Utc::now().to_rfc3339();
-    let credential = StoredCredential {
-        id: Uuid::new_v4().to_string(),
-        title: identifier,
-        username,
-        password,
-        notes: None,
-        created_at: now.clone(),
-        updated_at: now.clone(),
-    };
-
-    folder.credentials.push(credential);
-    folder.updated_at = now.clone();
-
-    let updated_file = encrypt_payload(&payload, master_password, Some(&vault_file.kdf))?;
-    let vault_json = serde_json::to_string_pretty(&updated_file).map_err(|e| e.to_string())?;
-    fs::write(path, vault_json).map_err(|e| e.to_string())?;
-
-    Ok(payload_to_public(&payload))
-}
-
-fn list_vaults_inner() -> Result<Vec<VaultSummary>, String> {
-    let base_dir = resolve_vault_directory()?;
-    if !base_dir.exists() {
-        return Ok(Vec::new());
+        return Err(PekaError::InvalidInput("Username or email is required.".to_string()));
     }
 
-    let mut summaries = Vec::new();
-    for entry in fs::read_dir(base_dir).map_err(|e| e.to_string())? {
-        let entry = entry.map_err(|e| e.to_string())?;
-        let path = entry.path();
-        if !path.is_file() {
-            continue;
-        }
-        if path.extension().and_then(|ext| ext.to_str()) != Some("peka") {
-            continue;
-        }
-
-        match fs::read_to_string(&path) {
-            Ok(raw) => match serde_json::from_str::<VaultFile>(&raw) {
-                Ok(vault_file) => summaries.push(VaultSummary {
-                    path: path.to_string_lossy().to_string(),
-                    vault_name: vault_file.vault_name,
-                }),
-                Err(_) => {
-                    // Skip corrupted entries silently
-                    continue;
-                }
-            },
-            Err(_) => continue,
-        }
-    }
+    let mut vault = Vault::open(path, master_password)?;
+    vault.add_credential(
+        folder_id,
+        NewCredential {
+            title: identifier,
+            username,
+            password,
+            ..NewCredential::default()
+        },
+    )?;
+    vault.save()?;
 
-    Ok(summaries)
+    Ok(vault.contents())
 }
 
 #[tauri::command]
@@ -713,12 +495,12 @@ async fn verify_folder_pin(
     masterPassword: String,
     folderId: String,
     pin: String,
-) -> Result<bool, String> {
+) -> Result<bool, PekaError> {
     tauri::async_runtime::spawn_blocking(move || {
         verify_folder_pin_inner(&vaultPath, &masterPassword, &folderId, &pin)
     })
     .await
-    .map_err(|e| e.to_string())?
+    .map_err(PekaError::internal)?
 }
 
 fn verify_folder_pin_inner(
@@ -726,119 +508,1102 @@ fn verify_folder_pin_inner(
     master_password: &str,
     folder_id: &str,
     pin: &str,
-) -> Result<bool, String> {
-    let (_vault_file, payload) = decrypt_vault(vault_path, master_password)?;
-
-    let folder = payload
-        .folders
-        .iter()
-        .find(|f| f.id == folder_id)
-        .ok_or_else(|| "Folder not found".to_string())?;
-
-    if !folder.secure {
-        return Ok(true); // Non-secure folders don't need PIN
-    }
+) -> Result<bool, PekaError> {
+    let mut vault = Vault::open(vault_path, master_password)?;
+    let verified = vault.verify_folder_pin(folder_id, pin)?;
+    vault.save()?;
 
-    let pin_hash = folder
-        .pin_hash
-        .as_ref()
-        .ok_or_else(|| "Folder PIN hash not found".to_string())?;
+    Ok(verified)
+}
 
-    let parsed_hash = PasswordHash::new(pin_hash).map_err(|e| e.to_string())?;
-    let argon2 = Argon2::default();
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn vault_health_report(
+    path: String,
+    masterPassword: String,
+    maxAgeDays: Option<u32>,
+) -> Result<VaultHealthReport, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        vault_health_report_inner(&path, &masterPassword, maxAgeDays)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
 
-    match argon2.verify_password(pin.as_bytes(), &parsed_hash) {
-        Ok(_) => Ok(true),
-        Err(_) => Ok(false),
-    }
+fn vault_health_report_inner(
+    path: &str,
+    master_password: &str,
+    max_age_days: Option<u32>,
+) -> Result<VaultHealthReport, PekaError> {
+    let vault = Vault::open(path, master_password)?;
+    vault.health_report(max_age_days.unwrap_or(health::DEFAULT_MAX_PASSWORD_AGE_DAYS))
 }
 
 #[tauri::command]
 #[allow(non_snake_case)]
-async fn import_vault(
-    sourcePath: String,
-    vaultName: String,
+async fn check_breached_passwords(
+    path: String,
     masterPassword: String,
-) -> Result<ImportVaultResult, String> {
+    corpusPath: String,
+) -> Result<BreachReport, PekaError> {
     tauri::async_runtime::spawn_blocking(move || {
-        import_vault_inner(&sourcePath, &vaultName, &masterPassword)
+        check_breached_passwords_inner(&path, &masterPassword, &corpusPath)
     })
     .await
-    .map_err(|e| e.to_string())?
+    .map_err(PekaError::internal)?
 }
 
-fn import_vault_inner(
-    source_path: &str,
-    vault_name: &str,
+fn check_breached_passwords_inner(
+    path: &str,
     master_password: &str,
-) -> Result<ImportVaultResult, String> {
-    let trimmed_vault_name = vault_name.trim();
-    if trimmed_vault_name.is_empty() {
-        return Err("Vault name cannot be empty".to_string());
-    }
-
-    if master_password.trim().is_empty() {
-        return Err("Master password cannot be empty".to_string());
-    }
+    corpus_path: &str,
+) -> Result<BreachReport, PekaError> {
+    let corpus = BreachCorpus::open(corpus_path)?;
+    let vault = Vault::open(path, master_password)?;
+    vault.check_breaches(&corpus)
+}
 
-    let source = PathBuf::from(source_path);
-    if !source.exists() {
-        return Err("Source vault file not found".to_string());
-    }
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn import_csv(
+    path: String,
+    masterPassword: String,
+    csvPath: String,
+    source: CsvSource,
+    mapping: Option<CsvColumnMapping>,
+    dryRun: bool,
+) -> Result<CsvImportResult, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        import_csv_inner(&path, &masterPassword, &csvPath, source, mapping, dryRun)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
 
-    if source
-        .extension()
-        .and_then(|ext| ext.to_str())
-        != Some("peka")
-    {
-        return Err("Source file must be a .peka vault file".to_string());
+fn import_csv_inner(
+    path: &str,
+    master_password: &str,
+    csv_path: &str,
+    source: CsvSource,
+    mapping: Option<CsvColumnMapping>,
+    dry_run: bool,
+) -> Result<CsvImportResult, PekaError> {
+    if csv_path.trim().is_empty() {
+        return Err(PekaError::InvalidInput("CSV file path is required".to_string()));
     }
 
-    // Validate the master password by trying to decrypt the vault
-    let (_vault_file, _payload) = decrypt_vault(source_path, master_password)
-        .map_err(|e| format!("Failed to decrypt vault: {}", e))?;
-
-    // If decryption succeeded, copy the file to the app data directory
-    let base_dir = resolve_vault_directory()?;
-    fs::create_dir_all(&base_dir).map_err(|e| e.to_string())?;
-
-    let file_name = format!("{}.peka", sanitize_file_name(trimmed_vault_name));
-    let destination_path = base_dir.join(&file_name);
-
-    // Check if a vault with this name already exists
-    if destination_path.exists() {
-        return Err(format!(
-            "A vault with the name '{}' already exists",
-            trimmed_vault_name
-        ));
-    }
+    let raw = fs::read(csv_path).map_err(|e| PekaError::io("Unable to read CSV file from disk", e))?;
 
-    fs::copy(source_path, &destination_path).map_err(|e| e.to_string())?;
+    let mut vault = Vault::open(path, master_password)?;
+    let result = vault.import_csv(&raw, source, mapping, dry_run)?;
+    vault.save()?;
 
-    Ok(ImportVaultResult {
-        path: destination_path.to_string_lossy().to_string(),
-    })
+    Ok(result)
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn import_kdbx(
+    path: String,
+    masterPassword: String,
+    kdbxPath: String,
+    kdbxPassword: String,
+    allowExpensiveKdf: Option<bool>,
+) -> Result<KdbxImportResult, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        import_kdbx_inner(
+            &path,
+            &masterPassword,
+            &kdbxPath,
+            &kdbxPassword,
+            allowExpensiveKdf.unwrap_or(false),
+        )
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn import_kdbx_inner(
+    path: &str,
+    master_password: &str,
+    kdbx_path: &str,
+    kdbx_password: &str,
+    allow_expensive_kdf: bool,
+) -> Result<KdbxImportResult, PekaError> {
+    if kdbx_path.trim().is_empty() {
+        return Err(PekaError::InvalidInput("KeePass database path is required".to_string()));
+    }
+
+    let raw = fs::read(kdbx_path)
+        .map_err(|e| PekaError::io("Unable to read KeePass database from disk", e))?;
+
+    let mut vault = Vault::open(path, master_password)?;
+    let result = vault.import_kdbx(&raw, kdbx_password, allow_expensive_kdf)?;
+    vault.save()?;
+
+    Ok(result)
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn export_kdbx(
+    path: String,
+    masterPassword: String,
+    destinationPath: String,
+    kdbxPassword: String,
+    cipher: Option<KdbxCipher>,
+) -> Result<(), PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        export_kdbx_inner(
+            &path,
+            &masterPassword,
+            &destinationPath,
+            &kdbxPassword,
+            cipher.unwrap_or_default(),
+        )
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn export_kdbx_inner(
+    path: &str,
+    master_password: &str,
+    destination_path: &str,
+    kdbx_password: &str,
+    cipher: KdbxCipher,
+) -> Result<(), PekaError> {
+    if destination_path.trim().is_empty() {
+        return Err(PekaError::InvalidInput("Destination path is required".to_string()));
+    }
+
+    let mut vault = Vault::open(path, master_password)?;
+    vault.export_kdbx(Path::new(destination_path), kdbx_password, cipher)?;
+    vault.save()
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn import_vault(
+    sourcePath: String,
+    vaultName: String,
+    masterPassword: String,
+    locationId: Option<String>,
+    allowExpensiveKdf: Option<bool>,
+) -> Result<ImportVaultResult, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        import_vault_inner(
+            &sourcePath,
+            &vaultName,
+            &masterPassword,
+            locationId.as_deref(),
+            allowExpensiveKdf.unwrap_or(false),
+        )
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn import_vault_inner(
+    source_path: &str,
+    vault_name: &str,
+    master_password: &str,
+    location_id: Option<&str>,
+    allow_expensive_kdf: bool,
+) -> Result<ImportVaultResult, PekaError> {
+    let vault = LocationRegistry::load_default()?.store(location_id)?.import(
+        Path::new(source_path),
+        vault_name,
+        master_password,
+        allow_expensive_kdf,
+    )?;
+
+    Ok(ImportVaultResult {
+        path: vault.path().to_string_lossy().to_string(),
+    })
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn merge_vault(
+    path: String,
+    masterPassword: String,
+    sourcePath: String,
+    sourcePassword: String,
+    strategy: MergeStrategy,
+    resolutions: Option<HashMap<String, ConflictResolution>>,
+    allowExpensiveKdf: Option<bool>,
+) -> Result<MergeResult, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        merge_vault_inner(
+            &path,
+            &masterPassword,
+            &sourcePath,
+            &sourcePassword,
+            strategy,
+            resolutions.unwrap_or_default(),
+            allowExpensiveKdf.unwrap_or(false),
+        )
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn merge_vault_inner(
+    path: &str,
+    master_password: &str,
+    source_path: &str,
+    source_password: &str,
+    strategy: MergeStrategy,
+    resolutions: HashMap<String, ConflictResolution>,
+    allow_expensive_kdf: bool,
+) -> Result<MergeResult, PekaError> {
+    let source = PathBuf::from(source_path);
+    if !source.exists() {
+        return Err(PekaError::NotFound("Source vault file not found".to_string()));
+    }
+
+    if source
+        .extension()
+        .and_then(|ext| ext.to_str())
+        != Some("peka")
+    {
+        return Err(PekaError::InvalidInput("Source file must be a .peka vault file".to_string()));
+    }
+
+    let source_vault = Vault::open_external(source, source_password, allow_expensive_kdf)?;
+    let mut vault = Vault::open(path, master_password)?;
+
+    let result = vault.merge_from(&source_vault, strategy, &resolutions)?;
+    vault.save()?;
+
+    Ok(result)
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn add_attachment(
+    path: String,
+    masterPassword: String,
+    folderId: String,
+    credentialId: String,
+    filePath: String,
+) -> Result<VaultContents, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        add_attachment_inner(&path, &masterPassword, &folderId, &credentialId, &filePath)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn add_attachment_inner(
+    path: &str,
+    master_password: &str,
+    folder_id: &str,
+    credential_id: &str,
+    file_path: &str,
+) -> Result<VaultContents, PekaError> {
+    if file_path.trim().is_empty() {
+        return Err(PekaError::InvalidInput("Attachment file path is required".to_string()));
+    }
+
+    let mut vault = Vault::open(path, master_password)?;
+    vault.add_attachment(folder_id, credential_id, Path::new(file_path))?;
+    vault.save()?;
+
+    Ok(vault.contents())
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn list_attachments(
+    path: String,
+    masterPassword: String,
+    folderId: String,
+    credentialId: String,
+) -> Result<Vec<AttachmentSummary>, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        list_attachments_inner(&path, &masterPassword, &folderId, &credentialId)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn list_attachments_inner(
+    path: &str,
+    master_password: &str,
+    folder_id: &str,
+    credential_id: &str,
+) -> Result<Vec<AttachmentSummary>, PekaError> {
+    let vault = Vault::open(path, master_password)?;
+    Ok(vault
+        .credential(folder_id, credential_id)?
+        .attachments
+        .iter()
+        .map(StoredAttachment::summary)
+        .collect())
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn extract_attachment(
+    path: String,
+    masterPassword: String,
+    folderId: String,
+    credentialId: String,
+    attachmentId: String,
+    destinationPath: String,
+) -> Result<(), PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        extract_attachment_inner(
+            &path,
+            &masterPassword,
+            &folderId,
+            &credentialId,
+            &attachmentId,
+            &destinationPath,
+        )
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn extract_attachment_inner(
+    path: &str,
+    master_password: &str,
+    folder_id: &str,
+    credential_id: &str,
+    attachment_id: &str,
+    destination_path: &str,
+) -> Result<(), PekaError> {
+    if destination_path.trim().is_empty() {
+        return Err(PekaError::InvalidInput("Destination path is required".to_string()));
+    }
+
+    let mut vault = Vault::open(path, master_password)?;
+    let contents = vault.read_attachment(folder_id, credential_id, attachment_id)?;
+
+    let destination = PathBuf::from(destination_path);
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| PekaError::io("Unable to create destination directory", e))?;
+    }
+
+    fs::write(destination, contents)
+        .map_err(|e| PekaError::io("Unable to write attachment", e))?;
+    vault.save()
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn delete_attachment(
+    path: String,
+    masterPassword: String,
+    folderId: String,
+    credentialId: String,
+    attachmentId: String,
+) -> Result<VaultContents, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        delete_attachment_inner(&path, &masterPassword, &folderId, &credentialId, &attachmentId)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn delete_attachment_inner(
+    path: &str,
+    master_password: &str,
+    folder_id: &str,
+    credential_id: &str,
+    attachment_id: &str,
+) -> Result<VaultContents, PekaError> {
+    let mut vault = Vault::open(path, master_password)?;
+    vault.delete_attachment(folder_id, credential_id, attachment_id)?;
+    vault.save()?;
+
+    Ok(vault.contents())
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn search_vault(
+    path: String,
+    masterPassword: String,
+    query: String,
+    filters: Option<SearchFilters>,
+    sort: Option<SearchSort>,
+    limit: Option<usize>,
+) -> Result<SearchResults, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        search_vault_inner(
+            &path,
+            &masterPassword,
+            &query,
+            filters.unwrap_or_default(),
+            sort.unwrap_or_default(),
+            limit,
+        )
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn search_vault_inner(
+    path: &str,
+    master_password: &str,
+    query: &str,
+    filters: SearchFilters,
+    sort: SearchSort,
+    limit: Option<usize>,
+) -> Result<SearchResults, PekaError> {
+    let vault = Vault::open(path, master_password)?;
+    vault.search(query, &filters, sort, limit)
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn list_tags(path: String, masterPassword: String) -> Result<Vec<TagSummary>, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || list_tags_inner(&path, &masterPassword))
+        .await
+        .map_err(PekaError::internal)?
+}
+
+fn list_tags_inner(path: &str, master_password: &str) -> Result<Vec<TagSummary>, PekaError> {
+    let vault = Vault::open(path, master_password)?;
+    Ok(vault.tags())
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn add_tag(
+    path: String,
+    masterPassword: String,
+    folderId: String,
+    credentialId: String,
+    tag: String,
+) -> Result<VaultContents, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        add_tag_inner(&path, &masterPassword, &folderId, &credentialId, &tag)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn add_tag_inner(
+    path: &str,
+    master_password: &str,
+    folder_id: &str,
+    credential_id: &str,
+    tag: &str,
+) -> Result<VaultContents, PekaError> {
+    let mut vault = Vault::open(path, master_password)?;
+    vault.add_tag(folder_id, credential_id, tag)?;
+    vault.save()?;
+
+    Ok(vault.contents())
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn remove_tag(
+    path: String,
+    masterPassword: String,
+    tag: String,
+    folderId: Option<String>,
+    credentialId: Option<String>,
+) -> Result<VaultContents, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        remove_tag_inner(
+            &path,
+            &masterPassword,
+            &tag,
+            folderId.as_deref(),
+            credentialId.as_deref(),
+        )
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+/// Removes `tag` from one credential, or from every credential in the vault when no
+/// credential is given.
+fn remove_tag_inner(
+    path: &str,
+    master_password: &str,
+    tag: &str,
+    folder_id: Option<&str>,
+    credential_id: Option<&str>,
+) -> Result<VaultContents, PekaError> {
+    let credential = match (folder_id, credential_id) {
+        (Some(folder_id), Some(credential_id)) => Some((folder_id, credential_id)),
+        (None, None) => None,
+        _ => return Err(PekaError::InvalidInput("Folder and credential must be given together".to_string())),
+    };
+
+    let mut vault = Vault::open(path, master_password)?;
+    vault.remove_tag(tag, credential)?;
+    vault.save()?;
+
+    Ok(vault.contents())
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn rename_tag(
+    path: String,
+    masterPassword: String,
+    from: String,
+    to: String,
+) -> Result<VaultContents, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        rename_tag_inner(&path, &masterPassword, &from, &to)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn rename_tag_inner(
+    path: &str,
+    master_password: &str,
+    from: &str,
+    to: &str,
+) -> Result<VaultContents, PekaError> {
+    let mut vault = Vault::open(path, master_password)?;
+    vault.rename_tag(from, to)?;
+    vault.save()?;
+
+    Ok(vault.contents())
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn set_favorite(
+    path: String,
+    masterPassword: String,
+    folderId: String,
+    credentialId: String,
+    favorite: bool,
+) -> Result<VaultContents, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        set_favorite_inner(&path, &masterPassword, &folderId, &credentialId, favorite)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn set_favorite_inner(
+    path: &str,
+    master_password: &str,
+    folder_id: &str,
+    credential_id: &str,
+    favorite: bool,
+) -> Result<VaultContents, PekaError> {
+    let mut vault = Vault::open(path, master_password)?;
+    vault.set_favorite(folder_id, credential_id, favorite)?;
+    vault.save()?;
+
+    Ok(vault.contents())
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn list_trash(path: String, masterPassword: String) -> Result<TrashContents, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || list_trash_inner(&path, &masterPassword))
+        .await
+        .map_err(PekaError::internal)?
+}
+
+fn list_trash_inner(path: &str, master_password: &str) -> Result<TrashContents, PekaError> {
+    let vault = Vault::open(path, master_password)?;
+    // Expired items are only dropped from the listing here; the next write purges them.
+    Ok(vault.trash())
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn restore_from_trash(
+    path: String,
+    masterPassword: String,
+    trashId: String,
+) -> Result<VaultContents, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        restore_from_trash_inner(&path, &masterPassword, &trashId)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn restore_from_trash_inner(
+    path: &str,
+    master_password: &str,
+    trash_id: &str,
+) -> Result<VaultContents, PekaError> {
+    let mut vault = Vault::open(path, master_password)?;
+    vault.restore_from_trash(trash_id)?;
+    vault.save()?;
+
+    Ok(vault.contents())
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn empty_trash(
+    path: String,
+    masterPassword: String,
+    trashIds: Option<Vec<String>>,
+) -> Result<TrashContents, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        empty_trash_inner(&path, &masterPassword, trashIds.as_deref())
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn empty_trash_inner(
+    path: &str,
+    master_password: &str,
+    trash_ids: Option<&[String]>,
+) -> Result<TrashContents, PekaError> {
+    let mut vault = Vault::open(path, master_password)?;
+    vault.empty_trash(trash_ids)?;
+    vault.save()?;
+
+    Ok(vault.trash())
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn set_trash_retention(
+    path: String,
+    masterPassword: String,
+    retentionDays: u32,
+) -> Result<TrashContents, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        set_trash_retention_inner(&path, &masterPassword, retentionDays)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn set_trash_retention_inner(
+    path: &str,
+    master_password: &str,
+    retention_days: u32,
+) -> Result<TrashContents, PekaError> {
+    let mut vault = Vault::open(path, master_password)?;
+    vault.set_trash_retention(retention_days)?;
+    vault.save()?;
+
+    Ok(vault.trash())
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn set_storage_format(
+    path: String,
+    masterPassword: String,
+    format: StorageFormat,
+) -> Result<VaultSummary, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        set_storage_format_inner(&path, &masterPassword, format)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn set_storage_format_inner(
+    path: &str,
+    master_password: &str,
+    format: StorageFormat,
+) -> Result<VaultSummary, PekaError> {
+    let mut vault = Vault::open(path, master_password)?;
+    vault.set_storage_format(format);
+    vault.save()?;
+
+    Ok(read_summary(vault.path()))
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn get_audit_log(path: String, masterPassword: String) -> Result<AuditLog, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || get_audit_log_inner(&path, &masterPassword))
+        .await
+        .map_err(PekaError::internal)?
+}
+
+fn get_audit_log_inner(path: &str, master_password: &str) -> Result<AuditLog, PekaError> {
+    let vault = Vault::open(path, master_password)?;
+    Ok(vault.audit_log())
+}
+
+#[tauri::command]
+async fn get_sync_status(path: String) -> Result<Option<SyncStatus>, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || sync::status(&registered_vault_path(&path)?))
+        .await
+        .map_err(PekaError::internal)?
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn link_sync_directory(
+    path: String,
+    masterPassword: String,
+    directory: String,
+) -> Result<SyncStatus, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        link_sync_directory_inner(&path, &masterPassword, &directory)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn link_sync_directory_inner(
+    path: &str,
+    master_password: &str,
+    directory: &str,
+) -> Result<SyncStatus, PekaError> {
+    if directory.trim().is_empty() {
+        return Err(PekaError::InvalidInput("Sync directory is required".to_string()));
+    }
+
+    let path = registered_vault_path(path)?;
+    Vault::open(&path, master_password)?;
+    sync::link(&path, Path::new(directory))
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn unlink_sync_directory(path: String, masterPassword: String) -> Result<(), PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        unlink_sync_directory_inner(&path, &masterPassword)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn unlink_sync_directory_inner(path: &str, master_password: &str) -> Result<(), PekaError> {
+    let path = registered_vault_path(path)?;
+    Vault::open(&path, master_password)?;
+    sync::unlink(&path)
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn sync_vault(
+    path: String,
+    masterPassword: String,
+    resolutions: Option<HashMap<String, SyncResolution>>,
+) -> Result<SyncReport, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        sync_vault_inner(&path, &masterPassword, resolutions.unwrap_or_default())
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn sync_vault_inner(
+    path: &str,
+    master_password: &str,
+    resolutions: HashMap<String, SyncResolution>,
+) -> Result<SyncReport, PekaError> {
+    let mut vault = Vault::open(registered_vault_path(path)?, master_password)?;
+    vault.sync(&resolutions)
+}
+
+/// Canonical path of a vault the registry knows about, for commands that work on the
+/// files next to it rather than through the vault itself.
+fn registered_vault_path(path: &str) -> Result<PathBuf, PekaError> {
+    if path.trim().is_empty() {
+        return Err(PekaError::InvalidInput("Vault path is required".to_string()));
+    }
+
+    LocationRegistry::load_default()?.resolve(Path::new(path), "Unable to resolve vault path")
+}
+
+#[tauri::command]
+async fn get_history_status(path: String) -> Result<Option<HistoryStatus>, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || history::status(&registered_vault_path(&path)?))
+        .await
+        .map_err(PekaError::internal)?
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn enable_vault_history(
+    path: String,
+    masterPassword: String,
+) -> Result<HistoryStatus, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        enable_vault_history_inner(&path, &masterPassword)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn enable_vault_history_inner(
+    path: &str,
+    master_password: &str,
+) -> Result<HistoryStatus, PekaError> {
+    let path = registered_vault_path(path)?;
+    // Every revision from now on is kept in a repository next to the vault.
+    let _vault = Vault::open(&path, master_password)?;
+    history::enable(&path)
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn disable_vault_history(path: String, masterPassword: String) -> Result<(), PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        disable_vault_history_inner(&path, &masterPassword)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn disable_vault_history_inner(path: &str, master_password: &str) -> Result<(), PekaError> {
+    let path = registered_vault_path(path)?;
+    // Deleting the history is as destructive as deleting the vault's data.
+    let _vault = Vault::open(&path, master_password)?;
+    history::disable(&path)
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn set_history_remote(
+    path: String,
+    masterPassword: String,
+    remote: Option<String>,
+) -> Result<HistoryStatus, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        set_history_remote_inner(&path, &masterPassword, remote.as_deref())
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn set_history_remote_inner(
+    path: &str,
+    master_password: &str,
+    remote: Option<&str>,
+) -> Result<HistoryStatus, PekaError> {
+    let path = registered_vault_path(path)?;
+    // The remote decides where every future push of the vault file goes.
+    let _vault = Vault::open(&path, master_password)?;
+    history::set_remote(&path, remote)
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn push_vault_history(path: String, masterPassword: String) -> Result<(), PekaError> {
+    tauri::async_runtime::spawn_blocking(move || push_vault_history_inner(&path, &masterPassword))
+        .await
+        .map_err(PekaError::internal)?
+}
+
+fn push_vault_history_inner(path: &str, master_password: &str) -> Result<(), PekaError> {
+    let path = registered_vault_path(path)?;
+    // Pushing sends every revision of the vault file off this device.
+    let _vault = Vault::open(&path, master_password)?;
+    history::push(&path)
+}
+
+#[tauri::command]
+async fn list_vault_history(path: String) -> Result<Vec<HistoryRevision>, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || history::list(&registered_vault_path(&path)?))
+        .await
+        .map_err(PekaError::internal)?
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn diff_vault_history(
+    path: String,
+    masterPassword: String,
+    from: String,
+    to: Option<String>,
+) -> Result<HistoryDiff, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        diff_vault_history_inner(&path, &masterPassword, &from, to.as_deref())
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn diff_vault_history_inner(
+    path: &str,
+    master_password: &str,
+    from: &str,
+    to: Option<&str>,
+) -> Result<HistoryDiff, PekaError> {
+    let vault = Vault::open(registered_vault_path(path)?, master_password)?;
+    vault.history_diff(from, to)
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn roll_back_vault(
+    path: String,
+    masterPassword: String,
+    revision: String,
+) -> Result<HistoryRollback, PekaError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        roll_back_vault_inner(&path, &masterPassword, &revision)
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn roll_back_vault_inner(
+    path: &str,
+    master_password: &str,
+    revision: &str,
+) -> Result<HistoryRollback, PekaError> {
+    let mut vault = Vault::open(registered_vault_path(path)?, master_password)?;
+    let result = vault.roll_back(revision)?;
+    vault.save()?;
+
+    Ok(result)
+}
+
+#[tauri::command]
+#[allow(non_snake_case)]
+async fn copy_secret_to_clipboard(
+    clipboard: tauri::State<'_, SecretClipboard<SystemClipboard>>,
+    path: String,
+    masterPassword: String,
+    folderId: String,
+    credentialId: String,
+    field: String,
+    clearAfterSeconds: Option<u32>,
+) -> Result<(), PekaError> {
+    let clipboard = clipboard.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        copy_secret_to_clipboard_inner(
+            &clipboard,
+            &path,
+            &masterPassword,
+            &folderId,
+            &credentialId,
+            &field,
+            clearAfterSeconds,
+        )
+    })
+    .await
+    .map_err(PekaError::internal)?
+}
+
+fn copy_secret_to_clipboard_inner(
+    clipboard: &SecretClipboard<SystemClipboard>,
+    path: &str,
+    master_password: &str,
+    folder_id: &str,
+    credential_id: &str,
+    field: &str,
+    clear_after_seconds: Option<u32>,
+) -> Result<(), PekaError> {
+    let clear_after_seconds = clear_after_seconds.unwrap_or(DEFAULT_CLEAR_AFTER_SECONDS);
+    if clear_after_seconds > MAX_CLEAR_AFTER_SECONDS {
+        return Err(PekaError::InvalidInput(format!(
+            "Clipboard timeout cannot be longer than {} seconds",
+            MAX_CLEAR_AFTER_SECONDS
+        )));
+    }
+
+    let vault = Vault::open(path, master_password)?;
+    let secret = vault.secret_value(folder_id, credential_id, field)?;
+    // 0 leaves the value on the clipboard.
+    let clear_after =
+        (clear_after_seconds > 0).then(|| Duration::from_secs(u64::from(clear_after_seconds)));
+    clipboard.copy(&secret, clear_after)?;
+    Ok(())
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .manage(SecretClipboard::new(SystemClipboard::default()))
+        .manage(LaunchVault::from_args(std::env::args_os()))
         .invoke_handler(tauri::generate_handler![
             greet,
             create_vault,
             open_vault,
+            describe_vault_file,
+            check_vault,
+            repair_vault,
+            list_recent_vaults,
+            forget_recent_vault,
+            take_launch_vault,
             create_folder,
             list_vaults,
             verify_folder_pin,
             delete_vault,
             export_vault_file,
+            list_vault_locations,
+            add_vault_location,
+            remove_vault_location,
+            export_vault,
             delete_folder,
             add_credential,
             delete_credential,
-            import_vault
+            import_vault,
+            estimate_password_strength,
+            vault_health_report,
+            check_breached_passwords,
+            import_csv,
+            import_kdbx,
+            export_kdbx,
+            merge_vault,
+            add_attachment,
+            list_attachments,
+            extract_attachment,
+            delete_attachment,
+            search_vault,
+            list_tags,
+            add_tag,
+            remove_tag,
+            rename_tag,
+            set_favorite,
+            list_trash,
+            restore_from_trash,
+            empty_trash,
+            set_trash_retention,
+            set_storage_format,
+            get_audit_log,
+            get_sync_status,
+            link_sync_directory,
+            unlink_sync_directory,
+            sync_vault,
+            get_history_status,
+            enable_vault_history,
+            disable_vault_history,
+            set_history_remote,
+            push_vault_history,
+            list_vault_history,
+            diff_vault_history,
+            roll_back_vault,
+            copy_secret_to_clipboard
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, _event| {
+            // macOS hands files opened through the file association to the running app
+            // instead of passing them as arguments.
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            if let tauri::RunEvent::Opened { urls } = _event {
+                use tauri::{Emitter, Manager};
+
+                let path = urls
+                    .iter()
+                    .filter_map(|url| url.to_file_path().ok())
+                    .find(|path| launch::is_vault_file(path));
+                if let Some(path) = path {
+                    let launch = _app.state::<LaunchVault>();
+                    launch.set(path.to_string_lossy().to_string());
+                    // The frontend picks the file up with take_launch_vault.
+                    let _ = _app.emit("vault-file-opened", ());
+                }
+            }
+        });
 }
@@ -1,12 +1,20 @@
+import { listen } from "@tauri-apps/api/event";
 import { AnimatePresence, motion } from "framer-motion";
-import { useEffect, useState } from "react";
+import { useCallback, useEffect, useRef, useState } from "react";
 import "./App.css";
 import ImportVaultScreen from "./screens/ImportVaultScreen";
 import MasterPasswordSetupScreen from "./screens/MasterPasswordSetupScreen";
 import VaultScreen from "./screens/VaultScreen";
 import WelcomeBackScreen from "./screens/WelcomeBackScreen";
 import WelcomeScreen from "./screens/WelcomeScreen";
-import { listVaults } from "./services/vaultService";
+import {
+  describeVaultFile,
+  forgetRecentVault,
+  listRecentVaults,
+  listVaults,
+  pickVaultFile,
+  takeLaunchVault,
+} from "./services/vaultService";
 import {
   Screen,
   VaultContext,
@@ -14,71 +22,160 @@ import {
   VaultFolder,
   VaultSummary,
 } from "./types";
+import { errorMessage } from "./utils/errors";
 
 function App() {
   const [screen, setScreen] = useState<Screen | null>(null);
   const [existingVaults, setExistingVaults] = useState<VaultSummary[]>([]);
+  // Vault files outside every location: recently opened ones and ones picked or
+  // passed in to be opened in place.
+  const [externalVaults, setExternalVaults] = useState<VaultSummary[]>([]);
+  const [selectedVaultPath, setSelectedVaultPath] = useState<string | null>(
+    null
+  );
   const [activeVault, setActiveVault] = useState<VaultContext | null>(null);
   const [activeFolder, setActiveFolder] = useState<VaultFolder | null>(null);
   const [initError, setInitError] = useState<string | null>(null);
+  const activeVaultRef = useRef<VaultContext | null>(null);
+  activeVaultRef.current = activeVault;
+
+  const unlockableVaults = [
+    ...existingVaults,
+    ...externalVaults.filter(
+      (external) =>
+        !existingVaults.some((vault) => vault.path === external.path)
+    ),
+  ];
+
+  const openVaultInPlace = useCallback(async (path: string) => {
+    try {
+      const summary = await describeVaultFile(path);
+      setExternalVaults((prev) => [
+        summary,
+        ...prev.filter((vault) => vault.path !== summary.path),
+      ]);
+      setSelectedVaultPath(summary.path);
+      setInitError(null);
+      setActiveFolder(null);
+      setScreen("welcomeBack");
+    } catch (err) {
+      console.error(err);
+      setInitError(errorMessage(err, "Unable to open the vault file."));
+    }
+  }, []);
+
+  // A vault file passed on the command line or through a file association waits in
+  // the backend until no vault is unlocked.
+  const checkLaunchVault = useCallback(async () => {
+    if (activeVaultRef.current) return;
+    const path = await takeLaunchVault();
+    if (path) {
+      await openVaultInPlace(path);
+    }
+  }, [openVaultInPlace]);
 
   useEffect(() => {
     let mounted = true;
     (async () => {
       try {
-        const vaults = await listVaults();
+        const [vaults, recent] = await Promise.all([
+          listVaults(),
+          listRecentVaults(),
+        ]);
+        const recentSummaries = await Promise.all(
+          recent.map(({ path }) => describeVaultFile(path))
+        );
         if (!mounted) return;
         setExistingVaults(vaults);
-        setScreen(vaults.length > 0 ? "welcomeBack" : "welcome");
+        setExternalVaults(recentSummaries);
+        setScreen(
+          vaults.length > 0 || recent.length > 0 ? "welcomeBack" : "welcome"
+        );
       } catch (err) {
         console.error(err);
         if (!mounted) return;
         setInitError("Unable to check existing vaults.");
         setScreen("welcome");
       }
+      if (mounted) {
+        await checkLaunchVault();
+      }
     })();
 
+    const unlisten = listen("vault-file-opened", () => {
+      checkLaunchVault().catch(console.error);
+    });
+
     return () => {
       mounted = false;
+      unlisten.then((stop) => stop());
     };
-  }, []);
+  }, [checkLaunchVault]);
 
   const goToLanding = () => {
     setActiveFolder(null);
-    setScreen(existingVaults.length > 0 ? "welcomeBack" : "welcome");
+    setScreen(unlockableVaults.length > 0 ? "welcomeBack" : "welcome");
   };
 
-  const handleVaultCreated = (vault: VaultContext) => {
-    setExistingVaults((prev) => {
-      if (prev.some((entry) => entry.path === vault.path)) {
-        return prev;
+  const handleOpenVaultFile = async () => {
+    try {
+      const path = await pickVaultFile();
+      if (path) {
+        await openVaultInPlace(path);
       }
-      return [
-        ...prev,
-        {
-          path: vault.path,
-          vaultName: vault.data.vaultName,
-        },
-      ];
-    });
+    } catch (err) {
+      console.error(err);
+      setInitError(errorMessage(err, "Unable to open the vault file."));
+    }
+  };
+
+  const handleForgetVault = async (path: string) => {
+    try {
+      await forgetRecentVault(path);
+      const remaining = externalVaults.filter((vault) => vault.path !== path);
+      setExternalVaults(remaining);
+      if (existingVaults.length === 0 && remaining.length === 0) {
+        setScreen("welcome");
+      }
+    } catch (err) {
+      console.error(err);
+      setInitError(errorMessage(err, "Unable to forget the vault file."));
+    }
+  };
+
+  const handleVaultRepaired = (path: string) => {
+    describeVaultFile(path)
+      .then((summary) => {
+        const update = (prev: VaultSummary[]) =>
+          prev.map((vault) => (vault.path === summary.path ? summary : vault));
+        setExistingVaults(update);
+        setExternalVaults(update);
+      })
+      .catch(console.error);
+  };
+
+  // The summary is read back so a new vault is listed like the others.
+  const addExistingVault = (path: string) => {
+    describeVaultFile(path)
+      .then((summary) =>
+        setExistingVaults((prev) =>
+          prev.some((entry) => entry.path === summary.path)
+            ? prev
+            : [...prev, summary]
+        )
+      )
+      .catch(console.error);
+  };
+
+  const handleVaultCreated = (vault: VaultContext) => {
+    addExistingVault(vault.path);
     setActiveVault(vault);
     setActiveFolder(null);
     setScreen("vault");
   };
 
   const handleVaultImported = (vault: VaultContext) => {
-    setExistingVaults((prev) => {
-      if (prev.some((entry) => entry.path === vault.path)) {
-        return prev;
-      }
-      return [
-        ...prev,
-        {
-          path: vault.path,
-          vaultName: vault.data.vaultName,
-        },
-      ];
-    });
+    addExistingVault(vault.path);
     setActiveVault(vault);
     setActiveFolder(null);
     setScreen("vault");
@@ -111,16 +208,20 @@ function App() {
   };
 
   const handleVaultDeleted = (path: string) => {
+    const remaining = unlockableVaults.filter((vault) => vault.path !== path);
     setExistingVaults((prev) => prev.filter((vault) => vault.path !== path));
+    setExternalVaults((prev) => prev.filter((vault) => vault.path !== path));
     setActiveVault(null);
     setActiveFolder(null);
-    setScreen("welcome");
+    setScreen(remaining.length > 0 ? "welcomeBack" : "welcome");
   };
 
   const handleVaultExit = () => {
+    activeVaultRef.current = null;
     setActiveVault(null);
     setActiveFolder(null);
-    setScreen(existingVaults.length > 0 ? "welcomeBack" : "welcome");
+    setScreen(unlockableVaults.length > 0 ? "welcomeBack" : "welcome");
+    checkLaunchVault().catch(console.error);
   };
 
   if (screen === null) {
@@ -151,6 +252,7 @@ function App() {
             <WelcomeScreen
               onGetStarted={() => setScreen("masterPasswordSetup")}
               onImportVault={() => setScreen("importVault")}
+              onOpenVaultFile={handleOpenVaultFile}
             />
             {initError && (
               <p className="submit-feedback error landing-error">{initError}</p>
@@ -167,9 +269,17 @@ function App() {
             transition={{ duration: 0.3 }}
           >
             <WelcomeBackScreen
-              vaults={existingVaults}
+              vaults={unlockableVaults}
+              selectedPath={selectedVaultPath}
+              onSelect={setSelectedVaultPath}
+              isExternal={(path) =>
+                !existingVaults.some((vault) => vault.path === path)
+              }
               onUnlock={handleVaultUnlocked}
               onVaultDeleted={handleVaultDeleted}
+              onOpenVaultFile={handleOpenVaultFile}
+              onForgetVault={handleForgetVault}
+              onVaultRepaired={handleVaultRepaired}
             />
             {initError && (
               <p className="submit-feedback error landing-error">{initError}</p>
@@ -1,11 +1,16 @@
 import { AnimatePresence, motion } from "framer-motion";
+import { useEffect, useState } from "react";
 import "../css/components/ViewCredentialModal.css";
+import { errorMessage } from "../utils/errors";
+
+type CopyField = "username" | "password";
 
 interface ViewCredentialModalProps {
   isOpen: boolean;
   identifier: string;
   username: string;
   password: string;
+  onCopy: (field: CopyField) => Promise<void>;
   onClose: () => void;
 }
 
@@ -14,8 +19,28 @@ export default function ViewCredentialModal({
   identifier,
   username,
   password,
+  onCopy,
   onClose,
 }: ViewCredentialModalProps) {
+  const [copyStatus, setCopyStatus] = useState<string | null>(null);
+
+  useEffect(() => {
+    setCopyStatus(null);
+  }, [isOpen, identifier]);
+
+  const handleCopy = async (field: CopyField) => {
+    try {
+      await onCopy(field);
+      setCopyStatus(
+        `${field === "password" ? "Password" : "Username"} copied. ` +
+          "The clipboard is cleared in 30 seconds."
+      );
+    } catch (err) {
+      console.error(err);
+      setCopyStatus(errorMessage(err, "Unable to copy to the clipboard."));
+    }
+  };
+
   return (
     <AnimatePresence>
       {isOpen && (
@@ -38,12 +63,25 @@ export default function ViewCredentialModal({
               <div className="detail-row">
                 <span className="label">Username / Email</span>
                 <span className="value">{username}</span>
+                <button
+                  className="action-button secondary copy-button"
+                  onClick={() => handleCopy("username")}
+                >
+                  Copy
+                </button>
               </div>
               <div className="detail-row">
                 <span className="label">Password</span>
                 <span className="value sensitive">{password}</span>
+                <button
+                  className="action-button secondary copy-button"
+                  onClick={() => handleCopy("password")}
+                >
+                  Copy
+                </button>
               </div>
             </div>
+            {copyStatus && <p className="copy-status">{copyStatus}</p>}
             <div className="modal-actions aligned-right">
               <button className="action-button" onClick={onClose}>
                 Close
@@ -4,6 +4,7 @@ import { useState } from "react";
 import "../css/screens/ImportVaultScreen.css";
 import { importVault, openVault } from "../services/vaultService";
 import { VaultContext } from "../types";
+import { errorMessage, withConfirmation } from "../utils/errors";
 
 interface ImportVaultScreenProps {
   onVaultImported: (vault: VaultContext) => void;
@@ -86,15 +87,23 @@ export default function ImportVaultScreen({
     setSubmitError(null);
 
     try {
-      const { path } = await importVault({
-        sourcePath: selectedFilePath,
-        vaultName: vaultName.trim(),
-        masterPassword,
+      // The imported copy keeps the key derivation settings of the source, so the
+      // go-ahead given for the import covers opening it as well.
+      let allowExpensiveKdf = false;
+      const { path } = await withConfirmation((confirmed) => {
+        allowExpensiveKdf = confirmed;
+        return importVault({
+          sourcePath: selectedFilePath,
+          vaultName: vaultName.trim(),
+          masterPassword,
+          allowExpensiveKdf: confirmed,
+        });
       });
 
       const vaultData = await openVault({
         path,
         masterPassword,
+        allowExpensiveKdf,
       });
 
       const vaultContext: VaultContext = {
@@ -110,10 +119,7 @@ export default function ImportVaultScreen({
       onVaultImported(vaultContext);
     } catch (error) {
       console.error(error);
-      const message =
-        error instanceof Error
-          ? error.message
-          : String(error ?? "Unknown error");
+      const message = errorMessage(error, "Unknown error");
       setSubmitError(message);
     } finally {
       setIsSubmitting(false);
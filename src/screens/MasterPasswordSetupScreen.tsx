@@ -1,8 +1,18 @@
 import { motion } from "framer-motion";
-import { useState } from "react";
+import { useEffect, useMemo, useState } from "react";
 import "../css/screens/MasterPasswordSetupScreen.css";
-import { createVault, openVault } from "../services/vaultService";
-import { ValidationResult, VaultContext, VaultData } from "../types";
+import {
+  createVault,
+  estimatePasswordStrength,
+  openVault,
+} from "../services/vaultService";
+import {
+  PasswordStrength,
+  ValidationResult,
+  VaultContext,
+  VaultData,
+} from "../types";
+import { errorMessage } from "../utils/errors";
 import { validatePassword } from "../utils/passwordValidation";
 
 interface MasterPasswordSetupScreenProps {
@@ -18,10 +28,10 @@ export default function MasterPasswordSetupScreen({
   const [vaultNameError, setVaultNameError] = useState("");
   const [masterPassword, setMasterPassword] = useState("");
   const [confirmPassword, setConfirmPassword] = useState("");
-  const [validation, setValidation] = useState<ValidationResult>({
-    isValid: false,
-    errors: [],
-  });
+  const [estimate, setEstimate] = useState<{
+    password: string;
+    strength: PasswordStrength;
+  } | null>(null);
   const [isSubmitting, setIsSubmitting] = useState(false);
   const [submitError, setSubmitError] = useState<string | null>(null);
   const [createdVault, setCreatedVault] = useState<VaultContext | null>(null);
@@ -36,21 +46,40 @@ export default function MasterPasswordSetupScreen({
     }
   };
 
+  // The backend rejects master passwords below the same score when creating the
+  // vault, so its estimate is the one shown here.
+  useEffect(() => {
+    if (!masterPassword) {
+      setEstimate(null);
+      return;
+    }
+    let cancelled = false;
+    estimatePasswordStrength(masterPassword, [vaultName])
+      .then((strength) => {
+        if (!cancelled) {
+          setEstimate({ password: masterPassword, strength });
+        }
+      })
+      .catch((error) => console.error(error));
+    return () => {
+      cancelled = true;
+    };
+  }, [masterPassword, vaultName]);
+
+  const validation = useMemo<ValidationResult>(() => {
+    if (!masterPassword && !confirmPassword) {
+      return { isValid: false, errors: [] };
+    }
+    const strength =
+      estimate?.password === masterPassword ? estimate.strength : null;
+    return validatePassword(masterPassword, confirmPassword, strength);
+  }, [masterPassword, confirmPassword, estimate]);
+
   const handlePasswordChange = (value: string, isConfirm: boolean) => {
     if (isConfirm) {
       setConfirmPassword(value);
-      if (masterPassword || value) {
-        setValidation(validatePassword(masterPassword, value));
-      } else {
-        setValidation({ isValid: false, errors: [] });
-      }
     } else {
       setMasterPassword(value);
-      if (confirmPassword || value) {
-        setValidation(validatePassword(value, confirmPassword));
-      } else {
-        setValidation({ isValid: false, errors: [] });
-      }
     }
   };
 
@@ -67,9 +96,7 @@ export default function MasterPasswordSetupScreen({
     }
     setVaultNameError("");
 
-    const result = validatePassword(masterPassword, confirmPassword);
-    setValidation(result);
-    if (result.isValid) {
+    if (validation.isValid) {
       setIsSubmitting(true);
       setSubmitError(null);
 
@@ -99,10 +126,7 @@ export default function MasterPasswordSetupScreen({
       } catch (error) {
         console.error(error);
         setCreatedVault(null);
-        const message =
-          error instanceof Error
-            ? error.message
-            : String(error ?? "Unknown error");
+        const message = errorMessage(error, "Unknown error");
         setSubmitError(message);
       } finally {
         setIsSubmitting(false);
@@ -10,6 +10,7 @@ import ViewCredentialModal from "../components/ViewCredentialModal";
 import "../css/screens/VaultScreen.css";
 import {
   addCredential,
+  copySecretToClipboard,
   createFolder,
   deleteCredential,
   deleteFolder,
@@ -17,6 +18,7 @@ import {
   verifyFolderPin,
 } from "../services/vaultService";
 import { VaultCredential, VaultData, VaultFolder } from "../types";
+import { errorMessage } from "../utils/errors";
 
 interface VaultScreenProps {
   vaultPath: string;
@@ -122,9 +124,7 @@ export default function VaultScreen({
       setCreateModalOpen(false);
     } catch (err) {
       const message =
-        err instanceof Error
-          ? err.message
-          : "Unable to create folder. Please try again.";
+        errorMessage(err, "Unable to create folder. Please try again.");
       setError(message);
     } finally {
       setIsSubmitting(false);
@@ -149,8 +149,7 @@ export default function VaultScreen({
       }
     } catch (err) {
       console.error(err);
-      const message =
-        err instanceof Error ? err.message : "Unable to delete folder.";
+      const message = errorMessage(err, "Unable to delete folder.");
       setDeleteError(message);
     } finally {
       setDeletingFolderId(null);
@@ -221,8 +220,7 @@ export default function VaultScreen({
       setIsCredentialModalOpen(false);
     } catch (err) {
       console.error(err);
-      const message =
-        err instanceof Error ? err.message : "Unable to save credential.";
+      const message = errorMessage(err, "Unable to save credential.");
       setCredentialError(message);
     } finally {
       setIsCredentialSubmitting(false);
@@ -247,8 +245,7 @@ export default function VaultScreen({
       }
     } catch (err) {
       console.error(err);
-      const message =
-        err instanceof Error ? err.message : "Unable to delete credential.";
+      const message = errorMessage(err, "Unable to delete credential.");
       setCredentialError(message);
     } finally {
       setCredentialDeletingId(null);
@@ -265,7 +262,10 @@ export default function VaultScreen({
     setIsExporting(true);
     setExportError(null);
     try {
-      const exported = await exportVaultFile(vaultPath);
+      const exported = await exportVaultFile({
+        path: vaultPath,
+        masterPassword,
+      });
       if (exported) {
         setIsExportSuccessOpen(true);
       }
@@ -273,11 +273,7 @@ export default function VaultScreen({
     } catch (err) {
       console.error("Export vault error:", err);
       const message =
-        err instanceof Error
-          ? err.message
-          : typeof err === "string"
-          ? err
-          : "Unable to export vault. Please try again.";
+        errorMessage(err, "Unable to export vault. Please try again.");
       setExportError(message);
     } finally {
       setIsExporting(false);
@@ -422,6 +418,15 @@ export default function VaultScreen({
           identifier={selectedCredential.title}
           username={selectedCredential.username}
           password={selectedCredential.password}
+          onCopy={(field) =>
+            copySecretToClipboard({
+              path: vaultPath,
+              masterPassword,
+              folderId: activeFolder?.id ?? "",
+              credentialId: selectedCredential.id,
+              field,
+            })
+          }
           onClose={() => setIsViewCredentialOpen(false)}
         />
       )}
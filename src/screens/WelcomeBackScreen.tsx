@@ -1,29 +1,117 @@
 import { motion } from "framer-motion";
 import { useState } from "react";
 import DeleteVaultConfirmModal from "../components/DeleteVaultConfirmModal";
-import { deleteVault, openVault } from "../services/vaultService";
-import { VaultContext, VaultSummary } from "../types";
+import {
+  checkVault,
+  deleteVault,
+  openVault,
+  repairVault,
+} from "../services/vaultService";
+import {
+  CheckReport,
+  Repair,
+  VaultContext,
+  VaultStatus,
+  VaultSummary,
+} from "../types";
 import vaultIcon from "../assets/vault.svg";
+import {
+  errorMessage,
+  isPekaError,
+  withConfirmation,
+} from "../utils/errors";
 import "../css/screens/WelcomeBackScreen.css";
 
+const STATUS_LABELS: Record<VaultStatus, string> = {
+  ok: "Ready",
+  unreadable: "Unreadable",
+  corrupted: "Corrupted",
+  unsupportedVersion: "Needs a newer version of PEKA",
+  unsupportedFeature: "Uses a feature PEKA does not support",
+  unsafeParameters: "Unsafe key derivation settings",
+};
+
+const REPAIR_LABELS: Record<Repair, string> = {
+  regenerateDuplicateIds: "give duplicate ids new ones",
+  fixTimestamps: "fix invalid timestamps",
+  removeOrphanedPinHashes: "remove leftover PIN hashes",
+  restoreBackup: "restore the newest good backup",
+};
+
+const reportSummary = (report: CheckReport): string => {
+  if (report.issues.length === 0) return "No problems found.";
+  const errors = report.issues.filter(
+    (issue) => issue.severity === "error"
+  ).length;
+  const warnings = report.issues.length - errors;
+  return [
+    errors > 0 && `${errors} error${errors === 1 ? "" : "s"}`,
+    warnings > 0 && `${warnings} warning${warnings === 1 ? "" : "s"}`,
+  ]
+    .filter(Boolean)
+    .join(" and ")
+    .concat(" found.");
+};
+
+const formatSize = (bytes: number): string => {
+  if (bytes < 1024) return `${bytes} B`;
+  if (bytes < 1024 * 1024) return `${(bytes / 1024).toFixed(1)} KB`;
+  return `${(bytes / (1024 * 1024)).toFixed(1)} MB`;
+};
+
+const vaultDetails = (vault: VaultSummary): string =>
+  [
+    formatSize(vault.sizeBytes),
+    vault.modifiedAt &&
+      `modified ${new Date(vault.modifiedAt).toLocaleString()}`,
+    vault.formatVersion !== null && `format v${vault.formatVersion}`,
+    vault.storage?.container === "binary" && "binary",
+    vault.storage?.compression === "deflate" && "compressed",
+    vault.storage?.layout === "entries" && "per-entry",
+    vault.readOnly && "read-only",
+    vault.lockedBy && `open on ${vault.lockedBy.device}`,
+  ]
+    .filter(Boolean)
+    .join(" · ");
+
 interface WelcomeBackScreenProps {
   vaults: VaultSummary[];
+  selectedPath: string | null;
+  onSelect: (path: string) => void;
+  /** Whether a vault is a file opened in place rather than one of a location. */
+  isExternal: (path: string) => boolean;
   onUnlock: (vault: VaultContext) => void;
   onVaultDeleted: (path: string) => void;
+  onOpenVaultFile: () => void;
+  onForgetVault: (path: string) => void;
+  /** A vault file was repaired, so its summary is out of date. */
+  onVaultRepaired: (path: string) => void;
 }
 
 export default function WelcomeBackScreen({
   vaults,
+  selectedPath,
+  onSelect,
+  isExternal,
   onUnlock,
   onVaultDeleted,
+  onOpenVaultFile,
+  onForgetVault,
+  onVaultRepaired,
 }: WelcomeBackScreenProps) {
-  const vault = vaults[0]; // Only one vault allowed
+  const vault =
+    vaults.find((entry) => entry.path === selectedPath) ?? vaults[0];
+  const canUnlock = vault?.status === "ok";
   const [masterPassword, setMasterPassword] = useState("");
   const [isUnlocking, setIsUnlocking] = useState(false);
   const [isDeleting, setIsDeleting] = useState(false);
   const [error, setError] = useState<string | null>(null);
   const [deleteError, setDeleteError] = useState<string | null>(null);
   const [isDeleteModalOpen, setIsDeleteModalOpen] = useState(false);
+  const [checkReport, setCheckReport] = useState<CheckReport | null>(null);
+  const [isChecking, setIsChecking] = useState(false);
+  const [checkError, setCheckError] = useState<string | null>(null);
+  const isBusy = isUnlocking || isDeleting || isChecking;
 
   const handleUnlock = async (e: React.FormEvent) => {
     e.preventDefault();
@@ -31,15 +119,18 @@ export default function WelcomeBackScreen({
       setError("Please enter your master password.");
       return;
     }
+    if (vault.status !== "ok") {
+      setError(vault.statusReason ?? STATUS_LABELS[vault.status]);
+      return;
+    }
 
     setIsUnlocking(true);
     setError(null);
 
     try {
-      const vaultData = await openVault({
-        path: vault.path,
-        masterPassword,
-      });
+      const vaultData = await withConfirmation((allowExpensiveKdf) =>
+        openVault({ path: vault.path, masterPassword, allowExpensiveKdf })
+      );
 
       const vaultContext: VaultContext = {
         path: vault.path,
@@ -52,13 +143,60 @@ export default function WelcomeBackScreen({
     } catch (err) {
       console.error(err);
       const message =
-        err instanceof Error ? err.message : "Failed to unlock vault";
+        isPekaError(err) && err.code === "wrongPassword"
+          ? "Incorrect master password."
+          : errorMessage(err, "Failed to unlock vault");
       setError(message);
     } finally {
       setIsUnlocking(false);
     }
   };
 
+  const handleCheck = async () => {
+    if (!vault || isBusy) {
+      return;
+    }
+    if (!masterPassword.trim()) {
+      setCheckError("Enter the master password to check the vault.");
+      return;
+    }
+
+    setIsChecking(true);
+    setCheckError(null);
+    try {
+      setCheckReport(await checkVault({ path: vault.path, masterPassword }));
+    } catch (err) {
+      console.error(err);
+      setCheckError(errorMessage(err, "Failed to check vault"));
+    } finally {
+      setIsChecking(false);
+    }
+  };
+
+  const handleRepair = async () => {
+    if (!vault || !checkReport || isBusy) {
+      return;
+    }
+
+    setIsChecking(true);
+    setCheckError(null);
+    try {
+      const result = await repairVault({
+        path: vault.path,
+        masterPassword,
+        repairs: checkReport.repairs,
+      });
+      setCheckReport(result.report);
+      setError(null);
+      onVaultRepaired(vault.path);
+    } catch (err) {
+      console.error(err);
+      setCheckError(errorMessage(err, "Failed to repair vault"));
+    } finally {
+      setIsChecking(false);
+    }
+  };
+
   const handleDeleteClick = () => {
     if (!vault || isUnlocking) {
       return;
@@ -79,8 +217,7 @@ export default function WelcomeBackScreen({
       onVaultDeleted(vault.path);
     } catch (err) {
       console.error(err);
-      const message =
-        err instanceof Error ? err.message : "Failed to delete vault";
+      const message = errorMessage(err, "Failed to delete vault");
       setDeleteError(message);
     } finally {
       setIsDeleting(false);
@@ -120,9 +257,46 @@ export default function WelcomeBackScreen({
         transition={{ duration: 0.5, delay: 0.3 }}
         onSubmit={handleUnlock}
       >
+        {vaults.length > 1 && (
+          <div className="form-group">
+            <label htmlFor="vaultSelect">Vault</label>
+            <select
+              id="vaultSelect"
+              value={vault?.path ?? ""}
+              onChange={(e) => {
+                onSelect(e.target.value);
+                setError(null);
+                setDeleteError(null);
+                setCheckReport(null);
+                setCheckError(null);
+              }}
+              disabled={isUnlocking}
+            >
+              {vaults.map((entry) => (
+                <option key={entry.path} value={entry.path}>
+                  {isExternal(entry.path)
+                    ? `${entry.vaultName} (${entry.path})`
+                    : entry.vaultName}
+                  {entry.status !== "ok" && ` - ${STATUS_LABELS[entry.status]}`}
+                </option>
+              ))}
+            </select>
+          </div>
+        )}
+
         {vault && (
           <div className="selected-vault-preview">
             <span className="vault-name">{vault.vaultName}</span>
+            {isExternal(vault.path) && (
+              <span className="vault-path">{vault.path}</span>
+            )}
+            <span className="vault-details">{vaultDetails(vault)}</span>
+            {vault.status !== "ok" && (
+              <span className="vault-status-error">
+                {STATUS_LABELS[vault.status]}
+                {vault.statusReason && `: ${vault.statusReason}`}
+              </span>
+            )}
           </div>
         )}
 
@@ -135,6 +309,7 @@ export default function WelcomeBackScreen({
             onChange={(e) => {
               setMasterPassword(e.target.value);
               setError(null);
+              setCheckError(null);
             }}
             disabled={isUnlocking}
             placeholder="Enter your master password"
@@ -156,14 +331,16 @@ export default function WelcomeBackScreen({
         <motion.button
           type="submit"
           className="action-button submit-button"
-          disabled={isUnlocking || !masterPassword.trim()}
+          disabled={isUnlocking || !canUnlock || !masterPassword.trim()}
           whileHover={
-            !isUnlocking && masterPassword.trim()
+            !isUnlocking && canUnlock && masterPassword.trim()
               ? { scale: 1.02, backgroundColor: "#e5e5e5" }
               : {}
           }
           whileTap={
-            !isUnlocking && masterPassword.trim() ? { scale: 0.98 } : {}
+            !isUnlocking && canUnlock && masterPassword.trim()
+              ? { scale: 0.98 }
+              : {}
           }
           transition={{ duration: 0.15, ease: "easeOut" }}
         >
@@ -178,6 +355,52 @@ export default function WelcomeBackScreen({
         </motion.button>
       </motion.form>
 
+      {(checkReport || checkError) && (
+        <div className="check-report">
+          {checkReport && (
+            <>
+              <p className="check-report-summary">
+                {reportSummary(checkReport)}
+              </p>
+              {checkReport.issues.length > 0 && (
+                <ul className="check-report-issues">
+                  {checkReport.issues.map((issue, index) => (
+                    <li
+                      key={`${issue.kind}-${issue.targetId ?? ""}-${index}`}
+                      className={`check-issue check-issue-${issue.severity}`}
+                    >
+                      {issue.message}
+                    </li>
+                  ))}
+                </ul>
+              )}
+              {checkReport.repairs.length > 0 && (
+                <>
+                  <p className="check-report-repairs">
+                    Repairing will{" "}
+                    {checkReport.repairs
+                      .map((repair) => REPAIR_LABELS[repair])
+                      .join(", ")}
+                    .
+                  </p>
+                  <button
+                    type="button"
+                    className="welcome-back-secondary"
+                    onClick={handleRepair}
+                    disabled={isBusy}
+                  >
+                    {isChecking ? "Repairing..." : "Repair Vault"}
+                  </button>
+                </>
+              )}
+            </>
+          )}
+          {checkError && (
+            <div className="submit-feedback error">{checkError}</div>
+          )}
+        </div>
+      )}
+
       <div className="welcome-back-actions">
         {deleteError && (
           <motion.div
@@ -206,6 +429,32 @@ export default function WelcomeBackScreen({
         >
           Delete Vault
         </motion.button>
+        <button
+          type="button"
+          className="welcome-back-secondary"
+          onClick={handleCheck}
+          disabled={!vault || isBusy}
+        >
+          {isChecking && !checkReport ? "Checking..." : "Check Vault"}
+        </button>
+        <button
+          type="button"
+          className="welcome-back-secondary"
+          onClick={onOpenVaultFile}
+          disabled={isUnlocking || isDeleting}
+        >
+          Open Another Vault File
+        </button>
+        {vault && isExternal(vault.path) && (
+          <button
+            type="button"
+            className="welcome-back-secondary"
+            onClick={() => onForgetVault(vault.path)}
+            disabled={isUnlocking || isDeleting}
+          >
+            Remove From Recent Files
+          </button>
+        )}
       </div>
 
       {vault && (
@@ -4,11 +4,13 @@ import "../css/screens/WelcomeScreen.css";
 interface WelcomeScreenProps {
   onGetStarted: () => void;
   onImportVault: () => void;
+  onOpenVaultFile: () => void;
 }
 
 export default function WelcomeScreen({
   onGetStarted,
   onImportVault,
+  onOpenVaultFile,
 }: WelcomeScreenProps) {
   return (
     <>
@@ -83,6 +85,31 @@ export default function WelcomeScreen({
             Import Vault
           </motion.button>
         </div>
+
+        <div className="divider"></div>
+
+        <div className="card-section">
+          <motion.h2
+            whileHover={{ scale: 1.02 }}
+            transition={{ duration: 0.2 }}
+          >
+            Open Vault File
+          </motion.h2>
+          <p className="section-description">
+            Unlock a .peka file where it is, such as on a shared drive or in a
+            git repository, without importing a copy. Your changes are saved
+            back to that file.
+          </p>
+          <motion.button
+            className="action-button"
+            whileHover={{ scale: 1.05, backgroundColor: "#e5e5e5" }}
+            whileTap={{ scale: 0.98 }}
+            transition={{ duration: 0.2 }}
+            onClick={onOpenVaultFile}
+          >
+            Open File
+          </motion.button>
+        </div>
       </motion.div>
     </>
   );
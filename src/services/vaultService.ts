@@ -1,17 +1,69 @@
 import { invoke } from "@tauri-apps/api/core";
-import { save } from "@tauri-apps/plugin-dialog";
+import { open, save } from "@tauri-apps/plugin-dialog";
 import {
+  AddAttachmentPayload,
   AddCredentialPayload,
+  AddVaultLocationPayload,
+  AttachmentSummary,
+  AttachmentTarget,
+  AuditLog,
+  BreachReport,
+  CheckBreachedPasswordsPayload,
+  CheckReport,
+  CheckVaultPayload,
+  CopySecretPayload,
   CreateFolderPayload,
   CreateVaultPayload,
   CreateVaultResponse,
+  CredentialTagPayload,
+  CsvImportResult,
+  DeleteAttachmentPayload,
   DeleteCredentialPayload,
   DeleteFolderPayload,
   DeleteVaultPayload,
+  DiffVaultHistoryPayload,
+  EmptyTrashPayload,
+  ExportKdbxPayload,
+  ExportResult,
+  ExportVaultPayload,
+  ExtractAttachmentPayload,
+  HistoryDiff,
+  HistoryRevision,
+  HistoryRollback,
+  HistoryStatus,
+  ImportCsvPayload,
+  ImportKdbxPayload,
   ImportVaultPayload,
   ImportVaultResponse,
+  KdbxImportResult,
+  LinkSyncDirectoryPayload,
+  MergeResult,
+  MergeVaultPayload,
   OpenVaultPayload,
+  PasswordStrength,
+  RecentVault,
+  RemoveTagPayload,
+  RenameTagPayload,
+  RepairResult,
+  RepairVaultPayload,
+  RestoreFromTrashPayload,
+  RollBackVaultPayload,
+  SearchResults,
+  SearchVaultPayload,
+  SetFavoritePayload,
+  SetHistoryRemotePayload,
+  SetStorageFormatPayload,
+  SetTrashRetentionPayload,
+  SyncReport,
+  SyncStatus,
+  SyncTarget,
+  SyncVaultPayload,
+  TagSummary,
+  TrashContents,
   VaultData,
+  VaultHealthReport,
+  VaultHealthReportPayload,
+  VaultLocation,
   VaultSummary,
   VerifyFolderPinPayload,
 } from "../types";
@@ -19,23 +71,79 @@ import {
 export async function createVault({
   vaultName,
   masterPassword,
+  locationId,
 }: CreateVaultPayload): Promise<CreateVaultResponse> {
   return invoke<CreateVaultResponse>("create_vault", {
     vaultName,
     masterPassword,
+    locationId,
   });
 }
 
 export async function openVault({
   path,
   masterPassword,
+  allowExpensiveKdf,
 }: OpenVaultPayload): Promise<VaultData> {
   return invoke<VaultData>("open_vault", {
     path,
     masterPassword,
+    allowExpensiveKdf,
   });
 }
 
+/** Name and path of a vault file, read without unlocking it. */
+export async function describeVaultFile(path: string): Promise<VaultSummary> {
+  return invoke<VaultSummary>("describe_vault_file", { path });
+}
+
+/** Checks a vault file end to end; works on vaults that no longer open. */
+export async function checkVault({
+  path,
+  masterPassword,
+}: CheckVaultPayload): Promise<CheckReport> {
+  return invoke<CheckReport>("check_vault", { path, masterPassword });
+}
+
+export async function repairVault({
+  path,
+  masterPassword,
+  repairs,
+}: RepairVaultPayload): Promise<RepairResult> {
+  return invoke<RepairResult>("repair_vault", {
+    path,
+    masterPassword,
+    repairs,
+  });
+}
+
+export async function listRecentVaults(): Promise<RecentVault[]> {
+  return invoke<RecentVault[]>("list_recent_vaults");
+}
+
+export async function forgetRecentVault(path: string): Promise<void> {
+  return invoke<void>("forget_recent_vault", { path });
+}
+
+/** The vault file the app was launched with, if it has not been picked up yet. */
+export async function takeLaunchVault(): Promise<string | null> {
+  return invoke<string | null>("take_launch_vault");
+}
+
+/** Asks for a vault file to open in place; null when the dialog is dismissed. */
+export async function pickVaultFile(): Promise<string | null> {
+  const selected = await open({
+    multiple: false,
+    filters: [
+      {
+        name: "PEKA Vault",
+        extensions: ["peka"],
+      },
+    ],
+  });
+  return typeof selected === "string" ? selected : null;
+}
+
 export async function createFolder({
   path,
   masterPassword,
@@ -56,6 +164,22 @@ export async function listVaults(): Promise<VaultSummary[]> {
   return invoke<VaultSummary[]>("list_vaults");
 }
 
+export async function listVaultLocations(): Promise<VaultLocation[]> {
+  return invoke<VaultLocation[]>("list_vault_locations");
+}
+
+export async function addVaultLocation({
+  path,
+  kind,
+  label,
+}: AddVaultLocationPayload): Promise<VaultLocation> {
+  return invoke<VaultLocation>("add_vault_location", { path, kind, label });
+}
+
+export async function removeVaultLocation(locationId: string): Promise<void> {
+  return invoke<void>("remove_vault_location", { locationId });
+}
+
 export async function verifyFolderPin({
   vaultPath,
   masterPassword,
@@ -74,7 +198,10 @@ export async function deleteVault({ path }: DeleteVaultPayload): Promise<void> {
   return invoke<void>("delete_vault", { path });
 }
 
-export async function exportVaultFile(path: string): Promise<boolean> {
+export async function exportVaultFile({
+  path,
+  masterPassword,
+}: OpenVaultPayload): Promise<boolean> {
   try {
     // Extract just the filename from the full path
     const pathParts = path.split(/[/\\]/);
@@ -96,6 +223,7 @@ export async function exportVaultFile(path: string): Promise<boolean> {
 
     await invoke<void>("export_vault_file", {
       sourcePath: path,
+      masterPassword,
       destinationPath: destination,
     });
     return true;
@@ -153,10 +281,450 @@ export async function importVault({
   sourcePath,
   vaultName,
   masterPassword,
+  locationId,
+  allowExpensiveKdf,
 }: ImportVaultPayload): Promise<ImportVaultResponse> {
   return invoke<ImportVaultResponse>("import_vault", {
     sourcePath,
     vaultName,
     masterPassword,
+    locationId,
+    allowExpensiveKdf,
+  });
+}
+
+export async function estimatePasswordStrength(
+  password: string,
+  userInputs: string[] = []
+): Promise<PasswordStrength> {
+  return invoke<PasswordStrength>("estimate_password_strength", {
+    password,
+    userInputs,
+  });
+}
+
+export async function vaultHealthReport({
+  path,
+  masterPassword,
+  maxAgeDays,
+}: VaultHealthReportPayload): Promise<VaultHealthReport> {
+  return invoke<VaultHealthReport>("vault_health_report", {
+    path,
+    masterPassword,
+    maxAgeDays,
+  });
+}
+
+export async function checkBreachedPasswords({
+  path,
+  masterPassword,
+  corpusPath,
+}: CheckBreachedPasswordsPayload): Promise<BreachReport> {
+  return invoke<BreachReport>("check_breached_passwords", {
+    path,
+    masterPassword,
+    corpusPath,
+  });
+}
+
+export async function importCsv({
+  path,
+  masterPassword,
+  csvPath,
+  source,
+  mapping,
+  dryRun,
+}: ImportCsvPayload): Promise<CsvImportResult> {
+  return invoke<CsvImportResult>("import_csv", {
+    path,
+    masterPassword,
+    csvPath,
+    source,
+    mapping,
+    dryRun,
+  });
+}
+
+export async function importKdbx({
+  path,
+  masterPassword,
+  kdbxPath,
+  kdbxPassword,
+  allowExpensiveKdf,
+}: ImportKdbxPayload): Promise<KdbxImportResult> {
+  return invoke<KdbxImportResult>("import_kdbx", {
+    path,
+    masterPassword,
+    kdbxPath,
+    kdbxPassword,
+    allowExpensiveKdf,
+  });
+}
+
+export async function exportKdbx({
+  path,
+  masterPassword,
+  destinationPath,
+  kdbxPassword,
+  cipher,
+}: ExportKdbxPayload): Promise<void> {
+  return invoke<void>("export_kdbx", {
+    path,
+    masterPassword,
+    destinationPath,
+    kdbxPassword,
+    cipher,
+  });
+}
+
+export async function exportVault({
+  path,
+  masterPassword,
+  destinationPath,
+  format,
+  folderIds,
+  exportPassword,
+  acknowledgePlaintext,
+}: ExportVaultPayload): Promise<ExportResult> {
+  return invoke<ExportResult>("export_vault", {
+    path,
+    masterPassword,
+    destinationPath,
+    format,
+    folderIds,
+    exportPassword,
+    acknowledgePlaintext,
+  });
+}
+
+export async function mergeVault({
+  path,
+  masterPassword,
+  sourcePath,
+  sourcePassword,
+  strategy,
+  resolutions,
+  allowExpensiveKdf,
+}: MergeVaultPayload): Promise<MergeResult> {
+  return invoke<MergeResult>("merge_vault", {
+    path,
+    masterPassword,
+    sourcePath,
+    sourcePassword,
+    strategy,
+    resolutions,
+    allowExpensiveKdf,
+  });
+}
+
+export async function addAttachment({
+  path,
+  masterPassword,
+  folderId,
+  credentialId,
+  filePath,
+}: AddAttachmentPayload): Promise<VaultData> {
+  return invoke<VaultData>("add_attachment", {
+    path,
+    masterPassword,
+    folderId,
+    credentialId,
+    filePath,
+  });
+}
+
+export async function listAttachments({
+  path,
+  masterPassword,
+  folderId,
+  credentialId,
+}: AttachmentTarget): Promise<AttachmentSummary[]> {
+  return invoke<AttachmentSummary[]>("list_attachments", {
+    path,
+    masterPassword,
+    folderId,
+    credentialId,
+  });
+}
+
+export async function extractAttachment({
+  path,
+  masterPassword,
+  folderId,
+  credentialId,
+  attachmentId,
+  destinationPath,
+}: ExtractAttachmentPayload): Promise<void> {
+  return invoke<void>("extract_attachment", {
+    path,
+    masterPassword,
+    folderId,
+    credentialId,
+    attachmentId,
+    destinationPath,
+  });
+}
+
+export async function deleteAttachment({
+  path,
+  masterPassword,
+  folderId,
+  credentialId,
+  attachmentId,
+}: DeleteAttachmentPayload): Promise<VaultData> {
+  return invoke<VaultData>("delete_attachment", {
+    path,
+    masterPassword,
+    folderId,
+    credentialId,
+    attachmentId,
+  });
+}
+
+export async function searchVault({
+  path,
+  masterPassword,
+  query,
+  filters,
+  sort,
+  limit,
+}: SearchVaultPayload): Promise<SearchResults> {
+  return invoke<SearchResults>("search_vault", {
+    path,
+    masterPassword,
+    query,
+    filters,
+    sort,
+    limit,
+  });
+}
+
+export async function listTags({
+  path,
+  masterPassword,
+}: OpenVaultPayload): Promise<TagSummary[]> {
+  return invoke<TagSummary[]>("list_tags", { path, masterPassword });
+}
+
+export async function addTag({
+  path,
+  masterPassword,
+  folderId,
+  credentialId,
+  tag,
+}: CredentialTagPayload): Promise<VaultData> {
+  return invoke<VaultData>("add_tag", {
+    path,
+    masterPassword,
+    folderId,
+    credentialId,
+    tag,
+  });
+}
+
+export async function removeTag({
+  path,
+  masterPassword,
+  tag,
+  folderId,
+  credentialId,
+}: RemoveTagPayload): Promise<VaultData> {
+  return invoke<VaultData>("remove_tag", {
+    path,
+    masterPassword,
+    tag,
+    folderId,
+    credentialId,
+  });
+}
+
+export async function renameTag({
+  path,
+  masterPassword,
+  from,
+  to,
+}: RenameTagPayload): Promise<VaultData> {
+  return invoke<VaultData>("rename_tag", { path, masterPassword, from, to });
+}
+
+export async function setFavorite({
+  path,
+  masterPassword,
+  folderId,
+  credentialId,
+  favorite,
+}: SetFavoritePayload): Promise<VaultData> {
+  return invoke<VaultData>("set_favorite", {
+    path,
+    masterPassword,
+    folderId,
+    credentialId,
+    favorite,
+  });
+}
+
+export async function listTrash({
+  path,
+  masterPassword,
+}: OpenVaultPayload): Promise<TrashContents> {
+  return invoke<TrashContents>("list_trash", { path, masterPassword });
+}
+
+export async function restoreFromTrash({
+  path,
+  masterPassword,
+  trashId,
+}: RestoreFromTrashPayload): Promise<VaultData> {
+  return invoke<VaultData>("restore_from_trash", {
+    path,
+    masterPassword,
+    trashId,
+  });
+}
+
+export async function emptyTrash({
+  path,
+  masterPassword,
+  trashIds,
+}: EmptyTrashPayload): Promise<TrashContents> {
+  return invoke<TrashContents>("empty_trash", {
+    path,
+    masterPassword,
+    trashIds,
+  });
+}
+
+export async function setTrashRetention({
+  path,
+  masterPassword,
+  retentionDays,
+}: SetTrashRetentionPayload): Promise<TrashContents> {
+  return invoke<TrashContents>("set_trash_retention", {
+    path,
+    masterPassword,
+    retentionDays,
+  });
+}
+
+export async function getAuditLog({
+  path,
+  masterPassword,
+}: OpenVaultPayload): Promise<AuditLog> {
+  return invoke<AuditLog>("get_audit_log", { path, masterPassword });
+}
+
+/** Where the vault syncs to, or null when it is not linked to a sync directory. */
+export async function getSyncStatus({ path }: SyncTarget): Promise<SyncStatus | null> {
+  return invoke<SyncStatus | null>("get_sync_status", { path });
+}
+
+export async function linkSyncDirectory({
+  path,
+  masterPassword,
+  directory,
+}: LinkSyncDirectoryPayload): Promise<SyncStatus> {
+  return invoke<SyncStatus>("link_sync_directory", { path, masterPassword, directory });
+}
+
+export async function unlinkSyncDirectory({
+  path,
+  masterPassword,
+}: OpenVaultPayload): Promise<void> {
+  return invoke<void>("unlink_sync_directory", { path, masterPassword });
+}
+
+/** Merges the vault with its copy in the sync directory and writes both back. */
+export async function syncVault({
+  path,
+  masterPassword,
+  resolutions,
+}: SyncVaultPayload): Promise<SyncReport> {
+  return invoke<SyncReport>("sync_vault", { path, masterPassword, resolutions });
+}
+
+/** The vault's git history, or null when it is not enabled. */
+export async function getHistoryStatus({ path }: SyncTarget): Promise<HistoryStatus | null> {
+  return invoke<HistoryStatus | null>("get_history_status", { path });
+}
+
+/** Commits every save of the vault to a git repository next to it from now on. */
+export async function enableVaultHistory({
+  path,
+  masterPassword,
+}: OpenVaultPayload): Promise<HistoryStatus> {
+  return invoke<HistoryStatus>("enable_vault_history", { path, masterPassword });
+}
+
+/** Deletes the local history; revisions already pushed stay on the remote. */
+export async function disableVaultHistory({
+  path,
+  masterPassword,
+}: OpenVaultPayload): Promise<void> {
+  return invoke<void>("disable_vault_history", { path, masterPassword });
+}
+
+export async function setHistoryRemote({
+  path,
+  masterPassword,
+  remote,
+}: SetHistoryRemotePayload): Promise<HistoryStatus> {
+  return invoke<HistoryStatus>("set_history_remote", { path, masterPassword, remote });
+}
+
+export async function pushVaultHistory({ path, masterPassword }: OpenVaultPayload): Promise<void> {
+  return invoke<void>("push_vault_history", { path, masterPassword });
+}
+
+/** Revisions of the vault, newest first. */
+export async function listVaultHistory({ path }: SyncTarget): Promise<HistoryRevision[]> {
+  return invoke<HistoryRevision[]>("list_vault_history", { path });
+}
+
+export async function diffVaultHistory({
+  path,
+  masterPassword,
+  from,
+  to,
+}: DiffVaultHistoryPayload): Promise<HistoryDiff> {
+  return invoke<HistoryDiff>("diff_vault_history", { path, masterPassword, from, to });
+}
+
+/** Brings the vault back to a revision; the rollback is saved as a new revision. */
+export async function rollBackVault({
+  path,
+  masterPassword,
+  revision,
+}: RollBackVaultPayload): Promise<HistoryRollback> {
+  return invoke<HistoryRollback>("roll_back_vault", { path, masterPassword, revision });
+}
+
+export async function copySecretToClipboard({
+  path,
+  masterPassword,
+  folderId,
+  credentialId,
+  field,
+  clearAfterSeconds,
+}: CopySecretPayload): Promise<void> {
+  return invoke<void>("copy_secret_to_clipboard", {
+    path,
+    masterPassword,
+    folderId,
+    credentialId,
+    field,
+    clearAfterSeconds,
+  });
+}
+
+/** Changes how the vault file is stored; returns the rewritten file's summary. */
+export async function setStorageFormat({
+  path,
+  masterPassword,
+  format,
+}: SetStorageFormatPayload): Promise<VaultSummary> {
+  return invoke<VaultSummary>("set_storage_format", {
+    path,
+    masterPassword,
+    format,
   });
 }
@@ -13,6 +13,8 @@ export interface ValidationResult {
 export interface CreateVaultPayload {
   vaultName: string;
   masterPassword: string;
+  /** Registered directory to create the vault in; the app data directory if unset. */
+  locationId?: string;
 }
 
 export interface CreateVaultResponse {
@@ -39,12 +41,24 @@ export interface VaultFolder {
   updatedAt: string;
 }
 
+export interface CustomField {
+  name: string;
+  value: string;
+  hidden: boolean;
+}
+
 export interface VaultCredential {
   id: string;
   title: string;
   username: string;
   password: string;
+  url?: string;
   notes?: string;
+  totp?: string;
+  customFields?: CustomField[];
+  attachments?: AttachmentSummary[];
+  tags: string[];
+  favorite: boolean;
   createdAt: string;
   updatedAt: string;
 }
@@ -52,6 +66,8 @@ export interface VaultCredential {
 export interface OpenVaultPayload {
   path: string;
   masterPassword: string;
+  /** Go ahead even if key derivation of the vault file is unusually costly. */
+  allowExpensiveKdf?: boolean;
 }
 
 export interface CreateFolderPayload {
@@ -62,9 +78,158 @@ export interface CreateFolderPayload {
   pin?: string;
 }
 
+export type VaultStatus =
+  | "ok"
+  | "unreadable"
+  | "corrupted"
+  | "unsupportedVersion"
+  | "unsupportedFeature"
+  | "unsafeParameters";
+
+/** Key derivation parameters as written in the vault file header. */
+export interface KdfParams {
+  algorithm: string;
+  memory_kib: number;
+  time_cost: number;
+  parallelism: number;
+  hash_length: number;
+  salt_length: number;
+}
+
+export type ContainerFormat = "json" | "binary";
+
+export type PayloadCompression = "none" | "deflate";
+
+/** `entries` encrypts every folder and credential on its own. */
+export type PayloadLayout = "single" | "entries";
+
+/** How a vault file is written: JSON or the compact binary container. */
+export interface StorageFormat {
+  container: ContainerFormat;
+  compression: PayloadCompression;
+  layout: PayloadLayout;
+}
+
+export interface SetStorageFormatPayload {
+  path: string;
+  masterPassword: string;
+  format: StorageFormat;
+}
+
+/** What can be told about a vault file without the master password. */
 export interface VaultSummary {
   path: string;
+  /** Name from the header, or the file name when the header is unreadable. */
   vaultName: string;
+  /** Registered location the vault was found through; unset for the app data directory. */
+  locationId?: string;
+  status: VaultStatus;
+  /** Why the status is not "ok". */
+  statusReason: string | null;
+  sizeBytes: number;
+  modifiedAt: string | null;
+  formatVersion: number | null;
+  kdf: KdfParams | null;
+  storage: StorageFormat | null;
+  /** The file's permissions forbid writing it, so changes could not be saved. */
+  readOnly: boolean;
+  /** Another process that has the vault open, if any. */
+  lockedBy: LockOwner | null;
+}
+
+/** A process that has a vault open and keeps others from opening it. */
+export interface LockOwner {
+  device: string;
+  processId: number;
+  acquiredAt: string;
+}
+
+/** A vault file outside every location that was opened in place. */
+export interface RecentVault {
+  path: string;
+  vaultName: string;
+  openedAt: string;
+}
+
+export type VaultLocationKind = "directory" | "file";
+
+export interface VaultLocation {
+  id: string;
+  kind: VaultLocationKind;
+  path: string;
+  label: string;
+  addedAt: string;
+}
+
+export interface AddVaultLocationPayload {
+  path: string;
+  kind: VaultLocationKind;
+  label?: string;
+}
+
+export type IssueSeverity = "error" | "warning";
+
+export type IssueKind =
+  | "invalidJson"
+  | "invalidContainer"
+  | "unsupportedVersion"
+  | "invalidEncoding"
+  | "invalidKdf"
+  | "decryptionFailed"
+  | "invalidPayload"
+  | "nameMismatch"
+  | "duplicateId"
+  | "invalidTimestamp"
+  | "orphanedPinHash"
+  | "missingPinHash"
+  | "invalidPinHash"
+  | "missingAttachment"
+  | "auditChainBroken";
+
+export type Repair =
+  | "regenerateDuplicateIds"
+  | "fixTimestamps"
+  | "removeOrphanedPinHashes"
+  | "restoreBackup";
+
+export interface CheckIssue {
+  kind: IssueKind;
+  severity: IssueSeverity;
+  message: string;
+  targetId?: string;
+  repair?: Repair;
+}
+
+/** A copy of the vault file from before one of its last saves. */
+export interface BackupSummary {
+  path: string;
+  createdAt: string;
+  sizeBytes: number;
+}
+
+export interface CheckReport {
+  path: string;
+  /** No errors were found; there may be warnings. */
+  healthy: boolean;
+  decrypted: boolean;
+  issues: CheckIssue[];
+  repairs: Repair[];
+  backups: BackupSummary[];
+}
+
+export interface RepairResult {
+  applied: Repair[];
+  restoredFrom?: string;
+  report: CheckReport;
+}
+
+export interface CheckVaultPayload {
+  path: string;
+  masterPassword: string;
+}
+
+export interface RepairVaultPayload extends CheckVaultPayload {
+  repairs: Repair[];
 }
 
 export interface VerifyFolderPinPayload {
@@ -104,8 +269,570 @@ export interface ImportVaultPayload {
   sourcePath: string;
   vaultName: string;
   masterPassword: string;
+  /** Registered directory to import into; the app data directory if unset. */
+  locationId?: string;
+  /** Go ahead even if key derivation of the vault file is unusually costly. */
+  allowExpensiveKdf?: boolean;
 }
 
 export interface ImportVaultResponse {
   path: string;
 }
+
+export interface PasswordStrength {
+  score: number;
+  entropyBits: number;
+  warnings: string[];
+  suggestions: string[];
+}
+
+export interface HealthCounts {
+  credentials: number;
+  weak: number;
+  reused: number;
+  old: number;
+  missingTotp: number;
+}
+
+export interface CredentialHealth {
+  credentialId: string;
+  title: string;
+  username: string;
+  score: number;
+  weak: boolean;
+  reusedWith: number;
+  old: boolean;
+  ageDays?: number;
+  missingTotp: boolean;
+}
+
+export interface FolderHealth {
+  folderId: string;
+  folderName: string;
+  counts: HealthCounts;
+  issues: CredentialHealth[];
+}
+
+export interface VaultHealthReport {
+  generatedAt: string;
+  maxAgeDays: number;
+  totals: HealthCounts;
+  folders: FolderHealth[];
+}
+
+export interface VaultHealthReportPayload {
+  path: string;
+  masterPassword: string;
+  maxAgeDays?: number;
+}
+
+export interface BreachMatch {
+  folderId: string;
+  folderName: string;
+  credentialId: string;
+  title: string;
+  username: string;
+  occurrences: number;
+}
+
+export interface BreachReport {
+  checked: number;
+  breached: number;
+  matches: BreachMatch[];
+}
+
+export interface CheckBreachedPasswordsPayload {
+  path: string;
+  masterPassword: string;
+  corpusPath: string;
+}
+
+export type CsvSource =
+  | "bitwarden"
+  | "1password"
+  | "keepassxc"
+  | "lastpass"
+  | "chrome"
+  | "generic";
+
+export interface CsvColumnMapping {
+  title?: string;
+  username?: string;
+  password?: string;
+  url?: string;
+  notes?: string;
+  totp?: string;
+  folder?: string;
+}
+
+export interface ImportCsvPayload {
+  path: string;
+  masterPassword: string;
+  csvPath: string;
+  source: CsvSource;
+  mapping?: CsvColumnMapping;
+  dryRun: boolean;
+}
+
+export interface CsvPreviewEntry {
+  line: number;
+  folder: string;
+  title: string;
+  username: string;
+  url?: string;
+  hasNotes: boolean;
+  hasTotp: boolean;
+}
+
+export interface CsvSkippedRow {
+  line: number;
+  reason: string;
+}
+
+export interface CsvImportResult {
+  dryRun: boolean;
+  imported: number;
+  foldersCreated: string[];
+  entries: CsvPreviewEntry[];
+  skipped: CsvSkippedRow[];
+  vault?: VaultData;
+}
+
+export type KdbxCipher = "aes256" | "chacha20";
+
+export interface ImportKdbxPayload {
+  path: string;
+  masterPassword: string;
+  kdbxPath: string;
+  kdbxPassword: string;
+  /** Go ahead even if key derivation of the database is unusually costly. */
+  allowExpensiveKdf?: boolean;
+}
+
+export interface KdbxImportResult {
+  imported: number;
+  skipped: number;
+  foldersCreated: string[];
+  vault: VaultData;
+}
+
+export interface ExportKdbxPayload {
+  path: string;
+  masterPassword: string;
+  destinationPath: string;
+  kdbxPassword: string;
+  cipher?: KdbxCipher;
+}
+
+export type ExportFormat = "json" | "csv" | "bitwarden" | "encrypted";
+
+export interface ExportVaultPayload {
+  path: string;
+  masterPassword: string;
+  destinationPath: string;
+  format: ExportFormat;
+  folderIds?: string[];
+  exportPassword?: string;
+  acknowledgePlaintext: boolean;
+}
+
+export interface ExportResult {
+  path: string;
+  folders: number;
+  credentials: number;
+}
+
+export type MergeStrategy = "newest" | "keepBoth" | "interactive";
+
+export type ConflictResolution = "target" | "source" | "both";
+
+export interface MergeVaultPayload {
+  path: string;
+  masterPassword: string;
+  sourcePath: string;
+  sourcePassword: string;
+  strategy: MergeStrategy;
+  resolutions?: Record<string, ConflictResolution>;
+  /** Go ahead even if key derivation of the vault file is unusually costly. */
+  allowExpensiveKdf?: boolean;
+}
+
+export interface MergeConflict {
+  sourceCredentialId: string;
+  targetCredentialId: string;
+  targetFolderId: string;
+  title: string;
+  username: string;
+  differingFields: string[];
+  sourceUpdatedAt: string;
+  targetUpdatedAt: string;
+  sourceIsNewer: boolean;
+}
+
+export interface MergeResult {
+  applied: boolean;
+  foldersAdded: number;
+  added: number;
+  updated: number;
+  unchanged: number;
+  keptBoth: number;
+  conflicts: MergeConflict[];
+  vault?: VaultData;
+}
+
+export interface SyncStatus {
+  directory: string;
+  /** The copy of the vault in the sync directory. */
+  sharedPath: string;
+  sharedExists: boolean;
+  lastSyncedAt: string | null;
+  /** The vault changed here since the last sync. */
+  localChanged: boolean;
+  /** The copy in the sync directory changed since the last sync. */
+  remoteChanged: boolean;
+}
+
+export interface HistoryStatus {
+  /** The git repository holding the history. */
+  directory: string;
+  remote: string | null;
+  revisions: number;
+  /** Why the last save is missing from the history; the save itself succeeded. */
+  lastError: string | null;
+}
+
+export interface HistoryRevision {
+  revision: string;
+  committedAt: string;
+  author: string;
+  /** What changed, e.g. "credential updated"; never titles or values. */
+  message: string;
+}
+
+export interface SetHistoryRemotePayload {
+  path: string;
+  masterPassword: string;
+  /** Any URL git understands; omit to remove the remote. */
+  remote?: string;
+}
+
+export interface DiffVaultHistoryPayload {
+  path: string;
+  masterPassword: string;
+  from: string;
+  /** Compares with the vault as it is now when omitted. */
+  to?: string;
+}
+
+export interface HistoryChange {
+  recordId: string;
+  kind: "folder" | "credential";
+  change: "added" | "removed" | "modified";
+  title: string;
+  /** Names of the changed fields; the values are not sent. */
+  fields: string[];
+}
+
+export interface HistoryDiff {
+  from: string;
+  to: string | null;
+  changes: HistoryChange[];
+}
+
+export interface RollBackVaultPayload {
+  path: string;
+  masterPassword: string;
+  revision: string;
+}
+
+export interface HistoryRollback {
+  revision: string;
+  /** Attachments of the revision whose files are gone and that were left out. */
+  missingAttachments: number;
+  vault?: VaultData;
+}
+
+export interface SyncTarget {
+  path: string;
+}
+
+export interface LinkSyncDirectoryPayload {
+  path: string;
+  masterPassword: string;
+  directory: string;
+}
+
+export type SyncResolution = "local" | "remote";
+
+export interface SyncVaultPayload {
+  path: string;
+  masterPassword: string;
+  /** Keyed by the record id of a conflict reported by an earlier sync. */
+  resolutions?: Record<string, SyncResolution>;
+}
+
+export interface SyncConflict {
+  recordId: string;
+  kind: "folder" | "credential";
+  conflict: "bothModified" | "deletedLocally" | "deletedRemotely";
+  /** Folder name or credential title. */
+  title: string;
+  /** Fields both copies changed; the values are not sent. */
+  fields: string[];
+}
+
+export interface SyncReport {
+  /** Nothing is applied while conflicts are unresolved. */
+  applied: boolean;
+  pushed: number;
+  pulled: number;
+  merged: number;
+  deleted: number;
+  conflicts: SyncConflict[];
+  /** The copy in the sync directory was written. */
+  published: boolean;
+  vault?: VaultData;
+}
+
+export interface AttachmentSummary {
+  id: string;
+  fileName: string;
+  size: number;
+  createdAt: string;
+}
+
+export interface AttachmentTarget {
+  path: string;
+  masterPassword: string;
+  folderId: string;
+  credentialId: string;
+}
+
+export interface AddAttachmentPayload extends AttachmentTarget {
+  filePath: string;
+}
+
+export interface ExtractAttachmentPayload extends AttachmentTarget {
+  attachmentId: string;
+  destinationPath: string;
+}
+
+export interface DeleteAttachmentPayload extends AttachmentTarget {
+  attachmentId: string;
+}
+
+export interface SearchFilters {
+  folderIds?: string[];
+  weak?: boolean;
+  reused?: boolean;
+  hasTotp?: boolean;
+  tags?: string[];
+  favorites?: boolean;
+  kind?: CredentialKind;
+}
+
+/**
+ * What a credential is used for: `login` has a username or password, `note` only
+ * notes, `totp` only a TOTP secret.
+ */
+export type CredentialKind = "login" | "note" | "totp";
+
+export type SearchSort =
+  | "relevance"
+  | "titleAsc"
+  | "titleDesc"
+  | "updatedNewest"
+  | "updatedOldest"
+  | "createdNewest"
+  | "createdOldest";
+
+export type SearchField = "title" | "username" | "url" | "notes" | "tags";
+
+export interface SearchHighlight {
+  field: SearchField;
+  snippet?: string;
+  tag?: number;
+  ranges: [number, number][];
+}
+
+export interface SearchHit {
+  credentialId: string;
+  folderId: string;
+  folderName: string;
+  secure: boolean;
+  title: string;
+  username: string;
+  url?: string;
+  hasTotp: boolean;
+  tags: string[];
+  favorite: boolean;
+  createdAt: string;
+  updatedAt: string;
+  score: number;
+  highlights: SearchHighlight[];
+}
+
+export interface SearchResults {
+  total: number;
+  hits: SearchHit[];
+}
+
+export interface SearchVaultPayload {
+  path: string;
+  masterPassword: string;
+  query: string;
+  filters?: SearchFilters;
+  sort?: SearchSort;
+  limit?: number;
+}
+
+export interface TagSummary {
+  name: string;
+  credentials: number;
+}
+
+export interface CredentialTagPayload {
+  path: string;
+  masterPassword: string;
+  folderId: string;
+  credentialId: string;
+  tag: string;
+}
+
+export interface RemoveTagPayload {
+  path: string;
+  masterPassword: string;
+  tag: string;
+  folderId?: string;
+  credentialId?: string;
+}
+
+export interface RenameTagPayload {
+  path: string;
+  masterPassword: string;
+  from: string;
+  to: string;
+}
+
+export interface SetFavoritePayload {
+  path: string;
+  masterPassword: string;
+  folderId: string;
+  credentialId: string;
+  favorite: boolean;
+}
+
+export interface CopySecretPayload {
+  path: string;
+  masterPassword: string;
+  folderId: string;
+  credentialId: string;
+  /** "password", "username", "url", "notes", "totp" or a custom field name. */
+  field: string;
+  /** Defaults to 30 seconds; 0 keeps the value on the clipboard. */
+  clearAfterSeconds?: number;
+}
+
+export type TrashItemKind = "folder" | "credential";
+
+export interface TrashItemSummary {
+  id: string;
+  kind: TrashItemKind;
+  name: string;
+  folderId: string;
+  folderName: string;
+  username?: string;
+  credentials: number;
+  deletedAt: string;
+  purgeAt?: string;
+}
+
+export interface TrashContents {
+  retentionDays: number;
+  items: TrashItemSummary[];
+}
+
+export interface RestoreFromTrashPayload {
+  path: string;
+  masterPassword: string;
+  trashId: string;
+}
+
+export interface EmptyTrashPayload {
+  path: string;
+  masterPassword: string;
+  trashIds?: string[];
+}
+
+export interface SetTrashRetentionPayload {
+  path: string;
+  masterPassword: string;
+  retentionDays: number;
+}
+
+export type AuditAction =
+  | "vaultCreated"
+  | "vaultImported"
+  | "vaultExported"
+  | "vaultMerged"
+  | "vaultSynced"
+  | "vaultRolledBack"
+  | "vaultRepaired"
+  | "folderCreated"
+  | "folderDeleted"
+  | "credentialAdded"
+  | "credentialUpdated"
+  | "credentialDeleted"
+  | "credentialsImported"
+  | "attachmentAdded"
+  | "attachmentExtracted"
+  | "attachmentDeleted"
+  | "tagAdded"
+  | "tagRemoved"
+  | "tagRenamed"
+  | "favoriteChanged"
+  | "trashRestored"
+  | "trashEmptied"
+  | "trashPurged"
+  | "trashRetentionChanged"
+  | "storageFormatChanged"
+  | "pinVerificationFailed";
+
+export interface AuditEntry {
+  sequence: number;
+  timestamp: string;
+  action: AuditAction;
+  targetId?: string;
+  device: string;
+  previousHash: string;
+  hash: string;
+}
+
+export interface AuditLog {
+  entries: AuditEntry[];
+  verified: boolean;
+  brokenAt?: number;
+}
+
+export type PekaErrorCode =
+  | "wrongPassword"
+  | "corrupted"
+  | "tampered"
+  | "notFound"
+  | "alreadyExists"
+  | "ioError"
+  | "policyViolation"
+  | "locked"
+  | "invalidInput"
+  | "unsupportedVersion"
+  | "unsupported"
+  | "unsafeParameters"
+  | "confirmationRequired"
+  | "internal";
+
+/** Error returned by every backend command. */
+export interface PekaError {
+  code: PekaErrorCode;
+  message: string;
+  details: string | null;
+}
@@ -0,0 +1,39 @@
+import { PekaError } from "../types";
+
+export const isPekaError = (error: unknown): error is PekaError =>
+  typeof error === "object" &&
+  error !== null &&
+  typeof (error as PekaError).code === "string" &&
+  typeof (error as PekaError).message === "string";
+
+/** Text to show for an error thrown by a backend command or the frontend. */
+export const errorMessage = (error: unknown, fallback: string): string => {
+  if (isPekaError(error) || error instanceof Error) {
+    return error.message;
+  }
+  if (typeof error === "string" && error.trim() !== "") {
+    return error;
+  }
+  return fallback;
+};
+
+/**
+ * Runs `action`, and when the backend asks for confirmation, asks the user and runs it
+ * again with the go-ahead.
+ */
+export const withConfirmation = async <T>(
+  action: (confirmed: boolean) => Promise<T>
+): Promise<T> => {
+  try {
+    return await action(false);
+  } catch (error) {
+    if (
+      isPekaError(error) &&
+      error.code === "confirmationRequired" &&
+      window.confirm(`${error.message}. Continue anyway?`)
+    ) {
+      return action(true);
+    }
+    throw error;
+  }
+};
@@ -1,9 +1,16 @@
-import zxcvbn from "zxcvbn";
-import { ValidationResult } from "../types";
+import { PasswordStrength, ValidationResult } from "../types";
 
+/** Minimum master password score, as enforced by the backend when creating a vault. */
+export const MIN_MASTER_PASSWORD_SCORE = 3;
+
+/**
+ * `strength` is the backend's estimate for `password`; the password is not valid
+ * while it is still being estimated.
+ */
 export const validatePassword = (
   password: string,
-  confirm: string
+  confirm: string,
+  strength: PasswordStrength | null
 ): ValidationResult => {
   const errors: string[] = [];
 
@@ -38,14 +45,13 @@ export const validatePassword = (
     errors.push("Passwords do not match");
   }
 
-  // 7. Password Entropy Score (using zxcvbn)
-  const zxcvbnResult = zxcvbn(password);
-  if (zxcvbnResult.score < 3) {
+  // 7. Password Strength Score (estimated by the backend)
+  if (strength && strength.score < MIN_MASTER_PASSWORD_SCORE) {
     errors.push("Password is too weak. Please use a stronger password");
   }
 
   return {
-    isValid: errors.length === 0,
+    isValid: strength !== null && errors.length === 0,
     errors,
   };
 };